@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use clap::{value_t, App, Arg};
+use serde::Deserialize;
+
+use filecoin_proofs::types::{MerkleTreeTrait, SectorSize};
+use filecoin_proofs::with_shape;
+use filecoin_proofs::{scrub_replicas, PrivateReplicaInfo, ScrubConfig, ScrubMode, ScrubReport};
+use storage_proofs::sector::SectorId;
+
+#[derive(Debug, Deserialize)]
+struct SectorEntry {
+    sector_id: u64,
+    replica: PathBuf,
+    cache: PathBuf,
+    /// Hex encoded.
+    comm_r: String,
+}
+
+fn parse_comm_r(hex_str: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(hex_str).context("comm_r must be hex encoded")?;
+    ensure!(bytes.len() == 32, "comm_r must be 32 bytes long");
+
+    let mut comm_r = [0u8; 32];
+    comm_r.copy_from_slice(&bytes);
+    Ok(comm_r)
+}
+
+fn scrub<Tree: 'static + MerkleTreeTrait>(
+    config: &ScrubConfig,
+    sectors: &[SectorEntry],
+) -> Result<ScrubReport> {
+    let mut replicas = BTreeMap::new();
+    for sector in sectors {
+        let replica = PrivateReplicaInfo::<Tree>::new(
+            sector.replica.clone(),
+            parse_comm_r(&sector.comm_r)?,
+            sector.cache.clone(),
+        )
+        .with_context(|| format!("invalid sector {}", sector.sector_id))?;
+        replicas.insert(SectorId::from(sector.sector_id), replica);
+    }
+
+    scrub_replicas(config, &replicas, &mut rand::thread_rng())
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let matches = App::new("scrub")
+        .version("0.1")
+        .about("Verifies sealed replicas against their tree-r-last to find corrupted data")
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .long("size")
+                .default_value("34359738368")
+                .help("The sector size in bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sectors")
+                .long("sectors")
+                .required(true)
+                .help("JSON file listing the sectors as [{sector_id, replica, cache, comm_r}]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sample")
+                .long("sample")
+                .default_value("16")
+                .help("The number of random ranges checked per replica")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("exhaustive")
+                .long("exhaustive")
+                .help("Check every leaf instead of sampling"),
+        )
+        .arg(
+            Arg::with_name("range-len")
+                .long("range-len")
+                .default_value("4096")
+                .help("The number of leafs checked per range")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bytes-per-second")
+                .long("bytes-per-second")
+                .help("Limit the replica bytes read per second")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-bytes")
+                .long("max-bytes")
+                .help("Stop after reading this many replica bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cursor")
+                .long("cursor")
+                .requires("exhaustive")
+                .help("File keeping the progress of an exhaustive scrub between runs")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print the report as JSON"),
+        )
+        .get_matches();
+
+    let sector_size = value_t!(matches, "size", u64)?;
+    let sectors_path = value_t!(matches, "sectors", PathBuf)?;
+    let sectors: Vec<SectorEntry> = serde_json::from_slice(
+        &fs::read(&sectors_path).with_context(|| format!("could not read {:?}", sectors_path))?,
+    )
+    .with_context(|| format!("could not parse {:?}", sectors_path))?;
+
+    let mode = if matches.is_present("exhaustive") {
+        ScrubMode::Exhaustive
+    } else {
+        ScrubMode::Sample {
+            ranges: value_t!(matches, "sample", usize)?,
+        }
+    };
+    let config = ScrubConfig {
+        sector_size: SectorSize(sector_size),
+        mode,
+        range_len: value_t!(matches, "range-len", usize)?,
+        bytes_per_second: matches
+            .value_of("bytes-per-second")
+            .map(str::parse)
+            .transpose()?,
+        max_bytes: matches.value_of("max-bytes").map(str::parse).transpose()?,
+        cursor_path: matches.value_of("cursor").map(PathBuf::from),
+    };
+
+    let report = with_shape!(sector_size, scrub, &config, &sectors)?;
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for (sector_id, sector) in &report.sectors {
+            if let Some(ref error) = sector.error {
+                println!("sector {}: error: {}", sector_id, error);
+                continue;
+            }
+            let status = if sector.corrupted.is_empty() {
+                "ok"
+            } else {
+                "CORRUPTED"
+            };
+            println!(
+                "sector {}: {} ({} leafs checked{})",
+                sector_id,
+                status,
+                sector.leafs_checked,
+                if sector.completed { ", completed" } else { "" }
+            );
+            for range in &sector.corrupted {
+                println!("  leafs {}..{}: {}", range.start, range.end, range.reason);
+            }
+        }
+        println!("{} bytes read", report.bytes_read);
+        if report.budget_exhausted {
+            println!("stopped after reaching --max-bytes");
+        }
+    }
+
+    ensure!(report.is_healthy(), "scrub found corrupted replicas");
+
+    Ok(())
+}
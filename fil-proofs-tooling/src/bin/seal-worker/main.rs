@@ -0,0 +1,54 @@
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use clap::{value_t, App, Arg};
+
+use fil_proofs_tooling::seal_worker::{seal_commit_phase2_request, SealWorker};
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let matches = App::new("seal-worker")
+        .version("0.1")
+        .about("Runs seal commit phase2 for requests received over a socket")
+        .arg(
+            Arg::with_name("tcp")
+                .long("tcp")
+                .help("The address to listen on, e.g. 127.0.0.1:9000")
+                .conflicts_with("unix")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("unix")
+                .long("unix")
+                .help("The path of the unix socket to listen on")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("queue-depth")
+                .long("queue-depth")
+                .default_value("16")
+                .help("The number of requests waiting to be proven before new requests block")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let queue_depth = value_t!(matches, "queue-depth", usize)?;
+    let worker = SealWorker::new(queue_depth, Arc::new(seal_commit_phase2_request));
+
+    if let Some(addr) = matches.value_of("tcp") {
+        return worker.serve_tcp(TcpListener::bind(addr)?);
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(path) = matches.value_of("unix") {
+            return worker.serve_unix(UnixListener::bind(path)?);
+        }
+    }
+
+    bail!("either --tcp or --unix must be given");
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use clap::{value_t, App, Arg};
+use rand::Rng;
+
+use fil_proofs_tooling::sector_inspect::{inspect_sector, InspectOptions};
+
+fn parse_commitment(name: &str, hex_str: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(hex_str).with_context(|| format!("{} must be hex encoded", name))?;
+    ensure!(bytes.len() == 32, "{} must be 32 bytes long", name);
+
+    let mut commitment = [0u8; 32];
+    commitment.copy_from_slice(&bytes);
+    Ok(commitment)
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let matches = App::new("sector-inspect")
+        .version("0.1")
+        .about("Inspects the cache directory and replica of a sealed sector")
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .long("size")
+                .default_value("34359738368")
+                .help("The sector size in bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .help("The cache directory of the sector")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replica")
+                .long("replica")
+                .help("The replica file, needed to check tree-r-last and run a PoSt")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("comm-r")
+                .long("comm-r")
+                .help("The hex encoded replica commitment to check against")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("comm-d")
+                .long("comm-d")
+                .help("The hex encoded data commitment to check tree-d against")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sector-id")
+                .long("sector-id")
+                .default_value("0")
+                .help("The sector id, used to derive the PoSt challenges")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("post")
+                .long("post")
+                .requires("replica")
+                .help("Run a local window PoSt (without SNARK) against the replica"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print the report as JSON"),
+        )
+        .get_matches();
+
+    let post_randomness = if matches.is_present("post") {
+        // Keep the randomness a valid field element.
+        let mut randomness: [u8; 32] = rand::thread_rng().gen();
+        randomness[31] &= 0b0011_1111;
+        Some(randomness)
+    } else {
+        None
+    };
+
+    let options = InspectOptions {
+        sector_size: value_t!(matches, "size", u64)?,
+        cache: value_t!(matches, "cache", PathBuf)?,
+        replica: matches.value_of("replica").map(PathBuf::from),
+        comm_r: matches
+            .value_of("comm-r")
+            .map(|s| parse_commitment("comm-r", s))
+            .transpose()?,
+        comm_d: matches
+            .value_of("comm-d")
+            .map(|s| parse_commitment("comm-d", s))
+            .transpose()?,
+        post_randomness,
+        sector_id: value_t!(matches, "sector-id", u64)?.into(),
+    };
+
+    let report = inspect_sector(&options)?;
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+
+    ensure!(report.passed(), "sector inspection found mismatches");
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use clap::{value_t, App, Arg, SubCommand};
+
+use fil_proofs_tooling::sector_migrate::{migrate, registry, select, SectorCache};
+use filecoin_proofs::types::OCT_ARITY;
+use filecoin_proofs::with_shape;
+use storage_proofs::merkle::get_base_tree_count;
+use storage_proofs::util::{default_rows_to_discard, NODE_SIZE};
+
+fn parse_porep_id(hex_str: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(hex_str).context("porep id must be hex encoded")?;
+    ensure!(bytes.len() == 32, "porep id must be 32 bytes long");
+
+    let mut porep_id = [0u8; 32];
+    porep_id.copy_from_slice(&bytes);
+    Ok(porep_id)
+}
+
+fn main() -> Result<()> {
+    fil_logger::init();
+
+    let list_cmd = SubCommand::with_name("list").about("List the known migrations");
+
+    let run_cmd = SubCommand::with_name("run")
+        .about("Migrate a sector cache directory")
+        .arg(
+            Arg::with_name("size")
+                .required(true)
+                .long("size")
+                .default_value("34359738368")
+                .help("The sector size in bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replica")
+                .long("replica")
+                .help("The replica file")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache")
+                .help("The cache directory of the sector")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("porep-id")
+                .long("porep-id")
+                .help("The hex encoded porep id the sector was sealed with")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rows-to-discard")
+                .long("rows-to-discard")
+                .help("The rows_to_discard for tree-r-last [default: from the settings]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .help("Only run the named migration")
+                .multiple(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only report the migrations that would be applied"),
+        );
+
+    let matches = App::new("sector-migrate")
+        .version("0.1")
+        .subcommand(list_cmd)
+        .subcommand(run_cmd)
+        .get_matches();
+
+    match matches.subcommand() {
+        ("list", Some(_)) => {
+            for migration in registry() {
+                println!("{:<24}{}", migration.name(), migration.description());
+            }
+        }
+        ("run", Some(m)) => {
+            let sector_size = value_t!(m, "size", u64)?;
+            let rows_to_discard = if m.is_present("rows-to-discard") {
+                value_t!(m, "rows-to-discard", usize)?
+            } else {
+                let tree_count = with_shape!(sector_size, get_base_tree_count);
+                let base_tree_leafs = sector_size as usize / NODE_SIZE / tree_count;
+                default_rows_to_discard(base_tree_leafs, OCT_ARITY)
+            };
+
+            let sector = SectorCache {
+                sector_size,
+                cache: value_t!(m, "cache", PathBuf)?,
+                replica: value_t!(m, "replica", PathBuf)?,
+                porep_id: m.value_of("porep-id").map(parse_porep_id).transpose()?,
+                rows_to_discard,
+            };
+            let migrations = match m.values_of("only") {
+                Some(names) => select(&names.collect::<Vec<_>>())?,
+                None => registry(),
+            };
+
+            let dry_run = m.is_present("dry-run");
+            let reports = migrate(&sector, &migrations, dry_run)?;
+            if reports.is_empty() {
+                println!("{:?} is up to date", sector.cache);
+            }
+            for report in reports {
+                let status = if report.applied {
+                    "applied"
+                } else {
+                    "would apply"
+                };
+                println!("{} {}: {}", status, report.name, report.reason);
+            }
+        }
+        _ => panic!("Unrecognized subcommand"),
+    }
+
+    Ok(())
+}
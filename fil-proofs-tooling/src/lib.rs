@@ -1,6 +1,10 @@
 pub mod measure;
 pub mod metadata;
+pub mod seal_worker;
+pub mod sector_inspect;
+pub mod sector_migrate;
 pub mod shared;
+pub mod tree_r_cache;
 pub use measure::{measure, FuncMeasurement};
 pub use metadata::Metadata;
 pub use shared::{create_replica, create_replicas};
@@ -0,0 +1,519 @@
+//! A service running `seal_commit_phase2` on behalf of clients in other processes or on other
+//! machines, e.g. to keep the GPU box busy with nothing but commit phase2.
+//!
+//! Every message is sent as a frame: a big endian `u32` length, followed by that many bytes of a
+//! bincode encoded [`Request`] or [`Response`]. A connection can be used for any number of
+//! requests, which are answered in order. The requests of all connections are queued and proven
+//! one at a time.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use filecoin_proofs::constants::POREP_PARTITIONS;
+use filecoin_proofs::types::{
+    MerkleTreeTrait, PoRepConfig, PoRepProofPartitions, ProverId, SealCommitOutput,
+    SealCommitPhase1Output, SectorSize,
+};
+use filecoin_proofs::{seal_commit_phase2, with_shape};
+use storage_proofs::sector::SectorId;
+
+/// Largest frame accepted, which bounds the memory a single message can claim.
+pub const MAX_FRAME_LEN: usize = 1 << 30;
+
+/// Everything needed to run `seal_commit_phase2` for a single sector.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SealCommitPhase2Request {
+    pub sector_size: u64,
+    pub partitions: u8,
+    pub porep_id: [u8; 32],
+    pub prover_id: ProverId,
+    pub sector_id: u64,
+    /// The JSON encoded `SealCommitPhase1Output` of the sector.
+    pub phase1_output: Vec<u8>,
+}
+
+impl SealCommitPhase2Request {
+    pub fn new<Tree: 'static + MerkleTreeTrait>(
+        porep_config: PoRepConfig,
+        phase1_output: &SealCommitPhase1Output<Tree>,
+        prover_id: ProverId,
+        sector_id: SectorId,
+    ) -> Result<Self> {
+        Ok(SealCommitPhase2Request {
+            sector_size: u64::from(porep_config.sector_size),
+            partitions: porep_config.partitions.0,
+            porep_id: porep_config.porep_id,
+            prover_id,
+            sector_id: u64::from(sector_id),
+            phase1_output: serde_json::to_vec(phase1_output)?,
+        })
+    }
+
+    pub fn porep_config(&self) -> PoRepConfig {
+        PoRepConfig {
+            sector_size: SectorSize(self.sector_size),
+            partitions: PoRepProofPartitions(self.partitions),
+            porep_id: self.porep_id,
+        }
+    }
+
+    fn validate(&self) -> std::result::Result<(), WorkerError> {
+        if !POREP_PARTITIONS
+            .read()
+            .unwrap()
+            .contains_key(&self.sector_size)
+        {
+            return Err(WorkerError::new(
+                WorkerErrorKind::InvalidRequest,
+                format!("unsupported sector size: {}", self.sector_size),
+            ));
+        }
+        if self.partitions == 0 {
+            return Err(WorkerError::new(
+                WorkerErrorKind::InvalidRequest,
+                "partitions must not be zero",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    SealCommitPhase2(SealCommitPhase2Request),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    /// The proof of a successful `seal_commit_phase2`.
+    SealCommitPhase2(Vec<u8>),
+    Error(WorkerError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorkerErrorKind {
+    /// The request could not be decoded, or contained invalid values.
+    InvalidRequest,
+    /// The request was valid, but proving failed.
+    ProofFailed,
+}
+
+/// An error reported by the seal worker. Clients return it wrapped in an `anyhow::Error`, so it
+/// can be recovered with `downcast_ref`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerError {
+    pub kind: WorkerErrorKind,
+    pub message: String,
+}
+
+impl WorkerError {
+    pub fn new<S: Into<String>>(kind: WorkerErrorKind, message: S) -> Self {
+        WorkerError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "seal worker error ({:?}): {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for WorkerError {}
+
+/// Writes `payload` as a single frame.
+pub fn write_frame<W: Write>(mut writer: W, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("frame too large: {} bytes", payload.len()),
+        ));
+    }
+
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// Reads a single frame, returns `None` if the stream ended before it.
+pub fn read_frame<R: Read>(mut reader: R) -> io::Result<Option<Vec<u8>>> {
+    let mut len_bytes = [0u8; 4];
+    match reader.read_exact(&mut len_bytes) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame too large: {} bytes", len),
+        ));
+    }
+
+    // The buffer grows with the bytes actually received, so a peer cannot claim a large frame
+    // without sending it.
+    let mut payload = Vec::new();
+    reader.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("frame truncated after {} of {} bytes", payload.len(), len),
+        ));
+    }
+
+    Ok(Some(payload))
+}
+
+fn write_message<W: Write, T: Serialize>(writer: W, message: &T) -> Result<()> {
+    let payload = bincode::serialize(message)?;
+    write_frame(writer, &payload)?;
+
+    Ok(())
+}
+
+/// Produces the proof for a request, see [`seal_commit_phase2_request`].
+pub type Prover = dyn Fn(&SealCommitPhase2Request) -> Result<SealCommitOutput> + Send + Sync;
+
+/// Runs `seal_commit_phase2` for `request`.
+pub fn seal_commit_phase2_request(request: &SealCommitPhase2Request) -> Result<SealCommitOutput> {
+    with_shape!(request.sector_size, seal_commit_phase2_shape, request)
+}
+
+fn seal_commit_phase2_shape<Tree: 'static + MerkleTreeTrait>(
+    request: &SealCommitPhase2Request,
+) -> Result<SealCommitOutput> {
+    let phase1_output: SealCommitPhase1Output<Tree> =
+        serde_json::from_slice(&request.phase1_output).context("invalid phase1 output")?;
+
+    seal_commit_phase2(
+        request.porep_config(),
+        phase1_output,
+        request.prover_id,
+        SectorId::from(request.sector_id),
+    )
+}
+
+struct Job {
+    request: SealCommitPhase2Request,
+    reply: mpsc::Sender<Response>,
+}
+
+/// Queues the requests of all connections and proves them one at a time.
+#[derive(Clone)]
+pub struct SealWorker {
+    queue: SyncSender<Job>,
+}
+
+impl SealWorker {
+    /// Starts the thread working off the queue. Once `queue_depth` requests are waiting, new
+    /// requests block until there is room again.
+    pub fn new(queue_depth: usize, prover: Arc<Prover>) -> Self {
+        let (queue, jobs) = mpsc::sync_channel(queue_depth);
+        thread::Builder::new()
+            .name("seal-worker".into())
+            .spawn(move || work(jobs, prover))
+            .expect("failed to spawn seal worker thread");
+
+        SealWorker { queue }
+    }
+
+    /// Serves all connections accepted on `listener`, each on its own thread.
+    pub fn serve_tcp(&self, listener: TcpListener) -> Result<()> {
+        info!("seal worker listening on {}", listener.local_addr()?);
+        for stream in listener.incoming() {
+            // A failed accept only concerns that connection, the worker keeps serving.
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("seal worker: failed to accept connection: {}", err);
+                    continue;
+                }
+            };
+            let peer = stream
+                .peer_addr()
+                .map(|peer| peer.to_string())
+                .unwrap_or_else(|_| "unknown peer".into());
+            self.spawn_connection(stream, peer);
+        }
+
+        Ok(())
+    }
+
+    /// Serves all connections accepted on `listener`, each on its own thread.
+    #[cfg(unix)]
+    pub fn serve_unix(&self, listener: UnixListener) -> Result<()> {
+        info!("seal worker listening on {:?}", listener.local_addr()?);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => self.spawn_connection(stream, "unix socket".into()),
+                Err(err) => warn!("seal worker: failed to accept connection: {}", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn spawn_connection<S: 'static + Read + Write + Send>(&self, stream: S, peer: String) {
+        let worker = self.clone();
+        thread::spawn(move || {
+            info!("seal worker: connection from {}", peer);
+            if let Err(err) = worker.handle_connection(stream) {
+                warn!("seal worker: connection from {} failed: {:#}", peer, err);
+            }
+        });
+    }
+
+    /// Answers the requests read from `stream` until it is closed.
+    pub fn handle_connection<S: Read + Write>(&self, mut stream: S) -> Result<()> {
+        while let Some(payload) = read_frame(&mut stream)? {
+            let response = match bincode::deserialize::<Request>(&payload) {
+                Ok(Request::SealCommitPhase2(request)) => match request.validate() {
+                    Ok(()) => self.submit(request)?,
+                    Err(err) => Response::Error(err),
+                },
+                Err(err) => Response::Error(WorkerError::new(
+                    WorkerErrorKind::InvalidRequest,
+                    format!("failed to decode request: {}", err),
+                )),
+            };
+            write_message(&mut stream, &response)?;
+        }
+
+        Ok(())
+    }
+
+    fn submit(&self, request: SealCommitPhase2Request) -> Result<Response> {
+        let (reply, response) = mpsc::channel();
+        self.queue
+            .send(Job { request, reply })
+            .map_err(|_| anyhow!("seal worker stopped"))?;
+
+        response.recv().context("seal worker stopped")
+    }
+}
+
+fn work(jobs: Receiver<Job>, prover: Arc<Prover>) {
+    for job in jobs.iter() {
+        let Job { request, reply } = job;
+        info!("seal worker: proving sector {}", request.sector_id);
+
+        // A panicking proof must not take down the worker, and with it all queued requests.
+        let response = match panic::catch_unwind(AssertUnwindSafe(|| prover(&request))) {
+            Ok(Ok(output)) => Response::SealCommitPhase2(output.proof),
+            Ok(Err(err)) => Response::Error(WorkerError::new(
+                WorkerErrorKind::ProofFailed,
+                format!("{:#}", err),
+            )),
+            Err(_) => Response::Error(WorkerError::new(
+                WorkerErrorKind::ProofFailed,
+                "proving panicked",
+            )),
+        };
+        info!("seal worker: done with sector {}", request.sector_id);
+
+        // The client might have gone away in the meantime, there is nobody to tell then.
+        let _ = reply.send(response);
+    }
+}
+
+/// A connection to a seal worker.
+pub struct SealWorkerClient<S> {
+    stream: S,
+}
+
+impl SealWorkerClient<TcpStream> {
+    pub fn connect_tcp<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(addr).context("failed to connect to seal worker")?;
+        Ok(SealWorkerClient::new(stream))
+    }
+}
+
+#[cfg(unix)]
+impl SealWorkerClient<UnixStream> {
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let stream = UnixStream::connect(path).context("failed to connect to seal worker")?;
+        Ok(SealWorkerClient::new(stream))
+    }
+}
+
+impl<S: Read + Write> SealWorkerClient<S> {
+    pub fn new(stream: S) -> Self {
+        SealWorkerClient { stream }
+    }
+
+    /// Runs `seal_commit_phase2` on the seal worker, returns once the proof is done.
+    pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
+        &mut self,
+        porep_config: PoRepConfig,
+        phase1_output: &SealCommitPhase1Output<Tree>,
+        prover_id: ProverId,
+        sector_id: SectorId,
+    ) -> Result<SealCommitOutput> {
+        let request =
+            SealCommitPhase2Request::new(porep_config, phase1_output, prover_id, sector_id)?;
+        self.send(request)
+    }
+
+    /// Sends `request` to the seal worker, returns once the proof is done. Errors reported by
+    /// the seal worker are returned as [`WorkerError`].
+    pub fn send(&mut self, request: SealCommitPhase2Request) -> Result<SealCommitOutput> {
+        write_message(&mut self.stream, &Request::SealCommitPhase2(request))?;
+
+        match read_message(&mut self.stream)? {
+            Some(Response::SealCommitPhase2(proof)) => Ok(SealCommitOutput { proof }),
+            Some(Response::Error(err)) => Err(err.into()),
+            None => Err(anyhow!("seal worker closed the connection")),
+        }
+    }
+}
+
+fn read_message<R: Read, T: DeserializeOwned>(reader: R) -> Result<Option<T>> {
+    match read_frame(reader)? {
+        Some(payload) => Ok(Some(bincode::deserialize(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use filecoin_proofs::constants::SECTOR_SIZE_2_KIB;
+
+    fn request(sector_id: u64) -> SealCommitPhase2Request {
+        SealCommitPhase2Request {
+            sector_size: SECTOR_SIZE_2_KIB,
+            partitions: 1,
+            porep_id: [1; 32],
+            prover_id: [2; 32],
+            sector_id,
+            phase1_output: b"{}".to_vec(),
+        }
+    }
+
+    /// Echoes the sector id as proof, and fails for odd sector ids.
+    fn fake_worker() -> SealWorker {
+        let prover = |request: &SealCommitPhase2Request| {
+            anyhow::ensure!(request.sector_id % 2 == 0, "odd sector id");
+            Ok(SealCommitOutput {
+                proof: request.sector_id.to_le_bytes().to_vec(),
+            })
+        };
+
+        SealWorker::new(4, Arc::new(prover))
+    }
+
+    fn check_client<S: Read + Write>(client: &mut SealWorkerClient<S>) {
+        for sector_id in &[2, 4] {
+            let output = client.send(request(*sector_id)).unwrap();
+            assert_eq!(output.proof, sector_id.to_le_bytes().to_vec());
+        }
+
+        let err = client.send(request(3)).unwrap_err();
+        let err = err.downcast_ref::<WorkerError>().unwrap();
+        assert_eq!(err.kind, WorkerErrorKind::ProofFailed);
+        assert!(err.message.contains("odd sector id"));
+
+        let mut invalid = request(6);
+        invalid.sector_size = 1234;
+        let err = client.send(invalid).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<WorkerError>().unwrap().kind,
+            WorkerErrorKind::InvalidRequest
+        );
+
+        // The connection is still usable after errors.
+        let output = client.send(request(8)).unwrap();
+        assert_eq!(output.proof, 8u64.to_le_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, b"hello").unwrap();
+        write_frame(&mut buf, b"").unwrap();
+        assert_eq!(&buf[..4], &5u32.to_be_bytes());
+
+        let mut reader = &buf[..];
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"hello");
+        assert_eq!(read_frame(&mut reader).unwrap().unwrap(), b"");
+        assert!(read_frame(&mut reader).unwrap().is_none());
+
+        // Truncated frames and oversized lengths are errors.
+        assert!(read_frame(&buf[..7]).is_err());
+        let oversized = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes();
+        assert!(read_frame(&oversized[..]).is_err());
+
+        // A large length without the payload is truncated, not allocated up front.
+        let mut unsent = (MAX_FRAME_LEN as u32).to_be_bytes().to_vec();
+        unsent.extend_from_slice(b"hello");
+        let err = read_frame(&unsent[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_tcp_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let worker = fake_worker();
+        thread::spawn(move || worker.serve_tcp(listener));
+
+        // Two clients share the queue of the worker.
+        let mut clients: Vec<_> = (0..2)
+            .map(|_| SealWorkerClient::connect_tcp(addr).unwrap())
+            .collect();
+        for client in &mut clients {
+            check_client(client);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_loopback() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seal-worker.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let worker = fake_worker();
+        thread::spawn(move || worker.serve_unix(listener));
+
+        let mut client = SealWorkerClient::connect_unix(&path).unwrap();
+        check_client(&mut client);
+    }
+
+    #[test]
+    fn test_undecodable_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let worker = fake_worker();
+        thread::spawn(move || worker.serve_tcp(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write_frame(&mut stream, b"garbage").unwrap();
+        match read_message::<_, Response>(&mut stream).unwrap().unwrap() {
+            Response::Error(err) => assert_eq!(err.kind, WorkerErrorKind::InvalidRequest),
+            response => panic!("unexpected response: {:?}", response),
+        }
+
+        let mut client = SealWorkerClient::new(stream);
+        check_client(&mut client);
+    }
+}
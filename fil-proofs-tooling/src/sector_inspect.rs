@@ -0,0 +1,624 @@
+//! Inspection of a sealed sector's cache directory and replica.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use bincode::deserialize;
+use generic_array::typenum::Unsigned;
+use merkletree::merkle::{get_merkle_tree_cache_size, get_merkle_tree_len};
+use merkletree::store::{DiskStore, StoreConfig};
+use serde::Serialize;
+
+use filecoin_proofs::constants::*;
+use filecoin_proofs::types::*;
+use filecoin_proofs::{check_window_post_sectors, with_shape, PrivateReplicaInfo};
+use storage_proofs::cache_key::CacheKey;
+use storage_proofs::hasher::{Domain, HashFunction, Hasher};
+use storage_proofs::merkle::{create_disk_tree, get_base_tree_count, split_config};
+use storage_proofs::porep::stacked::StackedDrg;
+use storage_proofs::sector::SectorId;
+use storage_proofs::sector_metadata::SectorMetadata;
+use storage_proofs::util::{default_rows_to_discard, NODE_SIZE};
+
+/// What to inspect.
+#[derive(Debug, Clone)]
+pub struct InspectOptions {
+    pub sector_size: u64,
+    pub cache: PathBuf,
+    /// Checks that need the replica are skipped without it.
+    pub replica: Option<PathBuf>,
+    /// The expected replica commitment.
+    pub comm_r: Option<Commitment>,
+    /// The expected data commitment.
+    pub comm_d: Option<Commitment>,
+    /// Randomness for a local window PoSt over the sector, which is skipped if `None`.
+    pub post_randomness: Option<ChallengeSeed>,
+    pub sector_id: SectorId,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PersistentAuxReport {
+    pub comm_c: String,
+    pub comm_r_last: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TemporaryAuxReport {
+    pub labels: Vec<StoreConfig>,
+    pub tree_d_config: StoreConfig,
+    pub tree_r_last_config: StoreConfig,
+    pub tree_c_config: StoreConfig,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactStatus {
+    Ok,
+    /// The file exists, but there is no size it can be checked against.
+    Present,
+    Missing,
+    SizeMismatch,
+}
+
+/// A file in the cache directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    pub key: String,
+    pub path: PathBuf,
+    pub size: Option<u64>,
+    pub expected_size: Option<u64>,
+    pub status: ArtifactStatus,
+}
+
+impl Artifact {
+    fn new(key: String, path: PathBuf, expected_size: Option<u64>) -> Self {
+        let size = fs::metadata(&path).ok().map(|m| m.len());
+        let status = match (size, expected_size) {
+            (None, _) => ArtifactStatus::Missing,
+            (Some(_), None) => ArtifactStatus::Present,
+            (Some(size), Some(expected)) if size == expected => ArtifactStatus::Ok,
+            (Some(_), Some(_)) => ArtifactStatus::SizeMismatch,
+        };
+
+        Artifact {
+            key,
+            path,
+            size,
+            expected_size,
+            status,
+        }
+    }
+}
+
+/// A value recomputed from the sector, compared against the stored or given one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+    pub error: Option<String>,
+    pub passed: bool,
+}
+
+impl Check {
+    fn new(name: &str, expected: Option<String>, actual: Result<String>) -> Self {
+        let (actual, error) = match actual {
+            Ok(actual) => (Some(actual), None),
+            Err(err) => (None, Some(format!("{:#}", err))),
+        };
+        let passed = error.is_none() && (expected.is_none() || expected == actual);
+
+        Check {
+            name: name.to_string(),
+            expected,
+            actual,
+            error,
+            passed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PostReport {
+    pub randomness: String,
+    pub challenge_count: usize,
+    /// Why the sector could not be proven, if it could not.
+    pub fault: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SectorReport {
+    pub sector_size: u64,
+    pub cache: PathBuf,
+    pub replica: Option<PathBuf>,
+    pub metadata: Option<SectorMetadata>,
+    pub p_aux: Option<PersistentAuxReport>,
+    pub t_aux: Option<TemporaryAuxReport>,
+    pub artifacts: Vec<Artifact>,
+    /// `comm_r` recomputed from `comm_c` and `comm_r_last`.
+    pub comm_r: Option<String>,
+    pub checks: Vec<Check>,
+    pub post: Option<PostReport>,
+}
+
+impl SectorReport {
+    /// Returns false if any check, or the PoSt, failed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+            && self
+                .post
+                .as_ref()
+                .map(|post| post.fault.is_none())
+                .unwrap_or(true)
+    }
+}
+
+fn hex_domain<D: Domain>(domain: &D) -> String {
+    hex::encode(domain.as_ref())
+}
+
+/// Inspects the sector described by `options`.
+pub fn inspect_sector(options: &InspectOptions) -> Result<SectorReport> {
+    with_shape!(options.sector_size, inspect, options)
+}
+
+fn inspect<Tree: 'static + MerkleTreeTrait>(options: &InspectOptions) -> Result<SectorReport> {
+    let cache = &options.cache;
+    let nodes = options.sector_size as usize / NODE_SIZE;
+    let tree_count = get_base_tree_count::<Tree>();
+    let base_tree_leafs = nodes / tree_count;
+    let base_tree_len = get_merkle_tree_len(base_tree_leafs, Tree::Arity::to_usize())?;
+
+    let metadata = SectorMetadata::read(cache)?;
+
+    let p_aux_path = cache.join(CacheKey::PAux.to_string());
+    let p_aux: Option<PersistentAux<<Tree::Hasher as Hasher>::Domain>> = if p_aux_path.exists() {
+        Some(
+            deserialize(&fs::read(&p_aux_path)?)
+                .with_context(|| format!("could not decode p_aux={:?}", p_aux_path))?,
+        )
+    } else {
+        None
+    };
+
+    let t_aux_path = cache.join(CacheKey::TAux.to_string());
+    let t_aux: Option<TemporaryAux<Tree, DefaultPieceHasher>> = if t_aux_path.exists() {
+        Some(
+            deserialize(&fs::read(&t_aux_path)?)
+                .with_context(|| format!("could not decode t_aux={:?}", t_aux_path))?,
+        )
+    } else {
+        None
+    };
+
+    // Same precedence as used when proving: the metadata, the t_aux config, the settings.
+    let tree_r_last_rows_to_discard = metadata
+        .as_ref()
+        .map(|m| m.tree_r_last_rows_to_discard)
+        .or_else(|| t_aux.as_ref().map(|t| t.tree_r_last_config.rows_to_discard))
+        .unwrap_or_else(|| default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()));
+
+    let tree_d_rows_to_discard = metadata
+        .as_ref()
+        .map(|m| m.tree_d_rows_to_discard)
+        .or_else(|| t_aux.as_ref().map(|t| t.tree_d_config.rows_to_discard))
+        .unwrap_or_else(|| default_rows_to_discard(nodes, BINARY_ARITY));
+
+    let mut artifacts = Vec::new();
+    for key in &[
+        CacheKey::PAux,
+        CacheKey::TAux,
+        CacheKey::SectorMetadata,
+        CacheKey::LabelsCheckpoint,
+    ] {
+        artifacts.push(Artifact::new(
+            key.to_string(),
+            cache.join(key.to_string()),
+            None,
+        ));
+    }
+
+    let layers = LAYERS
+        .read()
+        .expect("LAYERS poisoned")
+        .get(&options.sector_size)
+        .copied()
+        .unwrap_or(0);
+    for layer in 1..=layers {
+        let id = CacheKey::label_layer(layer);
+        let path = StoreConfig::data_path(cache, &id);
+        artifacts.push(Artifact::new(id, path, Some((nodes * NODE_SIZE) as u64)));
+    }
+
+    let tree_d_len = get_merkle_tree_len(nodes, BINARY_ARITY)?;
+    artifacts.push(Artifact::new(
+        CacheKey::CommDTree.to_string(),
+        StoreConfig::data_path(cache, &CacheKey::CommDTree.to_string()),
+        Some((tree_d_len * NODE_SIZE) as u64),
+    ));
+
+    let tree_c_configs = split_config(
+        StoreConfig::new(cache, CacheKey::CommCTree.to_string(), 0),
+        tree_count,
+    )?;
+    for config in &tree_c_configs {
+        artifacts.push(Artifact::new(
+            config.id.clone(),
+            StoreConfig::data_path(&config.path, &config.id),
+            Some((base_tree_len * NODE_SIZE) as u64),
+        ));
+    }
+
+    let tree_r_last_configs = split_config(
+        StoreConfig::new(
+            cache,
+            CacheKey::CommRLastTree.to_string(),
+            tree_r_last_rows_to_discard,
+        ),
+        tree_count,
+    )?;
+    let tree_r_last_cache_size = get_merkle_tree_cache_size(
+        base_tree_leafs,
+        Tree::Arity::to_usize(),
+        tree_r_last_rows_to_discard,
+    )?;
+    for config in &tree_r_last_configs {
+        artifacts.push(Artifact::new(
+            config.id.clone(),
+            StoreConfig::data_path(&config.path, &config.id),
+            Some((tree_r_last_cache_size * NODE_SIZE) as u64),
+        ));
+    }
+
+    let mut checks = Vec::new();
+
+    let comm_r = p_aux
+        .as_ref()
+        .map(|p_aux| <Tree::Hasher as Hasher>::Function::hash2(&p_aux.comm_c, &p_aux.comm_r_last));
+    if let Some(expected) = options.comm_r {
+        checks.push(Check::new(
+            "comm_r",
+            Some(hex::encode(expected)),
+            comm_r.as_ref().map(hex_domain).context("p_aux is missing"),
+        ));
+    }
+
+    if let Some(expected) = options.comm_d {
+        checks.push(Check::new(
+            "tree-d root",
+            Some(hex::encode(expected)),
+            tree_d_root(cache, nodes, tree_d_len, tree_d_rows_to_discard),
+        ));
+    }
+
+    if let Some(ref p_aux) = p_aux {
+        let tree_c_present = tree_c_configs
+            .iter()
+            .all(|config| StoreConfig::data_path(&config.path, &config.id).exists());
+        if tree_c_present {
+            checks.push(Check::new(
+                "tree-c root",
+                Some(hex_domain(&p_aux.comm_c)),
+                create_disk_tree::<Tree>(base_tree_len, &tree_c_configs)
+                    .map(|tree| hex_domain(&tree.root())),
+            ));
+        }
+    }
+
+    if let Some(ref replica) = options.replica {
+        checks.push(Check::new(
+            "tree-r-last root",
+            p_aux.as_ref().map(|p_aux| hex_domain(&p_aux.comm_r_last)),
+            tree_r_last_root::<Tree>(cache, replica, base_tree_len, tree_r_last_rows_to_discard),
+        ));
+    }
+
+    // The replica commitment used to open the sector for proving: the given one, so a
+    // mismatch with p_aux is caught, or else the recomputed one.
+    let replica_comm_r = match (options.comm_r, comm_r) {
+        (Some(comm_r), _) => Some(comm_r),
+        (None, Some(comm_r)) => {
+            let mut bytes = [0; 32];
+            bytes.copy_from_slice(comm_r.as_ref());
+            Some(bytes)
+        }
+        (None, None) => None,
+    };
+
+    let mut post = None;
+    if let (Some(replica), Some(_), Some(comm_r)) = (&options.replica, &p_aux, replica_comm_r) {
+        if let Some(randomness) = options.post_randomness {
+            let replica_info =
+                PrivateReplicaInfo::<Tree>::new(replica.clone(), comm_r, cache.clone())?;
+            post = Some(local_window_post(options, randomness, replica_info)?);
+        }
+    }
+
+    Ok(SectorReport {
+        sector_size: options.sector_size,
+        cache: cache.clone(),
+        replica: options.replica.clone(),
+        metadata,
+        p_aux: p_aux.as_ref().map(|p_aux| PersistentAuxReport {
+            comm_c: hex_domain(&p_aux.comm_c),
+            comm_r_last: hex_domain(&p_aux.comm_r_last),
+        }),
+        t_aux: t_aux.map(|t_aux| TemporaryAuxReport {
+            labels: t_aux.labels.labels,
+            tree_d_config: t_aux.tree_d_config,
+            tree_r_last_config: t_aux.tree_r_last_config,
+            tree_c_config: t_aux.tree_c_config,
+        }),
+        artifacts,
+        comm_r: comm_r.as_ref().map(hex_domain),
+        checks,
+        post,
+    })
+}
+
+fn tree_d_root(
+    cache: &Path,
+    nodes: usize,
+    tree_d_len: usize,
+    rows_to_discard: usize,
+) -> Result<String> {
+    let config = StoreConfig::new(cache, CacheKey::CommDTree.to_string(), rows_to_discard);
+    let store: DiskStore<DefaultPieceDomain> =
+        DiskStore::new_from_disk(tree_d_len, BINARY_ARITY, &config)?;
+    let tree = DataTree::from_data_store(store, nodes)?;
+
+    Ok(hex_domain(&tree.root()))
+}
+
+/// Recomputes the root of tree-r-last from the replica. The trees are built in a scratch
+/// directory next to the cache, so the stored ones are neither used nor touched.
+fn tree_r_last_root<Tree: 'static + MerkleTreeTrait>(
+    cache: &Path,
+    replica: &Path,
+    base_tree_len: usize,
+    rows_to_discard: usize,
+) -> Result<String> {
+    let scratch = tempfile::tempdir_in(cache)?;
+    let mut config = StoreConfig::new(
+        scratch.path(),
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+    config.size = Some(base_tree_len);
+    let root = StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_r_last(
+        config,
+        replica.to_path_buf(),
+    )?;
+
+    Ok(hex_domain(&root))
+}
+
+/// Runs the vanilla part of a window PoSt over the single sector, without generating the SNARK.
+fn local_window_post<Tree: 'static + MerkleTreeTrait>(
+    options: &InspectOptions,
+    randomness: ChallengeSeed,
+    replica_info: PrivateReplicaInfo<Tree>,
+) -> Result<PostReport> {
+    let post_config = PoStConfig {
+        sector_size: SectorSize(options.sector_size),
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        sector_count: 1,
+        typ: PoStType::Window,
+        priority: false,
+    };
+
+    let mut replicas = BTreeMap::new();
+    replicas.insert(options.sector_id, replica_info);
+    let faults = check_window_post_sectors(&post_config, &randomness, &replicas)?;
+
+    Ok(PostReport {
+        randomness: hex::encode(randomness),
+        challenge_count: post_config.challenge_count,
+        fault: faults.first().map(|f| format!("{:?}", f.fault)),
+    })
+}
+
+fn opt_or_dash<T: fmt::Display>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl fmt::Display for SectorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "sector size: {}", self.sector_size)?;
+        writeln!(f, "cache:       {:?}", self.cache)?;
+        if let Some(ref replica) = self.replica {
+            writeln!(f, "replica:     {:?}", replica)?;
+        }
+
+        writeln!(f)?;
+        match self.metadata {
+            Some(ref m) => {
+                writeln!(f, "metadata: layout version {}", m.layout_version)?;
+                writeln!(
+                    f,
+                    "  porep_id:                    {}",
+                    hex::encode(m.porep_id)
+                )?;
+                writeln!(
+                    f,
+                    "  tree shape:                  [{}, {}, {}]",
+                    m.tree_shape.base_arity,
+                    m.tree_shape.sub_tree_arity,
+                    m.tree_shape.top_tree_arity
+                )?;
+                writeln!(
+                    f,
+                    "  tree-d rows_to_discard:      {}",
+                    m.tree_d_rows_to_discard
+                )?;
+                writeln!(
+                    f,
+                    "  tree-r-last rows_to_discard: {}",
+                    m.tree_r_last_rows_to_discard
+                )?;
+            }
+            None => writeln!(f, "metadata: none")?,
+        }
+
+        match self.p_aux {
+            Some(ref p_aux) => {
+                writeln!(f, "p_aux:")?;
+                writeln!(f, "  comm_c:      {}", p_aux.comm_c)?;
+                writeln!(f, "  comm_r_last: {}", p_aux.comm_r_last)?;
+                writeln!(f, "  => comm_r:   {}", opt_or_dash(&self.comm_r))?;
+            }
+            None => writeln!(f, "p_aux: none")?,
+        }
+
+        match self.t_aux {
+            Some(ref t_aux) => {
+                writeln!(f, "t_aux:")?;
+                let configs = t_aux.labels.iter().chain(vec![
+                    &t_aux.tree_d_config,
+                    &t_aux.tree_c_config,
+                    &t_aux.tree_r_last_config,
+                ]);
+                for config in configs {
+                    writeln!(
+                        f,
+                        "  {:<16} size={:<12} rows_to_discard={} path={:?}",
+                        config.id,
+                        opt_or_dash(&config.size),
+                        config.rows_to_discard,
+                        config.path
+                    )?;
+                }
+            }
+            None => writeln!(f, "t_aux: none")?,
+        }
+
+        writeln!(f)?;
+        writeln!(f, "artifacts:")?;
+        for artifact in &self.artifacts {
+            writeln!(
+                f,
+                "  {:<20} {:<14} size={:<14} expected={}",
+                artifact.key,
+                format!("{:?}", artifact.status),
+                opt_or_dash(&artifact.size),
+                opt_or_dash(&artifact.expected_size)
+            )?;
+        }
+
+        if !self.checks.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "checks:")?;
+            for check in &self.checks {
+                let status = if check.passed { "MATCH" } else { "MISMATCH" };
+                writeln!(f, "  {:<18} {}", check.name, status)?;
+                writeln!(f, "    expected: {}", opt_or_dash(&check.expected))?;
+                writeln!(f, "    actual:   {}", opt_or_dash(&check.actual))?;
+                if let Some(ref error) = check.error {
+                    writeln!(f, "    error:    {}", error)?;
+                }
+            }
+        }
+
+        if let Some(ref post) = self.post {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "window post ({} challenges, randomness {}): {}",
+                post.challenge_count,
+                post.randomness,
+                post.fault.as_ref().map(String::as_str).unwrap_or("OK")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bincode::serialize;
+
+    use crate::tree_r_cache::rebuild_tree_r_last;
+
+    #[test]
+    fn test_inspect_sector() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache");
+        let replica = dir.path().join("replica");
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(&replica, vec![0u8; SECTOR_SIZE_2_KIB as usize]).unwrap();
+
+        let (comm_r_last, _) =
+            rebuild_tree_r_last(SECTOR_SIZE_2_KIB as usize, &cache, &replica, None).unwrap();
+        let p_aux = PersistentAux {
+            comm_c: DefaultTreeDomain::default(),
+            comm_r_last,
+        };
+        fs::write(
+            cache.join(CacheKey::PAux.to_string()),
+            serialize(&p_aux).unwrap(),
+        )
+        .unwrap();
+
+        let comm_r = <DefaultTreeHasher as Hasher>::Function::hash2(&p_aux.comm_c, &comm_r_last);
+        let mut comm_r_bytes = [0; 32];
+        comm_r_bytes.copy_from_slice(comm_r.as_ref());
+
+        let mut options = InspectOptions {
+            sector_size: SECTOR_SIZE_2_KIB,
+            cache,
+            replica: Some(replica),
+            comm_r: Some(comm_r_bytes),
+            comm_d: None,
+            post_randomness: Some([0; 32]),
+            sector_id: SectorId::from(1),
+        };
+
+        let report = inspect_sector(&options).unwrap();
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.comm_r, Some(hex::encode(comm_r_bytes)));
+        assert_eq!(report.post.as_ref().unwrap().fault, None);
+
+        let tree_r_last = report
+            .artifacts
+            .iter()
+            .find(|a| a.key == CacheKey::CommRLastTree.to_string())
+            .unwrap();
+        assert_eq!(tree_r_last.status, ArtifactStatus::Ok);
+        let tree_c = report
+            .artifacts
+            .iter()
+            .find(|a| a.key == CacheKey::CommCTree.to_string())
+            .unwrap();
+        assert_eq!(tree_c.status, ArtifactStatus::Missing);
+
+        serde_json::to_string(&report).unwrap();
+
+        // A wrong comm_r is reported.
+        options.comm_r = Some([1; 32]);
+        let report = inspect_sector(&options).unwrap();
+        assert!(!report.passed());
+
+        // A damaged replica is caught, even though the stored tree-r-last is intact.
+        let mut data = fs::read(options.replica.as_ref().unwrap()).unwrap();
+        data[0] = 1;
+        fs::write(options.replica.as_ref().unwrap(), data).unwrap();
+        options.comm_r = Some(comm_r_bytes);
+        options.post_randomness = None;
+        let report = inspect_sector(&options).unwrap();
+        let check = report
+            .checks
+            .iter()
+            .find(|check| check.name == "tree-r-last root")
+            .unwrap();
+        assert!(!check.passed);
+    }
+}
@@ -0,0 +1,676 @@
+//! Migrations of sector cache directories written by older versions of the proofs, or with a
+//! different configuration than the current one.
+//!
+//! Every migration checks whether a sector needs it, and writes the files it produces into a
+//! staging directory inside the cache directory. Only once all of them were written, they are
+//! renamed into place, so an interrupted migration never leaves a half written file behind.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use bincode::{deserialize, serialize};
+use generic_array::typenum::Unsigned;
+use log::info;
+use merkletree::merkle::{
+    get_merkle_tree_cache_size, get_merkle_tree_len, get_merkle_tree_row_count,
+};
+use merkletree::store::StoreConfig;
+
+use filecoin_proofs::constants::*;
+use filecoin_proofs::types::*;
+use filecoin_proofs::with_shape;
+use storage_proofs::cache_key::CacheKey;
+use storage_proofs::hasher::Hasher;
+use storage_proofs::merkle::{
+    create_disk_tree, create_lc_tree, get_base_tree_count, split_config, split_config_and_replica,
+};
+use storage_proofs::porep::stacked::StackedDrg;
+use storage_proofs::sector_metadata::{SectorMetadata, SECTOR_LAYOUT_VERSION};
+use storage_proofs::util::{default_rows_to_discard, NODE_SIZE};
+
+use crate::tree_r_cache::{get_tree_r_info, rebuild_tree_r_last};
+
+/// A sealed sector, as found on disk.
+#[derive(Debug, Clone)]
+pub struct SectorCache {
+    pub sector_size: u64,
+    pub cache: PathBuf,
+    pub replica: PathBuf,
+    /// The porep id the sector was sealed with, only needed to record the sector metadata.
+    pub porep_id: Option<[u8; 32]>,
+    /// The `rows_to_discard` tree-r-last should be stored with.
+    pub rows_to_discard: usize,
+}
+
+/// How tree-r-last is stored in a cache directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TreeRLastLayout {
+    Missing,
+    /// All rows of the trees are stored, as done before level cache stores were used.
+    DiskStore,
+    LevelCache {
+        rows_to_discard: usize,
+    },
+    /// The stored trees have a size that matches no known layout.
+    Unknown {
+        nodes: usize,
+    },
+}
+
+impl SectorCache {
+    fn nodes(&self) -> usize {
+        self.sector_size as usize / NODE_SIZE
+    }
+
+    /// Determines the layout of tree-r-last from the size of its base tree stores.
+    pub fn tree_r_last_layout(&self) -> Result<TreeRLastLayout> {
+        let (_, base_tree_leafs, configs, _) = get_tree_r_info(
+            self.sector_size as usize,
+            &self.cache,
+            &self.replica,
+            Some(0),
+        )?;
+
+        let arity = with_shape!(self.sector_size, base_tree_arity);
+        let mut layout = None;
+        for config in &configs {
+            let path = StoreConfig::data_path(&config.path, &config.id);
+            if !path.exists() {
+                return Ok(TreeRLastLayout::Missing);
+            }
+
+            let nodes = fs::metadata(&path)?.len() as usize / NODE_SIZE;
+            let base_tree_layout = classify_tree_r_last(nodes, base_tree_leafs, arity)?;
+            match layout {
+                None => layout = Some(base_tree_layout),
+                Some(layout) => ensure!(
+                    layout == base_tree_layout,
+                    "base trees of tree-r-last are stored inconsistently: {:?} != {:?}",
+                    layout,
+                    base_tree_layout
+                ),
+            }
+        }
+
+        Ok(layout.expect("tree-r-last has at least one base tree"))
+    }
+
+    fn p_aux_path(&self) -> PathBuf {
+        self.cache.join(CacheKey::PAux.to_string())
+    }
+
+    fn t_aux_path(&self) -> PathBuf {
+        self.cache.join(CacheKey::TAux.to_string())
+    }
+}
+
+fn base_tree_arity<Tree: MerkleTreeTrait>() -> usize {
+    Tree::Arity::to_usize()
+}
+
+/// Classifies a tree-r-last base tree store of `nodes` elements, built over `base_tree_leafs`
+/// with the given `arity`.
+fn classify_tree_r_last(
+    nodes: usize,
+    base_tree_leafs: usize,
+    arity: usize,
+) -> Result<TreeRLastLayout> {
+    if nodes == get_merkle_tree_len(base_tree_leafs, arity)? {
+        return Ok(TreeRLastLayout::DiskStore);
+    }
+
+    let row_count = get_merkle_tree_row_count(base_tree_leafs, arity);
+    for rows_to_discard in 0..row_count.saturating_sub(1) {
+        if nodes == get_merkle_tree_cache_size(base_tree_leafs, arity, rows_to_discard)? {
+            return Ok(TreeRLastLayout::LevelCache { rows_to_discard });
+        }
+    }
+
+    Ok(TreeRLastLayout::Unknown { nodes })
+}
+
+/// A single migration step of a cache directory.
+pub trait Migration {
+    /// Short name, used to select migrations on the command line.
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// Returns why `sector` needs this migration, or `None` if it does not.
+    fn check(&self, sector: &SectorCache) -> Result<Option<String>>;
+
+    /// Writes the migrated files into `staging`, named as they are named in the cache directory.
+    fn apply(&self, sector: &SectorCache, staging: &Path) -> Result<()>;
+}
+
+/// All known migrations, in the order they have to be applied.
+pub fn registry() -> Vec<Box<dyn Migration>> {
+    vec![
+        Box::new(ConvertDiskStore),
+        Box::new(RebuildTreeRLast),
+        Box::new(RegenerateAux),
+        Box::new(UpgradeLayout),
+    ]
+}
+
+/// Looks up the migrations named `names` in the registry, keeping the registry order.
+pub fn select(names: &[&str]) -> Result<Vec<Box<dyn Migration>>> {
+    for name in names {
+        ensure!(
+            registry().iter().any(|m| m.name() == *name),
+            "unknown migration: {}",
+            name
+        );
+    }
+
+    Ok(registry()
+        .into_iter()
+        .filter(|m| names.contains(&m.name()))
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub name: &'static str,
+    pub reason: String,
+    pub applied: bool,
+}
+
+/// Runs all `migrations` that `sector` needs. With `dry_run`, nothing is changed on disk and
+/// the report lists the migrations that would be applied. As the checks then all run against
+/// the current state, a later migration may be reported that an earlier one makes obsolete.
+pub fn migrate(
+    sector: &SectorCache,
+    migrations: &[Box<dyn Migration>],
+    dry_run: bool,
+) -> Result<Vec<MigrationReport>> {
+    ensure!(
+        sector.cache.is_dir(),
+        "cache directory {:?} does not exist",
+        sector.cache
+    );
+    ensure!(
+        sector.replica.is_file(),
+        "replica {:?} does not exist",
+        sector.replica
+    );
+
+    let mut reports = Vec::new();
+    for migration in migrations {
+        let reason = match migration.check(sector)? {
+            Some(reason) => reason,
+            None => continue,
+        };
+        info!("{}: {}", migration.name(), reason);
+
+        if !dry_run {
+            let staging = sector.cache.join(format!(".migrate-{}", migration.name()));
+            if staging.exists() {
+                fs::remove_dir_all(&staging)?;
+            }
+            fs::create_dir_all(&staging)?;
+
+            let result = migration
+                .apply(sector, &staging)
+                .and_then(|_| swap_in(&staging, &sector.cache));
+            fs::remove_dir_all(&staging)?;
+            result.with_context(|| format!("migration {} failed", migration.name()))?;
+        }
+
+        reports.push(MigrationReport {
+            name: migration.name(),
+            reason,
+            applied: !dry_run,
+        });
+    }
+
+    Ok(reports)
+}
+
+/// Moves all files from `staging` into `cache`. The aux files and the sector metadata are moved
+/// last, so they never describe trees that are not in place yet.
+fn swap_in(staging: &Path, cache: &Path) -> Result<()> {
+    let rank = |name: &str| {
+        if name == CacheKey::SectorMetadata.to_string() {
+            2
+        } else if name == CacheKey::PAux.to_string() || name == CacheKey::TAux.to_string() {
+            1
+        } else {
+            0
+        }
+    };
+
+    let mut names = fs::read_dir(staging)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<String>>>()?;
+    names.sort_by_key(|name| (rank(name), name.clone()));
+
+    for name in &names {
+        let from = staging.join(name);
+        File::open(&from)?.sync_all()?;
+        fs::rename(&from, cache.join(name))
+            .with_context(|| format!("could not move {:?} into {:?}", from, cache))?;
+    }
+    File::open(cache)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Rebuilds tree-r-last into `staging` and updates `t_aux` and the sector metadata to match.
+fn rebuild_into(sector: &SectorCache, staging: &Path) -> Result<()> {
+    let (root, _) = rebuild_tree_r_last(
+        sector.sector_size as usize,
+        &staging.to_path_buf(),
+        &sector.replica,
+        Some(sector.rows_to_discard),
+    )?;
+
+    if sector.p_aux_path().exists() {
+        let p_aux: PersistentAux<DefaultTreeDomain> = deserialize(&fs::read(sector.p_aux_path())?)?;
+        ensure!(
+            root == p_aux.comm_r_last,
+            "rebuilt tree-r-last does not match comm_r_last, is the replica intact?"
+        );
+    }
+
+    with_shape!(
+        sector.sector_size,
+        update_t_aux_rows,
+        sector,
+        staging,
+        sector.rows_to_discard
+    )?;
+
+    if let Some(mut metadata) = SectorMetadata::read(&sector.cache)? {
+        metadata.tree_r_last_rows_to_discard = sector.rows_to_discard;
+        metadata.write(staging)?;
+    }
+
+    Ok(())
+}
+
+fn update_t_aux_rows<Tree: 'static + MerkleTreeTrait>(
+    sector: &SectorCache,
+    staging: &Path,
+    rows_to_discard: usize,
+) -> Result<()> {
+    if !sector.t_aux_path().exists() {
+        return Ok(());
+    }
+
+    let mut t_aux: TemporaryAux<Tree, DefaultPieceHasher> =
+        deserialize(&fs::read(sector.t_aux_path())?)?;
+    t_aux.tree_r_last_config.rows_to_discard = rows_to_discard;
+    fs::write(staging.join(CacheKey::TAux.to_string()), serialize(&t_aux)?)?;
+
+    Ok(())
+}
+
+/// Converts tree-r-last stored as full `DiskStore`s into level cache stores.
+pub struct ConvertDiskStore;
+
+impl Migration for ConvertDiskStore {
+    fn name(&self) -> &'static str {
+        "convert-disk-store"
+    }
+
+    fn description(&self) -> &'static str {
+        "Convert tree-r-last from DiskStore to LevelCacheStore trees"
+    }
+
+    fn check(&self, sector: &SectorCache) -> Result<Option<String>> {
+        Ok(match sector.tree_r_last_layout()? {
+            TreeRLastLayout::DiskStore => Some("tree-r-last is stored as DiskStore".into()),
+            _ => None,
+        })
+    }
+
+    fn apply(&self, sector: &SectorCache, staging: &Path) -> Result<()> {
+        rebuild_into(sector, staging)
+    }
+}
+
+/// Rebuilds the level cache trees of tree-r-last, e.g. for a new `rows_to_discard`.
+pub struct RebuildTreeRLast;
+
+impl Migration for RebuildTreeRLast {
+    fn name(&self) -> &'static str {
+        "rebuild-tree-r-last"
+    }
+
+    fn description(&self) -> &'static str {
+        "Rebuild missing tree-r-last trees, or those with a different rows_to_discard"
+    }
+
+    fn check(&self, sector: &SectorCache) -> Result<Option<String>> {
+        Ok(match sector.tree_r_last_layout()? {
+            TreeRLastLayout::Missing => Some("tree-r-last is missing".into()),
+            TreeRLastLayout::LevelCache { rows_to_discard }
+                if rows_to_discard != sector.rows_to_discard =>
+            {
+                Some(format!(
+                    "tree-r-last has rows_to_discard {}, not {}",
+                    rows_to_discard, sector.rows_to_discard
+                ))
+            }
+            TreeRLastLayout::Unknown { nodes } => Some(format!(
+                "tree-r-last has an unknown layout with {} nodes",
+                nodes
+            )),
+            _ => None,
+        })
+    }
+
+    fn apply(&self, sector: &SectorCache, staging: &Path) -> Result<()> {
+        rebuild_into(sector, staging)
+    }
+}
+
+/// Regenerates missing `p_aux` and `t_aux` files. The commitments are recomputed from the layer
+/// labels and the replica, and the trees in the cache directory are checked against them.
+pub struct RegenerateAux;
+
+impl Migration for RegenerateAux {
+    fn name(&self) -> &'static str {
+        "regenerate-aux"
+    }
+
+    fn description(&self) -> &'static str {
+        "Regenerate missing p_aux and t_aux files from the labels and the replica"
+    }
+
+    fn check(&self, sector: &SectorCache) -> Result<Option<String>> {
+        let missing: Vec<_> = vec![
+            (CacheKey::PAux, sector.p_aux_path()),
+            (CacheKey::TAux, sector.t_aux_path()),
+        ]
+        .into_iter()
+        .filter(|(_, path)| !path.exists())
+        .map(|(key, _)| key.to_string())
+        .collect();
+
+        if missing.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(format!("{} missing", missing.join(" and "))))
+        }
+    }
+
+    fn apply(&self, sector: &SectorCache, staging: &Path) -> Result<()> {
+        with_shape!(sector.sector_size, regenerate_aux, sector, staging)
+    }
+}
+
+fn regenerate_aux<Tree: 'static + MerkleTreeTrait>(
+    sector: &SectorCache,
+    staging: &Path,
+) -> Result<()> {
+    let rows_to_discard = match sector.tree_r_last_layout()? {
+        TreeRLastLayout::LevelCache { rows_to_discard } => rows_to_discard,
+        layout => bail!("tree-r-last has to be rebuilt first, found {:?}", layout),
+    };
+
+    let nodes = sector.nodes();
+    let tree_count = get_base_tree_count::<Tree>();
+    let base_tree_leafs = nodes / tree_count;
+    let base_tree_len = get_merkle_tree_len(base_tree_leafs, Tree::Arity::to_usize())?;
+
+    let layers = *LAYERS
+        .read()
+        .expect("LAYERS poisoned")
+        .get(&sector.sector_size)
+        .context("unknown number of layers")?;
+    let tree_d_rows_to_discard = match SectorMetadata::read(&sector.cache)? {
+        Some(metadata) => metadata.tree_d_rows_to_discard,
+        None => default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    };
+
+    // Mirrors the configs created while sealing, based on the phase1 tree-d config.
+    let config = StoreConfig::new(
+        &sector.cache,
+        CacheKey::CommDTree.to_string(),
+        tree_d_rows_to_discard,
+    );
+    let labels = Labels::<Tree>::new(
+        (1..=layers)
+            .map(|layer| {
+                StoreConfig::from_config(&config, CacheKey::label_layer(layer), Some(nodes))
+            })
+            .collect(),
+    );
+    let tree_d_config = StoreConfig::from_config(
+        &config,
+        CacheKey::CommDTree.to_string(),
+        Some(get_merkle_tree_len(nodes, BINARY_ARITY)?),
+    );
+    let mut tree_r_last_config = StoreConfig::from_config(
+        &config,
+        CacheKey::CommRLastTree.to_string(),
+        Some(base_tree_len),
+    );
+    tree_r_last_config.rows_to_discard = rows_to_discard;
+    let mut tree_c_config = StoreConfig::from_config(
+        &config,
+        CacheKey::CommCTree.to_string(),
+        Some(base_tree_len),
+    );
+    tree_c_config.rows_to_discard =
+        default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize());
+
+    if !sector.p_aux_path().exists() {
+        // The commitments are recomputed from the labels and the replica, the stored trees are
+        // what they have to be checked against.
+        ensure!(
+            labels
+                .labels
+                .iter()
+                .all(|config| StoreConfig::data_path(&config.path, &config.id).exists()),
+            "the layer labels were removed from the cache directory, comm_c can not be recovered"
+        );
+
+        let scratch = tempfile::tempdir_in(&sector.cache)?;
+        let mut config = tree_c_config.clone();
+        config.path = scratch.path().to_path_buf();
+        let comm_c = StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_c(config, &labels)?;
+
+        let mut config = tree_r_last_config.clone();
+        config.path = scratch.path().to_path_buf();
+        let comm_r_last = StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_r_last(
+            config,
+            sector.replica.clone(),
+        )?;
+
+        let tree_c_configs = split_config(tree_c_config.clone(), tree_count)?;
+        if tree_c_configs
+            .iter()
+            .all(|config| StoreConfig::data_path(&config.path, &config.id).exists())
+        {
+            ensure!(
+                create_disk_tree::<Tree>(base_tree_len, &tree_c_configs)?.root() == comm_c,
+                "tree-c in the cache directory does not match the layer labels"
+            );
+        }
+
+        let (configs, replica_config) = split_config_and_replica(
+            tree_r_last_config.clone(),
+            sector.replica.clone(),
+            base_tree_leafs,
+            tree_count,
+        )?;
+        ensure!(
+            create_lc_tree::<Tree>(base_tree_len, &configs, &replica_config)?.root() == comm_r_last,
+            "tree-r-last in the cache directory does not match the replica"
+        );
+
+        let p_aux = PersistentAux::<<Tree::Hasher as Hasher>::Domain> {
+            comm_c,
+            comm_r_last,
+        };
+        fs::write(staging.join(CacheKey::PAux.to_string()), serialize(&p_aux)?)?;
+    }
+
+    if !sector.t_aux_path().exists() {
+        let t_aux = TemporaryAux::<Tree, DefaultPieceHasher> {
+            labels,
+            tree_d_config,
+            tree_r_last_config,
+            tree_c_config,
+            _g: Default::default(),
+        };
+        fs::write(staging.join(CacheKey::TAux.to_string()), serialize(&t_aux)?)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the sector metadata for sectors sealed before it was recorded, or with an older
+/// cache layout version.
+pub struct UpgradeLayout;
+
+impl Migration for UpgradeLayout {
+    fn name(&self) -> &'static str {
+        "upgrade-layout"
+    }
+
+    fn description(&self) -> &'static str {
+        "Upgrade the cache layout version recorded in the sector metadata"
+    }
+
+    fn check(&self, sector: &SectorCache) -> Result<Option<String>> {
+        Ok(match SectorMetadata::read(&sector.cache)? {
+            None => Some("sector metadata is missing".into()),
+            Some(metadata) if metadata.layout_version < SECTOR_LAYOUT_VERSION => Some(format!(
+                "layout version {} is older than {}",
+                metadata.layout_version, SECTOR_LAYOUT_VERSION
+            )),
+            Some(_) => None,
+        })
+    }
+
+    fn apply(&self, sector: &SectorCache, staging: &Path) -> Result<()> {
+        with_shape!(sector.sector_size, upgrade_layout, sector, staging)
+    }
+}
+
+fn upgrade_layout<Tree: 'static + MerkleTreeTrait>(
+    sector: &SectorCache,
+    staging: &Path,
+) -> Result<()> {
+    let rows_to_discard = match sector.tree_r_last_layout()? {
+        TreeRLastLayout::LevelCache { rows_to_discard } => rows_to_discard,
+        layout => bail!("tree-r-last has to be rebuilt first, found {:?}", layout),
+    };
+
+    let porep_id = match (SectorMetadata::read(&sector.cache)?, sector.porep_id) {
+        (Some(metadata), _) => metadata.porep_id,
+        (None, Some(porep_id)) => porep_id,
+        (None, None) => bail!("the porep id is needed to record the sector metadata"),
+    };
+
+    let tree_d_rows_to_discard = if sector.t_aux_path().exists() {
+        let t_aux: TemporaryAux<Tree, DefaultPieceHasher> =
+            deserialize(&fs::read(sector.t_aux_path())?)?;
+        t_aux.tree_d_config.rows_to_discard
+    } else {
+        let base_tree_leafs = sector.nodes() / get_base_tree_count::<Tree>();
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY)
+    };
+
+    SectorMetadata::new::<Tree>(
+        sector.sector_size,
+        porep_id,
+        tree_d_rows_to_discard,
+        rows_to_discard,
+    )
+    .write(staging)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_tree_r_last() {
+        let leafs = 64;
+        assert_eq!(
+            classify_tree_r_last(73, leafs, 8).unwrap(),
+            TreeRLastLayout::DiskStore
+        );
+        assert_eq!(
+            classify_tree_r_last(9, leafs, 8).unwrap(),
+            TreeRLastLayout::LevelCache { rows_to_discard: 0 }
+        );
+        assert_eq!(
+            classify_tree_r_last(1, leafs, 8).unwrap(),
+            TreeRLastLayout::LevelCache { rows_to_discard: 1 }
+        );
+        assert_eq!(
+            classify_tree_r_last(5, leafs, 8).unwrap(),
+            TreeRLastLayout::Unknown { nodes: 5 }
+        );
+        assert_eq!(
+            classify_tree_r_last(127, leafs, 2).unwrap(),
+            TreeRLastLayout::DiskStore
+        );
+        assert_eq!(
+            classify_tree_r_last(73, leafs, 2).unwrap(),
+            TreeRLastLayout::Unknown { nodes: 73 }
+        );
+    }
+
+    #[test]
+    fn test_migrate_rows_to_discard() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache");
+        let replica = dir.path().join("replica");
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(&replica, vec![0u8; SECTOR_SIZE_2_KIB as usize]).unwrap();
+
+        let (comm_r_last, _) =
+            rebuild_tree_r_last(SECTOR_SIZE_2_KIB as usize, &cache, &replica, Some(0)).unwrap();
+        let p_aux = PersistentAux {
+            comm_c: DefaultTreeDomain::default(),
+            comm_r_last,
+        };
+        fs::write(
+            cache.join(CacheKey::PAux.to_string()),
+            serialize(&p_aux).unwrap(),
+        )
+        .unwrap();
+
+        let sector = SectorCache {
+            sector_size: SECTOR_SIZE_2_KIB,
+            cache: cache.clone(),
+            replica,
+            porep_id: Some([1; 32]),
+            rows_to_discard: 1,
+        };
+
+        let reports = migrate(&sector, &registry(), true).unwrap();
+        let names: Vec<_> = reports.iter().map(|r| r.name).collect();
+        assert_eq!(
+            names,
+            vec!["rebuild-tree-r-last", "regenerate-aux", "upgrade-layout"]
+        );
+        assert!(reports.iter().all(|r| !r.applied));
+        assert_eq!(
+            sector.tree_r_last_layout().unwrap(),
+            TreeRLastLayout::LevelCache { rows_to_discard: 0 }
+        );
+
+        let reports = migrate(&sector, &registry(), false).unwrap();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(
+            sector.tree_r_last_layout().unwrap(),
+            TreeRLastLayout::LevelCache { rows_to_discard: 1 }
+        );
+        let metadata = SectorMetadata::read(&cache).unwrap().unwrap();
+        assert_eq!(metadata.tree_r_last_rows_to_discard, 1);
+        assert_eq!(metadata.porep_id, [1; 32]);
+        assert!(cache.join(CacheKey::TAux.to_string()).exists());
+
+        // Nothing is left to do.
+        assert!(migrate(&sector, &registry(), false).unwrap().is_empty());
+    }
+}
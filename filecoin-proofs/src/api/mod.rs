@@ -1,10 +1,11 @@
 use std::fs::File;
-use std::io::{BufWriter, Read, Write};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context, Result};
 use bincode::deserialize;
 use log::info;
+use merkletree::merkle::get_merkle_tree_len;
 use merkletree::store::{DiskStore, LevelCacheStore, StoreConfig};
 use storage_proofs::cache_key::CacheKey;
 use storage_proofs::hasher::Hasher;
@@ -15,11 +16,13 @@ use storage_proofs::porep::stacked::{
 };
 use storage_proofs::porep::PoRep;
 use storage_proofs::sector::SectorId;
-use storage_proofs::util::default_rows_to_discard;
+use storage_proofs::sector_metadata::SectorMetadata;
+use storage_proofs::util::{default_rows_to_discard, NODE_SIZE};
 use typenum::Unsigned;
 
 use crate::api::util::{as_safe_commitment, get_base_tree_leafs, get_base_tree_size};
 use crate::commitment_reader::CommitmentReader;
+use crate::commp_builder::CommPBuilder;
 use crate::constants::{
     DefaultBinaryTree, DefaultOctTree, DefaultPieceDomain, DefaultPieceHasher,
     MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
@@ -27,23 +30,30 @@ use crate::constants::{
 use crate::fr32::write_unpadded;
 use crate::parameters::public_params;
 use crate::types::{
-    Commitment, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig, PoRepProofPartitions,
-    ProverId, SealPreCommitPhase1Output, Ticket, UnpaddedByteIndex, UnpaddedBytesAmount,
+    Commitment, DataTree, MerkleTreeTrait, PaddedBytesAmount, PieceInclusionProof, PieceInfo,
+    PoRepConfig, PoRepProofPartitions, ProverId, SealPreCommitPhase1Output, SectorSize, Ticket,
+    UnpaddedByteIndex, UnpaddedBytesAmount, BINARY_ARITY,
 };
 
 mod post;
+mod recover;
+mod scrub;
 mod seal;
+mod update;
 pub(crate) mod util;
 
 pub use self::post::*;
+pub use self::recover::*;
+pub use self::scrub::*;
 pub use self::seal::*;
+pub use self::update::*;
 
-use storage_proofs::pieces::generate_piece_commitment_bytes_from_source;
+use storage_proofs::pieces::PieceSpec;
 
 /// Unseals the sector at `sealed_path` and returns the bytes for a piece
 /// whose first (unpadded) byte begins at `offset` and ends at `offset` plus
-/// `num_bytes`, inclusive. Note that the entire sector is unsealed each time
-/// this function is called.
+/// `num_bytes`, inclusive. Only the nodes covering the requested range are
+/// read and decoded.
 ///
 /// # Arguments
 ///
@@ -80,7 +90,7 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>, Tree: 'static + Merkle
 
     let buf_f_out = BufWriter::new(f_out);
 
-    let result = unseal_range::<_, _, _, Tree>(
+    let result = unseal_range_seekable::<_, _, _, Tree>(
         porep_config,
         cache_path,
         f_in,
@@ -99,8 +109,13 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>, Tree: 'static + Merkle
 
 /// Unseals the sector read from `sealed_sector` and returns the bytes for a
 /// piece whose first (unpadded) byte begins at `offset` and ends at `offset`
-/// plus `num_bytes`, inclusive. Note that the entire sector is unsealed each
-/// time this function is called.
+/// plus `num_bytes`, inclusive. Only the padded nodes covering the requested
+/// range are decoded, using the last layer labels from `cache_path`. The bytes
+/// before the range are read and discarded, use `unseal_range_seekable` to skip
+/// them instead.
+///
+/// If the last layer labels are no longer in `cache_path`, all layers of the
+/// sector are regenerated first, which costs as much as sealing it again.
 ///
 /// # Arguments
 ///
@@ -119,7 +134,7 @@ pub fn unseal_range<P, R, W, Tree>(
     porep_config: PoRepConfig,
     cache_path: P,
     mut sealed_sector: R,
-    mut unsealed_output: W,
+    unsealed_output: W,
     prover_id: ProverId,
     sector_id: SectorId,
     comm_d: Commitment,
@@ -134,6 +149,115 @@ where
     Tree: 'static + MerkleTreeTrait,
 {
     info!("unseal_range:start");
+
+    let result = unseal_range_inner::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        |start, buf: &mut [u8]| {
+            // Skip to the start of the range, without requiring the source to be seekable.
+            let skipped = io::copy(&mut (&mut sealed_sector).take(start), &mut io::sink())?;
+            ensure!(
+                skipped == start,
+                "sealed sector ended before offset {}",
+                start
+            );
+            sealed_sector.read_exact(buf)?;
+            Ok(())
+        },
+        unsealed_output,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+    );
+
+    info!("unseal_range:finish");
+    result
+}
+
+/// Same as `unseal_range`, but seeks `sealed_sector` to the requested range
+/// instead of reading the bytes before it.
+///
+/// If the last layer labels are no longer in `cache_path`, all layers of the
+/// sector are regenerated first, which costs as much as sealing it again.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size.
+/// * `cache_path` - path to the directory in which the sector data's Merkle Tree is written.
+/// * `sealed_sector` - a seekable byte source from which we read sealed sector data.
+/// * `unsealed_output` - a byte sink to which we write unsealed, un-bit-padded sector bytes.
+/// * `prover_id` - the prover-id that sealed the sector.
+/// * `sector_id` - the sector-id of the sealed sector.
+/// * `comm_d` - the commitment to the sector's data.
+/// * `ticket` - the ticket that was used to generate the sector's replica-id.
+/// * `offset` - the byte index in the unsealed sector of the first byte that we want to read.
+/// * `num_bytes` - the number of bytes that we want to read.
+#[allow(clippy::too_many_arguments)]
+pub fn unseal_range_seekable<P, R, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    mut sealed_sector: R,
+    unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    R: Read + Seek,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
+    info!("unseal_range_seekable:start");
+
+    let result = unseal_range_inner::<_, _, _, Tree>(
+        porep_config,
+        cache_path,
+        |start, buf: &mut [u8]| {
+            sealed_sector.seek(SeekFrom::Start(start))?;
+            sealed_sector.read_exact(buf)?;
+            Ok(())
+        },
+        unsealed_output,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        offset,
+        num_bytes,
+    );
+
+    info!("unseal_range_seekable:finish");
+    result
+}
+
+/// Decodes the requested range, reading the sealed nodes covering it with
+/// `read_sealed`, which fills the buffer with the replica bytes at the given offset.
+#[allow(clippy::too_many_arguments)]
+fn unseal_range_inner<P, F, W, Tree>(
+    porep_config: PoRepConfig,
+    cache_path: P,
+    read_sealed: F,
+    mut unsealed_output: W,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    comm_d: Commitment,
+    ticket: Ticket,
+    offset: UnpaddedByteIndex,
+    num_bytes: UnpaddedBytesAmount,
+) -> Result<UnpaddedBytesAmount>
+where
+    P: Into<PathBuf> + AsRef<Path>,
+    F: FnOnce(u64, &mut [u8]) -> Result<()>,
+    W: Write,
+    Tree: 'static + MerkleTreeTrait,
+{
     ensure!(comm_d != [0; 32], "Invalid all zero commitment (comm_d)");
 
     let comm_d =
@@ -147,20 +271,32 @@ where
         &porep_config.porep_id,
     );
 
-    let mut data = Vec::new();
-    sealed_sector.read_to_end(&mut data)?;
-
     let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
     let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+    // Prefer the rows recorded at sealing time, the configured default may have changed since.
+    let metadata = SectorMetadata::read(cache_path.as_ref())?;
+    let rows_to_discard = match metadata {
+        Some(metadata) => {
+            metadata.ensure_shape::<Tree>()?;
+            ensure!(
+                metadata.sector_size == u64::from(porep_config.sector_size)
+                    && metadata.porep_id == porep_config.porep_id,
+                "sector in {:?} was sealed with a different porep config",
+                cache_path.as_ref()
+            );
+            metadata.tree_d_rows_to_discard
+        }
+        None => default_rows_to_discard(
+            base_tree_leafs,
+            <DefaultBinaryTree as MerkleTreeTrait>::Arity::to_usize(),
+        ),
+    };
     // MT for original data is always named tree-d, and it will be
     // referenced later in the process as such.
     let config = StoreConfig::new(
         cache_path.as_ref(),
         CacheKey::CommDTree.to_string(),
-        default_rows_to_discard(
-            base_tree_leafs,
-            <DefaultBinaryTree as MerkleTreeTrait>::Arity::to_usize(),
-        ),
+        rows_to_discard,
     );
     let pp = public_params(
         PaddedBytesAmount::from(porep_config),
@@ -168,25 +304,44 @@ where
         porep_config.porep_id,
     )?;
 
-    let offset_padded: PaddedBytesAmount = UnpaddedBytesAmount::from(offset).into();
-    let num_bytes_padded: PaddedBytesAmount = num_bytes.into();
-
-    let unsealed_all =
-        StackedDrg::<Tree, DefaultPieceHasher>::extract_all(&pp, &replica_id, &data, Some(config))?;
-    let start: usize = offset_padded.into();
-    let end = start + usize::from(num_bytes_padded);
-    let unsealed = &unsealed_all[start..end];
+    // Fr32 padding maps every 127 unpadded bytes onto 128 padded bytes (4 nodes),
+    // so widen the requested range to whole 127 byte chunks to get node aligned
+    // bounds in the replica.
+    let offset = u64::from(offset);
+    let num_bytes = u64::from(num_bytes);
+    let sector_bytes = u64::from(PaddedBytesAmount::from(porep_config));
+    let first_chunk = offset / 127;
+    let end_chunk = (offset + num_bytes + 126) / 127;
+    let start = first_chunk * 128;
+    let end = std::cmp::min(end_chunk * 128, sector_bytes);
+    ensure!(
+        start <= end,
+        "offset {} is out of bounds for the sector",
+        offset
+    );
 
-    // If the call to `extract_range` was successful, the `unsealed` vector must
-    // have a length which equals `num_bytes_padded`. The byte at its 0-index
-    // byte will be the the byte at index `offset_padded` in the sealed sector.
-    let written = write_unpadded(unsealed, &mut unsealed_output, 0, num_bytes.into())
-        .context("write_unpadded failed")?;
+    let mut sealed = vec![0u8; (end - start) as usize];
+    read_sealed(start, &mut sealed).context("could not read sealed range")?;
 
-    let amount = UnpaddedBytesAmount(written as u64);
+    let unsealed = StackedDrg::<Tree, DefaultPieceHasher>::extract_range(
+        &pp,
+        &replica_id,
+        &sealed,
+        start as usize / NODE_SIZE,
+        config,
+    )?;
 
-    info!("unseal_range:finish");
-    Ok(amount)
+    // The `unsealed` vector starts at the beginning of `first_chunk`, so the
+    // requested bytes begin `offset - first_chunk * 127` unpadded bytes into it.
+    let written = write_unpadded(
+        &unsealed,
+        &mut unsealed_output,
+        (offset - first_chunk * 127) as usize,
+        num_bytes as usize,
+    )
+    .context("write_unpadded failed")?;
+
+    Ok(UnpaddedBytesAmount(written as u64))
 }
 
 /// Generates a piece commitment for the provided byte source. Returns an error
@@ -206,22 +361,158 @@ pub fn generate_piece_commitment<T: std::io::Read>(
     let result = measure_op(Operation::GeneratePieceCommitment, || {
         ensure_piece_size(piece_size)?;
 
-        // send the source through the preprocessor
-        let source = std::io::BufReader::new(source);
-        let mut fr32_reader = crate::fr32_reader::Fr32Reader::new(source);
-
-        let commitment = generate_piece_commitment_bytes_from_source::<DefaultPieceHasher>(
-            &mut fr32_reader,
-            PaddedBytesAmount::from(piece_size).into(),
-        )?;
+        // the preprocessing and hashing is split across threads by the builder
+        let mut builder = CommPBuilder::new(piece_size)?;
+        builder.update(source)?;
 
-        PieceInfo::new(commitment, piece_size)
+        builder.finish()
     });
 
     info!("generate_piece_commitment:finish");
     result
 }
 
+/// Generates a proof that the piece described by `piece_info`, whose first (unpadded) byte
+/// begins at `piece_offset` in the unsealed sector, is committed to by `comm_d`. The proof is
+/// generated from the sector's data tree (tree-d) in `cache_path`, which is only available
+/// until the cache is cleared, see `generate_piece_inclusion_proof_from_unsealed` otherwise.
+///
+/// # Arguments
+///
+/// * `cache_path` - path to the directory in which the sector data's Merkle Tree is written.
+/// * `sector_size` - the size of the sector.
+/// * `comm_d` - the commitment to the sector's data.
+/// * `piece_info` - the commitment and size of the piece.
+/// * `piece_offset` - the byte index in the unsealed sector of the first byte of the piece.
+pub fn generate_piece_inclusion_proof<T: AsRef<Path>>(
+    cache_path: T,
+    sector_size: SectorSize,
+    comm_d: &Commitment,
+    piece_info: &PieceInfo,
+    piece_offset: UnpaddedByteIndex,
+) -> Result<PieceInclusionProof> {
+    info!("generate_piece_inclusion_proof:start");
+
+    let leafs = u64::from(sector_size) as usize / NODE_SIZE;
+    let rows_to_discard = match SectorMetadata::read(cache_path.as_ref())? {
+        Some(metadata) => metadata.tree_d_rows_to_discard,
+        None => default_rows_to_discard(leafs, BINARY_ARITY),
+    };
+    let config = StoreConfig::new(
+        cache_path.as_ref(),
+        CacheKey::CommDTree.to_string(),
+        rows_to_discard,
+    );
+    let store = DiskStore::new_from_disk(
+        get_merkle_tree_len(leafs, BINARY_ARITY)?,
+        BINARY_ARITY,
+        &config,
+    )
+    .with_context(|| format!("could not open tree-d in {:?}", cache_path.as_ref()))?;
+    let data_tree = DataTree::from_data_store(store, leafs)?;
+
+    let proof = piece_inclusion_proof_from_tree(&data_tree, comm_d, piece_info, piece_offset)?;
+
+    info!("generate_piece_inclusion_proof:finish");
+    Ok(proof)
+}
+
+/// Same as `generate_piece_inclusion_proof`, but recomputes the proof from the (padded)
+/// unsealed sector bytes read from `unsealed`. The data is streamed, so neither the sector nor
+/// its data tree is held in memory.
+pub fn generate_piece_inclusion_proof_from_unsealed<R: Read>(
+    mut unsealed: R,
+    sector_size: SectorSize,
+    comm_d: &Commitment,
+    piece_info: &PieceInfo,
+    piece_offset: UnpaddedByteIndex,
+) -> Result<PieceInclusionProof> {
+    info!("generate_piece_inclusion_proof_from_unsealed:start");
+
+    let piece_spec = piece_spec(sector_size, piece_info, piece_offset)?;
+    let comm_d_safe: DefaultPieceDomain = as_safe_commitment(comm_d, "comm_d")?;
+
+    let proof = PieceInclusionProof::generate_from_source(
+        &piece_spec,
+        &mut unsealed,
+        u64::from(sector_size) as usize / NODE_SIZE,
+        &comm_d_safe,
+    )
+    .context("comm_d does not match the unsealed sector")?;
+
+    info!("generate_piece_inclusion_proof_from_unsealed:finish");
+    Ok(proof)
+}
+
+/// Verifies that the piece described by `piece_info`, whose first (unpadded) byte begins at
+/// `piece_offset` in the unsealed sector, is committed to by `comm_d`.
+pub fn verify_piece_inclusion_proof(
+    sector_size: SectorSize,
+    comm_d: &Commitment,
+    piece_info: &PieceInfo,
+    piece_offset: UnpaddedByteIndex,
+    proof: &PieceInclusionProof,
+) -> Result<bool> {
+    let piece_spec = piece_spec(sector_size, piece_info, piece_offset)?;
+    let comm_d_safe: DefaultPieceDomain = as_safe_commitment(comm_d, "comm_d")?;
+
+    proof.verify(
+        &piece_spec,
+        &comm_d_safe,
+        u64::from(sector_size) as usize / NODE_SIZE,
+    )
+}
+
+fn piece_inclusion_proof_from_tree(
+    data_tree: &DataTree,
+    comm_d: &Commitment,
+    piece_info: &PieceInfo,
+    piece_offset: UnpaddedByteIndex,
+) -> Result<PieceInclusionProof> {
+    let sector_size = SectorSize((data_tree.leafs() * NODE_SIZE) as u64);
+    let piece_spec = piece_spec(sector_size, piece_info, piece_offset)?;
+
+    let comm_d_safe: DefaultPieceDomain = as_safe_commitment(comm_d, "comm_d")?;
+    ensure!(
+        data_tree.root() == comm_d_safe,
+        "comm_d does not match the sector's data tree"
+    );
+
+    PieceInclusionProof::generate(&piece_spec, data_tree)
+}
+
+/// Locates the piece subtree in the sector's data tree.
+fn piece_spec(
+    sector_size: SectorSize,
+    piece_info: &PieceInfo,
+    piece_offset: UnpaddedByteIndex,
+) -> Result<PieceSpec> {
+    let piece_size = PaddedBytesAmount::from(piece_info.size);
+    ensure!(
+        u64::from(piece_size).is_power_of_two(),
+        "Piece size ({:?}) must be a power of 2.",
+        piece_size
+    );
+
+    // Pieces are aligned to their size, which is a multiple of 127 unpadded bytes.
+    ensure!(
+        u64::from(piece_offset) % 127 == 0,
+        "piece offset {} is not aligned",
+        u64::from(piece_offset)
+    );
+    let padded_offset = PaddedBytesAmount::from(UnpaddedBytesAmount::from(piece_offset));
+    ensure!(
+        u64::from(padded_offset) + u64::from(piece_size) <= u64::from(sector_size),
+        "piece exceeds the sector"
+    );
+
+    Ok(PieceSpec {
+        comm_p: piece_info.commitment,
+        position: usize::from(padded_offset) / NODE_SIZE,
+        number_of_leaves: usize::from(piece_size) / NODE_SIZE,
+    })
+}
+
 /// Computes a NUL-byte prefix and/or suffix for `source` using the provided
 /// `piece_lengths` and `piece_size` (such that the `source`, after
 /// preprocessing, will occupy a subtree of a merkle tree built using the bytes
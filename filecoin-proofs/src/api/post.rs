@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::{Hash, Hasher as StdHasher};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
@@ -6,17 +6,21 @@ use std::path::{Path, PathBuf};
 use anyhow::{ensure, Context, Result};
 use bincode::deserialize;
 use generic_array::typenum::Unsigned;
-use log::{info, trace};
+use log::{info, trace, warn};
 use merkletree::store::StoreConfig;
 use storage_proofs::cache_key::CacheKey;
 use storage_proofs::compound_proof::{self, CompoundProof};
-use storage_proofs::hasher::{Domain, Hasher};
+use storage_proofs::hasher::{Domain, HashFunction, Hasher};
 use storage_proofs::merkle::{
-    create_tree, get_base_tree_count, split_config_and_replica, MerkleTreeTrait, MerkleTreeWrapper,
+    create_tree, get_base_tree_count, split_config_and_replica, MerkleProofTrait, MerkleTreeTrait,
+    MerkleTreeWrapper,
 };
 use storage_proofs::multi_proof::MultiProof;
 use storage_proofs::post::fallback;
+use storage_proofs::progress::Progress;
+use storage_proofs::proof::ProofScheme;
 use storage_proofs::sector::*;
+use storage_proofs::sector_metadata::SectorMetadata;
 use storage_proofs::util::default_rows_to_discard;
 
 use crate::api::util::{as_safe_commitment, get_base_tree_leafs, get_base_tree_size};
@@ -40,6 +44,8 @@ pub struct PrivateReplicaInfo<Tree: MerkleTreeTrait> {
     aux: PersistentAux<<Tree::Hasher as Hasher>::Domain>,
     /// Contains sector-specific (e.g. merkle trees) assets
     cache_dir: PathBuf,
+    /// Parameters recorded at sealing time, absent for older sectors.
+    metadata: Option<SectorMetadata>,
 
     _t: PhantomData<Tree>,
 }
@@ -51,6 +57,7 @@ impl<Tree: MerkleTreeTrait> Clone for PrivateReplicaInfo<Tree> {
             comm_r: self.comm_r,
             aux: self.aux.clone(),
             cache_dir: self.cache_dir.clone(),
+            metadata: self.metadata.clone(),
             _t: Default::default(),
         }
     }
@@ -102,11 +109,17 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
 
         ensure!(replica.exists(), "Sealed replica does not exist");
 
+        let metadata = SectorMetadata::read(&cache_dir)?;
+        if let Some(ref metadata) = metadata {
+            metadata.ensure_shape::<Tree>()?;
+        }
+
         Ok(PrivateReplicaInfo {
             replica,
             comm_r,
             aux,
             cache_dir,
+            metadata,
             _t: Default::default(),
         })
     }
@@ -131,6 +144,13 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
         Ok(self.aux.comm_r_last)
     }
 
+    /// The rows discarded from tree-r-last when this replica was sealed, if recorded.
+    pub fn rows_to_discard(&self) -> Option<usize> {
+        self.metadata
+            .as_ref()
+            .map(|metadata| metadata.tree_r_last_rows_to_discard)
+    }
+
     /// Generate the merkle tree of this particular replica.
     pub fn merkle_tree(
         &self,
@@ -144,13 +164,25 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
             Tree::TopTreeArity,
         >,
     > {
+        if let Some(ref metadata) = self.metadata {
+            ensure!(
+                metadata.sector_size == u64::from(sector_size),
+                "sector was sealed with size {}, not {}",
+                metadata.sector_size,
+                u64::from(sector_size)
+            );
+        }
+
         let base_tree_size = get_base_tree_size::<Tree>(sector_size)?;
         let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+        let rows_to_discard = self
+            .rows_to_discard()
+            .unwrap_or_else(|| default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()));
         trace!(
             "post: base tree size {}, base tree leafs {}, rows_to_discard {}, arities [{}, {}, {}]",
             base_tree_size,
             base_tree_leafs,
-            default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+            rows_to_discard,
             Tree::Arity::to_usize(),
             Tree::SubTreeArity::to_usize(),
             Tree::TopTreeArity::to_usize(),
@@ -159,7 +191,7 @@ impl<Tree: 'static + MerkleTreeTrait> PrivateReplicaInfo<Tree> {
         let mut config = StoreConfig::new(
             self.cache_dir_path(),
             CacheKey::CommRLastTree.to_string(),
-            default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+            rows_to_discard,
         );
         config.size = Some(base_tree_size);
 
@@ -299,6 +331,7 @@ pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
                 tree,
                 comm_c,
                 comm_r_last,
+                rows_to_discard: replica.rows_to_discard(),
             });
         }
     }
@@ -446,6 +479,18 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
     randomness: &ChallengeSeed,
     replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
     prover_id: ProverId,
+) -> Result<SnarkProof> {
+    generate_window_post_with_progress(post_config, randomness, replicas, prover_id, None)
+}
+
+/// Same as `generate_window_post`, but reports circuit proof progress to `progress`.
+/// Once `progress` is cancelled, `Error::Cancelled` is returned.
+pub fn generate_window_post_with_progress<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    prover_id: ProverId,
+    progress: Option<&dyn Progress>,
 ) -> Result<SnarkProof> {
     info!("generate_window_post:start");
     ensure!(
@@ -491,6 +536,7 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
             tree,
             comm_c,
             comm_r_last,
+            rows_to_discard: replica.rows_to_discard(),
         });
     }
 
@@ -505,11 +551,12 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
         sectors: &priv_sectors,
     };
 
-    let proof = fallback::FallbackPoStCompound::prove(
+    let proof = fallback::FallbackPoStCompound::prove_with_progress(
         &pub_params,
         &pub_inputs,
         &priv_inputs,
         &groth_params,
+        progress,
     )?;
 
     info!("generate_window_post:finish");
@@ -517,6 +564,180 @@ pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
     Ok(proof.to_vec()?)
 }
 
+/// A vanilla proof for a single sector of a window proof-of-spacetime.
+pub type FallbackPoStSectorProof<Tree> = fallback::SectorProof<<Tree as MerkleTreeTrait>::Proof>;
+
+/// Generates the leaf challenges of a window proof-of-spacetime for each of the given sectors.
+///
+/// The challenges depend on the position of each sector among all challenged sectors, so
+/// `sectors` must contain the full set of sectors that will be proven together.
+pub fn generate_window_post_challenges<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    sectors: &[SectorId],
+) -> Result<BTreeMap<SectorId, Vec<u64>>> {
+    info!("generate_window_post_challenges:start");
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+
+    let randomness_safe: <Tree::Hasher as Hasher>::Domain =
+        as_safe_commitment(randomness, "randomness")?;
+    let vanilla_params =
+        fallback::FallbackPoSt::<Tree>::setup(&window_post_setup_params(&post_config))?;
+
+    // Sectors are proven in order of their ids, see `generate_window_post`.
+    let sectors: BTreeSet<SectorId> = sectors.iter().copied().collect();
+
+    let challenges = sectors
+        .into_iter()
+        .enumerate()
+        .map(|(sector_index, sector_id)| {
+            let challenges = fallback::generate_sector_leaf_challenges(
+                &vanilla_params,
+                randomness_safe,
+                sector_id,
+                sector_index,
+            )?;
+            Ok((sector_id, challenges))
+        })
+        .collect::<Result<_>>()?;
+
+    info!("generate_window_post_challenges:finish");
+
+    Ok(challenges)
+}
+
+/// Generates the vanilla proof for a single sector of a window proof-of-spacetime, given the
+/// challenges for that sector as returned by `generate_window_post_challenges`.
+pub fn generate_single_window_post_vanilla_proof<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    replica: &PrivateReplicaInfo<Tree>,
+    challenges: &[u64],
+) -> Result<FallbackPoStSectorProof<Tree>> {
+    info!("generate_single_window_post_vanilla_proof:start");
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+    ensure!(
+        challenges.len() == post_config.challenge_count,
+        "invalid number of challenges: {} != {}",
+        challenges.len(),
+        post_config.challenge_count
+    );
+
+    let tree = replica.merkle_tree(post_config.sector_size)?;
+    let priv_sector = fallback::PrivateSector {
+        tree: &tree,
+        comm_c: replica.safe_comm_c()?,
+        comm_r_last: replica.safe_comm_r_last()?,
+        rows_to_discard: replica.rows_to_discard(),
+    };
+
+    let proof = fallback::prove_sector(&priv_sector, challenges)?;
+
+    info!("generate_single_window_post_vanilla_proof:finish");
+
+    Ok(proof)
+}
+
+/// Generates a Window proof-of-spacetime from the vanilla proofs of all challenged sectors,
+/// as returned by `generate_single_window_post_vanilla_proof`. Every vanilla proof must match
+/// the comm_r of its sector in `replicas`.
+pub fn generate_window_post_with_vanilla<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+    prover_id: ProverId,
+    vanilla_proofs: BTreeMap<SectorId, FallbackPoStSectorProof<Tree>>,
+) -> Result<SnarkProof> {
+    info!("generate_window_post_with_vanilla:start");
+    ensure!(
+        post_config.typ == PoStType::Window,
+        "invalid post config type"
+    );
+    ensure!(!vanilla_proofs.is_empty(), "missing vanilla proofs");
+    ensure!(
+        vanilla_proofs.len() == replicas.len(),
+        "got {} vanilla proofs for {} replicas",
+        vanilla_proofs.len(),
+        replicas.len()
+    );
+
+    let randomness_safe = as_safe_commitment(randomness, "randomness")?;
+    let prover_id_safe = as_safe_commitment(&prover_id, "prover_id")?;
+
+    let vanilla_params = window_post_setup_params(&post_config);
+    let partitions = get_partitions_for_window_post(vanilla_proofs.len(), &post_config);
+
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params,
+        partitions,
+        priority: post_config.priority,
+    };
+
+    let pub_params: compound_proof::PublicParams<fallback::FallbackPoSt<Tree>> =
+        fallback::FallbackPoStCompound::setup(&setup_params)?;
+    let groth_params = get_post_params::<Tree>(&post_config)?;
+
+    // The comm_r of each sector comes from the caller, the vanilla proofs may come from
+    // another machine and must prove the sector they are listed for.
+    let pub_sectors = vanilla_proofs
+        .iter()
+        .map(|(sector_id, proof)| {
+            let comm_r = replicas
+                .get(sector_id)
+                .with_context(|| format!("missing replica info for sector {}", sector_id))?
+                .safe_comm_r()?;
+            ensure!(
+                <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &proof.comm_r_last)
+                    == comm_r,
+                "vanilla proof for sector {} does not match its comm_r",
+                sector_id
+            );
+
+            Ok(fallback::PublicSector {
+                id: *sector_id,
+                comm_r,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let pub_inputs = fallback::PublicInputs {
+        randomness: randomness_safe,
+        prover_id: prover_id_safe,
+        sectors: &pub_sectors,
+        k: None,
+    };
+
+    let partition_proofs = fallback::partition_sector_proofs(
+        &pub_params.vanilla_params,
+        vanilla_proofs.into_iter().map(|(_, proof)| proof).collect(),
+    );
+
+    let sanity_check = fallback::FallbackPoSt::<Tree>::verify_all_partitions(
+        &pub_params.vanilla_params,
+        &pub_inputs,
+        &partition_proofs,
+    )?;
+    ensure!(sanity_check, "invalid vanilla proofs");
+
+    let groth_proofs = fallback::FallbackPoStCompound::<Tree>::circuit_proofs(
+        &pub_inputs,
+        partition_proofs,
+        &pub_params.vanilla_params,
+        &groth_params,
+        pub_params.priority,
+    )?;
+    let proof = MultiProof::new(groth_proofs, &groth_params.vk);
+
+    info!("generate_window_post_with_vanilla:finish");
+
+    Ok(proof.to_vec()?)
+}
+
 /// Verifies a window proof-of-spacetime.
 pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
     post_config: &PoStConfig,
@@ -586,6 +807,98 @@ pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
     Ok(true)
 }
 
+/// The reason a sector was reported by `check_window_post_sectors`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SectorFault {
+    /// The replica commitment does not match the commitments in the persistent aux.
+    InvalidCommR,
+    /// tree-r-last could not be opened from the cache directory and the replica.
+    TreeUnreadable(String),
+    /// The root of tree-r-last does not match `comm_r_last`.
+    InvalidCommRLast,
+    /// The challenged leaf or its merkle path could not be read.
+    ChallengeUnreadable { challenge: u64, error: String },
+    /// The merkle path read for the challenged leaf does not verify.
+    InvalidInclusionProof { challenge: u64 },
+}
+
+/// A sector that can not be proven in a window proof-of-spacetime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultySector {
+    pub sector_id: SectorId,
+    pub fault: SectorFault,
+}
+
+/// Checks that every challenged leaf of the given replicas, and its merkle path, can be read
+/// and verified, without generating the SNARK. Returns the sectors that would make
+/// `generate_window_post` fail, so that they can be declared faulty and the remaining
+/// sectors proven.
+pub fn check_window_post_sectors<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    randomness: &ChallengeSeed,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+) -> Result<Vec<FaultySector>> {
+    info!("check_window_post_sectors:start");
+
+    let sector_ids: Vec<SectorId> = replicas.keys().copied().collect();
+    let challenges = generate_window_post_challenges::<Tree>(post_config, randomness, &sector_ids)?;
+
+    let mut faulty_sectors = Vec::new();
+    for (sector_id, replica) in replicas.iter() {
+        if let Err(fault) = check_sector(post_config, replica, &challenges[sector_id]) {
+            warn!("sector {} is faulty: {:?}", sector_id, fault);
+            faulty_sectors.push(FaultySector {
+                sector_id: *sector_id,
+                fault,
+            });
+        }
+    }
+
+    info!("check_window_post_sectors:finish");
+
+    Ok(faulty_sectors)
+}
+
+fn check_sector<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+    replica: &PrivateReplicaInfo<Tree>,
+    challenges: &[u64],
+) -> std::result::Result<(), SectorFault> {
+    let comm_c = replica.aux.comm_c;
+    let comm_r_last = replica.aux.comm_r_last;
+    let comm_r = replica
+        .safe_comm_r()
+        .map_err(|_| SectorFault::InvalidCommR)?;
+    if <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last) != comm_r {
+        return Err(SectorFault::InvalidCommR);
+    }
+
+    let tree = replica
+        .merkle_tree(post_config.sector_size)
+        .map_err(|err| SectorFault::TreeUnreadable(format!("{:#}", err)))?;
+    if tree.root() != comm_r_last {
+        return Err(SectorFault::InvalidCommRLast);
+    }
+
+    let rows_to_discard = replica
+        .rows_to_discard()
+        .unwrap_or_else(|| default_rows_to_discard(tree.leafs(), Tree::Arity::to_usize()));
+    for &challenge in challenges {
+        let proof = tree
+            .gen_cached_proof(challenge as usize, Some(rows_to_discard))
+            .map_err(|err| SectorFault::ChallengeUnreadable {
+                challenge,
+                error: format!("{:#}", err),
+            })?;
+
+        if proof.root() != comm_r_last || !proof.validate(challenge as usize) || !proof.verify() {
+            return Err(SectorFault::InvalidInclusionProof { challenge });
+        }
+    }
+
+    Ok(())
+}
+
 fn get_partitions_for_window_post(
     total_sector_count: usize,
     post_config: &PoStConfig,
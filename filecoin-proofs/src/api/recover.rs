@@ -0,0 +1,174 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use bincode::deserialize;
+use log::{info, warn};
+use merkletree::store::StoreConfig;
+use storage_proofs::cache_key::CacheKey;
+use storage_proofs::hasher::Hasher;
+use storage_proofs::merkle::{get_base_tree_count, split_config, MerkleTreeTrait};
+use storage_proofs::porep::stacked::{PersistentAux, StackedDrg, TemporaryAux};
+use storage_proofs::sector_metadata::SectorMetadata;
+
+use crate::constants::DefaultPieceHasher;
+
+/// Name of the directory within the cache in which trees are rebuilt before being moved
+/// into place.
+const REGENERATE_DIR: &str = ".regenerate";
+
+/// The trees rebuilt by `regenerate_missing_trees`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RegeneratedTrees {
+    pub tree_c: bool,
+    pub tree_r_last: bool,
+}
+
+/// Rebuilds tree-c and tree-r-last of a sector if any of their store files are missing from
+/// `cache_path`.
+///
+/// tree-r-last is rebuilt from the replica alone, tree-c from the layer labels, which are only
+/// present until the cache has been cleared; without them a missing tree-c is left alone. The
+/// trees are built next to the cache and only moved into place once their roots match the
+/// `PersistentAux` of the sector, so a failed rebuild leaves the cache as it was.
+///
+/// # Arguments
+///
+/// * `cache_path` - directory in which the merkle trees and aux files of the sector are kept.
+/// * `replica_path` - path of the sealed sector.
+pub fn regenerate_missing_trees<Tree: 'static + MerkleTreeTrait>(
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<RegeneratedTrees> {
+    info!("regenerate_missing_trees:start");
+
+    let p_aux: PersistentAux<<Tree::Hasher as Hasher>::Domain> = {
+        let p_aux_path = cache_path.join(CacheKey::PAux.to_string());
+        let p_aux_bytes = fs::read(&p_aux_path)
+            .with_context(|| format!("could not read file p_aux={:?}", p_aux_path))?;
+
+        deserialize(&p_aux_bytes)
+    }?;
+
+    let mut t_aux: TemporaryAux<Tree, DefaultPieceHasher> = {
+        let t_aux_path = cache_path.join(CacheKey::TAux.to_string());
+        let t_aux_bytes = fs::read(&t_aux_path)
+            .with_context(|| format!("could not read file t_aux={:?}", t_aux_path))?;
+
+        deserialize(&t_aux_bytes)
+    }?;
+    t_aux.set_cache_path(cache_path);
+
+    // The sector metadata describes how tree-r-last was laid out on disk, so it takes
+    // precedence over t_aux.
+    if let Some(metadata) = SectorMetadata::read(cache_path)? {
+        metadata.ensure_shape::<Tree>()?;
+        t_aux.tree_r_last_config.rows_to_discard = metadata.tree_r_last_rows_to_discard;
+    }
+
+    let tree_count = get_base_tree_count::<Tree>();
+    let mut regenerated = RegeneratedTrees {
+        tree_c: is_missing(&t_aux.tree_c_config, tree_count)?,
+        tree_r_last: is_missing(&t_aux.tree_r_last_config, tree_count)?,
+    };
+
+    // Clearing the cache removes tree-c together with the labels, it is not needed anymore
+    // once the sector is committed.
+    if regenerated.tree_c {
+        let labels_present = t_aux
+            .labels
+            .labels
+            .iter()
+            .all(|config| StoreConfig::data_path(&config.path, &config.id).exists());
+        if !labels_present {
+            warn!("tree-c is missing and can not be rebuilt without the layer labels");
+            regenerated.tree_c = false;
+        }
+    }
+    if !regenerated.tree_c && !regenerated.tree_r_last {
+        info!("regenerate_missing_trees:finish");
+        return Ok(regenerated);
+    }
+
+    let staging_path = cache_path.join(REGENERATE_DIR);
+    if staging_path.exists() {
+        fs::remove_dir_all(&staging_path)
+            .with_context(|| format!("could not remove {:?}", staging_path))?;
+    }
+    fs::create_dir(&staging_path)
+        .with_context(|| format!("could not create {:?}", staging_path))?;
+
+    let result = regenerate_into::<Tree>(&t_aux, &p_aux, replica_path, &staging_path, &regenerated);
+    let result = result.and_then(|files| {
+        for file in files {
+            let to = cache_path.join(file.file_name().expect("store paths have a file name"));
+            fs::rename(&file, &to)
+                .with_context(|| format!("could not move {:?} to {:?}", file, to))?;
+        }
+        Ok(())
+    });
+    if let Err(err) = fs::remove_dir_all(&staging_path) {
+        warn!("could not remove {:?}: {}", staging_path, err);
+    }
+    result?;
+
+    info!("regenerate_missing_trees:finish");
+    Ok(regenerated)
+}
+
+/// Whether any of the store files of the tree described by `config` are missing.
+fn is_missing(config: &StoreConfig, tree_count: usize) -> Result<bool> {
+    Ok(split_config(config.clone(), tree_count)?
+        .iter()
+        .any(|config| !StoreConfig::data_path(&config.path, &config.id).exists()))
+}
+
+/// Builds the trees flagged in `regenerated` in `staging_path` and checks their roots,
+/// returning the store files to move into the cache.
+fn regenerate_into<Tree: 'static + MerkleTreeTrait>(
+    t_aux: &TemporaryAux<Tree, DefaultPieceHasher>,
+    p_aux: &PersistentAux<<Tree::Hasher as Hasher>::Domain>,
+    replica_path: &Path,
+    staging_path: &Path,
+    regenerated: &RegeneratedTrees,
+) -> Result<Vec<PathBuf>> {
+    let tree_count = get_base_tree_count::<Tree>();
+    let mut files = Vec::new();
+
+    if regenerated.tree_c {
+        let mut config = t_aux.tree_c_config.clone();
+        config.path = staging_path.to_path_buf();
+        let comm_c = StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_c(
+            config.clone(),
+            &t_aux.labels,
+        )?;
+        ensure!(
+            comm_c == p_aux.comm_c,
+            "rebuilt tree-c root does not match comm_c"
+        );
+        files.extend(store_paths(&config, tree_count)?);
+    }
+
+    if regenerated.tree_r_last {
+        let mut config = t_aux.tree_r_last_config.clone();
+        config.path = staging_path.to_path_buf();
+        let comm_r_last = StackedDrg::<Tree, DefaultPieceHasher>::regenerate_tree_r_last(
+            config.clone(),
+            replica_path.to_path_buf(),
+        )?;
+        ensure!(
+            comm_r_last == p_aux.comm_r_last,
+            "rebuilt tree-r-last root does not match comm_r_last"
+        );
+        files.extend(store_paths(&config, tree_count)?);
+    }
+
+    Ok(files)
+}
+
+fn store_paths(config: &StoreConfig, tree_count: usize) -> Result<Vec<PathBuf>> {
+    Ok(split_config(config.clone(), tree_count)?
+        .iter()
+        .map(|config| StoreConfig::data_path(&config.path, &config.id))
+        .collect())
+}
@@ -0,0 +1,479 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Context, Result};
+use generic_array::typenum::Unsigned;
+use log::{info, warn};
+use merkletree::store::StoreConfig;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use storage_proofs::hasher::{Domain, Hasher};
+use storage_proofs::merkle::{get_base_tree_count, MerkleProofTrait, MerkleTreeTrait};
+use storage_proofs::sector::SectorId;
+use storage_proofs::util::{default_rows_to_discard, NODE_SIZE};
+
+use crate::api::post::PrivateReplicaInfo;
+use crate::types::SectorSize;
+
+/// How the leafs of each replica are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrubMode {
+    /// Check `ranges` randomly chosen leaf ranges per replica.
+    Sample { ranges: usize },
+    /// Check every leaf of every replica, resuming from the cursor if one is configured.
+    Exhaustive,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScrubConfig {
+    pub sector_size: SectorSize,
+    pub mode: ScrubMode,
+    /// Number of leafs checked per range, rounded up to whole cached segments.
+    pub range_len: usize,
+    /// Upper bound on the replica bytes read per second.
+    pub bytes_per_second: Option<u64>,
+    /// Upper bound on the replica bytes read by a single run.
+    pub max_bytes: Option<u64>,
+    /// File in which the progress of an exhaustive scrub is kept between runs.
+    pub cursor_path: Option<PathBuf>,
+}
+
+/// A range of leafs `[start, end)` of a replica that failed to verify.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorruptRange {
+    pub start: u64,
+    pub end: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorScrubReport {
+    pub leafs_checked: u64,
+    /// Whether an exhaustive scrub reached the end of the replica in this run.
+    pub completed: bool,
+    pub corrupted: Vec<CorruptRange>,
+    /// Set if the replica could not be scrubbed at all.
+    pub error: Option<String>,
+}
+
+impl SectorScrubReport {
+    pub fn is_healthy(&self) -> bool {
+        self.corrupted.is_empty() && self.error.is_none()
+    }
+
+    fn add_corrupted(&mut self, range: CorruptRange) {
+        if let Some(last) = self.corrupted.last_mut() {
+            if last.end == range.start && last.reason == range.reason {
+                last.end = range.end;
+                return;
+            }
+        }
+        self.corrupted.push(range);
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubReport {
+    pub sectors: BTreeMap<u64, SectorScrubReport>,
+    pub bytes_read: u64,
+    /// Whether the run stopped early because `max_bytes` was reached.
+    pub budget_exhausted: bool,
+}
+
+impl ScrubReport {
+    pub fn is_healthy(&self) -> bool {
+        self.sectors.values().all(SectorScrubReport::is_healthy)
+    }
+}
+
+/// The next leaf to check per sector, persisted between runs of an exhaustive scrub.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrubCursor {
+    pub next_leaf: BTreeMap<u64, u64>,
+}
+
+impl ScrubCursor {
+    /// Reads the cursor at `path`, starting from scratch if there is none yet.
+    pub fn read(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let bytes =
+            fs::read(path).with_context(|| format!("could not read scrub cursor {:?}", path))?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("could not parse scrub cursor {:?}", path))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp_path)
+                .with_context(|| format!("could not create file {:?}", tmp_path))?;
+            file.write_all(&serde_json::to_vec_pretty(self)?)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("could not rename {:?} to {:?}", tmp_path, path))?;
+
+        Ok(())
+    }
+}
+
+/// Throttles reads to a bytes per second budget.
+struct RateLimiter {
+    bytes_per_second: Option<u64>,
+    start: Instant,
+    bytes: u64,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_second: Option<u64>) -> Self {
+        RateLimiter {
+            bytes_per_second,
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    fn consume(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        if let Some(rate) = self.bytes_per_second {
+            let due = Duration::from_secs_f64(self.bytes as f64 / rate as f64);
+            let elapsed = self.start.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+    }
+}
+
+struct SectorScrubber<Tree: MerkleTreeTrait> {
+    tree: Tree,
+    replica: File,
+    comm_r_last: <Tree::Hasher as Hasher>::Domain,
+    rows_to_discard: usize,
+    /// Number of leafs covered by one cached proof.
+    segment_width: u64,
+    leafs: u64,
+}
+
+impl<Tree: 'static + MerkleTreeTrait> SectorScrubber<Tree> {
+    fn open(sector_size: SectorSize, replica: &PrivateReplicaInfo<Tree>) -> Result<Self> {
+        let tree = replica.merkle_tree(sector_size)?;
+        let comm_r_last = replica.safe_comm_r_last()?;
+        ensure!(
+            tree.root() == comm_r_last,
+            "tree-r-last root does not match comm_r_last"
+        );
+
+        let arity = Tree::Arity::to_usize();
+        let leafs = u64::from(sector_size) as usize / NODE_SIZE;
+        let base_tree_leafs = leafs / get_base_tree_count::<Tree>();
+        let rows_to_discard = replica
+            .rows_to_discard()
+            .unwrap_or_else(|| default_rows_to_discard(base_tree_leafs, arity))
+            .min(StoreConfig::default_rows_to_discard(base_tree_leafs, arity));
+
+        let replica_path = replica.replica_path();
+        let replica = File::open(replica_path)
+            .with_context(|| format!("could not open replica {:?}", replica_path))?;
+
+        Ok(SectorScrubber {
+            tree: Tree::from_merkle(tree.inner),
+            replica,
+            comm_r_last,
+            rows_to_discard,
+            segment_width: arity.pow(rows_to_discard as u32) as u64,
+            leafs: leafs as u64,
+        })
+    }
+
+    /// Rounds `len` up to whole segments.
+    fn align(&self, len: usize) -> u64 {
+        let len = (len as u64).max(1);
+        (len + self.segment_width - 1) / self.segment_width * self.segment_width
+    }
+
+    fn random_range<R: Rng>(&self, range_len: u64, rng: &mut R) -> (u64, u64) {
+        let range_len = range_len.min(self.leafs);
+        let starts = (self.leafs - range_len) / self.segment_width + 1;
+        let start = rng.gen_range(0, starts) * self.segment_width;
+        (start, start + range_len)
+    }
+
+    fn check_range(
+        &mut self,
+        start: u64,
+        end: u64,
+        limiter: &mut RateLimiter,
+        report: &mut SectorScrubReport,
+    ) -> Result<()> {
+        let mut segment_start = start;
+        while segment_start < end {
+            let segment_end = (segment_start + self.segment_width).min(self.leafs);
+            if let Err(reason) = self.check_segment(segment_start)? {
+                warn!(
+                    "scrub: leafs {}..{} are corrupted: {}",
+                    segment_start, segment_end, reason
+                );
+                report.add_corrupted(CorruptRange {
+                    start: segment_start,
+                    end: segment_end,
+                    reason,
+                });
+            }
+            limiter.consume((segment_end - segment_start) * NODE_SIZE as u64);
+            report.leafs_checked += segment_end - segment_start;
+            segment_start = segment_end;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the segment starting at leaf `i`. IO errors on the replica are returned as
+    /// errors, anything that does not verify as the reason the segment is corrupted.
+    fn check_segment(&mut self, i: u64) -> Result<std::result::Result<(), String>> {
+        let mut bytes = [0u8; NODE_SIZE];
+        self.replica.seek(SeekFrom::Start(i * NODE_SIZE as u64))?;
+        self.replica
+            .read_exact(&mut bytes)
+            .with_context(|| format!("could not read leaf {} of the replica", i))?;
+        let leaf = match <Tree::Hasher as Hasher>::Domain::try_from_bytes(&bytes) {
+            Ok(leaf) => leaf,
+            Err(_) => return Ok(Err("replica node is not a valid field element".into())),
+        };
+
+        let proof = match self
+            .tree
+            .gen_cached_proof(i as usize, Some(self.rows_to_discard))
+        {
+            Ok(proof) => proof,
+            Err(err) => return Ok(Err(format!("could not generate proof: {:#}", err))),
+        };
+
+        if proof.leaf() != leaf {
+            return Ok(Err("proof leaf does not match the replica".into()));
+        }
+        if proof.root() != self.comm_r_last {
+            return Ok(Err("proof root does not match comm_r_last".into()));
+        }
+        if !proof.validate(i as usize) || !proof.verify() {
+            return Ok(Err("merkle path does not verify".into()));
+        }
+
+        Ok(Ok(()))
+    }
+}
+
+/// Verifies the replicas against their tree-r-last, reading leaf ranges of the replica and
+/// recomputing their merkle paths from the cached rows of the tree.
+///
+/// A replica that can not be opened is reported through its `error`, corrupted leafs through
+/// `corrupted`; only failures to read a replica or the cursor fail the whole run. In
+/// `ScrubMode::Exhaustive` the cursor, if configured, is written after every range, so a run
+/// stopped by `max_bytes` or killed picks up where it left off.
+pub fn scrub_replicas<Tree: 'static + MerkleTreeTrait, R: Rng>(
+    config: &ScrubConfig,
+    replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+    rng: &mut R,
+) -> Result<ScrubReport> {
+    info!("scrub_replicas:start");
+    ensure!(config.range_len > 0, "range_len must be greater than zero");
+
+    let mut cursor = match (config.mode, &config.cursor_path) {
+        (ScrubMode::Exhaustive, Some(path)) => ScrubCursor::read(path)?,
+        _ => ScrubCursor::default(),
+    };
+    let mut limiter = RateLimiter::new(config.bytes_per_second);
+    let mut report = ScrubReport::default();
+
+    'sectors: for (sector_id, replica) in replicas {
+        let id = u64::from(*sector_id);
+        let sector_report = report.sectors.entry(id).or_default();
+
+        let mut scrubber = match SectorScrubber::open(config.sector_size, replica) {
+            Ok(scrubber) => scrubber,
+            Err(err) => {
+                warn!("scrub: could not open sector {}: {:#}", id, err);
+                sector_report.error = Some(format!("{:#}", err));
+                continue;
+            }
+        };
+        let range_len = scrubber.align(config.range_len);
+
+        let ranges: Vec<(u64, u64)> = match config.mode {
+            ScrubMode::Sample { ranges } => (0..ranges)
+                .map(|_| scrubber.random_range(range_len, rng))
+                .collect(),
+            ScrubMode::Exhaustive => {
+                let next_leaf = cursor.next_leaf.get(&id).copied().unwrap_or(0);
+                let start = if next_leaf < scrubber.leafs {
+                    next_leaf / scrubber.segment_width * scrubber.segment_width
+                } else {
+                    0
+                };
+                (start..scrubber.leafs)
+                    .step_by(range_len as usize)
+                    .map(|start| (start, (start + range_len).min(scrubber.leafs)))
+                    .collect()
+            }
+        };
+
+        for (start, end) in ranges {
+            if let Some(max_bytes) = config.max_bytes {
+                if limiter.bytes >= max_bytes {
+                    report.budget_exhausted = true;
+                    break 'sectors;
+                }
+            }
+
+            scrubber.check_range(start, end, &mut limiter, sector_report)?;
+
+            if config.mode == ScrubMode::Exhaustive {
+                if end == scrubber.leafs {
+                    sector_report.completed = true;
+                    cursor.next_leaf.remove(&id);
+                } else {
+                    cursor.next_leaf.insert(id, end);
+                }
+                if let Some(ref path) = config.cursor_path {
+                    cursor.write(path)?;
+                }
+            }
+        }
+    }
+    report.bytes_read = limiter.bytes;
+
+    info!("scrub_replicas:finish");
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bincode::serialize;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs::cache_key::CacheKey;
+    use storage_proofs::hasher::HashFunction;
+
+    use crate::constants::*;
+    use crate::types::PersistentAux;
+
+    fn create_replica(dir: &Path) -> (PathBuf, PathBuf, [u8; 32]) {
+        let cache = dir.join("cache");
+        let replica = dir.join("replica");
+        fs::create_dir_all(&cache).unwrap();
+
+        let data = vec![0u8; SECTOR_SIZE_2_KIB as usize];
+        fs::write(&replica, &data).unwrap();
+
+        let config = StoreConfig::new(
+            &cache,
+            CacheKey::CommRLastTree.to_string(),
+            default_rows_to_discard(SECTOR_SIZE_2_KIB as usize / NODE_SIZE, 8),
+        );
+        let comm_r_last = SectorShape2KiB::from_byte_slice_with_config(&data, config)
+            .unwrap()
+            .root();
+        let p_aux = PersistentAux {
+            comm_c: DefaultTreeDomain::default(),
+            comm_r_last,
+        };
+        fs::write(
+            cache.join(CacheKey::PAux.to_string()),
+            serialize(&p_aux).unwrap(),
+        )
+        .unwrap();
+
+        let comm_r = <DefaultTreeHasher as Hasher>::Function::hash2(&p_aux.comm_c, &comm_r_last);
+        let mut comm_r_bytes = [0; 32];
+        comm_r_bytes.copy_from_slice(comm_r.as_ref());
+
+        (replica, cache, comm_r_bytes)
+    }
+
+    fn config(mode: ScrubMode) -> ScrubConfig {
+        ScrubConfig {
+            sector_size: SectorSize(SECTOR_SIZE_2_KIB),
+            mode,
+            range_len: 16,
+            bytes_per_second: None,
+            max_bytes: None,
+            cursor_path: None,
+        }
+    }
+
+    #[test]
+    fn test_scrub_replicas() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let dir = tempfile::tempdir().unwrap();
+        let (replica, cache, comm_r) = create_replica(dir.path());
+
+        let mut replicas = BTreeMap::new();
+        replicas.insert(
+            SectorId::from(1),
+            PrivateReplicaInfo::<SectorShape2KiB>::new(replica.clone(), comm_r, cache).unwrap(),
+        );
+
+        let report = scrub_replicas(&config(ScrubMode::Exhaustive), &replicas, rng).unwrap();
+        assert!(report.is_healthy(), "{:?}", report);
+        assert!(report.sectors[&1].completed);
+        assert_eq!(report.sectors[&1].leafs_checked, 64);
+        assert_eq!(report.bytes_read, SECTOR_SIZE_2_KIB);
+
+        let report =
+            scrub_replicas(&config(ScrubMode::Sample { ranges: 2 }), &replicas, rng).unwrap();
+        assert!(report.is_healthy(), "{:?}", report);
+        assert_eq!(report.sectors[&1].leafs_checked, 32);
+
+        // Corrupt leaf 20, which is part of the segment of leafs 16..24.
+        let mut data = fs::read(&replica).unwrap();
+        data[20 * NODE_SIZE] = 1;
+        fs::write(&replica, &data).unwrap();
+
+        let report = scrub_replicas(&config(ScrubMode::Exhaustive), &replicas, rng).unwrap();
+        assert!(!report.is_healthy());
+        let corrupted = &report.sectors[&1].corrupted;
+        assert_eq!(corrupted.len(), 1, "{:?}", corrupted);
+        assert_eq!((corrupted[0].start, corrupted[0].end), (16, 24));
+    }
+
+    #[test]
+    fn test_scrub_replicas_resume() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let dir = tempfile::tempdir().unwrap();
+        let (replica, cache, comm_r) = create_replica(dir.path());
+
+        let mut replicas = BTreeMap::new();
+        replicas.insert(
+            SectorId::from(1),
+            PrivateReplicaInfo::<SectorShape2KiB>::new(replica, comm_r, cache).unwrap(),
+        );
+
+        let cursor_path = dir.path().join("cursor.json");
+        let mut config = config(ScrubMode::Exhaustive);
+        config.cursor_path = Some(cursor_path.clone());
+        config.max_bytes = Some(32 * NODE_SIZE as u64);
+
+        let report = scrub_replicas(&config, &replicas, rng).unwrap();
+        assert!(report.budget_exhausted);
+        assert!(!report.sectors[&1].completed);
+        assert_eq!(report.sectors[&1].leafs_checked, 32);
+        assert_eq!(ScrubCursor::read(&cursor_path).unwrap().next_leaf[&1], 32);
+
+        let report = scrub_replicas(&config, &replicas, rng).unwrap();
+        assert!(report.sectors[&1].completed);
+        assert_eq!(report.sectors[&1].leafs_checked, 32);
+        assert!(ScrubCursor::read(&cursor_path)
+            .unwrap()
+            .next_leaf
+            .is_empty());
+    }
+}
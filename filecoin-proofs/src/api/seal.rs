@@ -8,6 +8,8 @@ use log::{info, trace};
 use memmap::MmapOptions;
 use merkletree::store::{DiskStore, Store, StoreConfig};
 use paired::bls12_381::Fr;
+#[cfg(feature = "aggregation")]
+use storage_proofs::aggregate::AggregateProof;
 use storage_proofs::cache_key::CacheKey;
 use storage_proofs::compound_proof::{self, CompoundProof};
 use storage_proofs::drgraph::Graph;
@@ -19,13 +21,17 @@ use storage_proofs::porep::stacked::{
     self, generate_replica_id, ChallengeRequirements, StackedCompound, StackedDrg, Tau,
     TemporaryAux, TemporaryAuxCache,
 };
+use storage_proofs::progress::Progress;
 use storage_proofs::proof::ProofScheme;
 use storage_proofs::sector::SectorId;
+use storage_proofs::sector_metadata::SectorMetadata;
 use storage_proofs::util::default_rows_to_discard;
 
 use crate::api::util::{
     as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size,
 };
+#[cfg(feature = "aggregation")]
+use crate::caches::{get_srs_key_params, get_srs_verifier_key};
 use crate::caches::{get_stacked_params, get_stacked_verifying_key};
 use crate::constants::{
     DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher, POREP_MINIMUM_CHALLENGES,
@@ -34,10 +40,12 @@ use crate::constants::{
 use crate::parameters::setup_params;
 pub use crate::pieces;
 pub use crate::pieces::verify_pieces;
+#[cfg(feature = "aggregation")]
+use crate::types::AggregateSnarkProof;
 use crate::types::{
     Commitment, PaddedBytesAmount, PieceInfo, PoRepConfig, PoRepProofPartitions, ProverId,
-    SealCommitOutput, SealCommitPhase1Output, SealPreCommitOutput, SealPreCommitPhase1Output,
-    SectorSize, Ticket, BINARY_ARITY,
+    SealCommitOutput, SealCommitPhase1Output, SealPreCommitOutput, SealPreCommitPhase1Input,
+    SealPreCommitPhase1Output, SectorSize, Ticket, BINARY_ARITY,
 };
 
 #[allow(clippy::too_many_arguments)]
@@ -51,6 +59,38 @@ pub fn seal_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
     ticket: Ticket,
     piece_infos: &[PieceInfo],
 ) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    seal_pre_commit_phase1_with_progress(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        None,
+    )
+}
+
+/// Same as `seal_pre_commit_phase1`, but reports labeling progress to `progress`.
+/// Once `progress` is cancelled, labeling stops and `Error::Cancelled` is returned.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_with_progress<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    progress: Option<&dyn Progress>,
+) -> Result<SealPreCommitPhase1Output<Tree>>
 where
     R: AsRef<Path>,
     S: AsRef<Path>,
@@ -58,6 +98,253 @@ where
 {
     info!("seal_pre_commit_phase1:start");
 
+    let out = seal_pre_commit_phase1_inner(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        false,
+        progress,
+    )?;
+
+    info!("seal_pre_commit_phase1:finish");
+    Ok(out)
+}
+
+/// Same as `seal_pre_commit_phase1`, but continues labeling after the last layer recorded in the
+/// labels checkpoint of `cache_path`, instead of starting over. Every completed layer is checked
+/// against the digest recorded for it in the checkpoint before it is used. If no checkpoint
+/// exists, this behaves exactly like `seal_pre_commit_phase1`.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_resume<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    seal_pre_commit_phase1_resume_with_progress(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        None,
+    )
+}
+
+/// Same as `seal_pre_commit_phase1_resume`, but reports labeling progress to `progress`.
+/// Once `progress` is cancelled, labeling stops and `Error::Cancelled` is returned.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_pre_commit_phase1_resume_with_progress<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    progress: Option<&dyn Progress>,
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    info!("seal_pre_commit_phase1_resume:start");
+
+    let out = seal_pre_commit_phase1_inner(
+        porep_config,
+        cache_path,
+        in_path,
+        out_path,
+        prover_id,
+        sector_id,
+        ticket,
+        piece_infos,
+        true,
+        progress,
+    )?;
+
+    info!("seal_pre_commit_phase1_resume:finish");
+    Ok(out)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn seal_pre_commit_phase1_inner<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    piece_infos: &[PieceInfo],
+    resume: bool,
+    progress: Option<&dyn Progress>,
+) -> Result<SealPreCommitPhase1Output<Tree>>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    let public_params = pre_commit_phase1_public_params::<Tree>(porep_config)?;
+    let (config, comm_d) = prepare_pre_commit_phase1(
+        porep_config,
+        &public_params,
+        cache_path,
+        in_path,
+        out_path,
+        piece_infos,
+    )?;
+
+    let replica_id = generate_replica_id::<Tree::Hasher, _>(
+        &prover_id,
+        sector_id.into(),
+        &ticket,
+        comm_d,
+        &porep_config.porep_id,
+    );
+
+    let labels = if resume {
+        StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1_resume(
+            &public_params,
+            &replica_id,
+            config.clone(),
+            progress,
+        )?
+    } else {
+        StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1(
+            &public_params,
+            &replica_id,
+            config.clone(),
+            progress,
+        )?
+    };
+
+    Ok(SealPreCommitPhase1Output {
+        labels,
+        config,
+        comm_d,
+    })
+}
+
+/// Seals the sectors in `sectors` like `seal_pre_commit_phase1`, labeling them together.
+///
+/// All sectors share `porep_config`, so their labels are computed over the same graph in
+/// lockstep and the parents of each node are read only once for the whole batch. The output
+/// for each sector is identical to that of `seal_pre_commit_phase1`. A checkpoint is written
+/// for every sector after each layer, so if the batch fails each sector can be finished with
+/// `seal_pre_commit_phase1_resume`. Labeling progress is reported to `progress`.
+pub fn seal_pre_commit_phase1_batch<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    prover_id: ProverId,
+    sectors: &[SealPreCommitPhase1Input],
+    progress: Option<&dyn Progress>,
+) -> Result<Vec<SealPreCommitPhase1Output<Tree>>> {
+    info!("seal_pre_commit_phase1_batch:start");
+    ensure!(!sectors.is_empty(), "no sectors to seal");
+
+    let public_params = pre_commit_phase1_public_params::<Tree>(porep_config)?;
+
+    let mut configs = Vec::with_capacity(sectors.len());
+    let mut comm_ds = Vec::with_capacity(sectors.len());
+    let mut replica_ids = Vec::with_capacity(sectors.len());
+    for sector in sectors {
+        let (config, comm_d) = prepare_pre_commit_phase1(
+            porep_config,
+            &public_params,
+            &sector.cache_path,
+            &sector.in_path,
+            &sector.out_path,
+            &sector.piece_infos,
+        )?;
+
+        replica_ids.push(generate_replica_id::<Tree::Hasher, _>(
+            &prover_id,
+            sector.sector_id.into(),
+            &sector.ticket,
+            comm_d,
+            &porep_config.porep_id,
+        ));
+        configs.push(config);
+        comm_ds.push(comm_d);
+    }
+
+    let labels = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1_batch(
+        &public_params,
+        &replica_ids,
+        &configs,
+        progress,
+    )?;
+
+    let out = labels
+        .into_iter()
+        .zip(configs.into_iter())
+        .zip(comm_ds.into_iter())
+        .map(|((labels, config), comm_d)| SealPreCommitPhase1Output {
+            labels,
+            config,
+            comm_d,
+        })
+        .collect();
+
+    info!("seal_pre_commit_phase1_batch:finish");
+    Ok(out)
+}
+
+fn pre_commit_phase1_public_params<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<stacked::PublicParams<Tree>> {
+    let compound_setup_params = compound_proof::SetupParams {
+        vanilla_params: setup_params(
+            PaddedBytesAmount::from(porep_config),
+            usize::from(PoRepProofPartitions::from(porep_config)),
+            porep_config.porep_id,
+        )?,
+        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
+        priority: false,
+    };
+
+    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        StackedDrg<Tree, DefaultPieceHasher>,
+        _,
+    >>::setup(&compound_setup_params)?;
+
+    Ok(compound_public_params.vanilla_params)
+}
+
+/// Copies the unsealed data of a sector to `out_path`, where it will be sealed in place, and
+/// builds its tree-d, returning the tree-d config and comm_d.
+fn prepare_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    vanilla_params: &stacked::PublicParams<Tree>,
+    cache_path: R,
+    in_path: S,
+    out_path: T,
+    piece_infos: &[PieceInfo],
+) -> Result<(StoreConfig, Commitment)>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
     // Sanity check all input path types.
     ensure!(
         metadata(in_path.as_ref())?.is_file(),
@@ -103,27 +390,12 @@ where
             .with_context(|| format!("could not mmap out_path={:?}", out_path.as_ref().display()))?
     };
 
-    let compound_setup_params = compound_proof::SetupParams {
-        vanilla_params: setup_params(
-            PaddedBytesAmount::from(porep_config),
-            usize::from(PoRepProofPartitions::from(porep_config)),
-            porep_config.porep_id,
-        )?,
-        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
-        priority: false,
-    };
-
-    let compound_public_params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
-        StackedDrg<Tree, DefaultPieceHasher>,
-        _,
-    >>::setup(&compound_setup_params)?;
-
     info!("building merkle tree for the original data");
     let (config, comm_d) = measure_op(CommD, || -> Result<_> {
         let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
         let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
         ensure!(
-            compound_public_params.vanilla_params.graph.size() == base_tree_leafs,
+            vanilla_params.graph.size() == base_tree_leafs,
             "graph size and leaf size don't match"
         );
 
@@ -164,28 +436,7 @@ where
         "pieces and comm_d do not match"
     );
 
-    let replica_id = generate_replica_id::<Tree::Hasher, _>(
-        &prover_id,
-        sector_id.into(),
-        &ticket,
-        comm_d,
-        &porep_config.porep_id,
-    );
-
-    let labels = StackedDrg::<Tree, DefaultPieceHasher>::replicate_phase1(
-        &compound_public_params.vanilla_params,
-        &replica_id,
-        config.clone(),
-    )?;
-
-    let out = SealPreCommitPhase1Output {
-        labels,
-        config,
-        comm_d,
-    };
-
-    info!("seal_pre_commit_phase1:finish");
-    Ok(out)
+    Ok((config, comm_d))
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -195,6 +446,30 @@ pub fn seal_pre_commit_phase2<R, S, Tree: 'static + MerkleTreeTrait>(
     cache_path: S,
     replica_path: R,
 ) -> Result<SealPreCommitOutput>
+where
+    R: AsRef<Path>,
+    S: AsRef<Path>,
+{
+    seal_pre_commit_phase2_with_progress(
+        porep_config,
+        phase1_output,
+        cache_path,
+        replica_path,
+        None,
+    )
+}
+
+/// Same as `seal_pre_commit_phase2`, but reports the progress of building tree-c and
+/// tree-r-last to `progress`. Once `progress` is cancelled, `Error::Cancelled` is returned.
+/// A sector cancelled while tree-r-last is built has a partially encoded replica and
+/// must be sealed again from phase1 output and the unsealed data.
+pub fn seal_pre_commit_phase2_with_progress<R, S, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: SealPreCommitPhase1Output<Tree>,
+    cache_path: S,
+    replica_path: R,
+    progress: Option<&dyn Progress>,
+) -> Result<SealPreCommitOutput>
 where
     R: AsRef<Path>,
     S: AsRef<Path>,
@@ -284,6 +559,7 @@ where
         data_tree,
         config,
         replica_path.as_ref().to_path_buf(),
+        progress,
     )?;
 
     let comm_r = commitment_from_fr(tau.comm_r.into());
@@ -305,6 +581,16 @@ where
         .write_all(&t_aux_bytes)
         .with_context(|| format!("could not write to file t_aux={:?}", t_aux_path))?;
 
+    // Record how the trees were laid out, so the sector stays readable if the
+    // configured defaults change later on.
+    SectorMetadata::new::<Tree>(
+        u64::from(porep_config.sector_size),
+        porep_config.porep_id,
+        t_aux.tree_d_config.rows_to_discard,
+        t_aux.tree_r_last_config.rows_to_discard,
+    )
+    .write(cache_path.as_ref())?;
+
     let out = SealPreCommitOutput { comm_r, comm_d };
 
     info!("seal_pre_commit_phase2:finish");
@@ -322,6 +608,35 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
     seed: Ticket,
     pre_commit: SealPreCommitOutput,
     piece_infos: &[PieceInfo],
+) -> Result<SealCommitPhase1Output<Tree>> {
+    seal_commit_phase1_with_progress(
+        porep_config,
+        cache_path,
+        replica_path,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit,
+        piece_infos,
+        None,
+    )
+}
+
+/// Same as `seal_commit_phase1`, but reports the proven partitions to `progress`.
+/// Once `progress` is cancelled, `Error::Cancelled` is returned.
+#[allow(clippy::too_many_arguments)]
+pub fn seal_commit_phase1_with_progress<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: T,
+    replica_path: T,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+    pre_commit: SealPreCommitOutput,
+    piece_infos: &[PieceInfo],
+    progress: Option<&dyn Progress>,
 ) -> Result<SealCommitPhase1Output<Tree>> {
     info!("seal_commit_phase1:start");
 
@@ -411,11 +726,12 @@ pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
         _,
     >>::setup(&compound_setup_params)?;
 
-    let vanilla_proofs = StackedDrg::prove_all_partitions(
+    let vanilla_proofs = StackedDrg::prove_all_partitions_with_progress(
         &compound_public_params.vanilla_params,
         &public_inputs,
         &private_inputs,
         StackedCompound::partition_count(&compound_public_params),
+        progress,
     )?;
 
     let sanity_check = StackedDrg::<Tree, DefaultPieceHasher>::verify_all_partitions(
@@ -444,6 +760,18 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     phase1_output: SealCommitPhase1Output<Tree>,
     prover_id: ProverId,
     sector_id: SectorId,
+) -> Result<SealCommitOutput> {
+    seal_commit_phase2_with_progress(porep_config, phase1_output, prover_id, sector_id, None)
+}
+
+/// Same as `seal_commit_phase2`, but reports circuit proof progress to `progress`.
+/// Once `progress` is cancelled, `Error::Cancelled` is returned.
+pub fn seal_commit_phase2_with_progress<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: SealCommitPhase1Output<Tree>,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    progress: Option<&dyn Progress>,
 ) -> Result<SealCommitOutput> {
     info!("seal_commit_phase2:start");
 
@@ -495,12 +823,13 @@ pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
     >>::setup(&compound_setup_params)?;
 
     info!("snark_proof:start");
-    let groth_proofs = StackedCompound::<Tree, DefaultPieceHasher>::circuit_proofs(
+    let groth_proofs = StackedCompound::<Tree, DefaultPieceHasher>::circuit_proofs_with_progress(
         &public_inputs,
         vanilla_proofs,
         &compound_public_params.vanilla_params,
         &groth_params,
         compound_public_params.priority,
+        progress,
     )?;
     info!("snark_proof:finish");
 
@@ -664,15 +993,217 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
     proof_vecs: &[&[u8]],
 ) -> Result<bool> {
     info!("verify_batch_seal:start");
+    ensure!(comm_r_ins.len() == proof_vecs.len(), "Inconsistent inputs");
+
+    let public_inputs = seal_public_inputs::<Tree>(
+        porep_config,
+        comm_r_ins,
+        comm_d_ins,
+        prover_ids,
+        sector_ids,
+        tickets,
+        seeds,
+    )?;
+
+    let sector_bytes = PaddedBytesAmount::from(porep_config);
+
+    let verifying_key = get_stacked_verifying_key::<Tree>(porep_config)?;
+    info!(
+        "got verifying key ({}) while verifying seal",
+        u64::from(sector_bytes)
+    );
+
+    let compound_public_params = seal_compound_public_params::<Tree>(porep_config)?;
+
+    let proofs = proof_vecs
+        .iter()
+        .map(|proof_vec| {
+            MultiProof::new_from_reader(
+                Some(usize::from(PoRepProofPartitions::from(porep_config))),
+                *proof_vec,
+                &verifying_key,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let result = StackedCompound::<Tree, DefaultPieceHasher>::batch_verify(
+        &compound_public_params,
+        &public_inputs,
+        &proofs,
+        &seal_challenge_requirements(porep_config),
+    )
+    .map_err(Into::into);
+
+    info!("verify_batch_seal:finish");
+    result
+}
+
+/// Aggregates the seal commit proofs of many sectors, sealed with the same `porep_config`, into
+/// a single proof whose size is logarithmic in the number of sectors.
+///
+/// # Arguments
+///
+/// * `porep_config` - the porep config that contains the number of bytes in the sectors.
+/// * `[comm_r_ins]` - list of commitments to the sector's replica (`comm_r`).
+/// * `[comm_d_ins]` - list of commitments to the sector's data (`comm_d`).
+/// * `[prover_ids]` - list of prover-ids that sealed this sector.
+/// * `[sector_ids]` - list of the sector's sector-id.
+/// * `[tickets]` - list of tickets that was used to generate this sector's replica-id.
+/// * `[seeds]` - list of seeds used to derive the porep challenges.
+/// * `[commit_outputs]` - list of the sector's seal commit proofs.
+#[cfg(feature = "aggregation")]
+#[allow(clippy::too_many_arguments)]
+pub fn aggregate_seal_commit_proofs<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_ins: &[Commitment],
+    comm_d_ins: &[Commitment],
+    prover_ids: &[ProverId],
+    sector_ids: &[SectorId],
+    tickets: &[Ticket],
+    seeds: &[Ticket],
+    commit_outputs: &[SealCommitOutput],
+) -> Result<AggregateSnarkProof> {
+    info!("aggregate_seal_commit_proofs:start");
+    ensure!(
+        comm_r_ins.len() == commit_outputs.len(),
+        "Inconsistent inputs"
+    );
+
+    let public_inputs = seal_public_inputs::<Tree>(
+        porep_config,
+        comm_r_ins,
+        comm_d_ins,
+        prover_ids,
+        sector_ids,
+        tickets,
+        seeds,
+    )?;
+
+    let verifying_key = get_stacked_verifying_key::<Tree>(porep_config)?;
+    let srs = get_srs_key_params()?;
+    info!(
+        "got verifying key and srs ({}) while aggregating",
+        u64::from(PaddedBytesAmount::from(porep_config))
+    );
+
+    let compound_public_params = seal_compound_public_params::<Tree>(porep_config)?;
+
+    let proofs = commit_outputs
+        .iter()
+        .map(|commit_output| {
+            MultiProof::new_from_reader(
+                Some(usize::from(PoRepProofPartitions::from(porep_config))),
+                &commit_output.proof[..],
+                &verifying_key,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let aggregate_proof = StackedCompound::<Tree, DefaultPieceHasher>::aggregate_proofs(
+        &compound_public_params,
+        &public_inputs,
+        &proofs,
+        &srs,
+    )?;
+    let proof_bytes = aggregate_proof.to_vec();
+
+    // It is never correct to return a proof which does not verify.
+    let valid = verify_aggregate_seal::<Tree>(
+        porep_config,
+        comm_r_ins,
+        comm_d_ins,
+        prover_ids,
+        sector_ids,
+        tickets,
+        seeds,
+        &proof_bytes,
+    )
+    .context("post-aggregation verification sanity check failed")?;
+    ensure!(valid, "post-aggregation verification sanity check failed");
+
+    info!("aggregate_seal_commit_proofs:finish");
+    Ok(proof_bytes)
+}
+
+/// Verifies a proof created by `aggregate_seal_commit_proofs`.
+///
+/// # Arguments
+///
+/// * `porep_config` - the porep config that contains the number of bytes in the sectors.
+/// * `[comm_r_ins]` - list of commitments to the sector's replica (`comm_r`).
+/// * `[comm_d_ins]` - list of commitments to the sector's data (`comm_d`).
+/// * `[prover_ids]` - list of prover-ids that sealed this sector.
+/// * `[sector_ids]` - list of the sector's sector-id.
+/// * `[tickets]` - list of tickets that was used to generate this sector's replica-id.
+/// * `[seeds]` - list of seeds used to derive the porep challenges.
+/// * `aggregate_proof` - the aggregate proof serialized into a vector of bytes.
+#[cfg(feature = "aggregation")]
+#[allow(clippy::too_many_arguments)]
+pub fn verify_aggregate_seal<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_ins: &[Commitment],
+    comm_d_ins: &[Commitment],
+    prover_ids: &[ProverId],
+    sector_ids: &[SectorId],
+    tickets: &[Ticket],
+    seeds: &[Ticket],
+    aggregate_proof: &[u8],
+) -> Result<bool> {
+    info!("verify_aggregate_seal:start");
+
+    let public_inputs = seal_public_inputs::<Tree>(
+        porep_config,
+        comm_r_ins,
+        comm_d_ins,
+        prover_ids,
+        sector_ids,
+        tickets,
+        seeds,
+    )?;
+
+    let verifying_key = get_stacked_verifying_key::<Tree>(porep_config)?;
+    let srs = get_srs_verifier_key()?;
+    info!(
+        "got verifying key and srs ({}) while verifying aggregate seal",
+        u64::from(PaddedBytesAmount::from(porep_config))
+    );
+
+    let compound_public_params = seal_compound_public_params::<Tree>(porep_config)?;
+
+    let proof = AggregateProof::read(aggregate_proof)?;
+
+    let result = StackedCompound::<Tree, DefaultPieceHasher>::verify_aggregate_proofs(
+        &compound_public_params,
+        &public_inputs,
+        &verifying_key,
+        &srs,
+        &proof,
+        &seal_challenge_requirements(porep_config),
+    )
+    .map_err(Into::into);
+
+    info!("verify_aggregate_seal:finish");
+    result
+}
+
+/// Checks the inputs of a batch of sectors and derives their public inputs.
+#[allow(clippy::too_many_arguments)]
+fn seal_public_inputs<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_ins: &[Commitment],
+    comm_d_ins: &[Commitment],
+    prover_ids: &[ProverId],
+    sector_ids: &[SectorId],
+    tickets: &[Ticket],
+    seeds: &[Ticket],
+) -> Result<Vec<stacked::PublicInputs<<Tree::Hasher as Hasher>::Domain, DefaultPieceDomain>>> {
     ensure!(!comm_r_ins.is_empty(), "Cannot prove empty batch");
     let l = comm_r_ins.len();
     ensure!(l == comm_d_ins.len(), "Inconsistent inputs");
     ensure!(l == prover_ids.len(), "Inconsistent inputs");
-    ensure!(l == prover_ids.len(), "Inconsistent inputs");
     ensure!(l == sector_ids.len(), "Inconsistent inputs");
     ensure!(l == tickets.len(), "Inconsistent inputs");
     ensure!(l == seeds.len(), "Inconsistent inputs");
-    ensure!(l == proof_vecs.len(), "Inconsistent inputs");
 
     for comm_d_in in comm_d_ins {
         ensure!(
@@ -687,14 +1218,32 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
         );
     }
 
-    let sector_bytes = PaddedBytesAmount::from(porep_config);
-
-    let verifying_key = get_stacked_verifying_key::<Tree>(porep_config)?;
-    info!(
-        "got verifying key ({}) while verifying seal",
-        u64::from(sector_bytes)
-    );
+    (0..l)
+        .map(|i| {
+            let comm_r = as_safe_commitment(&comm_r_ins[i], "comm_r")?;
+            let comm_d = as_safe_commitment(&comm_d_ins[i], "comm_d")?;
+
+            let replica_id = generate_replica_id::<Tree::Hasher, _>(
+                &prover_ids[i],
+                sector_ids[i].into(),
+                &tickets[i],
+                comm_d,
+                &porep_config.porep_id,
+            );
+
+            Ok(stacked::PublicInputs {
+                replica_id,
+                tau: Some(Tau { comm_r, comm_d }),
+                seed: seeds[i],
+                k: None,
+            })
+        })
+        .collect()
+}
 
+fn seal_compound_public_params<'a, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<compound_proof::PublicParams<'a, StackedDrg<'a, Tree, DefaultPieceHasher>>> {
     let compound_setup_params = compound_proof::SetupParams {
         vanilla_params: setup_params(
             PaddedBytesAmount::from(porep_config),
@@ -705,58 +1254,17 @@ pub fn verify_batch_seal<Tree: 'static + MerkleTreeTrait>(
         priority: false,
     };
 
-    let compound_public_params: compound_proof::PublicParams<
-        '_,
-        StackedDrg<'_, Tree, DefaultPieceHasher>,
-    > = StackedCompound::setup(&compound_setup_params)?;
-
-    let mut public_inputs = Vec::with_capacity(l);
-    let mut proofs = Vec::with_capacity(l);
-
-    for i in 0..l {
-        let comm_r = as_safe_commitment(&comm_r_ins[i], "comm_r")?;
-        let comm_d = as_safe_commitment(&comm_d_ins[i], "comm_d")?;
-
-        let replica_id = generate_replica_id::<Tree::Hasher, _>(
-            &prover_ids[i],
-            sector_ids[i].into(),
-            &tickets[i],
-            comm_d,
-            &porep_config.porep_id,
-        );
+    StackedCompound::setup(&compound_setup_params)
+}
 
-        public_inputs.push(stacked::PublicInputs::<
-            <Tree::Hasher as Hasher>::Domain,
-            DefaultPieceDomain,
-        > {
-            replica_id,
-            tau: Some(Tau { comm_r, comm_d }),
-            seed: seeds[i],
-            k: None,
-        });
-        proofs.push(MultiProof::new_from_reader(
-            Some(usize::from(PoRepProofPartitions::from(porep_config))),
-            proof_vecs[i],
-            &verifying_key,
-        )?);
+fn seal_challenge_requirements(porep_config: PoRepConfig) -> ChallengeRequirements {
+    ChallengeRequirements {
+        minimum_challenges: *POREP_MINIMUM_CHALLENGES
+            .read()
+            .unwrap()
+            .get(&u64::from(SectorSize::from(porep_config)))
+            .expect("unknown sector size") as usize,
     }
-
-    let result = StackedCompound::<Tree, DefaultPieceHasher>::batch_verify(
-        &compound_public_params,
-        &public_inputs,
-        &proofs,
-        &ChallengeRequirements {
-            minimum_challenges: *POREP_MINIMUM_CHALLENGES
-                .read()
-                .unwrap()
-                .get(&u64::from(SectorSize::from(porep_config)))
-                .expect("unknown sector size") as usize,
-        },
-    )
-    .map_err(Into::into);
-
-    info!("verify_batch_seal:finish");
-    result
 }
 
 pub fn fauxrep<R: AsRef<Path>, S: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
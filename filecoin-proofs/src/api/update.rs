@@ -0,0 +1,593 @@
+use std::fs::{self, metadata, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use bincode::{deserialize, serialize};
+use log::{info, trace};
+use memmap::MmapOptions;
+use merkletree::store::{DiskStore, StoreConfig};
+use storage_proofs::cache_key::CacheKey;
+use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::hasher::{HashFunction, Hasher};
+use storage_proofs::merkle::{
+    create_base_merkle_tree, create_lc_tree, get_base_tree_count, split_config_and_replica,
+    BinaryMerkleTree, LCTree, MerkleTreeTrait,
+};
+use storage_proofs::multi_proof::MultiProof;
+use storage_proofs::porep::stacked::PersistentAux;
+use storage_proofs::porep::update::{self, ReplicaUpdate, ReplicaUpdateCompound};
+use storage_proofs::proof::{NoRequirements, ProofScheme};
+use storage_proofs::sector_metadata::SectorMetadata;
+use storage_proofs::util::default_rows_to_discard;
+use typenum::Unsigned;
+
+use crate::api::util::{
+    as_safe_commitment, commitment_from_fr, get_base_tree_leafs, get_base_tree_size,
+};
+use crate::caches::{get_empty_sector_update_params, get_empty_sector_update_verifying_key};
+use crate::constants::{
+    DefaultBinaryTree, DefaultPieceDomain, DefaultPieceHasher, SINGLE_PARTITION_PROOF_LEN,
+};
+use crate::fr32::write_unpadded;
+use crate::parameters::{update_public_params, update_setup_params};
+use crate::pieces::verify_pieces;
+use crate::types::{
+    Commitment, EmptySectorUpdateEncoded, EmptySectorUpdateProof, PaddedBytesAmount, PieceInfo,
+    PoRepConfig, PoRepProofPartitions, UnpaddedBytesAmount, VanillaUpdateProof, BINARY_ARITY,
+};
+
+/// Number of padded bytes of a replica decoded at once by `decode_from`.
+const DECODE_CHUNK_BYTES: usize = 128 * 32 * 1024;
+
+type TreeRLast<Tree> = LCTree<
+    <Tree as MerkleTreeTrait>::Hasher,
+    <Tree as MerkleTreeTrait>::Arity,
+    <Tree as MerkleTreeTrait>::SubTreeArity,
+    <Tree as MerkleTreeTrait>::TopTreeArity,
+>;
+
+/// Encodes the data of a staged sector into an already sealed, empty sector.
+///
+/// The replica of the sealed sector (the sector key) and its cache are left untouched, the
+/// updated replica is written to `new_replica_path` and its tree-d, tree-r-last and p_aux
+/// to `new_cache_path`.
+///
+/// The sector key is the replica of the sealed empty sector rather than the last layer of
+/// labels from its cache. As the data of an empty sector is all zeros and sealing adds the
+/// labels to the data, that replica is exactly the last layer of labels, which is usually
+/// removed from the cache once the sector is sealed. Its tree-r-last, which is kept, is
+/// committed to by `comm_r_old`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the number of bytes in this sector.
+/// * `new_replica_path` - path of the updated replica, which is created if needed.
+/// * `new_cache_path` - directory in which the merkle trees of the updated replica are written.
+/// * `sector_key_path` - path of the replica of the sealed empty sector.
+/// * `sector_key_cache_path` - cache directory of the sealed empty sector.
+/// * `staged_data_path` - path of the staged sector containing the new data.
+/// * `piece_infos` - the piece info (commitment and byte length) for each piece in the new data.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_into<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    new_replica_path: &Path,
+    new_cache_path: &Path,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    staged_data_path: &Path,
+    piece_infos: &[PieceInfo],
+) -> Result<EmptySectorUpdateEncoded> {
+    info!("encode_into:start");
+
+    // Sanity check all input path types.
+    ensure!(
+        metadata(new_cache_path)?.is_dir(),
+        "new_cache_path must be a directory"
+    );
+    ensure!(
+        metadata(sector_key_path)?.is_file(),
+        "sector_key_path must be a file"
+    );
+    ensure!(
+        metadata(sector_key_cache_path)?.is_dir(),
+        "sector_key_cache_path must be a directory"
+    );
+    ensure!(
+        metadata(staged_data_path)?.is_file(),
+        "staged_data_path must be a file"
+    );
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    let p_aux = read_p_aux::<Tree>(sector_key_cache_path)?;
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&p_aux.comm_c, &p_aux.comm_r_last);
+
+    // Copy the new data to the output location, where it will be encoded in place.
+    fs::copy(staged_data_path, new_replica_path).with_context(|| {
+        format!(
+            "could not copy staged_data_path={:?} to new_replica_path={:?}",
+            staged_data_path.display(),
+            new_replica_path.display()
+        )
+    })?;
+
+    let f_data = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(new_replica_path)
+        .with_context(|| format!("could not open new_replica_path={:?}", new_replica_path))?;
+
+    // Zero-pad the data to the requested size by extending the underlying file if needed.
+    f_data.set_len(sector_bytes as u64)?;
+
+    let mut data = unsafe {
+        MmapOptions::new()
+            .map_mut(&f_data)
+            .with_context(|| format!("could not mmap new_replica_path={:?}", new_replica_path))?
+    };
+
+    info!("building merkle tree for the new data");
+    let comm_d_new = {
+        let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+        let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+
+        // MT for the new data is always named tree-d, as for sealing.
+        let config = StoreConfig::new(
+            new_cache_path,
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+        );
+        let data_tree = create_base_merkle_tree::<BinaryMerkleTree<DefaultPieceHasher>>(
+            Some(config),
+            base_tree_leafs,
+            &data,
+        )?;
+
+        data_tree.root()
+    };
+
+    info!("verifying pieces");
+    ensure!(
+        verify_pieces(
+            &commitment_from_fr(comm_d_new.into()),
+            piece_infos,
+            porep_config.into()
+        )?,
+        "pieces and comm_d do not match"
+    );
+
+    let f_sector_key = File::open(sector_key_path)
+        .with_context(|| format!("could not open sector_key_path={:?}", sector_key_path))?;
+    let sector_key = unsafe {
+        MmapOptions::new()
+            .map(&f_sector_key)
+            .with_context(|| format!("could not mmap sector_key_path={:?}", sector_key_path))?
+    };
+    ensure!(
+        sector_key.len() == sector_bytes,
+        "sector key has the wrong size: {} != {}",
+        sector_key.len(),
+        sector_bytes
+    );
+
+    info!("encoding the new data");
+    let rho = ReplicaUpdate::<Tree, DefaultPieceHasher>::rho(&comm_r_old, &comm_d_new);
+    ReplicaUpdate::<Tree, DefaultPieceHasher>::encode(&sector_key, &mut data, &rho)?;
+    data.flush()?;
+
+    info!("building tree_r_last for the updated replica");
+    let tree_r_last = ReplicaUpdate::<Tree, DefaultPieceHasher>::generate_tree_r_last(
+        &data,
+        tree_r_last_config::<Tree>(porep_config, new_cache_path)?,
+        new_replica_path.to_path_buf(),
+    )?;
+
+    // The columns of the sector are unchanged, only comm_r_last is replaced.
+    let p_aux = PersistentAux {
+        comm_c: p_aux.comm_c,
+        comm_r_last: tree_r_last.root(),
+    };
+    let comm_r_new = <Tree::Hasher as Hasher>::Function::hash2(&p_aux.comm_c, &p_aux.comm_r_last);
+
+    let p_aux_path = new_cache_path.join(CacheKey::PAux.to_string());
+    let mut f_p_aux = File::create(&p_aux_path)
+        .with_context(|| format!("could not create file p_aux={:?}", p_aux_path))?;
+    f_p_aux
+        .write_all(&serialize(&p_aux)?)
+        .with_context(|| format!("could not write to file p_aux={:?}", p_aux_path))?;
+
+    let out = EmptySectorUpdateEncoded {
+        comm_r_new: commitment_from_fr(comm_r_new.into()),
+        comm_d_new: commitment_from_fr(comm_d_new.into()),
+    };
+
+    info!("encode_into:finish");
+    Ok(out)
+}
+
+/// Generates the vanilla proofs, one per partition, that the replica at `new_replica_path` is
+/// the encoding of the data committed to by `comm_d_new` into the sector key.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_empty_sector_update_vanilla_proofs<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    new_replica_path: &Path,
+    new_cache_path: &Path,
+) -> Result<Vec<VanillaUpdateProof<Tree>>> {
+    info!("generate_empty_sector_update_vanilla_proofs:start");
+
+    let p_aux = read_p_aux::<Tree>(sector_key_cache_path)?;
+    let (tree_r_last_old, tree_r_last_old_rows_to_discard) =
+        open_tree_r_last::<Tree>(porep_config, sector_key_cache_path, sector_key_path)?;
+    let (tree_r_last_new, tree_r_last_new_rows_to_discard) =
+        open_tree_r_last::<Tree>(porep_config, new_cache_path, new_replica_path)?;
+    let tree_d = open_tree_d(porep_config, new_cache_path)?;
+
+    let public_inputs = update_public_inputs::<Tree>(comm_r_old, comm_r_new, comm_d_new)?;
+    let private_inputs = update::PrivateInputs::<Tree, DefaultPieceHasher> {
+        comm_c: p_aux.comm_c,
+        tree_d: &tree_d,
+        tree_r_last_old: &tree_r_last_old,
+        tree_r_last_old_rows_to_discard,
+        tree_r_last_new: &tree_r_last_new,
+        tree_r_last_new_rows_to_discard,
+    };
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    let pub_params =
+        update_public_params::<Tree>(PaddedBytesAmount::from(porep_config), partitions)?;
+    let vanilla_proofs = ReplicaUpdate::<Tree, DefaultPieceHasher>::prove_all_partitions(
+        &pub_params,
+        &public_inputs,
+        &private_inputs,
+        partitions,
+    )?;
+
+    let sanity_check = ReplicaUpdate::<Tree, DefaultPieceHasher>::verify_all_partitions(
+        &pub_params,
+        &public_inputs,
+        &vanilla_proofs,
+    )?;
+    ensure!(sanity_check, "Invalid vanilla proof generated");
+
+    info!("generate_empty_sector_update_vanilla_proofs:finish");
+    Ok(vanilla_proofs)
+}
+
+/// Generates the SNARK proof of a replica update from the vanilla proofs.
+pub fn generate_empty_sector_update_proof_with_vanilla<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    vanilla_proofs: Vec<VanillaUpdateProof<Tree>>,
+) -> Result<EmptySectorUpdateProof> {
+    info!("generate_empty_sector_update_proof_with_vanilla:start");
+
+    let partitions = usize::from(PoRepProofPartitions::from(porep_config));
+    ensure!(
+        vanilla_proofs.len() == partitions,
+        "invalid number of vanilla proofs: {} != {}",
+        vanilla_proofs.len(),
+        partitions
+    );
+
+    let public_inputs = update_public_inputs::<Tree>(comm_r_old, comm_r_new, comm_d_new)?;
+    let compound_public_params = update_compound_public_params::<Tree>(porep_config)?;
+    let groth_params = get_empty_sector_update_params::<Tree>(porep_config)?;
+
+    info!("snark_proof:start");
+    let groth_proofs = ReplicaUpdateCompound::<Tree, DefaultPieceHasher>::circuit_proofs(
+        &public_inputs,
+        vanilla_proofs,
+        &compound_public_params.vanilla_params,
+        &groth_params,
+        compound_public_params.priority,
+    )?;
+    info!("snark_proof:finish");
+
+    let proof = MultiProof::new(groth_proofs, &groth_params.vk);
+
+    let mut buf = Vec::with_capacity(SINGLE_PARTITION_PROOF_LEN * partitions);
+    proof.write(&mut buf)?;
+
+    // Verification is cheap when parameters are cached,
+    // and it is never correct to return a proof which does not verify.
+    ensure!(
+        verify_empty_sector_update_proof::<Tree>(
+            porep_config,
+            &buf,
+            comm_r_old,
+            comm_r_new,
+            comm_d_new,
+        )
+        .context("post-update verification sanity check failed")?,
+        "post-update verification sanity check failed"
+    );
+
+    info!("generate_empty_sector_update_proof_with_vanilla:finish");
+    Ok(EmptySectorUpdateProof { proof: buf })
+}
+
+/// Generates the SNARK proof that the replica at `new_replica_path` is the encoding of the
+/// data committed to by `comm_d_new` into the sector key.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    new_replica_path: &Path,
+    new_cache_path: &Path,
+) -> Result<EmptySectorUpdateProof> {
+    let vanilla_proofs = generate_empty_sector_update_vanilla_proofs::<Tree>(
+        porep_config,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+        sector_key_path,
+        sector_key_cache_path,
+        new_replica_path,
+        new_cache_path,
+    )?;
+
+    generate_empty_sector_update_proof_with_vanilla::<Tree>(
+        porep_config,
+        comm_r_old,
+        comm_r_new,
+        comm_d_new,
+        vanilla_proofs,
+    )
+}
+
+/// Verifies the proof of a replica update.
+///
+/// # Arguments
+///
+/// * `porep_config` - this sector's porep config that contains the number of bytes in this sector.
+/// * `proof_vec` - the update circuit proof serialized into a vector of bytes.
+/// * `comm_r_old` - commitment to the replica of the sealed empty sector.
+/// * `comm_r_new` - commitment to the updated replica.
+/// * `comm_d_new` - commitment to the new data.
+pub fn verify_empty_sector_update_proof<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    proof_vec: &[u8],
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<bool> {
+    info!("verify_empty_sector_update_proof:start");
+
+    let public_inputs = update_public_inputs::<Tree>(comm_r_old, comm_r_new, comm_d_new)?;
+    let compound_public_params = update_compound_public_params::<Tree>(porep_config)?;
+    let verifying_key = get_empty_sector_update_verifying_key::<Tree>(porep_config)?;
+
+    let proof = MultiProof::new_from_reader(
+        Some(usize::from(PoRepProofPartitions::from(porep_config))),
+        proof_vec,
+        &verifying_key,
+    )?;
+
+    let result = ReplicaUpdateCompound::verify(
+        &compound_public_params,
+        &public_inputs,
+        &proof,
+        &NoRequirements,
+    )
+    .map_err(Into::into);
+
+    info!("verify_empty_sector_update_proof:finish");
+    result
+}
+
+/// Recovers the new data of an updated replica, given the sector key it was encoded into, and
+/// writes it unpadded to `out_data_path`.
+///
+/// # Arguments
+///
+/// * `porep_config` - porep configuration containing the sector size.
+/// * `out_data_path` - path to a file that the unpadded data is written to.
+/// * `replica_path` - path of the updated replica.
+/// * `sector_key_path` - path of the replica of the sealed empty sector.
+/// * `sector_key_cache_path` - cache directory of the sealed empty sector.
+/// * `comm_d_new` - commitment to the new data.
+pub fn decode_from<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    out_data_path: &Path,
+    replica_path: &Path,
+    sector_key_path: &Path,
+    sector_key_cache_path: &Path,
+    comm_d_new: Commitment,
+) -> Result<UnpaddedBytesAmount> {
+    info!("decode_from:start");
+
+    let sector_bytes = usize::from(PaddedBytesAmount::from(porep_config));
+    let p_aux = read_p_aux::<Tree>(sector_key_cache_path)?;
+    let comm_r_old = <Tree::Hasher as Hasher>::Function::hash2(&p_aux.comm_c, &p_aux.comm_r_last);
+    let comm_d_new: DefaultPieceDomain = as_safe_commitment(&comm_d_new, "comm_d_new")?;
+
+    let f_replica = File::open(replica_path)
+        .with_context(|| format!("could not open replica_path={:?}", replica_path))?;
+    let replica = unsafe {
+        MmapOptions::new()
+            .map(&f_replica)
+            .with_context(|| format!("could not mmap replica_path={:?}", replica_path))?
+    };
+    ensure!(
+        replica.len() == sector_bytes,
+        "replica has the wrong size: {} != {}",
+        replica.len(),
+        sector_bytes
+    );
+
+    let f_sector_key = File::open(sector_key_path)
+        .with_context(|| format!("could not open sector_key_path={:?}", sector_key_path))?;
+    let sector_key = unsafe {
+        MmapOptions::new()
+            .map(&f_sector_key)
+            .with_context(|| format!("could not mmap sector_key_path={:?}", sector_key_path))?
+    };
+    ensure!(
+        sector_key.len() == sector_bytes,
+        "sector key has the wrong size: {} != {}",
+        sector_key.len(),
+        sector_bytes
+    );
+
+    let rho = ReplicaUpdate::<Tree, DefaultPieceHasher>::rho(&comm_r_old, &comm_d_new);
+
+    let f_out = File::create(out_data_path)
+        .with_context(|| format!("could not create out_data_path={:?}", out_data_path))?;
+    let mut buf_writer = BufWriter::new(f_out);
+
+    // Decode the replica a chunk at a time, so that only the chunk being decoded is held in
+    // memory. Chunks are a multiple of 128 padded bytes, so each one unpads on its own.
+    let mut written = 0;
+    let mut chunk = vec![0; DECODE_CHUNK_BYTES.min(sector_bytes)];
+    for (replica_chunk, key_chunk) in replica
+        .chunks(DECODE_CHUNK_BYTES)
+        .zip(sector_key.chunks(DECODE_CHUNK_BYTES))
+    {
+        let chunk = &mut chunk[..replica_chunk.len()];
+        chunk.copy_from_slice(replica_chunk);
+        ReplicaUpdate::<Tree, DefaultPieceHasher>::decode(key_chunk, chunk, &rho)?;
+
+        let unpadded_len = usize::from(UnpaddedBytesAmount::from(PaddedBytesAmount(
+            chunk.len() as u64
+        )));
+        written += write_unpadded(chunk, &mut buf_writer, 0, unpadded_len)
+            .context("write_unpadded failed")?;
+    }
+    buf_writer.flush()?;
+
+    info!("decode_from:finish");
+    Ok(UnpaddedBytesAmount(written as u64))
+}
+
+fn update_compound_public_params<'a, Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<compound_proof::PublicParams<'a, ReplicaUpdate<'a, Tree, DefaultPieceHasher>>> {
+    let compound_setup_params = compound_proof::SetupParams {
+        vanilla_params: update_setup_params(
+            PaddedBytesAmount::from(porep_config),
+            usize::from(PoRepProofPartitions::from(porep_config)),
+        )?,
+        partitions: Some(usize::from(PoRepProofPartitions::from(porep_config))),
+        priority: false,
+    };
+
+    <ReplicaUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        ReplicaUpdate<Tree, DefaultPieceHasher>,
+        _,
+    >>::setup(&compound_setup_params)
+}
+
+fn update_public_inputs<Tree: 'static + MerkleTreeTrait>(
+    comm_r_old: Commitment,
+    comm_r_new: Commitment,
+    comm_d_new: Commitment,
+) -> Result<update::PublicInputs<<Tree::Hasher as Hasher>::Domain, DefaultPieceDomain>> {
+    ensure!(
+        comm_r_old != [0; 32],
+        "Invalid all zero commitment (comm_r_old)"
+    );
+    ensure!(
+        comm_r_new != [0; 32],
+        "Invalid all zero commitment (comm_r_new)"
+    );
+    ensure!(
+        comm_d_new != [0; 32],
+        "Invalid all zero commitment (comm_d_new)"
+    );
+
+    Ok(update::PublicInputs {
+        comm_r_old: as_safe_commitment(&comm_r_old, "comm_r_old")?,
+        comm_d_new: as_safe_commitment(&comm_d_new, "comm_d_new")?,
+        comm_r_new: as_safe_commitment(&comm_r_new, "comm_r_new")?,
+        k: None,
+    })
+}
+
+fn read_p_aux<Tree: MerkleTreeTrait>(
+    cache_path: &Path,
+) -> Result<PersistentAux<<Tree::Hasher as Hasher>::Domain>> {
+    let p_aux_path = cache_path.join(CacheKey::PAux.to_string());
+    let p_aux_bytes = fs::read(&p_aux_path)
+        .with_context(|| format!("could not read file p_aux={:?}", p_aux_path))?;
+
+    deserialize(&p_aux_bytes).map_err(Into::into)
+}
+
+fn tree_r_last_config<Tree: MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+) -> Result<StoreConfig> {
+    let base_tree_size = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let rows_to_discard = match SectorMetadata::read(cache_path)? {
+        Some(metadata) => {
+            metadata.ensure_shape::<Tree>()?;
+            metadata.tree_r_last_rows_to_discard
+        }
+        None => default_rows_to_discard(base_tree_leafs, Tree::Arity::to_usize()),
+    };
+
+    let mut config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommRLastTree.to_string(),
+        rows_to_discard,
+    );
+    config.size = Some(base_tree_size);
+
+    Ok(config)
+}
+
+fn open_tree_r_last<Tree: MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+    replica_path: &Path,
+) -> Result<(TreeRLast<Tree>, usize)> {
+    let config = tree_r_last_config::<Tree>(porep_config, cache_path)?;
+    let base_tree_size = get_base_tree_size::<Tree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<Tree>(base_tree_size)?;
+    let rows_to_discard = config.rows_to_discard;
+
+    let (configs, replica_config) = split_config_and_replica(
+        config,
+        replica_path.to_path_buf(),
+        base_tree_leafs,
+        get_base_tree_count::<Tree>(),
+    )?;
+    let tree = create_lc_tree::<TreeRLast<Tree>>(base_tree_size, &configs, &replica_config)?;
+
+    Ok((tree, rows_to_discard))
+}
+
+fn open_tree_d(
+    porep_config: PoRepConfig,
+    cache_path: &Path,
+) -> Result<BinaryMerkleTree<DefaultPieceHasher>> {
+    let base_tree_size = get_base_tree_size::<DefaultBinaryTree>(porep_config.sector_size)?;
+    let base_tree_leafs = get_base_tree_leafs::<DefaultBinaryTree>(base_tree_size)?;
+    trace!(
+        "update: base tree size {}, base tree leafs {}",
+        base_tree_size,
+        base_tree_leafs,
+    );
+
+    let config = StoreConfig::new(
+        cache_path,
+        CacheKey::CommDTree.to_string(),
+        default_rows_to_discard(base_tree_leafs, BINARY_ARITY),
+    );
+    let store: DiskStore<DefaultPieceDomain> =
+        DiskStore::new_from_disk(base_tree_size, BINARY_ARITY, &config)?;
+
+    BinaryMerkleTree::<DefaultPieceHasher>::from_data_store(store, base_tree_leafs)
+}
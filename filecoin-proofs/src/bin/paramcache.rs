@@ -7,7 +7,7 @@ use structopt::StructOpt;
 
 use filecoin_proofs::constants::*;
 use filecoin_proofs::parameters::{
-    public_params, window_post_public_params, winning_post_public_params,
+    public_params, update_public_params, window_post_public_params, winning_post_public_params,
 };
 use filecoin_proofs::types::*;
 use filecoin_proofs::with_shape;
@@ -15,6 +15,7 @@ use filecoin_proofs::PoStType;
 use storage_proofs::compound_proof::CompoundProof;
 use storage_proofs::parameter_cache::CacheableParameters;
 use storage_proofs::porep::stacked::{StackedCompound, StackedDrg};
+use storage_proofs::porep::update::{ReplicaUpdate, ReplicaUpdateCompound};
 use storage_proofs::post::fallback::{FallbackPoSt, FallbackPoStCircuit, FallbackPoStCompound};
 
 const PUBLISHED_SECTOR_SIZES: [u64; 10] = [
@@ -77,6 +78,52 @@ fn cache_porep_params<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig
     }
 }
 
+fn cache_empty_sector_update_params<Tree: 'static + MerkleTreeTrait>(porep_config: PoRepConfig) {
+    info!("Empty sector update params");
+
+    let public_params = update_public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+    )
+    .unwrap();
+
+    {
+        let circuit = <ReplicaUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            ReplicaUpdate<Tree, DefaultPieceHasher>,
+            _,
+        >>::blank_circuit(&public_params);
+        let _ = ReplicaUpdateCompound::<Tree, DefaultPieceHasher>::get_param_metadata(
+            circuit,
+            &public_params,
+        );
+    }
+    {
+        let circuit = <ReplicaUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            ReplicaUpdate<Tree, DefaultPieceHasher>,
+            _,
+        >>::blank_circuit(&public_params);
+        ReplicaUpdateCompound::<Tree, DefaultPieceHasher>::get_groth_params(
+            Some(&mut OsRng),
+            circuit,
+            &public_params,
+        )
+        .expect("failed to get groth params");
+    }
+    {
+        let circuit = <ReplicaUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            ReplicaUpdate<Tree, DefaultPieceHasher>,
+            _,
+        >>::blank_circuit(&public_params);
+
+        ReplicaUpdateCompound::<Tree, DefaultPieceHasher>::get_verifying_key(
+            Some(&mut OsRng),
+            circuit,
+            &public_params,
+        )
+        .expect("failed to get verifying key");
+    }
+}
+
 fn cache_winning_post_params<Tree: 'static + MerkleTreeTrait>(post_config: &PoStConfig) {
     info!("Winning PoSt params");
 
@@ -205,21 +252,20 @@ fn generate_params_post(sector_size: u64) {
 }
 
 fn generate_params_porep(sector_size: u64) {
-    with_shape!(
-        sector_size,
-        cache_porep_params,
-        PoRepConfig {
-            sector_size: SectorSize(sector_size),
-            partitions: PoRepProofPartitions(
-                *POREP_PARTITIONS
-                    .read()
-                    .unwrap()
-                    .get(&sector_size)
-                    .expect("missing sector size"),
-            ),
-            porep_id: [0; 32],
-        }
-    );
+    let porep_config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS
+                .read()
+                .unwrap()
+                .get(&sector_size)
+                .expect("missing sector size"),
+        ),
+        porep_id: [0; 32],
+    };
+
+    with_shape!(sector_size, cache_porep_params, porep_config);
+    with_shape!(sector_size, cache_empty_sector_update_params, porep_config);
 }
 
 // Run this from the command-line to pre-generate the groth parameters used by the API.
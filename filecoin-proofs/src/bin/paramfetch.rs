@@ -6,17 +6,24 @@ use std::io::{BufReader, Stdout};
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::process::Command;
+use std::time::Duration;
 use std::{fs, io};
 
 use anyhow::{bail, ensure, Context, Result};
-use clap::{values_t, App, Arg, ArgMatches};
+use clap::{value_t, values_t, App, Arg, ArgMatches};
 use flate2::read::GzDecoder;
 use itertools::Itertools;
 use pbr::{ProgressBar, Units};
-use reqwest::{blocking::Client, header, Proxy, Url};
+use reqwest::{header, Url};
 use tar::Archive;
 
 use filecoin_proofs::param::*;
+use filecoin_proofs::param_fetch::{
+    fetch_all, http_client, FetchConfig, FetchRequest, DEFAULT_GATEWAYS,
+};
+use filecoin_proofs::param_manifest::{
+    parse_public_key, signature_path, trusted_keys_from_settings, verify_manifest_signature,
+};
 use storage_proofs::parameter_cache::{
     parameter_cache_dir, parameter_cache_dir_name, GROTH_PARAMETER_EXT,
 };
@@ -65,6 +72,21 @@ Defaults to '{}'
                 .long("json")
                 .help("Use specific JSON file"),
         )
+        .arg(
+            Arg::with_name("trusted-key")
+                .value_name("KEY")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .short("k")
+                .long("trusted-key")
+                .help("Hex encoded ed25519 public key trusted to sign the manifest, in addition to FIL_PROOFS_TRUSTED_PARAMS_KEYS"),
+        )
+        .arg(
+            Arg::with_name("require-signature")
+                .long("require-signature")
+                .help("Refuse manifests without a valid signature from a trusted key"),
+        )
         .arg(
             Arg::with_name("retry")
                 .short("r")
@@ -94,6 +116,35 @@ Defaults to '{}'
                 .long("verbose")
                 .help("Print diagnostic information to stdout"),
         )
+        .arg(
+            Arg::with_name("gateway")
+                .short("g")
+                .long("gateway")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("IPFS gateway to download from, repeat to give mirrors to fail over to in order"),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .short("c")
+                .long("concurrency")
+                .takes_value(true)
+                .default_value("4")
+                .help("Number of files to download at the same time"),
+        )
+        .arg(
+            Arg::with_name("attempts")
+                .long("attempts")
+                .takes_value(true)
+                .default_value("3")
+                .help("Attempts on each gateway before failing over to the next one"),
+        )
+        .arg(
+            Arg::with_name("use-ipget")
+                .long("use-ipget")
+                .help("Download with ipget, one file at a time, instead of from HTTP gateways"),
+        )
         .arg(
             Arg::with_name("ipget-bin")
                 .conflicts_with("ipget-version")
@@ -127,7 +178,9 @@ Defaults to '{}'
 }
 
 fn fetch(matches: &ArgMatches) -> Result<()> {
-    let manifest = if matches.is_present("json") {
+    let require_signature = matches.is_present("require-signature");
+
+    let manifest: ParameterMap = if matches.is_present("json") {
         let json_path = PathBuf::from(matches.value_of("json").unwrap());
         println!("using JSON file: {:?}", json_path);
 
@@ -141,14 +194,26 @@ fn fetch(matches: &ArgMatches) -> Result<()> {
         let file = File::open(&json_path)?;
         let reader = BufReader::new(file);
 
-        serde_json::from_reader(reader).with_context(|| {
+        let manifest = serde_json::from_reader(reader).with_context(|| {
             format!(
                 "JSON file '{}' did not parse correctly",
                 &json_path.to_str().unwrap_or(""),
             )
-        })?
+        })?;
+
+        // a signature which is present is always checked, even if not required
+        if require_signature || signature_path(&json_path).exists() {
+            verify_manifest_signature(&manifest, &json_path, &trusted_keys(matches)?)?;
+            println!("manifest signature verified");
+        }
+
+        manifest
     } else {
         println!("using built-in manifest");
+        ensure!(
+            !require_signature,
+            "the built-in manifest is not signed, use --json with a signed manifest"
+        );
         serde_json::from_str(&DEFAULT_PARAMETERS)?
     };
 
@@ -202,39 +267,54 @@ fn fetch(matches: &ArgMatches) -> Result<()> {
     }
 
     let is_verbose = matches.is_present("verbose");
-    let ipget_bin_path = matches.value_of("ipget-bin");
-    let ipget_version = matches.value_of("ipget-version").unwrap_or(IPGET_VERSION);
-    let ipget_args = matches.value_of("ipget-args");
+    let use_ipget = matches.is_present("use-ipget")
+        || matches.is_present("ipget-bin")
+        || matches.is_present("ipget-version");
+
+    let mut ipget_path = None;
+    if use_ipget {
+        let ipget_bin_path = matches.value_of("ipget-bin");
+        let ipget_version = matches.value_of("ipget-version").unwrap_or(IPGET_VERSION);
+
+        // Make sure we have ipget available
+        if ipget_bin_path.is_none() {
+            ensure_ipget(is_verbose, ipget_version)?;
+        }
 
-    // Make sure we have ipget available
-    if ipget_bin_path.is_none() {
-        ensure_ipget(is_verbose, ipget_version)?;
+        ipget_path = Some(if let Some(p) = ipget_bin_path {
+            PathBuf::from(p)
+        } else {
+            PathBuf::from(&get_ipget_bin(ipget_version))
+        });
     }
+    let ipget_args = matches.value_of("ipget-args");
 
-    let ipget_path = if let Some(p) = ipget_bin_path {
-        PathBuf::from(p)
-    } else {
-        PathBuf::from(&get_ipget_bin(ipget_version))
-    };
+    let config = fetch_config(matches)?;
 
     loop {
         println!("{} files to fetch...", filenames.len());
         println!();
 
-        for filename in &filenames {
-            println!("fetching: {}", filename);
-            print!("downloading file... ");
-            io::stdout().flush().unwrap();
+        if let Some(ipget_path) = &ipget_path {
+            for filename in &filenames {
+                println!("fetching: {}", filename);
+                print!("downloading file... ");
+                io::stdout().flush().unwrap();
 
-            match fetch_parameter_file(is_verbose, &manifest, &filename, &ipget_path, ipget_args) {
-                Ok(_) => println!("ok\n"),
-                Err(err) => println!("error: {}\n", err),
+                match fetch_parameter_file(is_verbose, &manifest, &filename, ipget_path, ipget_args)
+                {
+                    Ok(_) => println!("ok\n"),
+                    Err(err) => println!("error: {}\n", err),
+                }
             }
-        }
 
-        // if we haven't downloaded a valid copy of each asset specified in the
-        // manifest, ask the user if they wish to try again
-        filenames = get_filenames_requiring_download(&manifest, filenames)?;
+            // if we haven't downloaded a valid copy of each asset specified in the
+            // manifest, ask the user if they wish to try again
+            filenames = get_filenames_requiring_download(&manifest, filenames)?;
+        } else {
+            // the digests are checked while downloading, only failed files are left
+            filenames = fetch_parameter_files(&config, &manifest, filenames)?;
+        }
 
         if filenames.is_empty() {
             break;
@@ -256,6 +336,44 @@ fn fetch(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// The keys given with `--trusted-key` together with those from the settings.
+fn trusted_keys(matches: &ArgMatches) -> Result<Vec<ed25519_dalek::PublicKey>> {
+    let mut keys = trusted_keys_from_settings()?;
+    if let Some(values) = matches.values_of("trusted-key") {
+        for value in values {
+            keys.push(parse_public_key(value)?);
+        }
+    }
+
+    Ok(keys)
+}
+
+fn fetch_config(matches: &ArgMatches) -> Result<FetchConfig> {
+    let gateways = match matches.values_of("gateway") {
+        Some(gateways) => gateways.collect_vec(),
+        None => DEFAULT_GATEWAYS.to_vec(),
+    };
+
+    Ok(FetchConfig {
+        gateways: gateways
+            .into_iter()
+            .map(|gateway| {
+                // without the trailing slash the last path segment is replaced by the cid
+                let gateway = if gateway.ends_with('/') {
+                    gateway.to_string()
+                } else {
+                    format!("{}/", gateway)
+                };
+                Url::parse(&gateway).with_context(|| format!("invalid gateway {}", gateway))
+            })
+            .collect::<Result<_>>()?,
+        concurrency: value_t!(matches, "concurrency", usize)?,
+        attempts: value_t!(matches, "attempts", usize)?,
+        retry_delay: Duration::from_secs(2),
+        progress: matches.is_present("verbose"),
+    })
+}
+
 fn get_ipget_bin(version: &str) -> String {
     format!("{}-{}/ipget/ipget", IPGET_PATH, version)
 }
@@ -316,9 +434,7 @@ fn download_ipget(is_verbose: bool, version: &str) -> Result<()> {
 fn download_file(url: Url, target: impl AsRef<Path>, is_verbose: bool) -> Result<()> {
     let mut file = File::create(target)?;
 
-    let client = Client::builder()
-        .proxy(Proxy::custom(move |url| env_proxy::for_url(&url).to_url()))
-        .build()?;
+    let client = http_client()?;
     let total_size = {
         let res = client.head(url.as_str()).send()?;
         if res.status().is_success() {
@@ -360,6 +476,11 @@ fn fetch_parameter_file(
 ) -> Result<()> {
     let parameter_data = parameter_map_lookup(parameter_map, filename)?;
     let path = get_full_path_for_file_within_cache(filename);
+    ensure!(
+        !parameter_data.cid.is_empty(),
+        "{} was not published to IPFS, fetch it without ipget",
+        filename
+    );
 
     create_dir_all(parameter_cache_dir())?;
     download_file_with_ipget(
@@ -371,6 +492,46 @@ fn fetch_parameter_file(
     )
 }
 
+/// Downloads the given files from the gateways, returning those which failed.
+fn fetch_parameter_files(
+    config: &FetchConfig,
+    parameter_map: &ParameterMap,
+    filenames: Vec<String>,
+) -> Result<Vec<String>> {
+    let requests = filenames
+        .iter()
+        .map(|filename| {
+            let parameter_data = parameter_map_lookup(parameter_map, filename)?;
+            Ok(FetchRequest {
+                locators: parameter_data.all_locators(),
+                digest: parameter_data
+                    .full_digest
+                    .clone()
+                    .unwrap_or_else(|| parameter_data.digest.clone()),
+                target: get_full_path_for_file_within_cache(filename),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    create_dir_all(parameter_cache_dir())?;
+    let results = fetch_all(config, &requests)?;
+
+    Ok(filenames
+        .into_iter()
+        .zip(results)
+        .filter_map(|(filename, result)| match result {
+            Ok(()) => {
+                println!("fetched: {}", filename);
+                None
+            }
+            Err(err) => {
+                println!("error: {:#}", err);
+                Some(filename)
+            }
+        })
+        .collect())
+}
+
 fn download_file_with_ipget(
     cid: impl AsRef<str>,
     target: impl AsRef<Path>,
@@ -447,13 +608,10 @@ fn get_filenames_from_parameter_map(parameter_map: &ParameterMap) -> Result<Vec<
 
 fn validate_parameter_file(parameter_map: &ParameterMap, filename: &str) -> Result<bool> {
     let parameter_data = parameter_map_lookup(parameter_map, filename)?;
-    let digest = get_digest_for_file_within_cache(filename)?;
+    let path = get_full_path_for_file_within_cache(filename);
+    let digest = get_full_digest_for_file(&path)?;
 
-    if parameter_data.digest != digest {
-        Ok(false)
-    } else {
-        Ok(true)
-    }
+    Ok(parameter_data.matches_digest(&digest))
 }
 
 fn invalidate_parameter_file(filename: &str) -> Result<()> {
@@ -1,21 +1,28 @@
 use std::collections::BTreeMap;
+use std::env;
 use std::fs::{read_dir, File};
 use std::io;
 use std::io::prelude::*;
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
-use std::process::{exit, Command};
+use std::process::exit;
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use clap::{App, Arg, ArgMatches};
 use dialoguer::{theme::ColorfulTheme, MultiSelect, Select};
 use humansize::{file_size_opts, FileSize};
 use itertools::Itertools;
 
 use filecoin_proofs::param::{
-    add_extension, choose_from, filename_to_parameter_id, get_digest_for_file_within_cache,
-    get_full_path_for_file_within_cache, has_extension, parameter_id_to_metadata_map,
-    ParameterData, ParameterMap,
+    add_extension, choose_from, filename_to_parameter_id, get_full_digest_for_file,
+    get_full_path_for_file_within_cache, has_extension, parameter_id_to_metadata_map, Locator,
+    ParameterData, ParameterMap, DIGEST_LEN,
+};
+use filecoin_proofs::param_manifest::{
+    generate_signing_key, read_signing_key, signature_path, write_manifest_signature,
+};
+use filecoin_proofs::param_publish::{
+    DirectoryBackend, HttpPutBackend, IpfsBackend, PublishBackend, S3Credentials,
 };
 use filecoin_proofs::{
     SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_512_MIB, SECTOR_SIZE_64_GIB,
@@ -26,8 +33,6 @@ use storage_proofs::parameter_cache::{
     PARAMETER_METADATA_EXT, VERIFYING_KEY_EXT,
 };
 
-const ERROR_IPFS_COMMAND: &str = "failed to run ipfs";
-const ERROR_IPFS_PUBLISH: &str = "failed to publish via ipfs";
 const PUBLISH_SECTOR_SIZES: [u64; 5] = [
     SECTOR_SIZE_2_KIB,
     SECTOR_SIZE_8_MIB,
@@ -71,6 +76,74 @@ Defaults to '{}'
                 .long("ipfs-bin")
                 .help("Use specific ipfs binary instead of searching for one in $PATH"),
         )
+        .arg(
+            Arg::with_name("no-ipfs")
+                .long("no-ipfs")
+                .conflicts_with("ipfs-bin")
+                .help("Do not publish to IPFS, the files are only found through the other backends"),
+        )
+        .arg(
+            Arg::with_name("mirror-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .long("mirror-dir")
+                .help("Also copy the files to this directory, e.g. a local mirror or an NFS mount"),
+        )
+        .arg(
+            Arg::with_name("put-url")
+                .value_name("URL")
+                .takes_value(true)
+                .long("put-url")
+                .help("Also upload the files with HTTP PUT below this URL, e.g. an S3-compatible bucket"),
+        )
+        .arg(
+            Arg::with_name("put-header")
+                .value_name("HEADER")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("put-header")
+                .requires("put-url")
+                .help("Header sent with the uploads, as 'Name: value'"),
+        )
+        .arg(
+            Arg::with_name("s3-access-key")
+                .value_name("KEY")
+                .takes_value(true)
+                .long("s3-access-key")
+                .requires_all(&["put-url", "s3-region"])
+                .help("Sign the uploads for S3 with this access key"),
+        )
+        .arg(
+            Arg::with_name("s3-secret-key")
+                .value_name("KEY")
+                .takes_value(true)
+                .long("s3-secret-key")
+                .requires("s3-access-key")
+                .help("Secret key of --s3-access-key, read from AWS_SECRET_ACCESS_KEY if not given"),
+        )
+        .arg(
+            Arg::with_name("s3-region")
+                .value_name("REGION")
+                .takes_value(true)
+                .long("s3-region")
+                .requires("s3-access-key")
+                .help("Region of the bucket the uploads are signed for"),
+        )
+        .arg(
+            Arg::with_name("signing-key")
+                .value_name("KEY")
+                .takes_value(true)
+                .short("s")
+                .long("signing-key")
+                .help("Sign the manifest with the ed25519 key in this file, the signature is written next to the manifest"),
+        )
+        .arg(
+            Arg::with_name("generate-signing-key")
+                .long("generate-signing-key")
+                .requires("signing-key")
+                .help("Generate a new key at the --signing-key path first"),
+        )
         .get_matches();
 
     match publish(&matches) {
@@ -83,7 +156,22 @@ Defaults to '{}'
 }
 
 fn publish(matches: &ArgMatches) -> Result<()> {
-    let ipfs_bin_path = matches.value_of("ipfs-bin").unwrap_or("ipfs");
+    let backends = publish_backends(matches)?;
+    ensure!(!backends.is_empty(), "no backends to publish to");
+
+    // read the key before publishing anything, so that a bad key does not waste a run
+    let signing_key = match matches.value_of("signing-key").map(Path::new) {
+        Some(path) if matches.is_present("generate-signing-key") => {
+            let keypair = generate_signing_key(path)?;
+            println!(
+                "generated signing key, public key: {}",
+                hex::encode(keypair.public.as_bytes())
+            );
+            Some(keypair)
+        }
+        Some(path) => Some(read_signing_key(path)?),
+        None => None,
+    };
 
     // Get all valid parameter IDs which have all three files, `.meta`, `.params and `.vk`
     // associated with them. If one of the files is missing, it won't show up in the selection.
@@ -260,6 +348,8 @@ fn publish(matches: &ArgMatches) -> Result<()> {
         println!("publishing {} files...", filenames.len());
         println!();
 
+        let mut failed = Vec::new();
+
         for filename in filenames {
             let id = filename_to_parameter_id(&filename)
                 .with_context(|| format!("failed to parse id from file name {}", filename))?;
@@ -269,33 +359,60 @@ fn publish(matches: &ArgMatches) -> Result<()> {
                 .with_context(|| format!("no metadata found for parameter id {}", id))?;
 
             println!("publishing: {}", filename);
-            print!("publishing to ipfs... ");
-            io::stdout().flush().unwrap();
-
-            match publish_parameter_file(&ipfs_bin_path, &filename) {
-                Ok(cid) => {
-                    println!("ok");
-                    print!("generating digest... ");
-                    io::stdout().flush().unwrap();
 
-                    let digest = get_digest_for_file_within_cache(&filename)?;
-                    let data = ParameterData {
-                        cid,
-                        digest,
-                        sector_size: meta.sector_size,
-                    };
-
-                    parameter_map.insert(filename, data);
-
-                    println!("ok");
-                }
-                Err(err) => println!("error: {}", err),
+            let (mut locators, failures) = publish_parameter_file(&backends, &filename);
+            failed.extend(failures);
+            if locators.is_empty() {
+                println!("error: {} was not published anywhere", filename);
+                println!();
+                continue;
             }
 
+            print!("generating digest... ");
+            io::stdout().flush().unwrap();
+
+            // the cid keeps its own field, for older versions of paramfetch
+            let cid = locators
+                .iter()
+                .find_map(|locator| match locator {
+                    Locator::Ipfs(cid) => Some(cid.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            locators.retain(|locator| !matches!(locator, Locator::Ipfs(_)));
+
+            let path = get_full_path_for_file_within_cache(&filename);
+            let full_digest = get_full_digest_for_file(&path)?;
+            let data = ParameterData {
+                cid,
+                digest: full_digest[..DIGEST_LEN].to_string(),
+                sector_size: meta.sector_size,
+                full_digest: Some(full_digest),
+                locators,
+            };
+
+            parameter_map.insert(filename, data);
+
+            println!("ok");
             println!();
         }
 
         write_parameter_map_to_disk(&parameter_map, &json)?;
+
+        if let Some(keypair) = signing_key {
+            write_manifest_signature(&parameter_map, &json, &keypair)?;
+            println!("signed manifest: {:?}", signature_path(&json));
+        }
+
+        // The manifest lists every place a file did get published to, but the failed uploads
+        // still need to be retried.
+        if !failed.is_empty() {
+            println!("failed to publish:");
+            for failure in &failed {
+                println!("  {}", failure);
+            }
+            bail!("{} uploads failed", failed.len());
+        }
     } else {
         println!("no files to publish");
     }
@@ -329,19 +446,70 @@ fn get_filenames_in_cache_dir() -> Result<Vec<String>> {
     }
 }
 
-fn publish_parameter_file(ipfs_bin_path: &str, filename: &str) -> Result<String> {
+fn publish_backends(matches: &ArgMatches) -> Result<Vec<Box<dyn PublishBackend>>> {
+    let mut backends: Vec<Box<dyn PublishBackend>> = Vec::new();
+
+    if !matches.is_present("no-ipfs") {
+        let ipfs_bin_path = matches.value_of("ipfs-bin").unwrap_or("ipfs");
+        backends.push(Box::new(IpfsBackend::new(ipfs_bin_path)));
+    }
+    if let Some(dir) = matches.value_of("mirror-dir") {
+        backends.push(Box::new(DirectoryBackend::new(dir)?));
+    }
+    if let Some(url) = matches.value_of("put-url") {
+        let headers = matches
+            .values_of("put-header")
+            .map(|headers| headers.collect_vec())
+            .unwrap_or_default();
+        let mut backend = HttpPutBackend::new(url, &headers)?;
+        if let Some(access_key) = matches.value_of("s3-access-key") {
+            let secret_key = match matches.value_of("s3-secret-key") {
+                Some(secret_key) => secret_key.to_string(),
+                None => env::var("AWS_SECRET_ACCESS_KEY")
+                    .context("--s3-secret-key or AWS_SECRET_ACCESS_KEY needs to be set")?,
+            };
+            backend = backend.s3_credentials(S3Credentials {
+                access_key: access_key.to_string(),
+                secret_key,
+                region: matches
+                    .value_of("s3-region")
+                    .expect("required by s3-access-key")
+                    .to_string(),
+            });
+        }
+        backends.push(Box::new(backend));
+    }
+
+    Ok(backends)
+}
+
+/// Publishes the file to every backend, returning the locators of the backends it was published
+/// to, in their order, and the failures of the others.
+fn publish_parameter_file(
+    backends: &[Box<dyn PublishBackend>],
+    filename: &str,
+) -> (Vec<Locator>, Vec<String>) {
     let path = get_full_path_for_file_within_cache(filename);
 
-    let output = Command::new(ipfs_bin_path)
-        .arg("add")
-        .arg("-Q")
-        .arg(&path)
-        .output()
-        .expect(ERROR_IPFS_COMMAND);
+    let mut locators = Vec::new();
+    let mut failures = Vec::new();
+    for backend in backends {
+        print!("publishing to {}... ", backend.name());
+        io::stdout().flush().unwrap();
 
-    ensure!(output.status.success(), ERROR_IPFS_PUBLISH);
+        match backend.publish(&path, filename) {
+            Ok(locator) => {
+                println!("ok");
+                locators.push(locator);
+            }
+            Err(err) => {
+                println!("error: {:#}", err);
+                failures.push(format!("{} to {}: {:#}", filename, backend.name(), err));
+            }
+        }
+    }
 
-    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    (locators, failures)
 }
 
 fn write_parameter_map_to_disk<P: AsRef<Path>>(
@@ -7,12 +7,25 @@ use bellperson::groth16;
 use lazy_static::lazy_static;
 use log::info;
 use paired::bls12_381::Bls12;
+#[cfg(feature = "aggregation")]
+use storage_proofs::aggregate::{GenericSRS, VerifierSRS};
 use storage_proofs::compound_proof::CompoundProof;
+#[cfg(feature = "aggregation")]
+use storage_proofs::parameter_cache::{get_srs_key, parameter_cache_srs_key_path, SRS_IDENTIFIER};
 use storage_proofs::porep::stacked::{StackedCompound, StackedDrg};
+use storage_proofs::porep::update::{ReplicaUpdate, ReplicaUpdateCompound};
 use storage_proofs::post::fallback;
+use storage_proofs::settings;
 
 use crate::constants::DefaultPieceHasher;
-use crate::parameters::{public_params, window_post_public_params, winning_post_public_params};
+#[cfg(feature = "aggregation")]
+use crate::constants::SRS_MAX_PROOFS_TO_AGGREGATE;
+#[cfg(feature = "aggregation")]
+use crate::param_manifest::ensure_trusted_srs;
+use crate::param_manifest::{ensure_trusted_params, ensure_trusted_verifying_key};
+use crate::parameters::{
+    public_params, update_public_params, window_post_public_params, winning_post_public_params,
+};
 use crate::types::*;
 
 type Bls12GrothParams = groth16::MappedParameters<Bls12>;
@@ -21,10 +34,18 @@ pub type Bls12VerifyingKey = groth16::VerifyingKey<Bls12>;
 type Cache<G> = HashMap<String, Arc<G>>;
 type GrothMemCache = Cache<Bls12GrothParams>;
 type VerifyingKeyMemCache = Cache<Bls12VerifyingKey>;
+#[cfg(feature = "aggregation")]
+type SrsMemCache = Cache<GenericSRS>;
+#[cfg(feature = "aggregation")]
+type SrsVerifierKeyMemCache = Cache<VerifierSRS>;
 
 lazy_static! {
     static ref GROTH_PARAM_MEMORY_CACHE: Mutex<GrothMemCache> = Default::default();
     static ref VERIFYING_KEY_MEMORY_CACHE: Mutex<VerifyingKeyMemCache> = Default::default();
+    #[cfg(feature = "aggregation")]
+    static ref SRS_MEMORY_CACHE: Mutex<SrsMemCache> = Default::default();
+    #[cfg(feature = "aggregation")]
+    static ref SRS_VERIFIER_KEY_MEMORY_CACHE: Mutex<SrsVerifierKeyMemCache> = Default::default();
 }
 
 pub fn cache_lookup<F, G>(
@@ -36,6 +57,20 @@ where
     F: FnOnce() -> Result<G>,
     G: Send + Sync,
 {
+    // Entries are loaded from the parameter cache in effect, which can differ between calls.
+    // Entries loaded without checking them against a trusted manifest are never handed to
+    // callers requiring one, and the other way around.
+    let settings = settings::current();
+    let trust = if settings.require_trusted_params {
+        format!(
+            "trusted({};{})",
+            settings.trusted_params_manifest, settings.trusted_params_keys
+        )
+    } else {
+        "untrusted".to_string()
+    };
+    let identifier = format!("{}:{}:{}", settings.parameter_cache, trust, identifier);
+
     info!("trying parameters memory cache for: {}", &identifier);
     {
         let cache = (*cache_ref).lock().unwrap();
@@ -75,6 +110,35 @@ where
     cache_lookup(&*VERIFYING_KEY_MEMORY_CACHE, vk_identifier, generator)
 }
 
+/// Returns the structured reference string used to aggregate seal proofs.
+#[cfg(feature = "aggregation")]
+pub fn get_srs_key_params() -> Result<Arc<GenericSRS>> {
+    let srs_generator = || {
+        let srs = get_srs_key(SRS_MAX_PROOFS_TO_AGGREGATE)?;
+        ensure_trusted_srs(&parameter_cache_srs_key_path(SRS_IDENTIFIER), &srs)?;
+
+        Ok(srs)
+    };
+
+    cache_lookup(
+        &*SRS_MEMORY_CACHE,
+        SRS_IDENTIFIER.to_string(),
+        srs_generator,
+    )
+}
+
+/// Returns the part of the structured reference string needed to verify aggregate seal proofs.
+#[cfg(feature = "aggregation")]
+pub fn get_srs_verifier_key() -> Result<Arc<VerifierSRS>> {
+    let verifier_key_generator = || get_srs_key_params()?.verifier_key().map_err(Into::into);
+
+    cache_lookup(
+        &*SRS_VERIFIER_KEY_MEMORY_CACHE,
+        format!("{}-verifier-key", SRS_IDENTIFIER),
+        verifier_key_generator,
+    )
+}
+
 pub fn get_stacked_params<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
 ) -> Result<Arc<Bls12GrothParams>> {
@@ -85,11 +149,13 @@ pub fn get_stacked_params<Tree: 'static + MerkleTreeTrait>(
     )?;
 
     let parameters_generator = || {
-        <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        let params = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
             StackedDrg<Tree, DefaultPieceHasher>,
             _,
-        >>::groth_params::<rand::rngs::OsRng>(None, &public_params)
-        .map_err(Into::into)
+        >>::groth_params::<rand::rngs::OsRng>(None, &public_params)?;
+        ensure_trusted_params(&porep_config.get_cache_params_path::<Tree>()?, &params)?;
+
+        Ok(params)
     };
 
     Ok(lookup_groth_params(
@@ -109,11 +175,15 @@ pub fn get_post_params<Tree: 'static + MerkleTreeTrait>(
             let post_public_params = winning_post_public_params::<Tree>(post_config)?;
 
             let parameters_generator = || {
-                <fallback::FallbackPoStCompound<Tree> as CompoundProof<
+                let params = <fallback::FallbackPoStCompound<Tree> as CompoundProof<
                     fallback::FallbackPoSt<Tree>,
                     fallback::FallbackPoStCircuit<Tree>,
-                >>::groth_params::<rand::rngs::OsRng>(None, &post_public_params)
-                .map_err(Into::into)
+                >>::groth_params::<rand::rngs::OsRng>(
+                    None, &post_public_params
+                )?;
+                ensure_trusted_params(&post_config.get_cache_params_path::<Tree>()?, &params)?;
+
+                Ok(params)
             };
 
             Ok(lookup_groth_params(
@@ -128,11 +198,15 @@ pub fn get_post_params<Tree: 'static + MerkleTreeTrait>(
             let post_public_params = window_post_public_params::<Tree>(post_config)?;
 
             let parameters_generator = || {
-                <fallback::FallbackPoStCompound<Tree> as CompoundProof<
+                let params = <fallback::FallbackPoStCompound<Tree> as CompoundProof<
                     fallback::FallbackPoSt<Tree>,
                     fallback::FallbackPoStCircuit<Tree>,
-                >>::groth_params::<rand::rngs::OsRng>(None, &post_public_params)
-                .map_err(Into::into)
+                >>::groth_params::<rand::rngs::OsRng>(
+                    None, &post_public_params
+                )?;
+                ensure_trusted_params(&post_config.get_cache_params_path::<Tree>()?, &params)?;
+
+                Ok(params)
             };
 
             Ok(lookup_groth_params(
@@ -146,6 +220,36 @@ pub fn get_post_params<Tree: 'static + MerkleTreeTrait>(
     }
 }
 
+pub fn get_empty_sector_update_params<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<Arc<Bls12GrothParams>> {
+    let public_params = update_public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+    )?;
+
+    let parameters_generator = || {
+        let params = <ReplicaUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            ReplicaUpdate<Tree, DefaultPieceHasher>,
+            _,
+        >>::groth_params::<rand::rngs::OsRng>(None, &public_params)?;
+        ensure_trusted_params(
+            &porep_config.get_update_cache_params_path::<Tree>()?,
+            &params,
+        )?;
+
+        Ok(params)
+    };
+
+    Ok(lookup_groth_params(
+        format!(
+            "EMPTY_SECTOR_UPDATE[{}]",
+            usize::from(PaddedBytesAmount::from(porep_config))
+        ),
+        parameters_generator,
+    )?)
+}
+
 pub fn get_stacked_verifying_key<Tree: 'static + MerkleTreeTrait>(
     porep_config: PoRepConfig,
 ) -> Result<Arc<Bls12VerifyingKey>> {
@@ -156,11 +260,13 @@ pub fn get_stacked_verifying_key<Tree: 'static + MerkleTreeTrait>(
     )?;
 
     let vk_generator = || {
-        <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
+        let vk = <StackedCompound<Tree, DefaultPieceHasher> as CompoundProof<
             StackedDrg<Tree, DefaultPieceHasher>,
             _,
-        >>::verifying_key::<rand::rngs::OsRng>(None, &public_params)
-        .map_err(Into::into)
+        >>::verifying_key::<rand::rngs::OsRng>(None, &public_params)?;
+        ensure_trusted_verifying_key(&porep_config.get_cache_verifying_key_path::<Tree>()?, &vk)?;
+
+        Ok(vk)
     };
 
     Ok(lookup_verifying_key(
@@ -180,11 +286,18 @@ pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
             let post_public_params = winning_post_public_params::<Tree>(post_config)?;
 
             let vk_generator = || {
-                <fallback::FallbackPoStCompound<Tree> as CompoundProof<
+                let vk = <fallback::FallbackPoStCompound<Tree> as CompoundProof<
                     fallback::FallbackPoSt<Tree>,
                     fallback::FallbackPoStCircuit<Tree>,
-                >>::verifying_key::<rand::rngs::OsRng>(None, &post_public_params)
-                .map_err(Into::into)
+                >>::verifying_key::<rand::rngs::OsRng>(
+                    None, &post_public_params
+                )?;
+                ensure_trusted_verifying_key(
+                    &post_config.get_cache_verifying_key_path::<Tree>()?,
+                    &vk,
+                )?;
+
+                Ok(vk)
             };
 
             Ok(lookup_verifying_key(
@@ -199,11 +312,18 @@ pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
             let post_public_params = window_post_public_params::<Tree>(post_config)?;
 
             let vk_generator = || {
-                <fallback::FallbackPoStCompound<Tree> as CompoundProof<
+                let vk = <fallback::FallbackPoStCompound<Tree> as CompoundProof<
                     fallback::FallbackPoSt<Tree>,
                     fallback::FallbackPoStCircuit<Tree>,
-                >>::verifying_key::<rand::rngs::OsRng>(None, &post_public_params)
-                .map_err(Into::into)
+                >>::verifying_key::<rand::rngs::OsRng>(
+                    None, &post_public_params
+                )?;
+                ensure_trusted_verifying_key(
+                    &post_config.get_cache_verifying_key_path::<Tree>()?,
+                    &vk,
+                )?;
+
+                Ok(vk)
             };
 
             Ok(lookup_verifying_key(
@@ -216,3 +336,65 @@ pub fn get_post_verifying_key<Tree: 'static + MerkleTreeTrait>(
         }
     }
 }
+
+pub fn get_empty_sector_update_verifying_key<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+) -> Result<Arc<Bls12VerifyingKey>> {
+    let public_params = update_public_params::<Tree>(
+        PaddedBytesAmount::from(porep_config),
+        usize::from(PoRepProofPartitions::from(porep_config)),
+    )?;
+
+    let vk_generator = || {
+        let vk = <ReplicaUpdateCompound<Tree, DefaultPieceHasher> as CompoundProof<
+            ReplicaUpdate<Tree, DefaultPieceHasher>,
+            _,
+        >>::verifying_key::<rand::rngs::OsRng>(None, &public_params)?;
+        ensure_trusted_verifying_key(
+            &porep_config.get_update_cache_verifying_key_path::<Tree>()?,
+            &vk,
+        )?;
+
+        Ok(vk)
+    };
+
+    Ok(lookup_verifying_key(
+        format!(
+            "EMPTY_SECTOR_UPDATE[{}]",
+            usize::from(PaddedBytesAmount::from(porep_config))
+        ),
+        vk_generator,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    #[test]
+    fn test_cache_lookup_keeps_trusted_entries_apart() {
+        let cache: Mutex<Cache<u32>> = Default::default();
+        let generated = Cell::new(0);
+        let lookup = || {
+            cache_lookup(&cache, "TEST".to_string(), || {
+                generated.set(generated.get() + 1);
+                Ok(generated.get())
+            })
+            .unwrap()
+        };
+
+        assert_eq!(*lookup(), 1);
+        assert_eq!(*lookup(), 1);
+
+        // An entry loaded without the check is not reused once it is required.
+        let mut config = settings::current().as_ref().clone();
+        config.require_trusted_params = !config.require_trusted_params;
+        settings::with_settings(Arc::new(config), || {
+            assert_eq!(*lookup(), 2);
+            assert_eq!(*lookup(), 2);
+        });
+        assert_eq!(*lookup(), 1);
+    }
+}
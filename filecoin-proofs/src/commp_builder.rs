@@ -0,0 +1,240 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{ensure, Context, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use storage_proofs::pieces::generate_piece_commitment_bytes_from_source;
+
+use crate::constants::{
+    DefaultPieceHasher,
+    MINIMUM_RESERVED_BYTES_FOR_PIECE_IN_FULLY_ALIGNED_SECTOR as MINIMUM_PIECE_SIZE,
+};
+use crate::fr32_reader::Fr32Reader;
+use crate::pieces::piece_hash;
+use crate::types::{Commitment, PaddedBytesAmount, PieceInfo, UnpaddedBytesAmount};
+
+/// Default size of the (padded) subtrees, which are hashed on a single thread.
+pub const DEFAULT_COMMP_SUBTREE_SIZE: PaddedBytesAmount = PaddedBytesAmount(1 << 20);
+
+/// Computes the piece commitment of a piece in parallel.
+///
+/// The padded piece is split into aligned subtrees of `subtree_size` bytes. Every 127 unpadded
+/// bytes are padded to 128 bytes independently, so each subtree can be padded and hashed on its
+/// own, and the subtree roots are merged with `piece_hash`. The result is identical to
+/// `generate_piece_commitment_bytes_from_source` run over the whole `Fr32Reader` output.
+///
+/// The builder only keeps the roots of the subtrees which were not merged yet, and can be
+/// serialized between calls to `update`, in order to resume an interrupted computation. The
+/// source then has to continue at `bytes_processed`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommPBuilder {
+    piece_size: UnpaddedBytesAmount,
+    subtree_size: PaddedBytesAmount,
+    subtrees_processed: u64,
+    /// Roots of completed subtrees, with their padded size, in decreasing size order.
+    stack: Vec<(Commitment, u64)>,
+}
+
+impl CommPBuilder {
+    pub fn new(piece_size: UnpaddedBytesAmount) -> Result<Self> {
+        Self::with_subtree_size(piece_size, DEFAULT_COMMP_SUBTREE_SIZE)
+    }
+
+    /// Creates a builder hashing subtrees of (at most) `subtree_size` padded bytes per thread.
+    pub fn with_subtree_size(
+        piece_size: UnpaddedBytesAmount,
+        subtree_size: PaddedBytesAmount,
+    ) -> Result<Self> {
+        ensure!(
+            u64::from(piece_size) >= MINIMUM_PIECE_SIZE,
+            "Piece must be at least {} bytes",
+            MINIMUM_PIECE_SIZE
+        );
+        let padded_piece_size = u64::from(PaddedBytesAmount::from(piece_size));
+        ensure!(
+            padded_piece_size.is_power_of_two(),
+            "Bit-padded piece size must be a power of 2"
+        );
+        ensure!(
+            u64::from(subtree_size).is_power_of_two()
+                && u64::from(subtree_size)
+                    >= u64::from(PaddedBytesAmount::from(UnpaddedBytesAmount(
+                        MINIMUM_PIECE_SIZE
+                    ))),
+            "invalid subtree size: {:?}",
+            subtree_size
+        );
+
+        Ok(CommPBuilder {
+            piece_size,
+            subtree_size: PaddedBytesAmount(std::cmp::min(
+                u64::from(subtree_size),
+                padded_piece_size,
+            )),
+            subtrees_processed: 0,
+            stack: Vec::new(),
+        })
+    }
+
+    /// The number of unpadded bytes of the piece, which were already hashed.
+    pub fn bytes_processed(&self) -> UnpaddedBytesAmount {
+        UnpaddedBytesAmount(self.subtrees_processed * u64::from(self.unpadded_subtree_size()))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.subtrees_processed == self.subtree_count()
+    }
+
+    /// Reads and hashes the next batch of subtrees from `source`, one per thread. Returns
+    /// `true` once the whole piece was processed.
+    pub fn update_batch<R: Read>(&mut self, source: &mut R) -> Result<bool> {
+        let remaining = self.subtree_count() - self.subtrees_processed;
+        let batch_size = std::cmp::min(rayon::current_num_threads() as u64, remaining) as usize;
+        let unpadded_subtree_size = usize::from(self.unpadded_subtree_size());
+        let subtree_size = usize::from(self.subtree_size);
+
+        let mut buf = vec![0u8; batch_size * unpadded_subtree_size];
+        source
+            .read_exact(&mut buf)
+            .context("not enough bytes provided for the piece")?;
+
+        let roots = buf
+            .par_chunks(unpadded_subtree_size)
+            .map(|chunk| {
+                let mut fr32_reader = Fr32Reader::new(Cursor::new(chunk));
+                generate_piece_commitment_bytes_from_source::<DefaultPieceHasher>(
+                    &mut fr32_reader,
+                    subtree_size,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for root in roots {
+            self.push(root, subtree_size as u64);
+        }
+        self.subtrees_processed += batch_size as u64;
+
+        Ok(self.is_complete())
+    }
+
+    /// Hashes the remaining subtrees of the piece from `source`.
+    pub fn update<R: Read>(&mut self, mut source: R) -> Result<()> {
+        while !self.update_batch(&mut source)? {}
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<PieceInfo> {
+        ensure!(
+            self.is_complete(),
+            "piece is incomplete: {:?} of {:?} bytes processed",
+            self.bytes_processed(),
+            self.piece_size
+        );
+        ensure!(
+            self.stack.len() == 1,
+            "Stack size ({}) must be 1.",
+            self.stack.len()
+        );
+
+        PieceInfo::new(self.stack[0].0, self.piece_size)
+    }
+
+    fn push(&mut self, mut commitment: Commitment, mut size: u64) {
+        while let Some(&(left, left_size)) = self.stack.last() {
+            if left_size != size {
+                break;
+            }
+            self.stack.pop();
+            commitment.copy_from_slice(AsRef::<[u8]>::as_ref(&piece_hash(&left, &commitment)));
+            size *= 2;
+        }
+        self.stack.push((commitment, size));
+    }
+
+    fn unpadded_subtree_size(&self) -> UnpaddedBytesAmount {
+        UnpaddedBytesAmount::from(self.subtree_size)
+    }
+
+    fn subtree_count(&self) -> u64 {
+        u64::from(PaddedBytesAmount::from(self.piece_size)) / u64::from(self.subtree_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    fn expected_comm_p(data: &[u8]) -> Commitment {
+        let mut fr32_reader = Fr32Reader::new(Cursor::new(data));
+        generate_piece_commitment_bytes_from_source::<DefaultPieceHasher>(
+            &mut fr32_reader,
+            PaddedBytesAmount::from(UnpaddedBytesAmount(data.len() as u64)).into(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_commp_builder_matches_single_threaded() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        for &piece_size in &[127, 254, 127 * 8, 127 * 64] {
+            let mut data = vec![0u8; piece_size];
+            rng.fill_bytes(&mut data);
+            let expected = expected_comm_p(&data);
+
+            for &subtree_size in &[128, 256, 1024, 1 << 20] {
+                let mut builder = CommPBuilder::with_subtree_size(
+                    UnpaddedBytesAmount(piece_size as u64),
+                    PaddedBytesAmount(subtree_size),
+                )
+                .unwrap();
+                builder.update(&data[..]).unwrap();
+
+                let piece_info = builder.finish().unwrap();
+                assert_eq!(piece_info.commitment, expected);
+                assert_eq!(piece_info.size, UnpaddedBytesAmount(piece_size as u64));
+            }
+        }
+    }
+
+    #[test]
+    fn test_commp_builder_resume() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let piece_size = 127 * 1024;
+
+        let mut data = vec![0u8; piece_size];
+        rng.fill_bytes(&mut data);
+
+        let mut builder = CommPBuilder::with_subtree_size(
+            UnpaddedBytesAmount(piece_size as u64),
+            PaddedBytesAmount(128),
+        )
+        .unwrap();
+        let mut source = &data[..];
+        assert!(!builder.update_batch(&mut source).unwrap());
+
+        // Persist the partial state and continue from where it left off.
+        let state = serde_json::to_vec(&builder).unwrap();
+        let mut builder: CommPBuilder = serde_json::from_slice(&state).unwrap();
+        assert!(!builder.is_complete());
+
+        let offset = usize::from(builder.bytes_processed());
+        builder.update(&data[offset..]).unwrap();
+
+        assert_eq!(builder.finish().unwrap().commitment, expected_comm_p(&data));
+    }
+
+    #[test]
+    fn test_commp_builder_not_enough_bytes() {
+        let data = vec![0u8; 127 * 3];
+        let mut builder =
+            CommPBuilder::with_subtree_size(UnpaddedBytesAmount(127 * 4), PaddedBytesAmount(128))
+                .unwrap();
+
+        assert!(builder.update(&data[..]).is_err());
+    }
+}
@@ -25,6 +25,10 @@ pub const WINNING_POST_SECTOR_COUNT: usize = 1;
 
 pub const WINDOW_POST_CHALLENGE_COUNT: usize = 10;
 
+/// Largest number of circuit proofs, i.e. sectors times partitions, that can be aggregated into
+/// a single proof.
+pub const SRS_MAX_PROOFS_TO_AGGREGATE: usize = 8192;
+
 pub const DRG_DEGREE: usize = storage_proofs::drgraph::BASE_DEGREE;
 pub const EXP_DEGREE: usize = storage_proofs::porep::stacked::EXP_DEGREE;
 
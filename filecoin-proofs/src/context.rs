@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use storage_proofs::settings::{self, Settings};
+
+/// The settings used by the proofs api calls made through [`ProofsContext::run`], instead of the
+/// process wide `storage_proofs::settings::SETTINGS`.
+///
+/// This allows e.g. sealing two sectors in one process with different parent caches, or with
+/// and without the GPU tree builders. Sectors must be proven with the same `rows_to_discard`
+/// they were sealed with.
+#[derive(Clone, Debug)]
+pub struct ProofsContext {
+    settings: Arc<Settings>,
+}
+
+impl ProofsContext {
+    pub fn new(settings: Settings) -> Self {
+        ProofsContext {
+            settings: Arc::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Runs `f`, with all proofs api calls made from within it on the calling thread using the
+    /// settings of this context. The api hands these settings on to the threads it spawns.
+    ///
+    /// ```ignore
+    /// let mut settings = ProofsContext::default().settings().clone();
+    /// settings.parent_cache = "/mnt/nvme/filecoin-parents".to_string();
+    ///
+    /// let phase1_output = ProofsContext::new(settings).run(|| {
+    ///     seal_pre_commit_phase1::<_, _, _, Tree>(config, cache_path, in_path, out_path, ..)
+    /// })?;
+    /// ```
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        settings::with_settings(self.settings.clone(), f)
+    }
+}
+
+impl Default for ProofsContext {
+    /// A context with the settings currently in effect, which are the global ones outside of
+    /// `run`.
+    fn default() -> Self {
+        ProofsContext {
+            settings: settings::current(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use storage_proofs::parameter_cache::parameter_cache_dir;
+
+    #[test]
+    fn test_run_uses_context_settings() {
+        let global_dir = parameter_cache_dir();
+
+        let mut settings = ProofsContext::default().settings().clone();
+        settings.parameter_cache = "/tmp/other-parameters/".to_string();
+        let ctx = ProofsContext::new(settings);
+
+        ctx.run(|| {
+            assert_eq!(parameter_cache_dir(), Path::new("/tmp/other-parameters/"));
+            assert_eq!(
+                ProofsContext::default().settings().parameter_cache,
+                "/tmp/other-parameters/"
+            );
+        });
+        assert_eq!(parameter_cache_dir(), global_dir);
+    }
+}
@@ -3,11 +3,16 @@
 mod api;
 mod caches;
 mod commitment_reader;
+mod commp_builder;
+mod context;
 
 pub mod constants;
 pub mod fr32;
 pub mod fr32_reader;
 pub mod param;
+pub mod param_fetch;
+pub mod param_manifest;
+pub mod param_publish;
 pub mod parameters;
 pub mod pieces;
 pub mod serde_big_array;
@@ -16,8 +21,10 @@ pub mod types;
 
 pub use self::api::*;
 pub use self::commitment_reader::*;
+pub use self::commp_builder::*;
 pub use self::constants::SINGLE_PARTITION_PROOF_LEN;
 pub use self::constants::*;
+pub use self::context::ProofsContext;
 pub use self::param::{ParameterData, ParameterMap};
 pub use self::types::*;
 
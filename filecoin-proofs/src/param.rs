@@ -1,18 +1,24 @@
 use std::collections::BTreeMap;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs::File;
 use std::io::{stdin, stdout, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use blake2b_simd::State as Blake2b;
-use serde::{Deserialize, Serialize};
+use reqwest::Url;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use storage_proofs::parameter_cache::{
     parameter_cache_dir, CacheEntryMetadata, PARAMETER_METADATA_EXT,
 };
 
 const ERROR_STRING: &str = "invalid string";
 
+/// Number of hex characters of the BLAKE2b digest kept in the `digest` of a manifest entry.
+pub const DIGEST_LEN: usize = 32;
+
 pub type ParameterMap = BTreeMap<String, ParameterData>;
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -20,6 +26,82 @@ pub struct ParameterData {
     pub cid: String,
     pub digest: String,
     pub sector_size: u64,
+    /// The full-length BLAKE2b digest, recorded by manifests meant to be signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_digest: Option<String>,
+    /// Where the file was published, in addition to IPFS under `cid`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locators: Vec<Locator>,
+}
+
+impl ParameterData {
+    /// Whether a file with the given full-length BLAKE2b digest matches this entry, checking
+    /// the full digest if the manifest has one.
+    pub fn matches_digest(&self, full_digest: &str) -> bool {
+        match &self.full_digest {
+            Some(expected) => expected == full_digest,
+            None => full_digest.get(..DIGEST_LEN) == Some(self.digest.as_str()),
+        }
+    }
+
+    /// The places the file can be downloaded from, in the order they should be tried: the
+    /// recorded locators, then IPFS.
+    pub fn all_locators(&self) -> Vec<Locator> {
+        let mut locators = self.locators.clone();
+        let ipfs = Locator::Ipfs(self.cid.clone());
+        if !self.cid.is_empty() && !locators.contains(&ipfs) {
+            locators.push(ipfs);
+        }
+        locators
+    }
+}
+
+/// A place a parameter file was published to, written as `ipfs://<cid>`, `file://<path>` or
+/// an `http://` or `https://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Locator {
+    Ipfs(String),
+    File(PathBuf),
+    Http(Url),
+}
+
+impl fmt::Display for Locator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Locator::Ipfs(cid) => write!(f, "ipfs://{}", cid),
+            Locator::File(path) => write!(f, "file://{}", path.display()),
+            Locator::Http(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+impl FromStr for Locator {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with("ipfs://") {
+            Ok(Locator::Ipfs(s["ipfs://".len()..].to_string()))
+        } else if s.starts_with("file://") {
+            Ok(Locator::File(PathBuf::from(&s["file://".len()..])))
+        } else if s.starts_with("http://") || s.starts_with("https://") {
+            Ok(Locator::Http(Url::parse(s)?))
+        } else {
+            bail!("unsupported locator {}", s)
+        }
+    }
+}
+
+impl Serialize for Locator {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Locator {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
 }
 
 // Produces an absolute path to a file within the cache
@@ -32,12 +114,17 @@ pub fn get_full_path_for_file_within_cache(filename: &str) -> PathBuf {
 // Produces a BLAKE2b checksum for a file within the cache
 pub fn get_digest_for_file_within_cache(filename: &str) -> Result<String> {
     let path = get_full_path_for_file_within_cache(filename);
+    Ok(get_full_digest_for_file(&path)?[..DIGEST_LEN].into())
+}
+
+// Produces the full-length BLAKE2b checksum of a file
+pub fn get_full_digest_for_file(path: &Path) -> Result<String> {
     let mut file = File::open(&path).with_context(|| format!("could not open path={:?}", path))?;
     let mut hasher = Blake2b::new();
 
     std::io::copy(&mut file, &mut hasher)?;
 
-    Ok(hasher.finalize().to_hex()[..32].into())
+    Ok(hasher.finalize().to_hex().to_string())
 }
 
 // Prompts the user to approve/reject the message
@@ -122,3 +209,27 @@ pub fn filename_to_parameter_id<'a, P: AsRef<Path> + 'a>(filename: P) -> Option<
         .and_then(OsStr::to_str)
         .map(ToString::to_string)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locator_roundtrip() {
+        for s in &[
+            "ipfs://QmeDRyxek34F1H6xJY6AkFdWvPsy5F6dKTrebV3ZtWT4ky",
+            "file:///mnt/mirror/v28-test.params",
+            "https://bucket.example.com/params/v28-test.params",
+        ] {
+            let locator: Locator = s.parse().unwrap();
+            assert_eq!(&locator.to_string(), s);
+
+            let json = serde_json::to_string(&locator).unwrap();
+            assert_eq!(serde_json::from_str::<Locator>(&json).unwrap(), locator);
+        }
+
+        assert!("ftp://example.com/v28-test.params"
+            .parse::<Locator>()
+            .is_err());
+    }
+}
@@ -0,0 +1,713 @@
+//! Downloading of parameter files from the locators of the manifest, over HTTP from IPFS
+//! gateways or mirrors, or from a local directory.
+//!
+//! Files are streamed into `<file>.partial` and hashed on the way, a download cut short is
+//! resumed with a `Range` request on the next attempt. Sources are tried in order, and once a
+//! file is complete its BLAKE2b digest is checked against the manifest before it is moved into
+//! place.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use blake2b_simd::State as Blake2b;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{info, warn};
+use rayon::prelude::*;
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::{header, Proxy, StatusCode, Url};
+
+use crate::param::{Locator, DIGEST_LEN};
+
+/// Gateways tried in order when none are given.
+pub const DEFAULT_GATEWAYS: &[&str] =
+    &["https://proofs.filecoin.io/ipfs/", "https://ipfs.io/ipfs/"];
+
+/// Extension of files still being downloaded.
+pub const PARTIAL_EXT: &str = "partial";
+
+const BUFFER_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// Gateways tried in order, a file is requested from `<gateway><cid>`.
+    pub gateways: Vec<Url>,
+    /// Number of files downloaded at the same time.
+    pub concurrency: usize,
+    /// Attempts on each source before failing over to the next one.
+    pub attempts: usize,
+    /// Pause between two attempts on the same source.
+    pub retry_delay: Duration,
+    /// Draw a progress bar for each file.
+    pub progress: bool,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        FetchConfig {
+            gateways: DEFAULT_GATEWAYS
+                .iter()
+                .map(|gateway| Url::parse(gateway).expect("invalid default gateway"))
+                .collect(),
+            concurrency: 4,
+            attempts: 3,
+            retry_delay: Duration::from_secs(2),
+            progress: false,
+        }
+    }
+}
+
+/// A file to download.
+#[derive(Debug, Clone)]
+pub struct FetchRequest {
+    /// Where the file can be found, in the order they are tried.
+    pub locators: Vec<Locator>,
+    /// The hex BLAKE2b digest, either truncated as in the `digest` of a manifest entry, or the
+    /// full-length one.
+    pub digest: String,
+    pub target: PathBuf,
+}
+
+/// Path of the file `target` is downloaded into until it is complete.
+pub fn partial_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(PARTIAL_EXT);
+    target.with_file_name(name)
+}
+
+/// Builds a client honouring the usual proxy environment variables.
+pub fn http_client() -> Result<Client> {
+    Ok(http_client_builder().build()?)
+}
+
+/// The builder behind [`http_client`], for clients needing further settings.
+pub fn http_client_builder() -> ClientBuilder {
+    Client::builder().proxy(Proxy::custom(move |url| env_proxy::for_url(&url).to_url()))
+}
+
+/// Downloads `requests`, up to `config.concurrency` at a time, returning the outcome of each
+/// request in order.
+pub fn fetch_all(config: &FetchConfig, requests: &[FetchRequest]) -> Result<Vec<Result<()>>> {
+    let client = http_client()?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.concurrency.max(1))
+        .build()?;
+
+    // All bars are added before drawing starts, `join` returns once every one is finished.
+    let multi = MultiProgress::new();
+    let bars: Vec<ProgressBar> = requests
+        .iter()
+        .map(|request| {
+            if !config.progress {
+                return ProgressBar::hidden();
+            }
+            let bar = multi.add(ProgressBar::new(0));
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{msg} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+                    .progress_chars("=> "),
+            );
+            bar.set_message(
+                &request
+                    .target
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+            );
+            bar
+        })
+        .collect();
+    let drawer = thread::spawn(move || multi.join());
+
+    let results = pool.install(|| {
+        requests
+            .par_iter()
+            .zip(bars.par_iter())
+            .map(|(request, bar)| {
+                let result = fetch(&client, config, request, bar);
+                match result {
+                    Ok(()) => bar.finish(),
+                    Err(_) => bar.abandon(),
+                }
+                result
+            })
+            .collect()
+    });
+
+    if let Err(err) = drawer.join().expect("progress drawing panicked") {
+        warn!("failed to draw progress: {}", err);
+    }
+
+    Ok(results)
+}
+
+/// A place a file is downloaded from.
+#[derive(Debug)]
+enum Source {
+    Http(Url),
+    File(PathBuf),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Source::Http(url) => write!(f, "{}", url),
+            Source::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The sources of the locators, in order, an IPFS locator stands for all gateways.
+fn sources(config: &FetchConfig, locators: &[Locator]) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for locator in locators {
+        match locator {
+            Locator::Ipfs(cid) => {
+                for gateway in &config.gateways {
+                    let url = gateway
+                        .join(cid)
+                        .with_context(|| format!("invalid gateway {}", gateway))?;
+                    sources.push(Source::Http(url));
+                }
+            }
+            Locator::Http(url) => sources.push(Source::Http(url.clone())),
+            Locator::File(path) => sources.push(Source::File(path.clone())),
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Downloads a single file, picking up a previous partial download, trying the sources of its
+/// locators in order.
+pub fn fetch(
+    client: &Client,
+    config: &FetchConfig,
+    request: &FetchRequest,
+    progress: &ProgressBar,
+) -> Result<()> {
+    let mut partial = Partial::open(&partial_path(&request.target))?;
+    let mut last_err = anyhow!("nowhere to fetch from");
+
+    'sources: for source in sources(config, &request.locators)? {
+        for attempt in 1..=config.attempts {
+            if attempt > 1 {
+                thread::sleep(config.retry_delay);
+            }
+
+            let result = match &source {
+                Source::Http(url) => download(client, url, &mut partial, progress),
+                Source::File(path) => copy_file(path, &mut partial, progress),
+            };
+            let err = match result {
+                Ok(()) => {
+                    let digest = partial.digest();
+                    if request.digest.len() >= DIGEST_LEN && digest.starts_with(&request.digest) {
+                        return partial.finish(&request.target);
+                    }
+                    // Some of the bytes are bad, the next attempt starts from scratch.
+                    partial.reset()?;
+                    anyhow!(
+                        "digest mismatch, expected {} got {}",
+                        request.digest,
+                        digest
+                    )
+                }
+                Err(DownloadError::Transient(err)) => err,
+                Err(DownloadError::Unavailable(err)) => {
+                    warn!("{}: {:#}", source, err);
+                    last_err = err;
+                    continue 'sources;
+                }
+                Err(DownloadError::Local(err)) => return Err(err),
+            };
+
+            warn!(
+                "{}: attempt {}/{} failed: {:#}",
+                source, attempt, config.attempts, err
+            );
+            last_err = err;
+        }
+    }
+
+    Err(last_err.context(format!("failed to fetch {:?}", request.target)))
+}
+
+enum DownloadError {
+    /// Worth another attempt on the same source.
+    Transient(anyhow::Error),
+    /// The source does not have the file.
+    Unavailable(anyhow::Error),
+    /// Writing the file failed, no source will help with that.
+    Local(anyhow::Error),
+}
+
+/// Downloads the rest of `url` into `partial`.
+fn download(
+    client: &Client,
+    url: &Url,
+    partial: &mut Partial,
+    progress: &ProgressBar,
+) -> std::result::Result<(), DownloadError> {
+    use DownloadError::*;
+
+    let mut request = client.get(url.clone());
+    if partial.len > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", partial.len));
+    }
+    let mut response = request.send().map_err(|err| Transient(err.into()))?;
+
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            if content_range_start(&response) != Some(partial.len) {
+                partial.reset().map_err(|err| Local(err.into()))?;
+                return Err(Transient(anyhow!("unexpected Content-Range")));
+            }
+        }
+        StatusCode::OK => {
+            if partial.len > 0 {
+                info!("{} ignored the range request, restarting", url);
+                partial.reset().map_err(|err| Local(err.into()))?;
+            }
+        }
+        // The partial file is complete already, the digest tells whether it is any good.
+        StatusCode::RANGE_NOT_SATISFIABLE if partial.len > 0 => return Ok(()),
+        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => {
+            return Err(Transient(anyhow!("status {}", response.status())));
+        }
+        status if status.is_client_error() => {
+            return Err(Unavailable(anyhow!("status {}", status)));
+        }
+        status => return Err(Transient(anyhow!("status {}", status))),
+    }
+
+    let total = response.content_length().map(|len| partial.len + len);
+    progress.set_length(total.unwrap_or(0));
+    progress.set_position(partial.len);
+
+    stream_into(&mut response, partial, progress)?;
+
+    if let Some(total) = total {
+        if partial.len != total {
+            return Err(Transient(anyhow!(
+                "connection closed after {} of {} bytes",
+                partial.len,
+                total
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies the rest of the file at `path` into `partial`.
+fn copy_file(
+    path: &Path,
+    partial: &mut Partial,
+    progress: &ProgressBar,
+) -> std::result::Result<(), DownloadError> {
+    use DownloadError::*;
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(Unavailable(err.into())),
+        Err(err) => return Err(Transient(err.into())),
+    };
+    let total = file.metadata().map_err(|err| Transient(err.into()))?.len();
+    if partial.len > total {
+        partial.reset().map_err(|err| Local(err.into()))?;
+    }
+    file.seek(SeekFrom::Start(partial.len))
+        .map_err(|err| Transient(err.into()))?;
+
+    progress.set_length(total);
+    progress.set_position(partial.len);
+
+    stream_into(&mut file, partial, progress)
+}
+
+/// Appends everything `reader` returns to `partial`.
+fn stream_into(
+    reader: &mut impl Read,
+    partial: &mut Partial,
+    progress: &ProgressBar,
+) -> std::result::Result<(), DownloadError> {
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(DownloadError::Transient(err.into())),
+        };
+        partial.append(&buf[..n]).map_err(|err| {
+            DownloadError::Local(anyhow::Error::from(err).context("failed to write"))
+        })?;
+        progress.inc(n as u64);
+    }
+}
+
+/// The first byte of a `Content-Range: bytes <start>-<end>/<size>` response.
+fn content_range_start(response: &reqwest::blocking::Response) -> Option<u64> {
+    let value = response
+        .headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?;
+    if !value.starts_with("bytes ") {
+        return None;
+    }
+    value["bytes ".len()..].split('-').next()?.parse().ok()
+}
+
+/// A download in progress, hashed as it is written.
+struct Partial {
+    path: PathBuf,
+    file: File,
+    hasher: Blake2b,
+    len: u64,
+}
+
+impl Partial {
+    /// Opens the partial file at `path`, hashing what an earlier run left there.
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("could not open {:?}", path))?;
+        let mut hasher = Blake2b::new();
+        let len = io::copy(&mut file, &mut hasher)?;
+        if len > 0 {
+            info!("resuming {:?} at {} bytes", path, len);
+        }
+
+        Ok(Partial {
+            path: path.to_path_buf(),
+            file,
+            hasher,
+            len,
+        })
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)?;
+        self.hasher.update(data);
+        self.len += data.len() as u64;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.hasher = Blake2b::new();
+        self.len = 0;
+        Ok(())
+    }
+
+    /// The full-length digest of what was downloaded so far.
+    fn digest(&self) -> String {
+        self.hasher.finalize().to_hex().to_string()
+    }
+
+    /// Moves the complete file to `target`.
+    fn finish(self, target: &Path) -> Result<()> {
+        let Partial { path, file, .. } = self;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&path, target)
+            .with_context(|| format!("could not move {:?} to {:?}", path, target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    use rand::{RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    /// A minimal HTTP server standing in for a gateway. It serves files at `/ipfs/<cid>` and
+    /// honours `Range: bytes=<start>-`.
+    struct StandIn {
+        url: Url,
+        /// The range start of every request received, `None` for requests without a range.
+        ranges: Arc<Mutex<Vec<Option<u64>>>>,
+    }
+
+    impl StandIn {
+        /// `cut_first` closes the connection of the first request after that many bytes of
+        /// the body, as a network blip would.
+        fn start(files: Vec<(&str, Vec<u8>)>, cut_first: Option<usize>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url =
+                Url::parse(&format!("http://{}/ipfs/", listener.local_addr().unwrap())).unwrap();
+            let files: HashMap<String, Vec<u8>> = files
+                .into_iter()
+                .map(|(cid, data)| (format!("/ipfs/{}", cid), data))
+                .collect();
+            let ranges = Arc::new(Mutex::new(Vec::new()));
+
+            let received = ranges.clone();
+            thread::spawn(move || {
+                for (i, stream) in listener.incoming().enumerate() {
+                    let cut = if i == 0 { cut_first } else { None };
+                    let _ = serve(stream.unwrap(), &files, &received, cut);
+                }
+            });
+
+            StandIn { url, ranges }
+        }
+
+        fn ranges(&self) -> Vec<Option<u64>> {
+            self.ranges.lock().unwrap().clone()
+        }
+    }
+
+    fn serve(
+        mut stream: TcpStream,
+        files: &HashMap<String, Vec<u8>>,
+        ranges: &Mutex<Vec<Option<u64>>>,
+        cut: Option<usize>,
+    ) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let path = line
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or_default()
+            .to_string();
+
+        let mut range = None;
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let header = line.trim_end().to_ascii_lowercase();
+            if header.is_empty() {
+                break;
+            }
+            if header.starts_with("range: bytes=") {
+                range = header["range: bytes=".len()..]
+                    .trim_end_matches('-')
+                    .parse::<u64>()
+                    .ok();
+            }
+        }
+        ranges.lock().unwrap().push(range);
+
+        let data = match files.get(&path) {
+            Some(data) => data,
+            None => {
+                return write!(
+                    stream,
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+            }
+        };
+        let start = range.unwrap_or(0) as usize;
+        if range.is_some() && start >= data.len() {
+            return write!(
+                stream,
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+        }
+
+        let body = &data[start..];
+        if range.is_some() {
+            write!(
+                stream,
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n",
+                start,
+                data.len() - 1,
+                data.len()
+            )?;
+        } else {
+            write!(stream, "HTTP/1.1 200 OK\r\n")?;
+        }
+        write!(
+            stream,
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )?;
+
+        let body = &body[..cut.unwrap_or(body.len()).min(body.len())];
+        stream.write_all(body)?;
+        stream.flush()
+    }
+
+    fn random_file(len: usize) -> Vec<u8> {
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+        let mut data = vec![0u8; len];
+        rng.fill_bytes(&mut data);
+        data
+    }
+
+    fn digest(data: &[u8]) -> String {
+        blake2b_simd::blake2b(data).to_hex()[..DIGEST_LEN].into()
+    }
+
+    fn test_config(gateways: Vec<Url>) -> FetchConfig {
+        FetchConfig {
+            gateways,
+            concurrency: 2,
+            attempts: 2,
+            retry_delay: Duration::from_millis(0),
+            progress: false,
+        }
+    }
+
+    fn request(dir: &Path, cid: &str, data: &[u8]) -> FetchRequest {
+        FetchRequest {
+            locators: vec![Locator::Ipfs(cid.to_string())],
+            digest: digest(data),
+            target: dir.join(cid),
+        }
+    }
+
+    #[test]
+    fn test_fetch_resumes_after_dropped_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = random_file(3 * BUFFER_SIZE + 17);
+        let cut = BUFFER_SIZE + 5;
+        let server = StandIn::start(vec![("cid", data.clone())], Some(cut));
+
+        let request = request(dir.path(), "cid", &data);
+        let results =
+            fetch_all(&test_config(vec![server.url.clone()]), &[request.clone()]).unwrap();
+        results[0].as_ref().unwrap();
+
+        assert_eq!(fs::read(&request.target).unwrap(), data);
+        assert!(!partial_path(&request.target).exists());
+
+        let ranges = server.ranges();
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0], None);
+        let resumed_at = ranges[1].expect("second request should resume");
+        assert!(resumed_at <= cut as u64);
+    }
+
+    #[test]
+    fn test_fetch_resumes_existing_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = random_file(100_000);
+        let server = StandIn::start(vec![("cid", data.clone())], None);
+
+        let request = request(dir.path(), "cid", &data);
+        fs::write(partial_path(&request.target), &data[..40_000]).unwrap();
+
+        let results =
+            fetch_all(&test_config(vec![server.url.clone()]), &[request.clone()]).unwrap();
+        results[0].as_ref().unwrap();
+
+        assert_eq!(fs::read(&request.target).unwrap(), data);
+        assert_eq!(server.ranges(), vec![Some(40_000)]);
+    }
+
+    #[test]
+    fn test_fetch_fails_over_to_next_gateway() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = random_file(10_000);
+        let missing = StandIn::start(vec![], None);
+        let server = StandIn::start(vec![("cid", data.clone())], None);
+
+        let request = request(dir.path(), "cid", &data);
+        let config = test_config(vec![missing.url.clone(), server.url.clone()]);
+        let results = fetch_all(&config, &[request.clone()]).unwrap();
+        results[0].as_ref().unwrap();
+
+        assert_eq!(fs::read(&request.target).unwrap(), data);
+        // A 404 is not retried on the same gateway.
+        assert_eq!(missing.ranges(), vec![None]);
+        assert_eq!(server.ranges(), vec![None]);
+    }
+
+    #[test]
+    fn test_fetch_rejects_digest_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = random_file(10_000);
+        let server = StandIn::start(vec![("cid", data.clone())], None);
+
+        let mut request = request(dir.path(), "cid", &data);
+        request.digest = digest(b"something else");
+
+        let results =
+            fetch_all(&test_config(vec![server.url.clone()]), &[request.clone()]).unwrap();
+        assert!(results[0].is_err());
+
+        assert!(!request.target.exists());
+        // Every attempt downloads the whole file again.
+        assert_eq!(server.ranges(), vec![None, None]);
+    }
+
+    #[test]
+    fn test_fetch_all_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<(String, Vec<u8>)> = (0..5)
+            .map(|i| (format!("cid{}", i), random_file(50_000 + i)))
+            .collect();
+        let server = StandIn::start(
+            files
+                .iter()
+                .map(|(cid, data)| (cid.as_str(), data.clone()))
+                .collect(),
+            None,
+        );
+
+        let requests: Vec<FetchRequest> = files
+            .iter()
+            .map(|(cid, data)| request(dir.path(), cid, data))
+            .collect();
+        let mut config = test_config(vec![server.url.clone()]);
+        config.concurrency = 3;
+        let results = fetch_all(&config, &requests).unwrap();
+
+        for ((result, request), (_, data)) in results.iter().zip(&requests).zip(&files) {
+            result.as_ref().unwrap();
+            assert_eq!(&fs::read(&request.target).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_fetch_from_mirrors() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = random_file(10_000);
+        let server = StandIn::start(vec![("cid", data.clone())], None);
+        let mirror = tempfile::tempdir().unwrap();
+        fs::write(mirror.path().join("mirrored"), &data).unwrap();
+
+        // A missing file in a mirror fails over to the next locator.
+        let mut from_http = request(dir.path(), "from-http", &data);
+        from_http.locators = vec![
+            Locator::File(mirror.path().join("missing")),
+            Locator::Http(server.url.join("cid").unwrap()),
+        ];
+        let mut from_file = request(dir.path(), "from-file", &data);
+        from_file.locators = vec![Locator::File(mirror.path().join("mirrored"))];
+        fs::write(partial_path(&from_file.target), &data[..4_000]).unwrap();
+
+        // No gateways are needed without IPFS locators.
+        let results = fetch_all(
+            &test_config(vec![]),
+            &[from_http.clone(), from_file.clone()],
+        )
+        .unwrap();
+        for (result, request) in results.iter().zip(&[from_http, from_file]) {
+            result.as_ref().unwrap();
+            assert_eq!(fs::read(&request.target).unwrap(), data);
+        }
+        assert_eq!(server.ranges(), vec![None]);
+    }
+}
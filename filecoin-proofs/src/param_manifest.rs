@@ -0,0 +1,313 @@
+//! Signed parameter manifests.
+//!
+//! A manifest is signed with ed25519 over its canonical encoding, which is the compact JSON of
+//! the `ParameterMap`: entries sorted by file name, the fields of each entry in declaration
+//! order and no whitespace. The detached signature is kept hex encoded next to the manifest, in
+//! `<manifest>.sig`, so the manifest itself stays readable by older tools.
+
+use std::convert::TryFrom;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use bellperson::groth16;
+use blake2b_simd::State as Blake2b;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer};
+use log::info;
+use paired::bls12_381::Bls12;
+use storage_proofs::aggregate::GenericSRS;
+use storage_proofs::settings;
+
+use crate::param::ParameterMap;
+
+/// Extension appended to the manifest path to locate its signature.
+pub const SIGNATURE_EXT: &str = "sig";
+
+/// The bytes a manifest signature is made over.
+pub fn canonical_encoding(parameter_map: &ParameterMap) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(parameter_map)?)
+}
+
+/// Path of the detached signature of the manifest at `manifest_path`.
+pub fn signature_path(manifest_path: &Path) -> PathBuf {
+    let mut path = manifest_path.as_os_str().to_os_string();
+    path.push(".");
+    path.push(SIGNATURE_EXT);
+    PathBuf::from(path)
+}
+
+/// Parses a hex encoded ed25519 public key.
+pub fn parse_public_key(hex_key: &str) -> Result<PublicKey> {
+    let bytes = hex::decode(hex_key.trim())
+        .with_context(|| format!("public key {} is not hex encoded", hex_key))?;
+    PublicKey::from_bytes(&bytes).with_context(|| format!("invalid public key {}", hex_key))
+}
+
+/// Reads a signing key, stored as the hex encoded 32 byte secret key.
+pub fn read_signing_key(path: &Path) -> Result<Keypair> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("could not read signing key {:?}", path))?;
+    let bytes = hex::decode(contents.trim())
+        .with_context(|| format!("signing key {:?} is not hex encoded", path))?;
+    let secret =
+        SecretKey::from_bytes(&bytes).with_context(|| format!("invalid signing key {:?}", path))?;
+    let public = PublicKey::from(&secret);
+
+    Ok(Keypair { secret, public })
+}
+
+/// Generates a new signing key and writes it to `path`, which must not exist yet.
+pub fn generate_signing_key(path: &Path) -> Result<Keypair> {
+    let keypair = Keypair::generate(&mut rand::rngs::OsRng);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("could not create signing key {:?}", path))?;
+    writeln!(file, "{}", hex::encode(keypair.secret.as_bytes()))?;
+
+    Ok(keypair)
+}
+
+/// Signs the manifest and writes the signature next to it.
+pub fn write_manifest_signature(
+    parameter_map: &ParameterMap,
+    manifest_path: &Path,
+    keypair: &Keypair,
+) -> Result<()> {
+    let signature = keypair.sign(&canonical_encoding(parameter_map)?);
+    let path = signature_path(manifest_path);
+    fs::write(&path, hex::encode(&signature.to_bytes()[..]))
+        .with_context(|| format!("could not write signature {:?}", path))
+}
+
+/// Checks that `parameter_map` was signed by one of `trusted_keys`, with the signature stored
+/// next to the manifest at `manifest_path`.
+pub fn verify_manifest_signature(
+    parameter_map: &ParameterMap,
+    manifest_path: &Path,
+    trusted_keys: &[PublicKey],
+) -> Result<()> {
+    ensure!(!trusted_keys.is_empty(), "no trusted manifest keys");
+
+    let path = signature_path(manifest_path);
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("could not read signature {:?}", path))?;
+    let bytes = hex::decode(contents.trim())
+        .with_context(|| format!("signature {:?} is not hex encoded", path))?;
+    let signature =
+        Signature::try_from(&bytes[..]).with_context(|| format!("invalid signature {:?}", path))?;
+
+    let message = canonical_encoding(parameter_map)?;
+    if trusted_keys
+        .iter()
+        .any(|key| key.verify_strict(&message, &signature).is_ok())
+    {
+        Ok(())
+    } else {
+        bail!(
+            "manifest {:?} is not signed by a trusted key",
+            manifest_path
+        )
+    }
+}
+
+/// Reads the manifest at `manifest_path` and checks its signature against `trusted_keys`.
+pub fn read_signed_manifest(
+    manifest_path: &Path,
+    trusted_keys: &[PublicKey],
+) -> Result<ParameterMap> {
+    let contents = fs::read(manifest_path)
+        .with_context(|| format!("could not read manifest {:?}", manifest_path))?;
+    let parameter_map: ParameterMap = serde_json::from_slice(&contents)
+        .with_context(|| format!("manifest {:?} did not parse correctly", manifest_path))?;
+    verify_manifest_signature(&parameter_map, manifest_path, trusted_keys)?;
+
+    Ok(parameter_map)
+}
+
+/// The keys configured in `trusted_params_keys`.
+pub fn trusted_keys_from_settings() -> Result<Vec<PublicKey>> {
+    settings::current()
+        .trusted_params_keys
+        .split(',')
+        .filter(|key| !key.trim().is_empty())
+        .map(parse_public_key)
+        .collect()
+}
+
+/// If `require_trusted_params` is set, checks that the Groth parameters loaded from
+/// `params_path` are listed with their full-length digest in the signed
+/// `trusted_params_manifest`.
+///
+/// The digest is taken over the mapped file the parameters are read from, so the check covers
+/// the bytes in use rather than what is on disk at the time of the check. This reads the whole
+/// mapping, so it is meant to run once when the parameters are first loaded.
+pub fn ensure_trusted_params(
+    params_path: &Path,
+    params: &groth16::MappedParameters<Bls12>,
+) -> Result<()> {
+    ensure_trusted(params_path, |hasher| {
+        hasher.update(&params.params[..]);
+        Ok(())
+    })
+}
+
+/// Like [`ensure_trusted_params`], for a verifying key loaded from `vk_path`.
+pub fn ensure_trusted_verifying_key(
+    vk_path: &Path,
+    vk: &groth16::VerifyingKey<Bls12>,
+) -> Result<()> {
+    ensure_trusted(vk_path, |hasher| Ok(vk.write(hasher)?))
+}
+
+/// Like [`ensure_trusted_params`], for a structured reference string loaded from `srs_path`.
+pub fn ensure_trusted_srs(srs_path: &Path, srs: &GenericSRS) -> Result<()> {
+    ensure_trusted(srs_path, |hasher| Ok(srs.write(hasher)?))
+}
+
+/// Checks the digest of the contents `write` feeds into the hasher against the full-length
+/// digest the signed manifest lists for the file name of `path`.
+fn ensure_trusted<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut Blake2b) -> Result<()>,
+{
+    let settings = settings::current();
+    if !settings.require_trusted_params {
+        return Ok(());
+    }
+    ensure!(
+        !settings.trusted_params_manifest.is_empty(),
+        "require_trusted_params is set without a trusted_params_manifest"
+    );
+
+    let manifest_path = Path::new(&settings.trusted_params_manifest);
+    let parameter_map = read_signed_manifest(manifest_path, &trusted_keys_from_settings()?)?;
+
+    let filename = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("invalid parameter path {:?}", path))?;
+    let expected = parameter_map
+        .get(filename)
+        .and_then(|data| data.full_digest.as_ref())
+        .with_context(|| {
+            format!(
+                "{} has no full digest in the trusted manifest {:?}",
+                filename, manifest_path
+            )
+        })?;
+
+    info!("checking digest of {:?}", path);
+    let mut hasher = Blake2b::new();
+    write(&mut hasher)?;
+    let digest = hasher.finalize().to_hex().to_string();
+    ensure!(
+        &digest == expected,
+        "digest of {:?} does not match the trusted manifest",
+        path
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::param::ParameterData;
+
+    fn parameter_map(full_digest: &str) -> ParameterMap {
+        let mut map = ParameterMap::new();
+        map.insert(
+            "v28-test.srs".to_string(),
+            ParameterData {
+                cid: "Qmtest".to_string(),
+                digest: full_digest[..32].to_string(),
+                sector_size: 2048,
+                full_digest: Some(full_digest.to_string()),
+                locators: vec![],
+            },
+        );
+        map
+    }
+
+    #[test]
+    fn test_signature_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = generate_signing_key(&dir.path().join("key")).unwrap();
+        let other = Keypair::generate(&mut rand::rngs::OsRng);
+
+        let manifest_path = dir.path().join("parameters.json");
+        let map = parameter_map(&"ab".repeat(64));
+        fs::write(&manifest_path, serde_json::to_vec_pretty(&map).unwrap()).unwrap();
+        write_manifest_signature(&map, &manifest_path, &keypair).unwrap();
+
+        // The signing key reads back to the same key pair.
+        let read = read_signing_key(&dir.path().join("key")).unwrap();
+        assert_eq!(read.public, keypair.public);
+
+        // Formatting of the manifest does not matter, only its contents.
+        read_signed_manifest(&manifest_path, &[other.public, keypair.public]).unwrap();
+        assert!(read_signed_manifest(&manifest_path, &[other.public]).is_err());
+
+        let tampered = parameter_map(&"cd".repeat(64));
+        fs::write(&manifest_path, serde_json::to_vec(&tampered).unwrap()).unwrap();
+        assert!(read_signed_manifest(&manifest_path, &[keypair.public]).is_err());
+    }
+
+    fn ensure_trusted_bytes(path: &Path, bytes: &[u8]) -> Result<()> {
+        ensure_trusted(path, |hasher| {
+            hasher.update(bytes);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_ensure_trusted() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = Keypair::generate(&mut rand::rngs::OsRng);
+
+        let loaded = b"inner product srs";
+        let full_digest = Blake2b::new()
+            .update(loaded)
+            .finalize()
+            .to_hex()
+            .to_string();
+
+        let manifest_path = dir.path().join("parameters.json");
+        let map = parameter_map(&full_digest);
+        fs::write(&manifest_path, serde_json::to_vec(&map).unwrap()).unwrap();
+        write_manifest_signature(&map, &manifest_path, &keypair).unwrap();
+
+        let mut config = settings::Settings::default();
+        config.require_trusted_params = true;
+        config.trusted_params_manifest = manifest_path.to_string_lossy().to_string();
+        config.trusted_params_keys = hex::encode(keypair.public.as_bytes());
+        let config = Arc::new(config);
+
+        let srs_path = dir.path().join("v28-test.srs");
+        settings::with_settings(config.clone(), || {
+            // Only what was loaded is checked, whatever the file holds by now.
+            fs::write(&srs_path, b"other srs").unwrap();
+            ensure_trusted_bytes(&srs_path, loaded).unwrap();
+
+            // Other contents, or files that are not listed, are refused.
+            assert!(ensure_trusted_bytes(&srs_path, b"other srs").is_err());
+            let unlisted = dir.path().join("v28-other.srs");
+            assert!(ensure_trusted_bytes(&unlisted, loaded).is_err());
+        });
+
+        // Without the setting nothing is checked.
+        ensure_trusted_bytes(&srs_path, b"other srs").unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{ensure, Result};
 use storage_proofs::porep::stacked::{self, LayerChallenges, StackedDrg};
+use storage_proofs::porep::update::{self, ReplicaUpdate};
 use storage_proofs::post::fallback;
 use storage_proofs::proof::ProofScheme;
 
@@ -107,6 +108,43 @@ pub fn setup_params(
     })
 }
 
+pub fn update_public_params<Tree: 'static + MerkleTreeTrait>(
+    sector_bytes: PaddedBytesAmount,
+    partitions: usize,
+) -> Result<update::PublicParams> {
+    ReplicaUpdate::<Tree, DefaultPieceHasher>::setup(&update_setup_params(
+        sector_bytes,
+        partitions,
+    )?)
+}
+
+/// The replica update is challenged as often as the porep of the same sector size, spread
+/// evenly over all partitions.
+pub fn update_setup_params(
+    sector_bytes: PaddedBytesAmount,
+    partitions: usize,
+) -> Result<update::SetupParams> {
+    ensure!(partitions > 0, "partitions must not be 0");
+
+    let minimum_challenges = *POREP_MINIMUM_CHALLENGES
+        .read()
+        .unwrap()
+        .get(&u64::from(sector_bytes))
+        .expect("unknown sector size") as usize;
+    let sector_bytes = u64::from(sector_bytes);
+
+    ensure!(
+        sector_bytes % 32 == 0,
+        "sector_bytes ({}) must be a multiple of 32",
+        sector_bytes,
+    );
+
+    Ok(update::SetupParams {
+        nodes: (sector_bytes / 32) as usize,
+        challenge_count: (minimum_challenges + partitions - 1) / partitions,
+    })
+}
+
 fn select_challenges(
     partitions: usize,
     minimum_total_challenges: usize,
@@ -157,4 +195,14 @@ mod tests {
         assert_eq!(params.challenge_count, 1);
         assert_eq!(params.sector_size, 2048);
     }
+
+    #[test]
+    fn test_update_params() {
+        let params = update_setup_params(PaddedBytesAmount(SECTOR_SIZE_32_GIB), 10).unwrap();
+        assert_eq!(params.challenge_count, 18);
+        assert_eq!(params.nodes, (SECTOR_SIZE_32_GIB / 32) as usize);
+
+        let params = update_setup_params(PaddedBytesAmount(SECTOR_SIZE_2_KIB), 1).unwrap();
+        assert_eq!(params.challenge_count, 2);
+    }
 }
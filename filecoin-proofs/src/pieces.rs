@@ -346,6 +346,180 @@ pub fn get_aligned_source<T: Read>(
     )
 }
 
+/// The placement of a piece, or of zero padding, in a planned sector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedPiece {
+    /// Index of the piece in the candidate piece sizes, or `None` for padding.
+    pub candidate: Option<usize>,
+    /// Byte index of the first (unpadded) byte of the piece in the sector.
+    pub offset: UnpaddedByteIndex,
+    pub size: UnpaddedBytesAmount,
+}
+
+/// The layout of the pieces in a single sector, as produced by `plan_sector` and
+/// `plan_sectors`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectorPlan {
+    /// Pieces and padding in sector order, covering the whole sector.
+    pub layout: Vec<PlannedPiece>,
+}
+
+impl SectorPlan {
+    /// Indices of the candidate pieces, in the order they must be added to the sector.
+    pub fn piece_order(&self) -> Vec<usize> {
+        self.layout
+            .iter()
+            .filter_map(|piece| piece.candidate)
+            .collect()
+    }
+
+    /// The number of bytes in the sector which are not used by pieces.
+    pub fn padding_bytes(&self) -> UnpaddedBytesAmount {
+        self.layout
+            .iter()
+            .filter(|piece| piece.candidate.is_none())
+            .fold(UnpaddedBytesAmount(0), |acc, piece| acc + piece.size)
+    }
+
+    /// Builds the `PieceInfo`s of the whole layout, including the padding pieces, from the
+    /// `PieceInfo`s of the candidates. The result can be passed to `compute_comm_d`.
+    pub fn piece_infos(&self, candidates: &[PieceInfo]) -> Result<Vec<PieceInfo>> {
+        self.layout
+            .iter()
+            .map(|piece| match piece.candidate {
+                Some(i) => {
+                    let info = candidates.get(i).context("missing candidate piece info")?;
+                    ensure!(
+                        info.size == piece.size,
+                        "candidate {} has size {:?}, but was planned with {:?}",
+                        i,
+                        info.size,
+                        piece.size
+                    );
+                    Ok(info.clone())
+                }
+                None => zero_padding(piece.size),
+            })
+            .collect()
+    }
+}
+
+/// Plans the order of `piece_sizes` in a single sector, such that no alignment padding is
+/// needed between them. Returns an error if the pieces do not fit into the sector.
+pub fn plan_sector(
+    piece_sizes: &[UnpaddedBytesAmount],
+    sector_size: SectorSize,
+) -> Result<SectorPlan> {
+    let mut plans = plan_sectors(piece_sizes, sector_size)?;
+    ensure!(
+        plans.len() <= 1,
+        "pieces do not fit into a single sector, {} sectors are needed",
+        plans.len()
+    );
+
+    Ok(plans
+        .pop()
+        .unwrap_or_else(|| layout_sector(&[], sector_size)))
+}
+
+/// Assigns `piece_sizes` to as few sectors as possible, and plans the order of the pieces in
+/// each of them.
+///
+/// Pieces are aligned to their own (power of two) size, so adding them in decreasing size
+/// order never requires alignment padding between them, and first fit decreasing uses the
+/// minimum number of sectors. The only padding left is at the end of each sector.
+pub fn plan_sectors(
+    piece_sizes: &[UnpaddedBytesAmount],
+    sector_size: SectorSize,
+) -> Result<Vec<SectorPlan>> {
+    let sector_bytes = u64::from(sector_size);
+
+    for (i, piece_size) in piece_sizes.iter().enumerate() {
+        let padded_piece_size = u64::from(PaddedBytesAmount::from(*piece_size));
+        ensure!(
+            *piece_size >= UnpaddedBytesAmount(MINIMUM_PIECE_SIZE),
+            "Piece {} must be at least {} bytes",
+            i,
+            MINIMUM_PIECE_SIZE
+        );
+        ensure!(
+            padded_piece_size.is_power_of_two(),
+            "Bit-padded piece size must be a power of 2 ({:?})",
+            PaddedBytesAmount(padded_piece_size),
+        );
+        ensure!(
+            padded_piece_size <= sector_bytes,
+            "Piece {} is larger than sector.",
+            i
+        );
+    }
+
+    let mut order: Vec<usize> = (0..piece_sizes.len()).collect();
+    order.sort_by(|a, b| piece_sizes[*b].cmp(&piece_sizes[*a]));
+
+    // (used padded bytes, candidates) for each sector
+    let mut sectors: Vec<(u64, Vec<usize>)> = Vec::new();
+    for i in order {
+        let padded_piece_size = u64::from(PaddedBytesAmount::from(piece_sizes[i]));
+        match sectors
+            .iter_mut()
+            .find(|(used, _)| used + padded_piece_size <= sector_bytes)
+        {
+            Some((used, candidates)) => {
+                *used += padded_piece_size;
+                candidates.push(i);
+            }
+            None => sectors.push((padded_piece_size, vec![i])),
+        }
+    }
+
+    Ok(sectors
+        .into_iter()
+        .map(|(_, candidates)| {
+            let pieces: Vec<_> = candidates
+                .into_iter()
+                .map(|i| (i, piece_sizes[i]))
+                .collect();
+            layout_sector(&pieces, sector_size)
+        })
+        .collect())
+}
+
+/// Lays out `pieces`, which are sorted by decreasing size, followed by the padding pieces
+/// which fill up the sector.
+fn layout_sector(pieces: &[(usize, UnpaddedBytesAmount)], sector_size: SectorSize) -> SectorPlan {
+    let sector_bytes = u64::from(sector_size);
+    let mut layout = Vec::with_capacity(pieces.len());
+    let mut offset = 0u64;
+
+    for (i, size) in pieces {
+        layout.push(PlannedPiece {
+            candidate: Some(*i),
+            offset: UnpaddedByteIndex::from(UnpaddedBytesAmount::from(PaddedBytesAmount(offset))),
+            size: *size,
+        });
+        offset += u64::from(PaddedBytesAmount::from(*size));
+    }
+
+    // Fill the rest with the largest padding pieces which are aligned at their offset.
+    while offset < sector_bytes {
+        let alignment = if offset == 0 {
+            sector_bytes
+        } else {
+            1 << offset.trailing_zeros()
+        };
+        let size = std::cmp::min(alignment, sector_bytes - offset);
+        layout.push(PlannedPiece {
+            candidate: None,
+            offset: UnpaddedByteIndex::from(UnpaddedBytesAmount::from(PaddedBytesAmount(offset))),
+            size: UnpaddedBytesAmount::from(PaddedBytesAmount(size)),
+        });
+        offset += size;
+    }
+
+    SectorPlan { layout }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -742,4 +916,117 @@ mod tests {
         x |= x >> 16;
         x - (x >> 1)
     }
+
+    #[test]
+    fn test_plan_sector() -> Result<()> {
+        let sector_size = SectorSize(2048);
+        let piece_sizes = [
+            UnpaddedBytesAmount(127),
+            UnpaddedBytesAmount(508),
+            UnpaddedBytesAmount(254),
+            UnpaddedBytesAmount(127),
+        ];
+
+        let plan = plan_sector(&piece_sizes, sector_size)?;
+        assert_eq!(plan.piece_order(), vec![1, 2, 0, 3]);
+        assert_eq!(plan.padding_bytes(), UnpaddedBytesAmount(2032 - 1016));
+
+        // Adding the pieces in the planned order does not need alignment padding.
+        let ordered: Vec<_> = plan.piece_order().iter().map(|i| piece_sizes[*i]).collect();
+        assert_eq!(
+            sum_piece_bytes_with_alignment(&ordered),
+            UnpaddedBytesAmount(1016)
+        );
+        for (i, piece) in plan
+            .layout
+            .iter()
+            .filter(|piece| piece.candidate.is_some())
+            .enumerate()
+        {
+            assert_eq!(
+                get_piece_start_byte(&ordered[..i], piece.size),
+                piece.offset
+            );
+        }
+
+        // The layout covers the whole sector.
+        let last = plan.layout.last().unwrap();
+        assert_eq!(
+            u64::from(last.offset) + u64::from(last.size),
+            u64::from(UnpaddedBytesAmount::from(sector_size))
+        );
+
+        // The layout, including the padding pieces, commits to the staged sector.
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let mut staged_sector = Vec::with_capacity(u64::from(sector_size) as usize);
+        let mut staged_sector_io = std::io::Cursor::new(&mut staged_sector);
+        let mut candidates = vec![PieceInfo::default(); piece_sizes.len()];
+        for (i, candidate) in plan.piece_order().into_iter().enumerate() {
+            let mut piece_bytes = vec![0u8; u64::from(piece_sizes[candidate]) as usize];
+            rng.fill_bytes(&mut piece_bytes);
+
+            let (piece_info, _) = crate::api::add_piece(
+                std::io::Cursor::new(&piece_bytes),
+                &mut staged_sector_io,
+                piece_sizes[candidate],
+                &ordered[..i],
+            )?;
+            candidates[candidate] = piece_info;
+        }
+        staged_sector.resize(u64::from(sector_size) as usize, 0);
+
+        let data_tree = create_base_merkle_tree::<DataTree>(
+            None,
+            u64::from(sector_size) as usize / NODE_SIZE,
+            &staged_sector,
+        )?;
+        let comm_d = commitment_from_fr(data_tree.root().into());
+        assert_eq!(
+            compute_comm_d(sector_size, &plan.piece_infos(&candidates)?)?,
+            comm_d
+        );
+
+        assert!(plan_sector(&[UnpaddedBytesAmount(1016); 3], sector_size).is_err());
+        assert!(plan_sector(&[UnpaddedBytesAmount(100)], sector_size).is_err());
+        assert!(plan_sector(&[UnpaddedBytesAmount(4064)], sector_size).is_err());
+
+        let empty = plan_sector(&[], sector_size)?;
+        assert_eq!(empty.layout.len(), 1);
+        assert_eq!(
+            empty.padding_bytes(),
+            UnpaddedBytesAmount::from(sector_size)
+        );
+        assert_eq!(
+            compute_comm_d(sector_size, &empty.piece_infos(&[])?)?,
+            compute_comm_d(sector_size, &[])?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_sectors() -> Result<()> {
+        let sector_size = SectorSize(2048);
+        let piece_sizes = [
+            UnpaddedBytesAmount(508),
+            UnpaddedBytesAmount(1016),
+            UnpaddedBytesAmount(127),
+            UnpaddedBytesAmount(1016),
+            UnpaddedBytesAmount(508),
+            UnpaddedBytesAmount(254),
+        ];
+
+        let plans = plan_sectors(&piece_sizes, sector_size)?;
+        assert_eq!(plans.len(), 2);
+        assert_eq!(plans[0].piece_order(), vec![1, 3]);
+        assert_eq!(plans[1].piece_order(), vec![0, 4, 5, 2]);
+        assert_eq!(plans[0].padding_bytes(), UnpaddedBytesAmount(0));
+        assert_eq!(plans[1].padding_bytes(), UnpaddedBytesAmount(635));
+
+        let mut placed: Vec<_> = plans.iter().flat_map(|plan| plan.piece_order()).collect();
+        placed.sort();
+        assert_eq!(placed, (0..piece_sizes.len()).collect::<Vec<_>>());
+
+        Ok(())
+    }
 }
@@ -1,6 +1,9 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 use storage_proofs::hasher::Hasher;
 use storage_proofs::porep::stacked;
+use storage_proofs::sector::SectorId;
 
 use crate::constants::*;
 
@@ -34,6 +37,7 @@ pub type LCTree = storage_proofs::merkle::OctLCMerkleTree<DefaultTreeHasher>;
 
 pub use storage_proofs::porep::stacked::Labels;
 pub type DataTree = storage_proofs::merkle::BinaryMerkleTree<DefaultPieceHasher>;
+pub type PieceInclusionProof = storage_proofs::pieces::PieceInclusionProof<DefaultPieceHasher>;
 
 pub use storage_proofs::merkle::MerkleProof;
 pub use storage_proofs::merkle::MerkleTreeTrait;
@@ -71,8 +75,37 @@ pub struct SealCommitOutput {
     pub proof: Vec<u8>,
 }
 
+/// The seal commit proofs of many sectors, aggregated into a single proof.
+pub type AggregateSnarkProof = Vec<u8>;
+
+/// The commitments of a sector, after new data was encoded into it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmptySectorUpdateEncoded {
+    pub comm_r_new: Commitment,
+    pub comm_d_new: Commitment,
+}
+
+pub type VanillaUpdateProof<Tree> = storage_proofs::porep::update::Proof<Tree, DefaultPieceHasher>;
+
+#[derive(Clone, Debug)]
+pub struct EmptySectorUpdateProof {
+    pub proof: Vec<u8>,
+}
+
 pub use merkletree::store::StoreConfig;
 
+/// One sector of `seal_pre_commit_phase1_batch`, with the arguments `seal_pre_commit_phase1`
+/// takes per sector.
+#[derive(Debug, Clone)]
+pub struct SealPreCommitPhase1Input {
+    pub cache_path: PathBuf,
+    pub in_path: PathBuf,
+    pub out_path: PathBuf,
+    pub sector_id: SectorId,
+    pub ticket: Ticket,
+    pub piece_infos: Vec<PieceInfo>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SealPreCommitPhase1Output<Tree: MerkleTreeTrait> {
     #[serde(bound(
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use storage_proofs::parameter_cache::{self, CacheableParameters};
 use storage_proofs::porep::stacked::{StackedCircuit, StackedCompound};
+use storage_proofs::porep::update::{ReplicaUpdateCircuit, ReplicaUpdateCompound};
 
 use crate::constants::DefaultPieceHasher;
 use crate::types::*;
@@ -73,4 +74,31 @@ impl PoRepConfig {
         let id = self.get_cache_identifier::<Tree>()?;
         Ok(parameter_cache::parameter_cache_params_path(&id))
     }
+
+    /// Returns the cache identifier of the empty sector update parameters.
+    pub fn get_update_cache_identifier<Tree: 'static + MerkleTreeTrait>(&self) -> Result<String> {
+        let params = crate::parameters::update_public_params::<Tree>(
+            self.sector_size.into(),
+            self.partitions.into(),
+        )?;
+
+        Ok(
+            <ReplicaUpdateCompound<Tree, DefaultPieceHasher> as CacheableParameters<
+                ReplicaUpdateCircuit<Tree, DefaultPieceHasher>,
+                _,
+            >>::cache_identifier(&params),
+        )
+    }
+
+    pub fn get_update_cache_verifying_key_path<Tree: 'static + MerkleTreeTrait>(
+        &self,
+    ) -> Result<PathBuf> {
+        let id = self.get_update_cache_identifier::<Tree>()?;
+        Ok(parameter_cache::parameter_cache_verifying_key_path(&id))
+    }
+
+    pub fn get_update_cache_params_path<Tree: 'static + MerkleTreeTrait>(&self) -> Result<PathBuf> {
+        let id = self.get_update_cache_identifier::<Tree>()?;
+        Ok(parameter_cache::parameter_cache_params_path(&id))
+    }
 }
@@ -4,11 +4,14 @@ use std::sync::Once;
 
 use anyhow::Result;
 use ff::Field;
+use merkletree::store::StoreConfig;
 use paired::bls12_381::Fr;
 use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
+use storage_proofs::cache_key::CacheKey;
 use storage_proofs::hasher::Hasher;
 use storage_proofs::sector::*;
+use storage_proofs::sector_metadata::SectorMetadata;
 use tempfile::NamedTempFile;
 
 use filecoin_proofs::*;
@@ -289,6 +292,273 @@ fn test_window_post_single_partition_matching_2kib_base_8() -> Result<()> {
     window_post::<SectorShape2KiB>(sector_size, sector_count, sector_count, true)
 }
 
+#[test]
+fn test_check_window_post_sectors_2kib_base_8() -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+
+    let mut sectors = Vec::new();
+    let mut replicas = BTreeMap::new();
+    for _ in 0..3 {
+        let (sector_id, replica, comm_r, cache_dir) =
+            create_fake_seal::<_, SectorShape2KiB>(rng, sector_size)?;
+        replicas.insert(
+            sector_id,
+            PrivateReplicaInfo::<SectorShape2KiB>::new(
+                replica.path().into(),
+                comm_r,
+                cache_dir.path().into(),
+            )?,
+        );
+        sectors.push((sector_id, replica, comm_r, cache_dir));
+    }
+
+    let config = PoStConfig {
+        sector_size: sector_size.into(),
+        sector_count: 3,
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        typ: PoStType::Window,
+        priority: false,
+    };
+    let randomness = [7u8; 32];
+
+    let faulty = check_window_post_sectors(&config, &randomness, &replicas)?;
+    assert!(faulty.is_empty(), "unexpected faults: {:?}", faulty);
+
+    // Remove tree-r-last of the first sector.
+    let (missing_tree_id, _, _, ref cache_dir) = sectors[0];
+    for entry in std::fs::read_dir(cache_dir.path())? {
+        let path = entry?.path();
+        if path.to_string_lossy().contains("tree-r-last") {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    // Use a wrong replica commitment for the second sector.
+    let (wrong_comm_r_id, ref replica, _, ref cache_dir) = sectors[1];
+    let mut wrong_comm_r = sectors[2].2;
+    wrong_comm_r[0] ^= 1;
+    replicas.insert(
+        wrong_comm_r_id,
+        PrivateReplicaInfo::new(replica.path().into(), wrong_comm_r, cache_dir.path().into())?,
+    );
+
+    let faulty = check_window_post_sectors(&config, &randomness, &replicas)?;
+    assert_eq!(faulty.len(), 2, "unexpected faults: {:?}", faulty);
+
+    let missing_tree = faulty
+        .iter()
+        .find(|f| f.sector_id == missing_tree_id)
+        .expect("missing sector with missing tree");
+    match missing_tree.fault {
+        SectorFault::TreeUnreadable(_) | SectorFault::ChallengeUnreadable { .. } => {}
+        ref fault => panic!("unexpected fault: {:?}", fault),
+    }
+
+    let wrong_comm_r = faulty
+        .iter()
+        .find(|f| f.sector_id == wrong_comm_r_id)
+        .expect("missing sector with wrong comm_r");
+    assert_eq!(wrong_comm_r.fault, SectorFault::InvalidCommR);
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_regenerate_missing_trees_2kib_base_8() -> Result<()> {
+    init_logger();
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_bytes: Vec<u8> = (0..number_of_bytes_in_piece.0)
+        .map(|_| rng.gen::<u8>())
+        .collect();
+    let mut piece_file = NamedTempFile::new()?;
+    piece_file.write_all(&piece_bytes)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+
+    let config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS.read().unwrap().get(&sector_size).unwrap(),
+        ),
+        porep_id: [28; 32],
+    };
+    let cache_dir = tempfile::tempdir()?;
+    let sealed_sector_file = NamedTempFile::new()?;
+
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        [0; 32],
+        SectorId::from(1),
+        rng.gen(),
+        &[piece_info],
+    )?;
+    seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let regenerated =
+        regenerate_missing_trees::<SectorShape2KiB>(cache_dir.path(), sealed_sector_file.path())?;
+    assert_eq!(regenerated, RegeneratedTrees::default());
+
+    let remove_trees = |name: &str| -> Result<()> {
+        for entry in std::fs::read_dir(cache_dir.path())? {
+            let path = entry?.path();
+            if path.to_string_lossy().contains(name) {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    };
+    remove_trees("tree-r-last")?;
+    remove_trees("tree-c")?;
+
+    let regenerated =
+        regenerate_missing_trees::<SectorShape2KiB>(cache_dir.path(), sealed_sector_file.path())?;
+    assert!(regenerated.tree_c && regenerated.tree_r_last);
+    validate_cache_for_commit::<_, _, SectorShape2KiB>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    // Once the labels are gone, only tree-r-last can be rebuilt.
+    clear_cache::<SectorShape2KiB>(cache_dir.path())?;
+    remove_trees("tree-r-last")?;
+    let regenerated =
+        regenerate_missing_trees::<SectorShape2KiB>(cache_dir.path(), sealed_sector_file.path())?;
+    assert!(!regenerated.tree_c && regenerated.tree_r_last);
+
+    // A tree built from a corrupted replica is not moved into the cache.
+    remove_trees("tree-r-last")?;
+    let mut replica = std::fs::read(sealed_sector_file.path())?;
+    replica[0] ^= 1;
+    std::fs::write(sealed_sector_file.path(), &replica)?;
+    assert!(regenerate_missing_trees::<SectorShape2KiB>(
+        cache_dir.path(),
+        sealed_sector_file.path()
+    )
+    .is_err());
+    assert!(!std::fs::read_dir(cache_dir.path())?.any(|entry| entry
+        .unwrap()
+        .path()
+        .to_string_lossy()
+        .contains("tree-r-last")));
+
+    // Nodes that are not valid field elements are an error, not a panic.
+    replica[..32].copy_from_slice(&[0xff; 32]);
+    std::fs::write(sealed_sector_file.path(), &replica)?;
+    assert!(regenerate_missing_trees::<SectorShape2KiB>(
+        cache_dir.path(),
+        sealed_sector_file.path()
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_seal_pre_commit_phase1_batch_2kib_base_8() -> Result<()> {
+    init_logger();
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS.read().unwrap().get(&sector_size).unwrap(),
+        ),
+        porep_id: [28; 32],
+    };
+    let prover_id = [9; 32];
+
+    let mut staged = Vec::new();
+    let mut batch_dirs = Vec::new();
+    let mut inputs = Vec::new();
+    for i in 0..3 {
+        let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+        let piece_bytes: Vec<u8> = (0..number_of_bytes_in_piece.0)
+            .map(|_| rng.gen::<u8>())
+            .collect();
+        let mut piece_file = NamedTempFile::new()?;
+        piece_file.write_all(&piece_bytes)?;
+        piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+        let piece_info =
+            generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+        piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+        let mut staged_sector_file = NamedTempFile::new()?;
+        add_piece(
+            &mut piece_file,
+            &mut staged_sector_file,
+            number_of_bytes_in_piece,
+            &[],
+        )?;
+
+        let cache_dir = tempfile::tempdir()?;
+        let sealed_sector_file = NamedTempFile::new()?;
+        inputs.push(SealPreCommitPhase1Input {
+            cache_path: cache_dir.path().into(),
+            in_path: staged_sector_file.path().into(),
+            out_path: sealed_sector_file.path().into(),
+            sector_id: SectorId::from(i),
+            ticket: rng.gen(),
+            piece_infos: vec![piece_info],
+        });
+        staged.push(staged_sector_file);
+        batch_dirs.push((cache_dir, sealed_sector_file));
+    }
+
+    let outputs =
+        seal_pre_commit_phase1_batch::<SectorShape2KiB>(config, prover_id, &inputs, None)?;
+    assert_eq!(outputs.len(), inputs.len());
+
+    for (input, output) in inputs.iter().zip(outputs.into_iter()) {
+        let cache_dir = tempfile::tempdir()?;
+        let sealed_sector_file = NamedTempFile::new()?;
+        let single = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+            config,
+            cache_dir.path(),
+            &input.in_path,
+            sealed_sector_file.path(),
+            prover_id,
+            input.sector_id,
+            input.ticket,
+            &input.piece_infos,
+        )?;
+        assert_eq!(single.comm_d, output.comm_d);
+
+        let single =
+            seal_pre_commit_phase2(config, single, cache_dir.path(), sealed_sector_file.path())?;
+        let batched = seal_pre_commit_phase2(config, output, &input.cache_path, &input.out_path)?;
+        assert_eq!(single.comm_r, batched.comm_r);
+        assert_eq!(
+            std::fs::read(sealed_sector_file.path())?,
+            std::fs::read(&input.out_path)?
+        );
+    }
+
+    Ok(())
+}
+
 fn window_post<Tree: 'static + MerkleTreeTrait>(
     sector_size: u64,
     total_sector_count: usize,
@@ -338,9 +608,496 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
 
     let valid = verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
     assert!(valid, "proof did not verify");
+
+    // Generate the same proof in separate steps, from the vanilla proofs of each sector.
+    let sector_ids: Vec<SectorId> = priv_replicas.keys().copied().collect();
+    let challenges = generate_window_post_challenges::<Tree>(&config, &randomness, &sector_ids)?;
+    assert_eq!(challenges.len(), total_sector_count);
+
+    let mut vanilla_proofs = BTreeMap::new();
+    for (sector_id, sector_challenges) in &challenges {
+        let vanilla_proof = generate_single_window_post_vanilla_proof::<Tree>(
+            &config,
+            &priv_replicas[sector_id],
+            sector_challenges,
+        )?;
+        // Vanilla proofs are meant to be sent between machines.
+        let vanilla_proof = serde_json::from_slice(&serde_json::to_vec(&vanilla_proof)?)?;
+        vanilla_proofs.insert(*sector_id, vanilla_proof);
+    }
+
+    // Proofs listed for the wrong sector are refused.
+    if pub_replicas.len() > 1 {
+        let mut swapped = pub_replicas.clone();
+        let ids: Vec<SectorId> = swapped.keys().copied().collect();
+        let first = swapped[&ids[0]].clone();
+        let second = swapped[&ids[1]].clone();
+        swapped.insert(ids[0], second);
+        swapped.insert(ids[1], first);
+        assert!(generate_window_post_with_vanilla::<Tree>(
+            &config,
+            &randomness,
+            &swapped,
+            prover_id,
+            vanilla_proofs.clone(),
+        )
+        .is_err());
+    }
+
+    let proof = generate_window_post_with_vanilla::<Tree>(
+        &config,
+        &randomness,
+        &pub_replicas,
+        prover_id,
+        vanilla_proofs,
+    )?;
+
+    let valid = verify_window_post::<Tree>(&config, &randomness, &pub_replicas, prover_id, &proof)?;
+    assert!(valid, "proof from vanilla proofs did not verify");
+
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_2kib_base_8() -> Result<()> {
+    empty_sector_update::<SectorShape2KiB>(SECTOR_SIZE_2_KIB)
+}
+
+#[test]
+#[ignore]
+fn test_empty_sector_update_4kib_sub_8_2() -> Result<()> {
+    empty_sector_update::<SectorShape4KiB>(SECTOR_SIZE_4_KIB)
+}
+
+fn empty_sector_update<Tree: 'static + MerkleTreeTrait>(sector_size: u64) -> Result<()> {
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS.read().unwrap().get(&sector_size).unwrap(),
+        ),
+        porep_id: [28; 32],
+    };
+
+    // The sealed sector is used as the sector key.
+    let (_, sector_key_file, comm_r_old, sector_key_cache_dir) =
+        create_seal::<_, Tree>(rng, sector_size, prover_id, true)?;
+
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let piece_bytes: Vec<u8> = (0..number_of_bytes_in_piece.0)
+        .map(|_| rand::random::<u8>())
+        .collect();
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    let (piece_info, _) = add_piece(
+        &piece_bytes[..],
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+    let piece_infos = vec![piece_info];
+
+    let new_replica_file = NamedTempFile::new()?;
+    let new_cache_dir = tempfile::tempdir()?;
+
+    let encoded = encode_into::<Tree>(
+        config,
+        new_replica_file.path(),
+        new_cache_dir.path(),
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        staged_sector_file.path(),
+        &piece_infos,
+    )?;
+    assert_eq!(
+        encoded.comm_d_new,
+        compute_comm_d(config.sector_size, &piece_infos)?
+    );
+
+    let proof = generate_empty_sector_update_proof::<Tree>(
+        config,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        new_replica_file.path(),
+        new_cache_dir.path(),
+    )?;
+
+    assert!(verify_empty_sector_update_proof::<Tree>(
+        config,
+        &proof.proof,
+        comm_r_old,
+        encoded.comm_r_new,
+        encoded.comm_d_new,
+    )?);
+
+    // The proof must not verify for other data.
+    let mut other_comm_d = encoded.comm_d_new;
+    other_comm_d[0] ^= 1;
+    assert!(!verify_empty_sector_update_proof::<Tree>(
+        config,
+        &proof.proof,
+        comm_r_old,
+        encoded.comm_r_new,
+        other_comm_d,
+    )?);
+
+    let out_file = NamedTempFile::new()?;
+    let written = decode_from::<Tree>(
+        config,
+        out_file.path(),
+        new_replica_file.path(),
+        sector_key_file.path(),
+        sector_key_cache_dir.path(),
+        encoded.comm_d_new,
+    )?;
+    assert_eq!(written, number_of_bytes_in_piece);
+
+    let contents = std::fs::read(out_file.path())?;
+    assert_eq!(&piece_bytes[..], &contents[..]);
+
     Ok(())
 }
 
+#[test]
+#[ignore]
+fn test_empty_sector_replica_is_last_layer_2kib() -> Result<()> {
+    // Replica updates use the replica of a sealed empty sector as its sector key, which relies
+    // on that replica being the last layer of labels.
+    let sector_size = SECTOR_SIZE_2_KIB;
+    let config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS.read().unwrap().get(&sector_size).unwrap(),
+        ),
+        porep_id: [28; 32],
+    };
+
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    let zeros: Vec<u8> = vec![0; usize::from(number_of_bytes_in_piece)];
+    let mut staged_sector_file = NamedTempFile::new()?;
+    let (piece_info, _) = add_piece(
+        &zeros[..],
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempfile::tempdir()?;
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, SectorShape2KiB>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        [0; 32],
+        SectorId::from(1),
+        [1; 32],
+        &[piece_info],
+    )?;
+    seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let layers = *LAYERS.read().unwrap().get(&sector_size).unwrap();
+    let labels = std::fs::read(StoreConfig::data_path(
+        cache_dir.path(),
+        &CacheKey::label_layer(layers),
+    ))?;
+    let replica = std::fs::read(sealed_sector_file.path())?;
+    assert_eq!(replica, labels);
+
+    Ok(())
+}
+
+#[test]
+fn test_piece_inclusion_proof_2kib() -> Result<()> {
+    let sector_size = SectorSize(SECTOR_SIZE_2_KIB);
+    let piece_sizes = [UnpaddedBytesAmount(127), UnpaddedBytesAmount(1016)];
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    let mut piece_infos = Vec::new();
+    let mut piece_offsets = Vec::new();
+    for (i, piece_size) in piece_sizes.iter().enumerate() {
+        let piece_bytes: Vec<u8> = (0..u64::from(*piece_size))
+            .map(|_| rand::random::<u8>())
+            .collect();
+
+        piece_offsets.push(filecoin_proofs::pieces::get_piece_start_byte(
+            &piece_sizes[..i],
+            *piece_size,
+        ));
+        let (piece_info, _) = add_piece(
+            &piece_bytes[..],
+            &mut staged_sector_file,
+            *piece_size,
+            &piece_sizes[..i],
+        )?;
+        piece_infos.push(piece_info);
+    }
+
+    let comm_d = filecoin_proofs::pieces::compute_comm_d(sector_size, &piece_infos)?;
+
+    for (piece_info, piece_offset) in piece_infos.iter().zip(piece_offsets.iter()) {
+        staged_sector_file.seek(SeekFrom::Start(0))?;
+        let proof = generate_piece_inclusion_proof_from_unsealed(
+            staged_sector_file.as_file_mut(),
+            sector_size,
+            &comm_d,
+            piece_info,
+            *piece_offset,
+        )?;
+
+        assert!(verify_piece_inclusion_proof(
+            sector_size,
+            &comm_d,
+            piece_info,
+            *piece_offset,
+            &proof
+        )?);
+
+        // The proof does not hold for another piece, nor for another offset.
+        let other_piece = PieceInfo::new([1; 32], piece_info.size)?;
+        assert!(!verify_piece_inclusion_proof(
+            sector_size,
+            &comm_d,
+            &other_piece,
+            *piece_offset,
+            &proof
+        )?);
+
+        // Depending on the piece size, another offset is either misaligned (an error) or does
+        // not verify; it must never be accepted.
+        let other_offset = UnpaddedByteIndex(u64::from(*piece_offset) + 1016);
+        assert!(!matches!(
+            verify_piece_inclusion_proof(sector_size, &comm_d, piece_info, other_offset, &proof),
+            Ok(true)
+        ));
+    }
+
+    Ok(())
+}
+
+// The aggregation tests need a fake srs, run them with `--features fake-srs`.
+
+#[cfg(feature = "fake-srs")]
+#[test]
+#[ignore]
+fn test_aggregate_seal_2kib_base_8() -> Result<()> {
+    aggregate_seal::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, 3)
+}
+
+#[cfg(feature = "fake-srs")]
+#[test]
+#[ignore]
+fn test_aggregate_seal_4kib_sub_8_2() -> Result<()> {
+    aggregate_seal::<SectorShape4KiB>(SECTOR_SIZE_4_KIB, 2)
+}
+
+#[cfg(feature = "fake-srs")]
+fn aggregate_seal<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    num_sectors: usize,
+) -> Result<()> {
+    init_logger();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let config = PoRepConfig {
+        sector_size: SectorSize(sector_size),
+        partitions: PoRepProofPartitions(
+            *POREP_PARTITIONS.read().unwrap().get(&sector_size).unwrap(),
+        ),
+        porep_id: [28; 32],
+    };
+
+    // The fake srs goes into a parameter cache of its own, never next to the real parameters,
+    // as anyone knowing the seed could forge aggregate proofs with it.
+    let (_parameter_cache, ctx) = fake_srs_context()?;
+    ctx.run(|| {
+        storage_proofs::parameter_cache::get_fake_srs_key(rng, SRS_MAX_PROOFS_TO_AGGREGATE)?;
+
+        let mut comm_rs = Vec::with_capacity(num_sectors);
+        let mut comm_ds = Vec::with_capacity(num_sectors);
+        let mut sector_ids = Vec::with_capacity(num_sectors);
+        let mut tickets = Vec::with_capacity(num_sectors);
+        let mut seeds = Vec::with_capacity(num_sectors);
+        let mut commit_outputs = Vec::with_capacity(num_sectors);
+        for _ in 0..num_sectors {
+            let ticket = rng.gen();
+            let seed = rng.gen();
+            let sector_id: SectorId = rng.gen::<u64>().into();
+            let (comm_r, comm_d, commit_output) =
+                create_seal_commit::<Tree>(config, prover_id, sector_id, ticket, seed)?;
+
+            comm_rs.push(comm_r);
+            comm_ds.push(comm_d);
+            sector_ids.push(sector_id);
+            tickets.push(ticket);
+            seeds.push(seed);
+            commit_outputs.push(commit_output);
+        }
+        let prover_ids = vec![prover_id; num_sectors];
+
+        let aggregate_proof = aggregate_seal_commit_proofs::<Tree>(
+            config,
+            &comm_rs,
+            &comm_ds,
+            &prover_ids,
+            &sector_ids,
+            &tickets,
+            &seeds,
+            &commit_outputs,
+        )?;
+        assert!(verify_aggregate_seal::<Tree>(
+            config,
+            &comm_rs,
+            &comm_ds,
+            &prover_ids,
+            &sector_ids,
+            &tickets,
+            &seeds,
+            &aggregate_proof,
+        )?);
+
+        // The proof must not verify for different inputs.
+        let mut wrong_seeds = seeds.clone();
+        wrong_seeds.swap(0, 1);
+        assert!(!verify_aggregate_seal::<Tree>(
+            config,
+            &comm_rs,
+            &comm_ds,
+            &prover_ids,
+            &sector_ids,
+            &tickets,
+            &wrong_seeds,
+            &aggregate_proof,
+        )?);
+
+        // Nor for a subset of the sectors.
+        assert!(!verify_aggregate_seal::<Tree>(
+            config,
+            &comm_rs[1..],
+            &comm_ds[1..],
+            &prover_ids[1..],
+            &sector_ids[1..],
+            &tickets[1..],
+            &seeds[1..],
+            &aggregate_proof,
+        )
+        .unwrap_or(false));
+
+        Ok(())
+    })
+}
+
+/// A context using a parameter cache of its own, with links to the files of the configured one
+/// besides its srs.
+#[cfg(feature = "fake-srs")]
+fn fake_srs_context() -> Result<(tempfile::TempDir, ProofsContext)> {
+    let mut settings = ProofsContext::default().settings().clone();
+    let parameter_cache = tempfile::tempdir()?;
+    for entry in std::fs::read_dir(&settings.parameter_cache)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("srs") {
+            continue;
+        }
+        if let Some(name) = path.file_name() {
+            std::os::unix::fs::symlink(&path, parameter_cache.path().join(name))?;
+        }
+    }
+    settings.parameter_cache = parameter_cache.path().to_string_lossy().to_string();
+
+    Ok((parameter_cache, ProofsContext::new(settings)))
+}
+
+/// Seals a sector filled with random data, returning its `comm_r`, `comm_d` and commit proof.
+#[cfg(feature = "fake-srs")]
+fn create_seal_commit<Tree: 'static + MerkleTreeTrait>(
+    config: PoRepConfig,
+    prover_id: ProverId,
+    sector_id: SectorId,
+    ticket: Ticket,
+    seed: Ticket,
+) -> Result<(Commitment, Commitment, SealCommitOutput)> {
+    let number_of_bytes_in_piece =
+        UnpaddedBytesAmount::from(PaddedBytesAmount::from(config.sector_size));
+
+    let piece_bytes: Vec<u8> = (0..number_of_bytes_in_piece.0)
+        .map(|_| rand::random::<u8>())
+        .collect();
+
+    let mut piece_file = NamedTempFile::new()?;
+    piece_file.write_all(&piece_bytes)?;
+    piece_file.as_file_mut().sync_all()?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    let piece_info = generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+
+    let piece_infos = vec![piece_info];
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempfile::tempdir()?;
+
+    let phase1_output = seal_pre_commit_phase1::<_, _, _, Tree>(
+        config,
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+    let pre_commit_output = seal_pre_commit_phase2(
+        config,
+        phase1_output,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+    )?;
+
+    let comm_d = pre_commit_output.comm_d;
+    let comm_r = pre_commit_output.comm_r;
+
+    let phase1_output = seal_commit_phase1::<_, Tree>(
+        config,
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output,
+        &piece_infos,
+    )?;
+    clear_cache::<Tree>(cache_dir.path())?;
+
+    let commit_output = seal_commit_phase2(config, phase1_output, prover_id, sector_id)?;
+
+    Ok((comm_r, comm_d, commit_output))
+}
+
 fn create_seal<R: Rng, Tree: 'static + MerkleTreeTrait>(
     rng: &mut R,
     sector_size: u64,
@@ -371,7 +1128,7 @@ fn create_seal<R: Rng, Tree: 'static + MerkleTreeTrait>(
         &[],
     )?;
 
-    let piece_infos = vec![piece_info];
+    let piece_infos = vec![piece_info.clone()];
     let arbitrary_porep_id = [28; 32];
     let sealed_sector_file = NamedTempFile::new()?;
     let mut unseal_file = NamedTempFile::new()?;
@@ -416,6 +1173,26 @@ fn create_seal<R: Rng, Tree: 'static + MerkleTreeTrait>(
     let comm_d = pre_commit_output.comm_d;
     let comm_r = pre_commit_output.comm_r;
 
+    let metadata = SectorMetadata::read(cache_dir.path())?.expect("missing sector metadata");
+    assert_eq!(metadata.sector_size, u64::from(config.sector_size));
+    assert_eq!(metadata.porep_id, config.porep_id);
+    metadata.ensure_shape::<Tree>()?;
+
+    let piece_inclusion_proof = generate_piece_inclusion_proof(
+        cache_dir.path(),
+        config.sector_size,
+        &comm_d,
+        &piece_info,
+        UnpaddedByteIndex(0),
+    )?;
+    assert!(verify_piece_inclusion_proof(
+        config.sector_size,
+        &comm_d,
+        &piece_info,
+        UnpaddedByteIndex(0),
+        &piece_inclusion_proof,
+    )?);
+
     validate_cache_for_commit::<_, _, Tree>(cache_dir.path(), sealed_sector_file.path())?;
 
     if skip_proof {
@@ -460,6 +1237,22 @@ fn create_seal<R: Rng, Tree: 'static + MerkleTreeTrait>(
         assert_eq!(contents.len(), 508);
         assert_eq!(&piece_bytes[508..508 + 508], &contents[..]);
 
+        // Seeking to the range decodes the same bytes.
+        let mut seeked = Vec::new();
+        unseal_range_seekable::<_, _, _, Tree>(
+            config,
+            cache_dir.path(),
+            &sealed_sector_file,
+            &mut seeked,
+            prover_id,
+            sector_id,
+            comm_d,
+            ticket,
+            UnpaddedByteIndex(508),
+            UnpaddedBytesAmount(508),
+        )?;
+        assert_eq!(contents, seeked);
+
         let computed_comm_d = compute_comm_d(config.sector_size, &piece_infos)?;
 
         assert_eq!(
@@ -9,6 +9,7 @@ use crate::paramfetch::support::session::ParamFetchSessionBuilder;
 use crate::support::tmp_manifest;
 use blake2b_simd::State as Blake2b;
 use filecoin_proofs::param::{ParameterData, ParameterMap};
+use filecoin_proofs::param_manifest::{generate_signing_key, write_manifest_signature};
 use rand::Rng;
 
 /// Produce a random sequence of bytes and first 32 characters of hex encoded
@@ -43,6 +44,8 @@ fn nothing_to_fetch_if_cache_fully_hydrated() -> Result<(), FailureError> {
             cid: "".to_string(),
             digest: aaa_checksum,
             sector_size: 1234,
+            full_digest: None,
+            locators: vec![],
         },
     );
 
@@ -70,6 +73,8 @@ fn prompts_to_download_if_file_in_manifest_is_missing() -> Result<(), FailureErr
             cid: "".to_string(),
             digest: "".to_string(),
             sector_size: 1234,
+            full_digest: None,
+            locators: vec![],
         },
     );
 
@@ -99,6 +104,8 @@ fn prompts_to_download_if_file_checksum_does_not_match_manifest() -> Result<(),
             cid: "".to_string(),
             digest: "obviouslywrong".to_string(),
             sector_size: 5555,
+            full_digest: None,
+            locators: vec![],
         },
     );
 
@@ -129,6 +136,8 @@ fn fetches_vk_even_if_sector_size_does_not_match() -> Result<(), FailureError> {
             cid: "".to_string(),
             digest: "".to_string(),
             sector_size: 1234,
+            full_digest: None,
+            locators: vec![],
         },
     );
 
@@ -138,6 +147,8 @@ fn fetches_vk_even_if_sector_size_does_not_match() -> Result<(), FailureError> {
             cid: "".to_string(),
             digest: "".to_string(),
             sector_size: 1234,
+            full_digest: None,
+            locators: vec![],
         },
     );
 
@@ -202,3 +213,51 @@ fn no_json_path_uses_default_manifest() -> Result<(), FailureError> {
 
     Ok(())
 }
+
+#[test]
+fn refuses_unsigned_manifest_if_signature_required() -> Result<(), FailureError> {
+    let manifest_pbuf = tmp_manifest(Some(BTreeMap::new()))?;
+
+    let key_dir = tempfile::tempdir()?;
+    let keypair =
+        generate_signing_key(&key_dir.path().join("signing.key")).map_err(failure::err_msg)?;
+
+    let mut session = ParamFetchSessionBuilder::new(Some(manifest_pbuf))
+        .with_session_timeout_ms(1000)
+        .with_arg("--require-signature")
+        .with_arg(format!(
+            "--trusted-key={}",
+            hex::encode(keypair.public.as_bytes())
+        ))
+        .build();
+
+    session.exp_string("fatal error: could not read signature")?;
+
+    Ok(())
+}
+
+#[test]
+fn refuses_manifest_signed_by_untrusted_key() -> Result<(), FailureError> {
+    let manifest: ParameterMap = BTreeMap::new();
+    let manifest_pbuf = tmp_manifest(Some(BTreeMap::new()))?;
+
+    let key_dir = tempfile::tempdir()?;
+    let signer =
+        generate_signing_key(&key_dir.path().join("signer.key")).map_err(failure::err_msg)?;
+    let trusted =
+        generate_signing_key(&key_dir.path().join("trusted.key")).map_err(failure::err_msg)?;
+    write_manifest_signature(&manifest, &manifest_pbuf, &signer).map_err(failure::err_msg)?;
+
+    // the signature is checked because it is present, even without --require-signature
+    let mut session = ParamFetchSessionBuilder::new(Some(manifest_pbuf))
+        .with_session_timeout_ms(1000)
+        .with_arg(format!(
+            "--trusted-key={}",
+            hex::encode(trusted.public.as_bytes())
+        ))
+        .build();
+
+    session.exp_string("is not signed by a trusted key")?;
+
+    Ok(())
+}
@@ -15,6 +15,7 @@ pub struct ParamFetchSessionBuilder {
     whitelisted_sector_sizes: Option<Vec<String>>,
     manifest: Option<PathBuf>,
     prompt_enabled: bool,
+    extra_args: Vec<String>,
 }
 
 impl ParamFetchSessionBuilder {
@@ -27,6 +28,7 @@ impl ParamFetchSessionBuilder {
             manifest,
             prompt_enabled: true,
             whitelisted_sector_sizes: None,
+            extra_args: vec![],
         }
     }
 
@@ -45,6 +47,12 @@ impl ParamFetchSessionBuilder {
         self
     }
 
+    /// Pass an additional argument to paramfetch.
+    pub fn with_arg<S: Into<String>>(mut self, arg: S) -> ParamFetchSessionBuilder {
+        self.extra_args.push(arg.into());
+        self
+    }
+
     /// Create a file with the provided bytes in the cache directory.
     pub fn with_file_and_bytes<P: AsRef<Path>, R: Read>(
         self,
@@ -86,13 +94,14 @@ impl ParamFetchSessionBuilder {
         };
 
         let cmd = format!(
-            "{}={} {:?} {} {} {} --ipget-bin={:?}",
+            "{}={} {:?} {} {} {} {} --ipget-bin={:?}",
             "FIL_PROOFS_PARAMETER_CACHE", // related to var name in core/src/settings.rs
             cache_dir_path,
             paramfetch_path,
             if self.prompt_enabled { "" } else { "--all" },
             json_argument,
             whitelist,
+            self.extra_args.join(" "),
             "true"
         );
 
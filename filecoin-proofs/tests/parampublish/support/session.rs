@@ -19,6 +19,8 @@ pub struct ParamPublishSessionBuilder {
     manifest: PathBuf,
     ipfs_bin_path: PathBuf,
     prompt_enabled: bool,
+    signing_key: Option<PathBuf>,
+    mirror_dir: Option<PathBuf>,
 }
 
 impl ParamPublishSessionBuilder {
@@ -37,6 +39,8 @@ impl ParamPublishSessionBuilder {
             manifest: pbuf,
             ipfs_bin_path: cargo_bin("fakeipfsadd"),
             prompt_enabled: true,
+            signing_key: None,
+            mirror_dir: None,
         }
     }
 
@@ -47,6 +51,12 @@ impl ParamPublishSessionBuilder {
         self
     }
 
+    /// Configure an IPFS binary which does not exist, so that publishing to IPFS fails.
+    pub fn with_missing_ipfs_bin(mut self) -> ParamPublishSessionBuilder {
+        self.ipfs_bin_path = self.cache_dir.path().join("missing-ipfs");
+        self
+    }
+
     /// Create empty files with the given names in the cache directory.
     pub fn with_files<P: AsRef<Path>>(self, filenames: &[P]) -> ParamPublishSessionBuilder {
         filenames.iter().fold(self, |acc, item| acc.with_file(item))
@@ -115,6 +125,18 @@ impl ParamPublishSessionBuilder {
         self
     }
 
+    /// Sign the manifest with the key in the provided file.
+    pub fn with_signing_key(mut self, key_path: PathBuf) -> ParamPublishSessionBuilder {
+        self.signing_key = Some(key_path);
+        self
+    }
+
+    /// Also publish the files to the provided directory.
+    pub fn with_mirror_dir(mut self, mirror_dir: PathBuf) -> ParamPublishSessionBuilder {
+        self.mirror_dir = Some(mirror_dir);
+        self
+    }
+
     /// Launch parampublish in an environment configured by the builder.
     pub fn build(self) -> (ParamPublishSession, Vec<PathBuf>) {
         let mut p = spawn_bash_with_retries(10, Some(self.session_timeout_ms))
@@ -130,14 +152,26 @@ impl ParamPublishSessionBuilder {
 
         let parampublish_path = cargo_bin("parampublish");
 
+        let signing_key_argument = self
+            .signing_key
+            .map(|path| format!("--signing-key={:?}", path))
+            .unwrap_or_else(|| "".to_string());
+
+        let mirror_dir_argument = self
+            .mirror_dir
+            .map(|path| format!("--mirror-dir={:?}", path))
+            .unwrap_or_else(|| "".to_string());
+
         let cmd = format!(
-            "{}={} {:?} {} --ipfs-bin={:?} --json={:?}",
+            "{}={} {:?} {} --ipfs-bin={:?} --json={:?} {} {}",
             "FIL_PROOFS_PARAMETER_CACHE", // related to var name in core/src/settings.rs
             cache_dir_path,
             parampublish_path,
             if self.prompt_enabled { "" } else { "--all" },
             self.ipfs_bin_path,
-            self.manifest
+            self.manifest,
+            signing_key_argument,
+            mirror_dir_argument
         );
 
         p.execute(&cmd, ".*")
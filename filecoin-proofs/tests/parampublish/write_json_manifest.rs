@@ -4,7 +4,8 @@ use std::path::Path;
 
 use failure::Error as FailureError;
 
-use filecoin_proofs::param::ParameterData;
+use filecoin_proofs::param::{Locator, ParameterData};
+use filecoin_proofs::param_manifest::{generate_signing_key, read_signed_manifest};
 use storage_proofs::parameter_cache::CacheEntryMetadata;
 
 use crate::parampublish::support::session::ParamPublishSessionBuilder;
@@ -67,6 +68,147 @@ fn writes_json_manifest() -> Result<(), FailureError> {
     Ok(())
 }
 
+#[test]
+fn writes_signed_json_manifest() -> Result<(), FailureError> {
+    let filenames = vec!["v10-aaa.vk", "v10-aaa.params"];
+
+    let manifest_path = tmp_manifest(None)?;
+    let key_dir = tempfile::tempdir()?;
+    let key_path = key_dir.path().join("signing.key");
+    let keypair = generate_signing_key(&key_path).map_err(failure::err_msg)?;
+
+    let ipfs = FakeIpfsBin::new();
+
+    let (mut session, _) = ParamPublishSessionBuilder::new()
+        .with_session_timeout_ms(1000)
+        .with_files(&filenames)
+        .with_metadata("v10-aaa.meta", &CacheEntryMetadata { sector_size: 1234 })
+        .write_manifest_to(manifest_path.clone())
+        .with_ipfs_bin(&ipfs)
+        .with_signing_key(key_path)
+        .with_prompt_disabled()
+        .build();
+
+    session.exp_string("Select a version")?;
+    session.send_line("")?;
+    session.exp_string("Select the sizes to publish")?;
+    session.send_line("")?;
+
+    session.exp_string("publishing 2 files")?;
+    session.exp_string("signed manifest")?;
+    session.exp_string("done")?;
+
+    // the signature verifies, and each entry carries its full digest
+    let manifest_map =
+        read_signed_manifest(&manifest_path, &[keypair.public]).map_err(failure::err_msg)?;
+    for filename in filenames.iter().cloned() {
+        let entry = manifest_map
+            .get(filename)
+            .unwrap_or_else(|| panic!("{} must be present in manifest", filename));
+        let full_digest = entry.full_digest.as_ref().expect("missing full digest");
+        assert_eq!(full_digest.len(), 128);
+        assert!(full_digest.starts_with(&entry.digest));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn writes_mirror_locators_to_json_manifest() -> Result<(), FailureError> {
+    let filenames = vec!["v10-aaa.vk", "v10-aaa.params"];
+
+    let manifest_path = tmp_manifest(None)?;
+    let mirror_dir = tempfile::tempdir()?;
+
+    let ipfs = FakeIpfsBin::new();
+
+    let (mut session, files_in_cache) = ParamPublishSessionBuilder::new()
+        .with_session_timeout_ms(1000)
+        .with_files(&filenames)
+        .with_metadata("v10-aaa.meta", &CacheEntryMetadata { sector_size: 1234 })
+        .write_manifest_to(manifest_path.clone())
+        .with_ipfs_bin(&ipfs)
+        .with_mirror_dir(mirror_dir.path().to_path_buf())
+        .with_prompt_disabled()
+        .build();
+
+    session.exp_string("Select a version")?;
+    session.send_line("")?;
+    session.exp_string("Select the sizes to publish")?;
+    session.send_line("")?;
+
+    session.exp_string("publishing 2 files")?;
+    session.exp_string("done")?;
+
+    let manifest_file = File::open(&manifest_path)?;
+    let manifest_map: BTreeMap<String, ParameterData> = serde_json::from_reader(manifest_file)?;
+
+    // each entry keeps its cid and records the copy in the mirror, which
+    // paramfetch tries after the recorded locators
+    for path in files_in_cache.iter().filter(|path| {
+        path.extension()
+            .map(|ext| ext == "vk" || ext == "params")
+            .unwrap_or(false)
+    }) {
+        let filename = path.file_name().and_then(|name| name.to_str()).unwrap();
+        let entry = manifest_map
+            .get(filename)
+            .unwrap_or_else(|| panic!("{} must be present in manifest", filename));
+        assert!(!entry.cid.is_empty());
+
+        match entry.locators.as_slice() {
+            [Locator::File(mirrored)] => {
+                assert_eq!(std::fs::read(mirrored)?, std::fs::read(path)?);
+            }
+            other => panic!("unexpected locators {:?} for {}", other, filename),
+        }
+        assert_eq!(entry.all_locators().len(), 2);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn keeps_locators_of_successful_backends() -> Result<(), FailureError> {
+    let filenames = vec!["v10-aaa.vk", "v10-aaa.params"];
+
+    let manifest_path = tmp_manifest(None)?;
+    let mirror_dir = tempfile::tempdir()?;
+
+    let (mut session, _) = ParamPublishSessionBuilder::new()
+        .with_session_timeout_ms(1000)
+        .with_files(&filenames)
+        .with_metadata("v10-aaa.meta", &CacheEntryMetadata { sector_size: 1234 })
+        .write_manifest_to(manifest_path.clone())
+        .with_missing_ipfs_bin()
+        .with_mirror_dir(mirror_dir.path().to_path_buf())
+        .with_prompt_disabled()
+        .build();
+
+    session.exp_string("Select a version")?;
+    session.send_line("")?;
+    session.exp_string("Select the sizes to publish")?;
+    session.send_line("")?;
+
+    session.exp_string("publishing 2 files")?;
+    session.exp_string("failed to publish")?;
+    session.exp_string("fatal error: 2 uploads failed")?;
+
+    // the files are still listed, with the mirror as their only place
+    let manifest_file = File::open(&manifest_path)?;
+    let manifest_map: BTreeMap<String, ParameterData> = serde_json::from_reader(manifest_file)?;
+    assert_eq!(manifest_map.len(), 2);
+    for (filename, entry) in manifest_map {
+        assert!(entry.cid.is_empty(), "{} has a cid", filename);
+        match entry.locators.as_slice() {
+            [Locator::File(mirrored)] => assert!(mirrored.ends_with(&filename)),
+            other => panic!("unexpected locators {:?} for {}", other, filename),
+        }
+    }
+
+    Ok(())
+}
+
 /// Produce a map of filename (not path) to the checksum produced by the ipfs
 /// binary.
 fn filename_to_checksum<P: AsRef<Path>>(
@@ -10,8 +10,13 @@ mod consts;
 mod platform;
 mod sha256;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod sha256_avx2;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod sha256_intrinsics;
 mod sha256_utils;
+mod stream;
 
 pub use digest::Digest;
-pub use sha256::Sha256;
+pub use platform::LANES;
+pub use sha256::{compress256_x8, Sha256, Sha256x8};
+pub use stream::Sha256Stream;
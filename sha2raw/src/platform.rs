@@ -1,3 +1,8 @@
+use crate::consts::STATE_LEN;
+
+/// The number of messages hashed at once by `LanesImplementation`.
+pub const LANES: usize = 8;
+
 #[allow(dead_code)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Platform {
@@ -11,6 +16,19 @@ enum Platform {
 #[derive(Clone, Copy, Debug)]
 pub struct Implementation(Platform);
 
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+enum LanesPlatform {
+    /// One lane after the other, using a single message implementation.
+    Serial(Implementation),
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    Avx2,
+}
+
+/// Hashes `LANES` independent messages at once.
+#[derive(Clone, Copy, Debug)]
+pub struct LanesImplementation(LanesPlatform);
+
 impl Implementation {
     pub fn detect() -> Self {
         // Try the different implementations in order of how fast/modern they are.
@@ -85,3 +103,70 @@ impl Implementation {
         }
     }
 }
+
+impl LanesImplementation {
+    pub fn detect() -> Self {
+        // SHA-NI on a single message at a time beats AVX2 across all lanes.
+        #[cfg(target_arch = "x86_64")]
+        {
+            if let Some(sha_impl) = Implementation::sha_if_supported() {
+                return Self::serial(sha_impl);
+            }
+            if let Some(avx2_impl) = Self::avx2_if_supported() {
+                return avx2_impl;
+            }
+        }
+
+        Self::serial(Implementation::detect())
+    }
+
+    pub fn serial(implementation: Implementation) -> Self {
+        LanesImplementation(LanesPlatform::Serial(implementation))
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn avx2_if_supported() -> Option<Self> {
+        // Use raw_cpuid instead of is_x86_feature_detected, to ensure the check
+        // never happens at compile time.
+        if cpuid_bool::cpuid_bool!("avx2") {
+            return Some(LanesImplementation(LanesPlatform::Avx2));
+        }
+
+        None
+    }
+
+    /// Compresses the blocks of each lane into the state of the lane. Every lane must have the
+    /// same, even number of 32 byte blocks.
+    ///
+    /// # Panics
+    ///
+    /// If the lanes do not have the same, even number of 32 byte blocks, whatever the backend.
+    #[inline]
+    pub fn compress256_x8(self, states: &mut [[u32; STATE_LEN]; LANES], blocks: [&[&[u8]]; LANES]) {
+        let len = blocks[0].len();
+        assert_eq!(len % 2, 0, "the number of blocks must be even");
+        for lane in &blocks {
+            assert_eq!(
+                lane.len(),
+                len,
+                "all lanes must have the same number of blocks"
+            );
+            for block in lane.iter() {
+                assert_eq!(block.len(), 32, "blocks must be 32 bytes");
+            }
+        }
+
+        match self.0 {
+            LanesPlatform::Serial(implementation) => {
+                for (state, blocks) in states.iter_mut().zip(blocks.iter()) {
+                    implementation.compress256(state, blocks);
+                }
+            }
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            LanesPlatform::Avx2 => {
+                use crate::sha256_avx2;
+                unsafe { sha256_avx2::compress256_x8(states, blocks) };
+            }
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use block_buffer::byteorder::{ByteOrder, BE};
 
-use crate::consts::H256;
-use crate::platform::Implementation;
+use crate::consts::{H256, STATE_LEN};
+use crate::platform::{Implementation, LanesImplementation, LANES};
 
 lazy_static::lazy_static! {
-    static ref IMPL: Implementation = Implementation::detect();
+    pub(crate) static ref IMPL: Implementation = Implementation::detect();
+    static ref LANES_IMPL: LanesImplementation = LanesImplementation::detect();
 }
 
 #[derive(Clone)]
@@ -81,6 +82,105 @@ impl Sha256 {
 
 opaque_debug::impl_opaque_debug!(Sha256);
 
+/// Compresses the blocks of `LANES` independent messages into their states at once. Every lane
+/// must have the same, even number of 32 byte blocks.
+pub fn compress256_x8(states: &mut [[u32; 8]; LANES], blocks: [&[&[u8]]; LANES]) {
+    LANES_IMPL.compress256_x8(states, blocks);
+}
+
+/// Hashes `LANES` messages of the same length in lockstep, using AVX2 where it is faster than
+/// hashing them one by one.
+#[derive(Clone)]
+pub struct Sha256x8 {
+    len: u64,
+    states: [[u32; STATE_LEN]; LANES],
+}
+
+impl Default for Sha256x8 {
+    fn default() -> Self {
+        Sha256x8 {
+            len: 0,
+            states: [H256; LANES],
+        }
+    }
+}
+
+impl Sha256x8 {
+    pub fn new() -> Self {
+        Sha256x8::default()
+    }
+
+    /// Adds the blocks of each lane to its message, all lanes must have the same, even number of
+    /// 32 byte blocks.
+    ///
+    /// # Panics
+    ///
+    /// If the lanes differ in their number of blocks, or it is odd.
+    pub fn input(&mut self, blocks: [&[&[u8]]; LANES]) {
+        LANES_IMPL.compress256_x8(&mut self.states, blocks);
+
+        self.len += (blocks[0].len() as u64) << 8;
+    }
+
+    pub fn finish(mut self) -> [[u8; 32]; LANES] {
+        let mut block0 = [0u8; 32];
+        let mut block1 = [0u8; 32];
+
+        // Append single 1 bit
+        block0[0] = 0b1000_0000;
+
+        // Write L as 64 big endian integer
+        let l = self.len;
+        block1[32 - 8..].copy_from_slice(&l.to_be_bytes()[..]);
+
+        let padding = [&block0[..], &block1[..]];
+        LANES_IMPL.compress256_x8(&mut self.states, [&padding[..]; LANES]);
+
+        self.output()
+    }
+
+    pub fn finish_with(mut self, block0: [&[u8]; LANES]) -> [[u8; 32]; LANES] {
+        let mut block1 = [0u8; 32];
+
+        // Append single 1 bit
+        block1[0] = 0b1000_0000;
+
+        // Write L as 64 big endian integer
+        let l = self.len + 256;
+        block1[32 - 8..].copy_from_slice(&l.to_be_bytes()[..]);
+
+        let mut padding = [[&block1[..]; 2]; LANES];
+        for (padding, block0) in padding.iter_mut().zip(block0.iter()) {
+            padding[0] = *block0;
+        }
+        LANES_IMPL.compress256_x8(
+            &mut self.states,
+            [
+                &padding[0][..],
+                &padding[1][..],
+                &padding[2][..],
+                &padding[3][..],
+                &padding[4][..],
+                &padding[5][..],
+                &padding[6][..],
+                &padding[7][..],
+            ],
+        );
+
+        self.output()
+    }
+
+    fn output(&self) -> [[u8; 32]; LANES] {
+        let mut out = [[0u8; 32]; LANES];
+        for (out, state) in out.iter_mut().zip(self.states.iter()) {
+            BE::write_u32_into(state, out);
+        }
+        out
+    }
+}
+
+opaque_debug::impl_opaque_debug!(Sha256x8);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,6 +200,89 @@ mod tests {
         fuzz(1_000);
     }
 
+    #[test]
+    fn test_fuzz_x8() {
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+
+        for k in 0..10 {
+            let mut inputs = vec![vec![0u8; 64 * k + 32]; LANES];
+            for input in &mut inputs {
+                rng.fill_bytes(input);
+            }
+            let blocks: Vec<Vec<&[u8]>> = inputs
+                .iter()
+                .map(|input| input[..64 * k].chunks(32).collect())
+                .collect();
+            let lanes = [
+                &blocks[0][..],
+                &blocks[1][..],
+                &blocks[2][..],
+                &blocks[3][..],
+                &blocks[4][..],
+                &blocks[5][..],
+                &blocks[6][..],
+                &blocks[7][..],
+            ];
+
+            let mut hasher = Sha256x8::new();
+            hasher.input(lanes);
+            let hashes = hasher.clone().finish();
+            for (hash, input) in hashes.iter().zip(&inputs) {
+                assert_eq!(&hash[..], &Original::digest(&input[..64 * k])[..]);
+            }
+
+            let last = |i: usize| &inputs[i][64 * k..];
+            let hashes = hasher.finish_with([
+                last(0),
+                last(1),
+                last(2),
+                last(3),
+                last(4),
+                last(5),
+                last(6),
+                last(7),
+            ]);
+            for (hash, input) in hashes.iter().zip(&inputs) {
+                assert_eq!(&hash[..], &Original::digest(input)[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lanes_are_checked_by_every_backend() {
+        let block = [0u8; 32];
+        let one = [&block[..]];
+        let two = [&block[..]; 2];
+        let four = [&block[..]; 4];
+        let short = [&block[..16]; 2];
+
+        #[allow(unused_mut)]
+        let mut backends = vec![LanesImplementation::serial(Implementation::portable())];
+        #[cfg(target_arch = "x86_64")]
+        backends.extend(LanesImplementation::avx2_if_supported());
+
+        for backend in backends {
+            let accepts = |blocks: [&[&[u8]]; LANES]| {
+                std::panic::catch_unwind(|| {
+                    let mut states = [H256; LANES];
+                    backend.compress256_x8(&mut states, blocks);
+                })
+                .is_ok()
+            };
+
+            let mut lanes = [&two[..]; LANES];
+            assert!(accepts(lanes), "{:?}", backend);
+            lanes[LANES - 1] = &four[..];
+            assert!(!accepts(lanes), "{:?}", backend);
+            lanes[LANES - 1] = &short[..];
+            assert!(!accepts(lanes), "{:?}", backend);
+            assert!(!accepts([&one[..]; LANES]), "{:?}", backend);
+        }
+    }
+
     fn fuzz(n: usize) {
         let rng = &mut XorShiftRng::from_seed([
             0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
@@ -0,0 +1,226 @@
+#![allow(clippy::many_single_char_names)]
+#![allow(clippy::cast_ptr_alignment)] // Safe to cast without alignment checks as the loads and stores do not require alignment.
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use block_buffer::byteorder::{ByteOrder, BE};
+
+use crate::consts::{K32, STATE_LEN};
+use crate::platform::LANES;
+
+macro_rules! add {
+    ($a:expr, $b:expr) => {
+        _mm256_add_epi32($a, $b)
+    };
+    ($a:expr, $b:expr, $($rest:expr),+) => {
+        add!(_mm256_add_epi32($a, $b), $($rest),+)
+    };
+}
+
+macro_rules! xor {
+    ($a:expr, $b:expr) => {
+        _mm256_xor_si256($a, $b)
+    };
+    ($a:expr, $b:expr, $c:expr) => {
+        _mm256_xor_si256(_mm256_xor_si256($a, $b), $c)
+    };
+}
+
+macro_rules! rotr {
+    ($x:expr, $n:expr) => {
+        _mm256_or_si256(_mm256_srli_epi32($x, $n), _mm256_slli_epi32($x, 32 - $n))
+    };
+}
+
+macro_rules! sigma0 {
+    ($x:expr) => {
+        xor!(rotr!($x, 7), rotr!($x, 18), _mm256_srli_epi32($x, 3))
+    };
+}
+
+macro_rules! sigma1 {
+    ($x:expr) => {
+        xor!(rotr!($x, 17), rotr!($x, 19), _mm256_srli_epi32($x, 10))
+    };
+}
+
+macro_rules! big_sigma0 {
+    ($x:expr) => {
+        xor!(rotr!($x, 2), rotr!($x, 13), rotr!($x, 22))
+    };
+}
+
+macro_rules! big_sigma1 {
+    ($x:expr) => {
+        xor!(rotr!($x, 6), rotr!($x, 11), rotr!($x, 25))
+    };
+}
+
+/// Choose, `(e & f) ^ (!e & g)`.
+macro_rules! ch {
+    ($e:expr, $f:expr, $g:expr) => {
+        _mm256_xor_si256(_mm256_and_si256($e, $f), _mm256_andnot_si256($e, $g))
+    };
+}
+
+/// Majority, `(a & b) ^ (a & c) ^ (b & c)`.
+macro_rules! maj {
+    ($a:expr, $b:expr, $c:expr) => {
+        _mm256_or_si256(
+            _mm256_and_si256($a, $b),
+            _mm256_and_si256($c, _mm256_or_si256($a, $b)),
+        )
+    };
+}
+
+/// Loads word `word` of each lane, big endian, from the 64 byte block made of the two 32 byte
+/// halves `blocks[lane][index]` and `blocks[lane][index + 1]`.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn load_word(blocks: &[&[&[u8]]; LANES], index: usize, word: usize) -> __m256i {
+    let half = index + word / 8;
+    let start = (word % 8) * 4;
+
+    let mut lanes = [0u32; LANES];
+    for (lane, value) in lanes.iter_mut().enumerate() {
+        *value = BE::read_u32(&blocks[lane][half][start..start + 4]);
+    }
+    _mm256_loadu_si256(lanes.as_ptr() as *const __m256i)
+}
+
+/// Processes the blocks of eight messages at once, each lane of the 256 bit registers holding
+/// the state of one message.
+///
+/// # Safety
+///
+/// AVX2 must be available, and every lane must have the same, even number of 32 byte blocks, as
+/// checked by `LanesImplementation::compress256_x8`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn compress256_x8(states: &mut [[u32; STATE_LEN]; LANES], blocks: [&[&[u8]]; LANES]) {
+    let len = blocks[0].len();
+
+    let mut state = [_mm256_setzero_si256(); STATE_LEN];
+    for (i, s) in state.iter_mut().enumerate() {
+        let mut lanes = [0u32; LANES];
+        for (lane, value) in lanes.iter_mut().enumerate() {
+            *value = states[lane][i];
+        }
+        *s = _mm256_loadu_si256(lanes.as_ptr() as *const __m256i);
+    }
+
+    for index in (0..len).step_by(2) {
+        let mut w = [_mm256_setzero_si256(); 16];
+        for (word, w) in w.iter_mut().enumerate() {
+            *w = load_word(&blocks, index, word);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+
+        for (t, k) in K32.iter().enumerate() {
+            // Message schedule, kept in a sliding window of 16 words.
+            let wt = if t < 16 {
+                w[t]
+            } else {
+                let next = add!(
+                    sigma1!(w[(t - 2) % 16]),
+                    w[(t - 7) % 16],
+                    sigma0!(w[(t - 15) % 16]),
+                    w[t % 16]
+                );
+                w[t % 16] = next;
+                next
+            };
+
+            let t1 = add!(
+                h,
+                big_sigma1!(e),
+                ch!(e, f, g),
+                _mm256_set1_epi32(*k as i32),
+                wt
+            );
+            let t2 = add!(big_sigma0!(a), maj!(a, b, c));
+
+            h = g;
+            g = f;
+            f = e;
+            e = add!(d, t1);
+            d = c;
+            c = b;
+            b = a;
+            a = add!(t1, t2);
+        }
+
+        for (s, v) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *s = add!(*s, *v);
+        }
+    }
+
+    for (i, s) in state.iter().enumerate() {
+        let mut lanes = [0u32; LANES];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, *s);
+        for (lane, value) in lanes.iter().enumerate() {
+            states[lane][i] = *value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    use crate::consts::H256;
+    use crate::sha256_utils;
+
+    #[test]
+    fn test_compress256_x8_matches_portable() {
+        if !is_x86_feature_detected!("avx2") {
+            println!("WARN: avx2 not available, skipping");
+            return;
+        }
+
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+
+        for blocks_len in (0..12).step_by(2) {
+            let mut data = vec![0u8; LANES * blocks_len * 32];
+            rng.fill_bytes(&mut data);
+
+            let blocks: Vec<Vec<&[u8]>> = (0..LANES)
+                .map(|lane| {
+                    let start = lane * blocks_len * 32;
+                    data[start..start + blocks_len * 32].chunks(32).collect()
+                })
+                .collect();
+
+            let mut states = [H256; LANES];
+            // Different initial states per lane, to catch mixed up lanes.
+            for (lane, state) in states.iter_mut().enumerate() {
+                state[lane] ^= 0xdead_beef;
+            }
+            let mut expected = states;
+
+            unsafe {
+                compress256_x8(
+                    &mut states,
+                    [
+                        &blocks[0], &blocks[1], &blocks[2], &blocks[3], &blocks[4], &blocks[5],
+                        &blocks[6], &blocks[7],
+                    ],
+                )
+            };
+            for (state, blocks) in expected.iter_mut().zip(&blocks) {
+                sha256_utils::compress256(state, blocks);
+            }
+
+            assert_eq!(states, expected, "{} blocks", blocks_len);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use block_buffer::byteorder::{ByteOrder, BE};
+use digest::consts::{U32, U64};
+use digest::generic_array::GenericArray;
+use digest::{BlockInput, FixedOutputDirty, Reset, Update};
+
+use crate::consts::{H256, STATE_LEN};
+use crate::sha256::IMPL;
+
+/// The number of 64 byte blocks handed to the compression function at once.
+const BATCH_BLOCKS: usize = 8;
+
+/// Incremental SHA-256 over byte slices of any length, taking care of the padding.
+///
+/// Unlike `Sha256`, which only takes whole blocks, this implements the `digest` traits and can
+/// be used in place of `sha2::Sha256`, with the same choice of SHA-NI, asm or portable
+/// compression.
+#[derive(Clone)]
+pub struct Sha256Stream {
+    /// The number of bytes hashed so far.
+    len: u64,
+    state: [u32; STATE_LEN],
+    /// Bytes not forming a whole block yet.
+    buffer: [u8; 64],
+    buffered: usize,
+}
+
+impl Default for Sha256Stream {
+    fn default() -> Self {
+        Sha256Stream {
+            len: 0,
+            state: H256,
+            buffer: [0; 64],
+            buffered: 0,
+        }
+    }
+}
+
+impl Sha256Stream {
+    fn process(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+
+        if self.buffered > 0 {
+            let n = (64 - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + n].copy_from_slice(&data[..n]);
+            self.buffered += n;
+            data = &data[n..];
+
+            if self.buffered < 64 {
+                return;
+            }
+            IMPL.compress256(
+                &mut self.state,
+                &[&self.buffer[..32], &self.buffer[32..]][..],
+            );
+            self.buffered = 0;
+        }
+
+        let whole = data.len() / 64 * 64;
+        for chunk in data[..whole].chunks(64 * BATCH_BLOCKS) {
+            let mut blocks: [&[u8]; 2 * BATCH_BLOCKS] = [&[]; 2 * BATCH_BLOCKS];
+            let count = chunk.len() / 32;
+            for (block, half) in blocks.iter_mut().zip(chunk.chunks(32)) {
+                *block = half;
+            }
+            IMPL.compress256(&mut self.state, &blocks[..count]);
+        }
+
+        let rest = &data[whole..];
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+}
+
+impl BlockInput for Sha256Stream {
+    type BlockSize = U64;
+}
+
+impl Update for Sha256Stream {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        self.process(data.as_ref());
+    }
+}
+
+impl FixedOutputDirty for Sha256Stream {
+    type OutputSize = U32;
+
+    fn finalize_into_dirty(&mut self, out: &mut GenericArray<u8, U32>) {
+        let bit_len = self.len * 8;
+
+        // Append single 1 bit
+        self.buffer[self.buffered] = 0b1000_0000;
+        for byte in &mut self.buffer[self.buffered + 1..] {
+            *byte = 0;
+        }
+
+        // The length needs the last 8 bytes of a block, if they are taken start another one.
+        if self.buffered + 1 > 64 - 8 {
+            IMPL.compress256(
+                &mut self.state,
+                &[&self.buffer[..32], &self.buffer[32..]][..],
+            );
+            self.buffer = [0; 64];
+        }
+
+        // Write L as 64 big endian integer
+        self.buffer[64 - 8..].copy_from_slice(&bit_len.to_be_bytes()[..]);
+        IMPL.compress256(
+            &mut self.state,
+            &[&self.buffer[..32], &self.buffer[32..]][..],
+        );
+
+        BE::write_u32_into(&self.state, &mut out[..]);
+    }
+}
+
+impl Reset for Sha256Stream {
+    fn reset(&mut self) {
+        *self = Sha256Stream::default();
+    }
+}
+
+opaque_debug::impl_opaque_debug!(Sha256Stream);
+
+#[cfg(test)]
+mod tests {
+    use super::Sha256Stream;
+
+    use digest::Digest;
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use sha2::Sha256 as Original;
+
+    #[test]
+    fn test_lengths() {
+        // Every length around the padding boundaries, hashed in one go.
+        for len in 0..300 {
+            let input = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            assert_eq!(
+                Sha256Stream::digest(&input),
+                Original::digest(&input),
+                "len: {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_fuzz_updates() {
+        let rng = &mut XorShiftRng::from_seed([
+            0x59, 0x62, 0xbe, 0x5d, 0x76, 0x3d, 0x31, 0x8d, 0x17, 0xdb, 0x37, 0x32, 0x54, 0x06,
+            0xbc, 0xe5,
+        ]);
+
+        for _ in 0..200 {
+            let mut input = vec![0u8; rng.gen_range(0, 4096)];
+            rng.fill_bytes(&mut input);
+
+            // Split the input at random points, to cover partially filled buffers.
+            let mut hasher = Sha256Stream::new();
+            let mut rest = &input[..];
+            while !rest.is_empty() {
+                let n = rng.gen_range(0, rest.len().min(200) + 1);
+                hasher.update(&rest[..n]);
+                rest = &rest[n..];
+            }
+
+            let mut reset = hasher.clone();
+            assert_eq!(hasher.finalize(), Original::digest(&input));
+
+            reset.reset();
+            reset.update(&input);
+            assert_eq!(reset.finalize(), Original::digest(&input));
+        }
+    }
+}
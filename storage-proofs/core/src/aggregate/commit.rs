@@ -0,0 +1,125 @@
+use anyhow::ensure;
+use groupy::CurveAffine;
+use paired::bls12_381::{Fq12, Fr, G1Affine, G2Affine};
+use rayon::prelude::*;
+
+use super::{fold_points, multi_pairing};
+use crate::error::Result;
+
+/// The output of a pairing commitment, consisting of one target group element per half of the
+/// commitment key.
+pub type Output = (Fq12, Fq12);
+
+/// Commitment key in G2, used to commit to vectors of G1 elements. The `a` half holds
+/// `h^{alpha^i}` and the `b` half `h^{beta^i}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VKey {
+    pub a: Vec<G2Affine>,
+    pub b: Vec<G2Affine>,
+}
+
+/// Commitment key in G1, used to commit to vectors of G2 elements. The `a` half holds
+/// `g^{alpha^{n + i}}` and the `b` half `g^{beta^{n + i}}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WKey {
+    pub a: Vec<G1Affine>,
+    pub b: Vec<G1Affine>,
+}
+
+macro_rules! impl_key {
+    ($key:ident, $elem:ty) => {
+        impl $key {
+            pub fn len(&self) -> usize {
+                self.a.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.a.is_empty()
+            }
+
+            /// Splits the key into its left and right halves at `at`.
+            pub fn split(&self, at: usize) -> (Self, Self) {
+                let (a_l, a_r) = self.a.split_at(at);
+                let (b_l, b_r) = self.b.split_at(at);
+
+                (
+                    $key {
+                        a: a_l.to_vec(),
+                        b: b_l.to_vec(),
+                    },
+                    $key {
+                        a: a_r.to_vec(),
+                        b: b_r.to_vec(),
+                    },
+                )
+            }
+
+            /// Returns the key `self[i] + scale * right[i]`.
+            pub fn fold(&self, right: &Self, scale: &Fr) -> Result<Self> {
+                ensure!(self.len() == right.len(), "keys must have the same length");
+
+                Ok($key {
+                    a: fold_points(&self.a, &right.a, scale),
+                    b: fold_points(&self.b, &right.b, scale),
+                })
+            }
+
+            /// Returns the key `self[i] * scales[i]`.
+            pub fn scale(&self, scales: &[Fr]) -> Result<Self> {
+                ensure!(self.len() == scales.len(), "invalid number of scalars");
+
+                let scale = |points: &[$elem]| {
+                    points
+                        .par_iter()
+                        .zip(scales.par_iter())
+                        .map(|(p, s)| p.mul(*s).into_affine())
+                        .collect()
+                };
+
+                Ok($key {
+                    a: scale(&self.a),
+                    b: scale(&self.b),
+                })
+            }
+
+            /// Returns the first element of both halves, once the key has been folded down
+            /// to a single element.
+            pub fn first(&self) -> Result<($elem, $elem)> {
+                ensure!(!self.is_empty(), "empty commitment key");
+
+                Ok((self.a[0], self.b[0]))
+            }
+        }
+    };
+}
+
+impl_key!(VKey, G2Affine);
+impl_key!(WKey, G1Affine);
+
+/// Commits to the vectors `a` and `b` as
+/// `(prod e(a_i, v.a_i) * e(w.a_i, b_i), prod e(a_i, v.b_i) * e(w.b_i, b_i))`.
+pub fn pair(vkey: &VKey, wkey: &WKey, a: &[G1Affine], b: &[G2Affine]) -> Result<Output> {
+    ensure!(
+        vkey.len() == a.len() && wkey.len() == b.len() && a.len() == b.len(),
+        "commitment keys and vectors must have the same length"
+    );
+
+    let commit = |v: &[G2Affine], w: &[G1Affine]| {
+        let g1: Vec<G1Affine> = a.iter().chain(w.iter()).copied().collect();
+        let g2: Vec<G2Affine> = v.iter().chain(b.iter()).copied().collect();
+
+        multi_pairing(&g1, &g2)
+    };
+
+    Ok((commit(&vkey.a, &wkey.a), commit(&vkey.b, &wkey.b)))
+}
+
+/// Commits to the vector `c` as `(prod e(c_i, v.a_i), prod e(c_i, v.b_i))`.
+pub fn single_g1(vkey: &VKey, c: &[G1Affine]) -> Result<Output> {
+    ensure!(
+        vkey.len() == c.len(),
+        "commitment key and vector must have the same length"
+    );
+
+    Ok((multi_pairing(c, &vkey.a), multi_pairing(c, &vkey.b)))
+}
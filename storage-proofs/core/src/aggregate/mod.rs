@@ -0,0 +1,289 @@
+//! Aggregation of many Groth16 proofs for the same circuit into a single proof.
+//!
+//! This follows the SnarkPack construction: the prover commits to the `A`, `B` and `C` points of
+//! all proofs with pairing based commitments, and then shows with an inner pairing product
+//! argument (TIPP) and a multi-exponentiation inner product argument (MIPP) that a random linear
+//! combination of the Groth16 verification equations holds. The final commitment keys are checked
+//! with KZG openings against a structured reference string, so both the proof size and the
+//! verification cost (besides accumulating the public inputs) are logarithmic in the number of
+//! aggregated proofs.
+
+mod commit;
+mod poly;
+mod proof;
+mod prove;
+mod srs;
+mod transcript;
+mod verify;
+
+pub use self::commit::{VKey, WKey};
+pub use self::proof::{AggregateProof, GipaProof, KZGOpening};
+pub use self::prove::aggregate_proofs;
+#[cfg(any(test, feature = "fake-srs"))]
+pub use self::srs::setup_fake_srs;
+pub use self::srs::{GenericSRS, ProverSRS, VerifierSRS};
+pub use self::verify::verify_aggregate_proof;
+
+use ff::Field;
+use groupy::{CurveAffine, CurveProjective};
+use paired::bls12_381::{Bls12, Fq12, Fr, G1Affine, G2Affine};
+use paired::{Engine, PairingCurveAffine};
+use rayon::prelude::*;
+
+/// Number of pairs handled by a single miller loop when computing products of pairings.
+const MILLER_LOOP_CHUNK_SIZE: usize = 64;
+
+/// Computes the product of the pairings `e(g1[i], g2[i])`.
+pub(crate) fn multi_pairing(g1: &[G1Affine], g2: &[G2Affine]) -> Fq12 {
+    debug_assert_eq!(g1.len(), g2.len());
+
+    let miller_loop = g1
+        .par_chunks(MILLER_LOOP_CHUNK_SIZE)
+        .zip(g2.par_chunks(MILLER_LOOP_CHUNK_SIZE))
+        .map(|(a, b)| {
+            let prepared: Vec<_> = a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| (a.prepare(), b.prepare()))
+                .collect();
+            let pairs: Vec<_> = prepared.iter().map(|(a, b)| (a, b)).collect();
+
+            Bls12::miller_loop(pairs.iter())
+        })
+        .reduce(Fq12::one, |mut acc, ml| {
+            acc.mul_assign(&ml);
+            acc
+        });
+
+    Bls12::final_exponentiation(&miller_loop).expect("final exponentiation failed")
+}
+
+/// Computes `sum(bases[i] * scalars[i])`.
+pub(crate) fn multiexp<G: CurveAffine<Scalar = Fr>>(bases: &[G], scalars: &[Fr]) -> G::Projective {
+    debug_assert_eq!(bases.len(), scalars.len());
+
+    bases
+        .par_iter()
+        .zip(scalars.par_iter())
+        .map(|(base, scalar)| base.mul(*scalar))
+        .reduce(G::Projective::zero, |mut acc, p| {
+            acc.add_assign(&p);
+            acc
+        })
+}
+
+/// Computes `left[i] + scale * right[i]` for all `i`.
+pub(crate) fn fold_points<G: CurveAffine<Scalar = Fr>>(
+    left: &[G],
+    right: &[G],
+    scale: &Fr,
+) -> Vec<G> {
+    debug_assert_eq!(left.len(), right.len());
+
+    left.par_iter()
+        .zip(right.par_iter())
+        .map(|(l, r)| {
+            let mut p = r.mul(*scale);
+            p.add_assign_mixed(l);
+            p.into_affine()
+        })
+        .collect()
+}
+
+/// Computes `left[i] + scale * right[i]` for all `i`.
+pub(crate) fn fold_scalars(left: &[Fr], right: &[Fr], scale: &Fr) -> Vec<Fr> {
+    debug_assert_eq!(left.len(), right.len());
+
+    left.par_iter()
+        .zip(right.par_iter())
+        .map(|(l, r)| {
+            let mut s = *r;
+            s.mul_assign(scale);
+            s.add_assign(l);
+            s
+        })
+        .collect()
+}
+
+/// Pads `items` to a power of two of at least two elements by repeating the last element, as only
+/// such numbers of proofs can be aggregated.
+pub fn pad_to_power_of_two<T: Clone>(items: &mut Vec<T>) {
+    if let Some(last) = items.last().cloned() {
+        let len = std::cmp::max(items.len().next_power_of_two(), 2);
+        items.resize(len, last);
+    }
+}
+
+/// Returns `[1, s, s^2, ..., s^(n - 1)]`.
+pub(crate) fn structured_scalar_power(n: usize, s: &Fr) -> Vec<Fr> {
+    let mut powers = Vec::with_capacity(n);
+    let mut cur = Fr::one();
+    for _ in 0..n {
+        powers.push(cur);
+        cur.mul_assign(s);
+    }
+
+    powers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::groth16;
+    use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::TEST_SEED;
+
+    /// Proves knowledge of `x` and `y` with `x * y == z`, where `z` is public.
+    #[derive(Clone)]
+    struct MulCircuit {
+        x: Option<Fr>,
+        y: Option<Fr>,
+    }
+
+    impl Circuit<Bls12> for MulCircuit {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let x = cs.alloc(|| "x", || self.x.ok_or(SynthesisError::AssignmentMissing))?;
+            let y = cs.alloc(|| "y", || self.y.ok_or(SynthesisError::AssignmentMissing))?;
+            let z = cs.alloc_input(
+                || "z",
+                || {
+                    let mut z = self.x.ok_or(SynthesisError::AssignmentMissing)?;
+                    z.mul_assign(&self.y.ok_or(SynthesisError::AssignmentMissing)?);
+                    Ok(z)
+                },
+            )?;
+
+            cs.enforce(|| "x * y = z", |lc| lc + x, |lc| lc + y, |lc| lc + z);
+
+            Ok(())
+        }
+    }
+
+    fn aggregate(nproofs: usize) {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+        let params = groth16::generate_random_parameters::<Bls12, _, _>(
+            MulCircuit { x: None, y: None },
+            rng,
+        )
+        .unwrap();
+        let pvk = groth16::prepare_verifying_key(&params.vk);
+
+        let mut proofs = Vec::with_capacity(nproofs);
+        let mut public_inputs = Vec::with_capacity(nproofs);
+        for _ in 0..nproofs {
+            let x = Fr::random(rng);
+            let y = Fr::random(rng);
+            let mut z = x;
+            z.mul_assign(&y);
+
+            let proof = groth16::create_random_proof(
+                MulCircuit {
+                    x: Some(x),
+                    y: Some(y),
+                },
+                &params,
+                rng,
+            )
+            .unwrap();
+            assert!(groth16::verify_proof(&pvk, &proof, &[z]).unwrap());
+
+            proofs.push(proof);
+            public_inputs.push(vec![z]);
+        }
+
+        let srs = setup_fake_srs(rng, 8);
+        let (prover_srs, verifier_srs) = srs.specialize(nproofs).unwrap();
+
+        let aggregate_proof =
+            aggregate_proofs(&prover_srs, &params.vk, &public_inputs, &proofs).unwrap();
+        assert!(verify_aggregate_proof(
+            &verifier_srs,
+            &params.vk,
+            &public_inputs,
+            &aggregate_proof
+        )
+        .unwrap());
+
+        // Serialization roundtrip.
+        let bytes = aggregate_proof.to_vec();
+        let read_proof = AggregateProof::read(&bytes[..]).unwrap();
+        assert_eq!(read_proof, aggregate_proof);
+        assert!(
+            verify_aggregate_proof(&verifier_srs, &params.vk, &public_inputs, &read_proof).unwrap()
+        );
+
+        // Wrong public inputs must not verify.
+        let mut wrong_inputs = public_inputs.clone();
+        wrong_inputs[nproofs - 1][0].add_assign(&Fr::one());
+        assert!(!verify_aggregate_proof(
+            &verifier_srs,
+            &params.vk,
+            &wrong_inputs,
+            &aggregate_proof
+        )
+        .unwrap());
+
+        // Neither must a tampered aggregate proof.
+        let mut two = Fr::one();
+        two.double();
+        let mut wrong_proof = aggregate_proof.clone();
+        wrong_proof.agg_c = wrong_proof.agg_c.mul(two).into_affine();
+        assert!(
+            !verify_aggregate_proof(&verifier_srs, &params.vk, &public_inputs, &wrong_proof)
+                .unwrap()
+        );
+
+        // The aggregate is bound to the circuit and the srs it was made for.
+        let other_params = groth16::generate_random_parameters::<Bls12, _, _>(
+            MulCircuit { x: None, y: None },
+            rng,
+        )
+        .unwrap();
+        assert!(!verify_aggregate_proof(
+            &verifier_srs,
+            &other_params.vk,
+            &public_inputs,
+            &aggregate_proof
+        )
+        .unwrap());
+        let (_, other_verifier_srs) = setup_fake_srs(rng, 8).specialize(nproofs).unwrap();
+        assert!(!verify_aggregate_proof(
+            &other_verifier_srs,
+            &params.vk,
+            &public_inputs,
+            &aggregate_proof
+        )
+        .unwrap());
+
+        // An invalid proof can not be hidden in the aggregate.
+        let mut wrong_proofs = proofs.clone();
+        wrong_proofs[0].c = wrong_proofs[1].c;
+        let wrong_aggregate =
+            aggregate_proofs(&prover_srs, &params.vk, &public_inputs, &wrong_proofs).unwrap();
+        assert!(!verify_aggregate_proof(
+            &verifier_srs,
+            &params.vk,
+            &public_inputs,
+            &wrong_aggregate
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_aggregate_2() {
+        aggregate(2);
+    }
+
+    #[test]
+    fn test_aggregate_8() {
+        aggregate(8);
+    }
+}
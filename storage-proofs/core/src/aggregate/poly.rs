@@ -0,0 +1,137 @@
+use anyhow::ensure;
+use ff::Field;
+use groupy::CurveAffine;
+use paired::bls12_381::Fr;
+
+use super::multiexp;
+use crate::error::Result;
+
+/// Returns the coefficients of `prod_j (1 + coeffs[j] * X^{2^(m - 1 - j)})`, where `m` is the
+/// number of coefficients, i.e. the polynomial that a commitment key is folded with when
+/// `coeffs[j]` is used as the scale of the `j`-th folding round.
+pub fn polynomial_coefficients_from_transcript(coeffs: &[Fr]) -> Vec<Fr> {
+    let mut poly = Vec::with_capacity(1 << coeffs.len());
+    poly.push(Fr::one());
+
+    // The last round contributes the factor of degree 1, so multiply from the back.
+    for c in coeffs.iter().rev() {
+        let shifted: Vec<Fr> = poly
+            .iter()
+            .map(|p| {
+                let mut p = *p;
+                p.mul_assign(c);
+                p
+            })
+            .collect();
+        poly.extend(shifted);
+    }
+
+    poly
+}
+
+/// Evaluates `prod_j (1 + coeffs[j] * X^{2^(m - 1 - j)})` at `point`.
+pub fn polynomial_evaluation_product_form_from_transcript(coeffs: &[Fr], point: &Fr) -> Fr {
+    let mut res = Fr::one();
+    let mut power = *point;
+
+    for c in coeffs.iter().rev() {
+        let mut term = power;
+        term.mul_assign(c);
+        term.add_assign(&Fr::one());
+        res.mul_assign(&term);
+
+        power.square();
+    }
+
+    res
+}
+
+/// Returns the quotient of the division of the polynomial with coefficients `coeffs` by
+/// `(X - point)`, dropping the remainder.
+pub fn quotient(coeffs: &[Fr], point: &Fr) -> Vec<Fr> {
+    if coeffs.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut quotient = vec![Fr::zero(); coeffs.len() - 1];
+    let mut acc = Fr::zero();
+    for i in (1..coeffs.len()).rev() {
+        acc.mul_assign(point);
+        acc.add_assign(&coeffs[i]);
+        quotient[i - 1] = acc;
+    }
+
+    quotient
+}
+
+/// Creates a KZG opening of the polynomial with coefficients `coeffs` at `point`, given the
+/// powers of the secret in the group of the commitment.
+pub fn create_kzg_opening<G: CurveAffine<Scalar = Fr>>(
+    powers: &[G],
+    coeffs: &[Fr],
+    point: &Fr,
+) -> Result<G> {
+    let quotient = quotient(coeffs, point);
+    ensure!(
+        powers.len() >= quotient.len(),
+        "not enough powers in the srs to open a polynomial of degree {}",
+        coeffs.len() - 1
+    );
+
+    Ok(multiexp(&powers[..quotient.len()], &quotient).into_affine())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::TEST_SEED;
+
+    fn evaluate(coeffs: &[Fr], point: &Fr) -> Fr {
+        coeffs.iter().rev().fold(Fr::zero(), |mut acc, c| {
+            acc.mul_assign(point);
+            acc.add_assign(c);
+            acc
+        })
+    }
+
+    #[test]
+    fn test_product_form() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+        for m in 0..6 {
+            let coeffs: Vec<Fr> = (0..m).map(|_| Fr::random(rng)).collect();
+            let point = Fr::random(rng);
+
+            let poly = polynomial_coefficients_from_transcript(&coeffs);
+            assert_eq!(poly.len(), 1 << m);
+            assert_eq!(
+                evaluate(&poly, &point),
+                polynomial_evaluation_product_form_from_transcript(&coeffs, &point)
+            );
+        }
+    }
+
+    #[test]
+    fn test_quotient() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+        let coeffs: Vec<Fr> = (0..17).map(|_| Fr::random(rng)).collect();
+        let point = Fr::random(rng);
+        let x = Fr::random(rng);
+
+        // q(x) * (x - point) == f(x) - f(point)
+        let mut lhs = evaluate(&quotient(&coeffs, &point), &x);
+        let mut x_minus_point = x;
+        x_minus_point.sub_assign(&point);
+        lhs.mul_assign(&x_minus_point);
+
+        let mut rhs = evaluate(&coeffs, &x);
+        rhs.sub_assign(&evaluate(&coeffs, &point));
+
+        assert_eq!(lhs, rhs);
+    }
+}
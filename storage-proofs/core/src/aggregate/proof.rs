@@ -0,0 +1,233 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use ff::{PrimeField, PrimeFieldRepr};
+use groupy::{CurveAffine, EncodedPoint};
+use paired::bls12_381::{
+    Fq, Fq12, Fq2, Fq6, FqRepr, G1Affine, G1Compressed, G2Affine, G2Compressed,
+};
+
+use super::commit::Output;
+
+/// Largest number of proofs an aggregate proof can be read for.
+const MAX_AGGREGATED_PROOFS: u32 = 1 << 24;
+
+/// A KZG opening of a commitment key, for both the `alpha` and the `beta` half.
+pub type KZGOpening<G> = (G, G);
+
+/// The recursive part of the aggregate proof, proving the inner pairing product of `A` and `B`
+/// (TIPP) and the multi exponentiation of `C` (MIPP) at the same time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GipaProof {
+    /// Number of aggregated proofs, always a power of two.
+    pub nproofs: u32,
+    /// Left and right cross commitments of `A` and `B`, one pair per round.
+    pub comms_ab: Vec<(Output, Output)>,
+    /// Left and right cross commitments of `C`, one pair per round.
+    pub comms_c: Vec<(Output, Output)>,
+    /// Left and right cross inner pairing products of `A` and `B`, one pair per round.
+    pub z_ab: Vec<(Fq12, Fq12)>,
+    /// Left and right cross multi exponentiations of `C`, one pair per round.
+    pub z_c: Vec<(G1Affine, G1Affine)>,
+    pub final_a: G1Affine,
+    pub final_b: G2Affine,
+    pub final_c: G1Affine,
+    /// The commitment key in G2, folded down to a single element per half.
+    pub final_vkey: (G2Affine, G2Affine),
+    /// The commitment key in G1, folded down to a single element per half.
+    pub final_wkey: (G1Affine, G1Affine),
+}
+
+/// A proof for the validity of many Groth16 proofs of the same circuit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AggregateProof {
+    /// Commitment to the `A` and `B` points of all proofs.
+    pub com_ab: Output,
+    /// Commitment to the `C` points of all proofs.
+    pub com_c: Output,
+    /// The inner pairing product `prod e(A_i, B_i)^{r^i}`.
+    pub ip_ab: Fq12,
+    /// The multi exponentiation `sum C_i * r^i`.
+    pub agg_c: G1Affine,
+    pub gipa: GipaProof,
+    /// Opening of the final commitment key in G2.
+    pub vkey_opening: KZGOpening<G2Affine>,
+    /// Opening of the final commitment key in G1.
+    pub wkey_opening: KZGOpening<G1Affine>,
+}
+
+impl AggregateProof {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let gipa = &self.gipa;
+
+        writer.write_u32::<BigEndian>(gipa.nproofs)?;
+        write_output(&mut writer, &self.com_ab)?;
+        write_output(&mut writer, &self.com_c)?;
+        write_gt(&mut writer, &self.ip_ab)?;
+        write_g1(&mut writer, &self.agg_c)?;
+
+        for ((comm_ab, comm_c), (z_ab, z_c)) in gipa
+            .comms_ab
+            .iter()
+            .zip(gipa.comms_c.iter())
+            .zip(gipa.z_ab.iter().zip(gipa.z_c.iter()))
+        {
+            write_output(&mut writer, &comm_ab.0)?;
+            write_output(&mut writer, &comm_ab.1)?;
+            write_output(&mut writer, &comm_c.0)?;
+            write_output(&mut writer, &comm_c.1)?;
+            write_gt(&mut writer, &z_ab.0)?;
+            write_gt(&mut writer, &z_ab.1)?;
+            write_g1(&mut writer, &z_c.0)?;
+            write_g1(&mut writer, &z_c.1)?;
+        }
+
+        write_g1(&mut writer, &gipa.final_a)?;
+        write_g2(&mut writer, &gipa.final_b)?;
+        write_g1(&mut writer, &gipa.final_c)?;
+        write_g2(&mut writer, &gipa.final_vkey.0)?;
+        write_g2(&mut writer, &gipa.final_vkey.1)?;
+        write_g1(&mut writer, &gipa.final_wkey.0)?;
+        write_g1(&mut writer, &gipa.final_wkey.1)?;
+
+        write_g2(&mut writer, &self.vkey_opening.0)?;
+        write_g2(&mut writer, &self.vkey_opening.1)?;
+        write_g1(&mut writer, &self.wkey_opening.0)?;
+        write_g1(&mut writer, &self.wkey_opening.1)?;
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let nproofs = reader.read_u32::<BigEndian>()?;
+        if nproofs < 2 || !nproofs.is_power_of_two() || nproofs > MAX_AGGREGATED_PROOFS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid number of aggregated proofs: {}", nproofs),
+            ));
+        }
+        let rounds = nproofs.trailing_zeros() as usize;
+
+        let com_ab = read_output(&mut reader)?;
+        let com_c = read_output(&mut reader)?;
+        let ip_ab = read_gt(&mut reader)?;
+        let agg_c = read_g1(&mut reader)?;
+
+        let mut comms_ab = Vec::with_capacity(rounds);
+        let mut comms_c = Vec::with_capacity(rounds);
+        let mut z_ab = Vec::with_capacity(rounds);
+        let mut z_c = Vec::with_capacity(rounds);
+        for _ in 0..rounds {
+            comms_ab.push((read_output(&mut reader)?, read_output(&mut reader)?));
+            comms_c.push((read_output(&mut reader)?, read_output(&mut reader)?));
+            z_ab.push((read_gt(&mut reader)?, read_gt(&mut reader)?));
+            z_c.push((read_g1(&mut reader)?, read_g1(&mut reader)?));
+        }
+
+        let final_a = read_g1(&mut reader)?;
+        let final_b = read_g2(&mut reader)?;
+        let final_c = read_g1(&mut reader)?;
+        let final_vkey = (read_g2(&mut reader)?, read_g2(&mut reader)?);
+        let final_wkey = (read_g1(&mut reader)?, read_g1(&mut reader)?);
+
+        let vkey_opening = (read_g2(&mut reader)?, read_g2(&mut reader)?);
+        let wkey_opening = (read_g1(&mut reader)?, read_g1(&mut reader)?);
+
+        Ok(AggregateProof {
+            com_ab,
+            com_c,
+            ip_ab,
+            agg_c,
+            gipa: GipaProof {
+                nproofs,
+                comms_ab,
+                comms_c,
+                z_ab,
+                z_c,
+                final_a,
+                final_b,
+                final_c,
+                final_vkey,
+                final_wkey,
+            },
+            vkey_opening,
+            wkey_opening,
+        })
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out).expect("known allocation target");
+        out
+    }
+}
+
+pub(crate) fn write_gt<W: Write>(mut writer: W, gt: &Fq12) -> io::Result<()> {
+    for fq6 in [&gt.c0, &gt.c1].iter() {
+        for fq2 in [&fq6.c0, &fq6.c1, &fq6.c2].iter() {
+            fq2.c0.into_repr().write_be(&mut writer)?;
+            fq2.c1.into_repr().write_be(&mut writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn read_gt<R: Read>(mut reader: R) -> io::Result<Fq12> {
+    Ok(Fq12 {
+        c0: read_fq6(&mut reader)?,
+        c1: read_fq6(&mut reader)?,
+    })
+}
+
+fn read_fq6<R: Read>(mut reader: R) -> io::Result<Fq6> {
+    Ok(Fq6 {
+        c0: read_fq2(&mut reader)?,
+        c1: read_fq2(&mut reader)?,
+        c2: read_fq2(&mut reader)?,
+    })
+}
+
+fn read_fq2<R: Read>(mut reader: R) -> io::Result<Fq2> {
+    Ok(Fq2 {
+        c0: read_fq(&mut reader)?,
+        c1: read_fq(&mut reader)?,
+    })
+}
+
+fn read_fq<R: Read>(mut reader: R) -> io::Result<Fq> {
+    let mut repr = FqRepr::default();
+    repr.read_be(&mut reader)?;
+    Fq::from_repr(repr).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_output<W: Write>(mut writer: W, output: &Output) -> io::Result<()> {
+    write_gt(&mut writer, &output.0)?;
+    write_gt(&mut writer, &output.1)
+}
+
+fn read_output<R: Read>(mut reader: R) -> io::Result<Output> {
+    Ok((read_gt(&mut reader)?, read_gt(&mut reader)?))
+}
+
+fn write_g1<W: Write>(mut writer: W, p: &G1Affine) -> io::Result<()> {
+    writer.write_all(p.into_compressed().as_ref())
+}
+
+fn read_g1<R: Read>(mut reader: R) -> io::Result<G1Affine> {
+    let mut repr = G1Compressed::empty();
+    reader.read_exact(repr.as_mut())?;
+    repr.into_affine()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_g2<W: Write>(mut writer: W, p: &G2Affine) -> io::Result<()> {
+    writer.write_all(p.into_compressed().as_ref())
+}
+
+fn read_g2<R: Read>(mut reader: R) -> io::Result<G2Affine> {
+    let mut repr = G2Compressed::empty();
+    reader.read_exact(repr.as_mut())?;
+    repr.into_affine()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
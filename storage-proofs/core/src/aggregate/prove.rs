@@ -0,0 +1,282 @@
+use anyhow::ensure;
+use bellperson::groth16;
+use ff::Field;
+use groupy::{CurveAffine, CurveProjective};
+use paired::bls12_381::{Bls12, Fr, G1Affine, G2Affine};
+use rayon::prelude::*;
+
+use super::commit::{self, VKey, WKey};
+use super::poly::{create_kzg_opening, polynomial_coefficients_from_transcript};
+use super::proof::{AggregateProof, GipaProof, KZGOpening};
+use super::srs::{ProverSRS, VerifierSRS};
+use super::transcript::Transcript;
+use super::{fold_points, fold_scalars, multi_pairing, multiexp, structured_scalar_power};
+use crate::error::Result;
+
+/// Domain separation label of the aggregation transcript.
+pub(crate) const TRANSCRIPT_LABEL: &[u8] = b"filecoin-proofs-aggregate-v1";
+
+/// Aggregates `proofs`, which must all be valid proofs for the circuit of the verifying key
+/// `vk`, into a single proof. The number of proofs must match the size of the SRS, the
+/// `public_inputs` of every proof are bound to the aggregate proof.
+pub fn aggregate_proofs(
+    srs: &ProverSRS,
+    vk: &groth16::VerifyingKey<Bls12>,
+    public_inputs: &[Vec<Fr>],
+    proofs: &[groth16::Proof<Bls12>],
+) -> Result<AggregateProof> {
+    ensure!(
+        proofs.len() == srs.n,
+        "the srs is specialized for {} proofs, not {}",
+        srs.n,
+        proofs.len()
+    );
+    ensure!(
+        public_inputs.len() == proofs.len(),
+        "invalid number of public inputs"
+    );
+
+    let a: Vec<G1Affine> = proofs.iter().map(|proof| proof.a).collect();
+    let b: Vec<G2Affine> = proofs.iter().map(|proof| proof.b).collect();
+    let c: Vec<G1Affine> = proofs.iter().map(|proof| proof.c).collect();
+
+    // Commit to A, B and C.
+    let com_ab = commit::pair(&srs.vkey, &srs.wkey, &a, &b)?;
+    let com_c = commit::single_g1(&srs.vkey, &c)?;
+
+    let mut transcript = new_transcript(vk, &srs.verifier_key());
+    append_public_inputs(&mut transcript, public_inputs);
+    transcript.append_output(&com_ab);
+    transcript.append_output(&com_c);
+    let r = transcript.challenge(b"r");
+
+    let r_vec = structured_scalar_power(proofs.len(), &r);
+    let r_inv: Vec<Fr> = r_vec
+        .par_iter()
+        .map(|r| r.inverse().expect("r is not zero"))
+        .collect();
+
+    // B^{r^i}, paired with the commitment key w^{r^-i}, keeps the commitment to A and B intact.
+    let b_r: Vec<G2Affine> = b
+        .par_iter()
+        .zip(r_vec.par_iter())
+        .map(|(b, r)| b.mul(*r).into_affine())
+        .collect();
+    let wkey_r_inv = srs.wkey.scale(&r_inv)?;
+
+    let ip_ab = multi_pairing(&a, &b_r);
+    let agg_c = multiexp(&c, &r_vec).into_affine();
+    transcript.append_gt(&ip_ab);
+    transcript.append_g1(&agg_c);
+
+    let (gipa, challenges) = prove_gipa(
+        &mut transcript,
+        a,
+        b_r,
+        c,
+        r_vec,
+        srs.vkey.clone(),
+        wkey_r_inv,
+    )?;
+
+    let z = transcript.challenge(b"z");
+    let (vkey_opening, wkey_opening) = prove_commitment_keys(srs, &challenges, &r, &z)?;
+
+    Ok(AggregateProof {
+        com_ab,
+        com_c,
+        ip_ab,
+        agg_c,
+        gipa,
+        vkey_opening,
+        wkey_opening,
+    })
+}
+
+/// Starts the transcript of an aggregation, bound to the circuit and the SRS so its challenges
+/// can not be reused for other ones.
+pub(crate) fn new_transcript(vk: &groth16::VerifyingKey<Bls12>, srs: &VerifierSRS) -> Transcript {
+    let mut transcript = Transcript::new(TRANSCRIPT_LABEL);
+    transcript.append_verifying_key(vk);
+    transcript.append_srs(srs);
+    transcript
+}
+
+pub(crate) fn append_public_inputs(transcript: &mut Transcript, public_inputs: &[Vec<Fr>]) {
+    for inputs in public_inputs {
+        transcript.append_bytes(&(inputs.len() as u64).to_le_bytes());
+        for input in inputs {
+            transcript.append_fr(input);
+        }
+    }
+}
+
+/// Appends the values sent in a single round of the recursive argument to the transcript.
+pub(crate) fn append_round(transcript: &mut Transcript, gipa: &GipaProof, round: usize) {
+    let (tab_l, tab_r) = &gipa.comms_ab[round];
+    let (tuc_l, tuc_r) = &gipa.comms_c[round];
+    let (zab_l, zab_r) = &gipa.z_ab[round];
+    let (zc_l, zc_r) = &gipa.z_c[round];
+
+    transcript.append_output(tab_l);
+    transcript.append_output(tab_r);
+    transcript.append_output(tuc_l);
+    transcript.append_output(tuc_r);
+    transcript.append_gt(zab_l);
+    transcript.append_gt(zab_r);
+    transcript.append_g1(zc_l);
+    transcript.append_g1(zc_r);
+}
+
+/// Appends the final values of the recursive argument to the transcript.
+pub(crate) fn append_final(transcript: &mut Transcript, gipa: &GipaProof) {
+    transcript.append_g1(&gipa.final_a);
+    transcript.append_g2(&gipa.final_b);
+    transcript.append_g1(&gipa.final_c);
+    transcript.append_g2(&gipa.final_vkey.0);
+    transcript.append_g2(&gipa.final_vkey.1);
+    transcript.append_g1(&gipa.final_wkey.0);
+    transcript.append_g1(&gipa.final_wkey.1);
+}
+
+/// Proves the inner pairing product of `a` and `b` and the multi exponentiation of `c` with
+/// `r`, by halving the vectors and commitment keys in every round. Returns the proof together
+/// with the challenges of all rounds.
+fn prove_gipa(
+    transcript: &mut Transcript,
+    mut m_a: Vec<G1Affine>,
+    mut m_b: Vec<G2Affine>,
+    mut m_c: Vec<G1Affine>,
+    mut m_r: Vec<Fr>,
+    mut vkey: VKey,
+    mut wkey: WKey,
+) -> Result<(GipaProof, Vec<Fr>)> {
+    let nproofs = m_a.len();
+    let rounds = nproofs.trailing_zeros() as usize;
+
+    let mut gipa = GipaProof {
+        nproofs: nproofs as u32,
+        comms_ab: Vec::with_capacity(rounds),
+        comms_c: Vec::with_capacity(rounds),
+        z_ab: Vec::with_capacity(rounds),
+        z_c: Vec::with_capacity(rounds),
+        final_a: G1Affine::zero(),
+        final_b: G2Affine::zero(),
+        final_c: G1Affine::zero(),
+        final_vkey: (G2Affine::zero(), G2Affine::zero()),
+        final_wkey: (G1Affine::zero(), G1Affine::zero()),
+    };
+    let mut challenges = Vec::with_capacity(rounds);
+
+    while m_a.len() > 1 {
+        let split = m_a.len() / 2;
+
+        let (a_l, a_r) = m_a.split_at(split);
+        let (b_l, b_r) = m_b.split_at(split);
+        let (c_l, c_r) = m_c.split_at(split);
+        let (r_l, r_r) = m_r.split_at(split);
+        let (vk_l, vk_r) = vkey.split(split);
+        let (wk_l, wk_r) = wkey.split(split);
+
+        // Cross commitments and inner pairing products of A and B.
+        let tab_l = commit::pair(&vk_l, &wk_r, a_r, b_l)?;
+        let tab_r = commit::pair(&vk_r, &wk_l, a_l, b_r)?;
+        let zab_l = multi_pairing(a_r, b_l);
+        let zab_r = multi_pairing(a_l, b_r);
+
+        // Cross commitments and multi exponentiations of C.
+        let tuc_l = commit::single_g1(&vk_l, c_r)?;
+        let tuc_r = commit::single_g1(&vk_r, c_l)?;
+        let zc_l = multiexp(c_r, r_l).into_affine();
+        let zc_r = multiexp(c_l, r_r).into_affine();
+
+        gipa.comms_ab.push((tab_l, tab_r));
+        gipa.comms_c.push((tuc_l, tuc_r));
+        gipa.z_ab.push((zab_l, zab_r));
+        gipa.z_c.push((zc_l, zc_r));
+        append_round(transcript, &gipa, challenges.len());
+
+        let x = transcript.challenge(b"x");
+        let x_inv = x.inverse().expect("challenges are not zero");
+
+        let next_a = fold_points(a_l, a_r, &x);
+        let next_b = fold_points(b_l, b_r, &x_inv);
+        let next_c = fold_points(c_l, c_r, &x);
+        let next_r = fold_scalars(r_l, r_r, &x_inv);
+        m_a = next_a;
+        m_b = next_b;
+        m_c = next_c;
+        m_r = next_r;
+        vkey = vk_l.fold(&vk_r, &x_inv)?;
+        wkey = wk_l.fold(&wk_r, &x)?;
+
+        challenges.push(x);
+    }
+
+    gipa.final_a = m_a[0];
+    gipa.final_b = m_b[0];
+    gipa.final_c = m_c[0];
+    gipa.final_vkey = vkey.first()?;
+    gipa.final_wkey = wkey.first()?;
+    append_final(transcript, &gipa);
+
+    Ok((gipa, challenges))
+}
+
+/// Opens the final commitment keys at `z`, showing they were derived from the SRS.
+fn prove_commitment_keys(
+    srs: &ProverSRS,
+    challenges: &[Fr],
+    r: &Fr,
+    z: &Fr,
+) -> Result<(KZGOpening<G2Affine>, KZGOpening<G1Affine>)> {
+    // The commitment key v is folded with the inverses of the challenges.
+    let challenges_inv: Vec<Fr> = challenges
+        .iter()
+        .map(|x| x.inverse().expect("challenges are not zero"))
+        .collect();
+    let vkey_poly = polynomial_coefficients_from_transcript(&challenges_inv);
+
+    let vkey_opening = (
+        create_kzg_opening(&srs.h_alpha_powers, &vkey_poly, z)?,
+        create_kzg_opening(&srs.h_beta_powers, &vkey_poly, z)?,
+    );
+
+    // The commitment key w starts at the n-th power and was rescaled by r^-i before being
+    // folded with the challenges.
+    let wkey_poly = {
+        let r_inv = r.inverse().expect("r is not zero");
+        let coeffs = wkey_coefficients(challenges, &r_inv);
+        let mut poly = vec![Fr::zero(); srs.n];
+        poly.extend(polynomial_coefficients_from_transcript(&coeffs));
+        poly
+    };
+
+    let wkey_opening = (
+        create_kzg_opening(&srs.g_alpha_powers, &wkey_poly, z)?,
+        create_kzg_opening(&srs.g_beta_powers, &wkey_poly, z)?,
+    );
+
+    Ok((vkey_opening, wkey_opening))
+}
+
+/// Returns `challenges[j] * r_inv^{2^(m - 1 - j)}`, the factors the commitment key w is folded
+/// with once its rescaling by `r^-i` is taken into account, where `m` is the number of rounds.
+pub(crate) fn wkey_coefficients(challenges: &[Fr], r_inv: &Fr) -> Vec<Fr> {
+    let mut powers = Vec::with_capacity(challenges.len());
+    let mut power = *r_inv;
+    for _ in 0..challenges.len() {
+        powers.push(power);
+        power.square();
+    }
+
+    challenges
+        .iter()
+        .zip(powers.iter().rev())
+        .map(|(x, power)| {
+            let mut c = *x;
+            c.mul_assign(power);
+            c
+        })
+        .collect()
+}
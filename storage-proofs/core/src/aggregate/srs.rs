@@ -0,0 +1,252 @@
+use std::io::{self, Read, Write};
+
+use anyhow::ensure;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(any(test, feature = "fake-srs"))]
+use ff::Field;
+#[cfg(any(test, feature = "fake-srs"))]
+use groupy::CurveProjective;
+use groupy::{CurveAffine, EncodedPoint};
+#[cfg(any(test, feature = "fake-srs"))]
+use paired::bls12_381::Fr;
+use paired::bls12_381::{G1Affine, G1Uncompressed, G2Affine, G2Uncompressed};
+#[cfg(any(test, feature = "fake-srs"))]
+use rand::RngCore;
+use rayon::prelude::*;
+
+use super::commit::{VKey, WKey};
+#[cfg(any(test, feature = "fake-srs"))]
+use super::structured_scalar_power;
+use crate::error::Result;
+
+/// Structured reference string for the aggregation of up to `g_alpha_powers.len() / 2` proofs,
+/// containing the powers of two secrets `alpha` and `beta` in both groups.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenericSRS {
+    /// `g^{alpha^i}` for `i` in `0..2n`.
+    pub g_alpha_powers: Vec<G1Affine>,
+    /// `h^{alpha^i}` for `i` in `0..n`.
+    pub h_alpha_powers: Vec<G2Affine>,
+    /// `g^{beta^i}` for `i` in `0..2n`.
+    pub g_beta_powers: Vec<G1Affine>,
+    /// `h^{beta^i}` for `i` in `0..n`.
+    pub h_beta_powers: Vec<G2Affine>,
+}
+
+/// The part of the SRS used to aggregate exactly `n` proofs.
+#[derive(Clone, Debug)]
+pub struct ProverSRS {
+    pub n: usize,
+    /// `g^{alpha^i}` for `i` in `0..2n`, used to open the commitment key in G1.
+    pub g_alpha_powers: Vec<G1Affine>,
+    /// `h^{alpha^i}` for `i` in `0..n`, used to open the commitment key in G2.
+    pub h_alpha_powers: Vec<G2Affine>,
+    /// `g^{beta^i}` for `i` in `0..2n`, used to open the commitment key in G1.
+    pub g_beta_powers: Vec<G1Affine>,
+    /// `h^{beta^i}` for `i` in `0..n`, used to open the commitment key in G2.
+    pub h_beta_powers: Vec<G2Affine>,
+    pub vkey: VKey,
+    pub wkey: WKey,
+}
+
+impl ProverSRS {
+    /// The part of the SRS the verifier uses.
+    pub fn verifier_key(&self) -> VerifierSRS {
+        VerifierSRS {
+            g: self.g_alpha_powers[0],
+            h: self.h_alpha_powers[0],
+            g_alpha: self.g_alpha_powers[1],
+            g_beta: self.g_beta_powers[1],
+            h_alpha: self.h_alpha_powers[1],
+            h_beta: self.h_beta_powers[1],
+        }
+    }
+}
+
+/// The part of the SRS needed to verify aggregate proofs, independent of the number of proofs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifierSRS {
+    pub g: G1Affine,
+    pub h: G2Affine,
+    pub g_alpha: G1Affine,
+    pub g_beta: G1Affine,
+    pub h_alpha: G2Affine,
+    pub h_beta: G2Affine,
+}
+
+impl GenericSRS {
+    /// Largest number of proofs that can be aggregated with this SRS.
+    pub fn max_proofs(&self) -> usize {
+        self.h_alpha_powers.len()
+    }
+
+    /// Returns the prover and verifier keys to aggregate `n` proofs, where `n` must be a power of
+    /// two.
+    pub fn specialize(&self, n: usize) -> Result<(ProverSRS, VerifierSRS)> {
+        ensure!(
+            n >= 2 && n.is_power_of_two(),
+            "the number of proofs to aggregate must be a power of two, larger than one"
+        );
+        ensure!(
+            n <= self.max_proofs(),
+            "the srs supports the aggregation of at most {} proofs, not {}",
+            self.max_proofs(),
+            n
+        );
+
+        let vkey = VKey {
+            a: self.h_alpha_powers[..n].to_vec(),
+            b: self.h_beta_powers[..n].to_vec(),
+        };
+        let wkey = WKey {
+            a: self.g_alpha_powers[n..2 * n].to_vec(),
+            b: self.g_beta_powers[n..2 * n].to_vec(),
+        };
+
+        let prover = ProverSRS {
+            n,
+            g_alpha_powers: self.g_alpha_powers[..2 * n].to_vec(),
+            h_alpha_powers: self.h_alpha_powers[..n].to_vec(),
+            g_beta_powers: self.g_beta_powers[..2 * n].to_vec(),
+            h_beta_powers: self.h_beta_powers[..n].to_vec(),
+            vkey,
+            wkey,
+        };
+
+        Ok((prover, self.verifier_key()?))
+    }
+
+    pub fn verifier_key(&self) -> Result<VerifierSRS> {
+        ensure!(self.max_proofs() >= 2, "the srs is too small");
+
+        Ok(VerifierSRS {
+            g: self.g_alpha_powers[0],
+            h: self.h_alpha_powers[0],
+            g_alpha: self.g_alpha_powers[1],
+            g_beta: self.g_beta_powers[1],
+            h_alpha: self.h_alpha_powers[1],
+            h_beta: self.h_beta_powers[1],
+        })
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u32::<BigEndian>(self.h_alpha_powers.len() as u32)?;
+
+        for p in self.g_alpha_powers.iter().chain(self.g_beta_powers.iter()) {
+            writer.write_all(p.into_uncompressed().as_ref())?;
+        }
+        for p in self.h_alpha_powers.iter().chain(self.h_beta_powers.iter()) {
+            writer.write_all(p.into_uncompressed().as_ref())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let n = reader.read_u32::<BigEndian>()? as usize;
+
+        let mut read_g1 = |len: usize| -> io::Result<Vec<G1Affine>> {
+            let mut points = Vec::with_capacity(len);
+            let mut repr = G1Uncompressed::empty();
+            for _ in 0..len {
+                reader.read_exact(repr.as_mut())?;
+                points.push(repr);
+            }
+            points
+                .into_par_iter()
+                .map(|repr| {
+                    repr.into_affine()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                })
+                .collect()
+        };
+        let g_alpha_powers = read_g1(2 * n)?;
+        let g_beta_powers = read_g1(2 * n)?;
+
+        let mut read_g2 = |len: usize| -> io::Result<Vec<G2Affine>> {
+            let mut points = Vec::with_capacity(len);
+            let mut repr = G2Uncompressed::empty();
+            for _ in 0..len {
+                reader.read_exact(repr.as_mut())?;
+                points.push(repr);
+            }
+            points
+                .into_par_iter()
+                .map(|repr| {
+                    repr.into_affine()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                })
+                .collect()
+        };
+        let h_alpha_powers = read_g2(n)?;
+        let h_beta_powers = read_g2(n)?;
+
+        Ok(GenericSRS {
+            g_alpha_powers,
+            h_alpha_powers,
+            g_beta_powers,
+            h_beta_powers,
+        })
+    }
+}
+
+/// Generates an SRS for the aggregation of up to `size` proofs from freshly sampled secrets.
+///
+/// Whoever knows the secrets can forge aggregate proofs, so this is only available for testing.
+#[cfg(any(test, feature = "fake-srs"))]
+pub fn setup_fake_srs<R: RngCore>(rng: &mut R, size: usize) -> GenericSRS {
+    let alpha = Fr::random(rng);
+    let beta = Fr::random(rng);
+
+    let alpha_powers = structured_scalar_power(2 * size, &alpha);
+    let beta_powers = structured_scalar_power(2 * size, &beta);
+
+    let g1_powers = |powers: &[Fr]| -> Vec<G1Affine> {
+        powers
+            .par_iter()
+            .map(|s| G1Affine::one().mul(*s).into_affine())
+            .collect()
+    };
+    let g2_powers = |powers: &[Fr]| -> Vec<G2Affine> {
+        powers
+            .par_iter()
+            .map(|s| G2Affine::one().mul(*s).into_affine())
+            .collect()
+    };
+
+    GenericSRS {
+        g_alpha_powers: g1_powers(&alpha_powers),
+        h_alpha_powers: g2_powers(&alpha_powers[..size]),
+        g_beta_powers: g1_powers(&beta_powers),
+        h_beta_powers: g2_powers(&beta_powers[..size]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::TEST_SEED;
+
+    #[test]
+    fn test_srs_roundtrip() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let srs = setup_fake_srs(rng, 8);
+
+        let mut bytes = Vec::new();
+        srs.write(&mut bytes).unwrap();
+        assert_eq!(GenericSRS::read(&bytes[..]).unwrap(), srs);
+
+        let (prover, verifier) = srs.specialize(4).unwrap();
+        assert_eq!(prover.vkey.len(), 4);
+        assert_eq!(prover.wkey.len(), 4);
+        assert_eq!(prover.wkey.a[0], srs.g_alpha_powers[4]);
+        assert_eq!(verifier, srs.verifier_key().unwrap());
+
+        assert!(srs.specialize(3).is_err());
+        assert!(srs.specialize(16).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use bellperson::groth16;
+use ff::{Field, PrimeField, PrimeFieldRepr};
+use groupy::{CurveAffine, EncodedPoint};
+use paired::bls12_381::{Bls12, Fq12, Fr, FrRepr, G1Affine, G2Affine};
+use sha2::{Digest, Sha256};
+
+use super::commit::Output;
+use super::proof::write_gt;
+use super::srs::VerifierSRS;
+
+/// Fiat-Shamir transcript, deriving the challenges of the aggregation from everything the
+/// prover has sent so far.
+pub struct Transcript {
+    hasher: Sha256,
+}
+
+impl Transcript {
+    pub fn new(label: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(label);
+
+        Transcript { hasher }
+    }
+
+    pub fn append_bytes(&mut self, bytes: &[u8]) {
+        self.hasher.update(&(bytes.len() as u64).to_le_bytes());
+        self.hasher.update(bytes);
+    }
+
+    pub fn append_fr(&mut self, fr: &Fr) {
+        let mut bytes = Vec::with_capacity(32);
+        fr.into_repr()
+            .write_le(&mut bytes)
+            .expect("known allocation target");
+        self.hasher.update(&bytes);
+    }
+
+    pub fn append_g1(&mut self, p: &G1Affine) {
+        self.hasher.update(p.into_uncompressed().as_ref());
+    }
+
+    pub fn append_g2(&mut self, p: &G2Affine) {
+        self.hasher.update(p.into_uncompressed().as_ref());
+    }
+
+    pub fn append_gt(&mut self, gt: &Fq12) {
+        let mut bytes = Vec::new();
+        write_gt(&mut bytes, gt).expect("known allocation target");
+        self.hasher.update(&bytes);
+    }
+
+    /// Binds the transcript to the circuit the aggregated proofs are for.
+    pub fn append_verifying_key(&mut self, vk: &groth16::VerifyingKey<Bls12>) {
+        let mut bytes = Vec::new();
+        vk.write(&mut bytes).expect("known allocation target");
+        self.append_bytes(&bytes);
+    }
+
+    /// Binds the transcript to the SRS the commitment keys are derived from.
+    pub fn append_srs(&mut self, srs: &VerifierSRS) {
+        self.append_g1(&srs.g);
+        self.append_g2(&srs.h);
+        self.append_g1(&srs.g_alpha);
+        self.append_g1(&srs.g_beta);
+        self.append_g2(&srs.h_alpha);
+        self.append_g2(&srs.h_beta);
+    }
+
+    pub fn append_output(&mut self, output: &Output) {
+        self.append_gt(&output.0);
+        self.append_gt(&output.1);
+    }
+
+    /// Derives a non zero challenge and binds it to the transcript.
+    pub fn challenge(&mut self, label: &[u8]) -> Fr {
+        let mut counter = 0u64;
+        loop {
+            let mut digest = self
+                .hasher
+                .clone()
+                .chain(label)
+                .chain(&counter.to_le_bytes())
+                .finalize();
+
+            // Strip the two most significant bits, so the value is always below the modulus.
+            digest[31] &= 0b0011_1111;
+
+            let mut repr = FrRepr::default();
+            repr.read_le(&digest[..]).expect("digest is 32 bytes");
+            let challenge = Fr::from_repr(repr).expect("value is below the modulus");
+
+            if !challenge.is_zero() {
+                self.append_bytes(label);
+                self.append_bytes(&digest);
+
+                return challenge;
+            }
+
+            counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_challenges() {
+        let mut t1 = Transcript::new(b"test");
+        let mut t2 = Transcript::new(b"test");
+
+        t1.append_bytes(b"hello");
+        t2.append_bytes(b"hello");
+        let c1 = t1.challenge(b"x");
+        assert_eq!(c1, t2.challenge(b"x"));
+
+        // Consecutive challenges differ, as do challenges over different inputs.
+        assert_ne!(c1, t1.challenge(b"x"));
+
+        let mut t3 = Transcript::new(b"test");
+        t3.append_bytes(b"world");
+        assert_ne!(c1, t3.challenge(b"x"));
+    }
+}
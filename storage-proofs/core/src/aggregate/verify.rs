@@ -0,0 +1,250 @@
+use anyhow::ensure;
+use bellperson::groth16;
+use ff::{Field, PrimeField};
+use groupy::{CurveAffine, CurveProjective};
+use log::info;
+use paired::bls12_381::{Bls12, Fq12, Fr, G1Affine, G2Affine};
+use rayon::prelude::*;
+
+use super::commit::{self, Output, VKey, WKey};
+use super::poly::polynomial_evaluation_product_form_from_transcript;
+use super::proof::AggregateProof;
+use super::prove::{
+    append_final, append_public_inputs, append_round, new_transcript, wkey_coefficients,
+};
+use super::srs::VerifierSRS;
+use super::{multi_pairing, multiexp, structured_scalar_power};
+use crate::error::Result;
+
+/// Verifies that `proof` aggregates valid proofs for `public_inputs` under the verifying key
+/// `vk`. The number of public inputs must match the number of aggregated proofs.
+pub fn verify_aggregate_proof(
+    srs: &VerifierSRS,
+    vk: &groth16::VerifyingKey<Bls12>,
+    public_inputs: &[Vec<Fr>],
+    proof: &AggregateProof,
+) -> Result<bool> {
+    info!("verify_aggregate_proof:start");
+
+    let gipa = &proof.gipa;
+    let nproofs = gipa.nproofs as usize;
+    let rounds = nproofs.trailing_zeros() as usize;
+
+    ensure!(
+        nproofs >= 2 && nproofs.is_power_of_two(),
+        "invalid number of aggregated proofs: {}",
+        nproofs
+    );
+    ensure!(
+        public_inputs.len() == nproofs,
+        "invalid number of public inputs: {} != {}",
+        public_inputs.len(),
+        nproofs
+    );
+    ensure!(
+        gipa.comms_ab.len() == rounds
+            && gipa.comms_c.len() == rounds
+            && gipa.z_ab.len() == rounds
+            && gipa.z_c.len() == rounds,
+        "invalid number of rounds"
+    );
+    for inputs in public_inputs {
+        ensure!(
+            inputs.len() + 1 == vk.ic.len(),
+            "invalid number of public inputs for the verifying key"
+        );
+    }
+
+    // Replay the transcript of the prover.
+    let mut transcript = new_transcript(vk, srs);
+    append_public_inputs(&mut transcript, public_inputs);
+    transcript.append_output(&proof.com_ab);
+    transcript.append_output(&proof.com_c);
+    let r = transcript.challenge(b"r");
+    transcript.append_gt(&proof.ip_ab);
+    transcript.append_g1(&proof.agg_c);
+
+    let mut challenges = Vec::with_capacity(rounds);
+    for round in 0..rounds {
+        append_round(&mut transcript, gipa, round);
+        challenges.push(transcript.challenge(b"x"));
+    }
+    append_final(&mut transcript, gipa);
+    let z = transcript.challenge(b"z");
+
+    let challenges_inv: Vec<Fr> = challenges
+        .iter()
+        .map(|x| x.inverse().expect("challenges are not zero"))
+        .collect();
+
+    // Fold the commitments and claimed values the same way the prover folded the vectors.
+    let mut com_ab = proof.com_ab;
+    let mut com_c = proof.com_c;
+    let mut z_ab = proof.ip_ab;
+    let mut z_c = proof.agg_c.into_projective();
+    for (round, (x, x_inv)) in challenges.iter().zip(challenges_inv.iter()).enumerate() {
+        let (tab_l, tab_r) = &gipa.comms_ab[round];
+        let (tuc_l, tuc_r) = &gipa.comms_c[round];
+        let (zab_l, zab_r) = &gipa.z_ab[round];
+        let (zc_l, zc_r) = &gipa.z_c[round];
+
+        com_ab = fold_output(&com_ab, tab_l, tab_r, x, x_inv);
+        com_c = fold_output(&com_c, tuc_l, tuc_r, x, x_inv);
+        z_ab = fold_gt(&z_ab, zab_l, zab_r, x, x_inv);
+
+        z_c.add_assign(&zc_l.mul(*x));
+        z_c.add_assign(&zc_r.mul(*x_inv));
+    }
+
+    // The final values must be consistent with the folded commitments.
+    let final_vkey = VKey {
+        a: vec![gipa.final_vkey.0],
+        b: vec![gipa.final_vkey.1],
+    };
+    let final_wkey = WKey {
+        a: vec![gipa.final_wkey.0],
+        b: vec![gipa.final_wkey.1],
+    };
+
+    let tipp_valid = com_ab
+        == commit::pair(&final_vkey, &final_wkey, &[gipa.final_a], &[gipa.final_b])?
+        && z_ab == multi_pairing(&[gipa.final_a], &[gipa.final_b]);
+
+    let r_final = polynomial_evaluation_product_form_from_transcript(&challenges_inv, &r);
+    let mipp_valid = com_c == commit::single_g1(&final_vkey, &[gipa.final_c])?
+        && z_c == gipa.final_c.mul(r_final);
+
+    // The final commitment keys must have been derived from the SRS.
+    let vkey_valid = verify_vkey_opening(srs, proof, &challenges_inv, &z);
+    let wkey_valid = verify_wkey_opening(srs, proof, &challenges, &r, &z, nproofs);
+
+    // The random linear combination of the Groth16 verification equations must hold.
+    let groth16_valid = verify_groth16_combination(vk, public_inputs, proof, &r);
+
+    let result = tipp_valid && mipp_valid && vkey_valid && wkey_valid && groth16_valid;
+
+    info!("verify_aggregate_proof:finish");
+    Ok(result)
+}
+
+fn fold_gt(value: &Fq12, left: &Fq12, right: &Fq12, x: &Fr, x_inv: &Fr) -> Fq12 {
+    let mut res = *value;
+    res.mul_assign(&left.pow(x.into_repr()));
+    res.mul_assign(&right.pow(x_inv.into_repr()));
+    res
+}
+
+fn fold_output(value: &Output, left: &Output, right: &Output, x: &Fr, x_inv: &Fr) -> Output {
+    (
+        fold_gt(&value.0, &left.0, &right.0, x, x_inv),
+        fold_gt(&value.1, &left.1, &right.1, x, x_inv),
+    )
+}
+
+/// Checks the KZG openings of the commitment key in G2 at `z`, i.e. that
+/// `e(g^{s - z}, opening) == e(g, final_vkey - h^{f_v(z)})` for `s` in `alpha` and `beta`.
+fn verify_vkey_opening(
+    srs: &VerifierSRS,
+    proof: &AggregateProof,
+    challenges_inv: &[Fr],
+    z: &Fr,
+) -> bool {
+    let v_z = polynomial_evaluation_product_form_from_transcript(challenges_inv, z);
+    let h_v_z = srs.h.mul(v_z);
+    let g_z = srs.g.mul(*z);
+
+    let check = |g_secret: &G1Affine, final_key: &G2Affine, opening: &G2Affine| {
+        let mut lhs_g1 = g_secret.into_projective();
+        lhs_g1.sub_assign(&g_z);
+        let mut rhs_g2 = final_key.into_projective();
+        rhs_g2.sub_assign(&h_v_z);
+
+        multi_pairing(&[lhs_g1.into_affine()], &[*opening])
+            == multi_pairing(&[srs.g], &[rhs_g2.into_affine()])
+    };
+
+    check(
+        &srs.g_alpha,
+        &proof.gipa.final_vkey.0,
+        &proof.vkey_opening.0,
+    ) && check(&srs.g_beta, &proof.gipa.final_vkey.1, &proof.vkey_opening.1)
+}
+
+/// Checks the KZG openings of the commitment key in G1 at `z`, i.e. that
+/// `e(final_wkey - g^{z^n f_w(z)}, h) == e(opening, h^{s - z})` for `s` in `alpha` and `beta`.
+fn verify_wkey_opening(
+    srs: &VerifierSRS,
+    proof: &AggregateProof,
+    challenges: &[Fr],
+    r: &Fr,
+    z: &Fr,
+    nproofs: usize,
+) -> bool {
+    let r_inv = r.inverse().expect("r is not zero");
+    let coeffs = wkey_coefficients(challenges, &r_inv);
+    let mut w_z = polynomial_evaluation_product_form_from_transcript(&coeffs, z);
+    w_z.mul_assign(&z.pow(&[nproofs as u64]));
+
+    let g_w_z = srs.g.mul(w_z);
+    let h_z = srs.h.mul(*z);
+
+    let check = |h_secret: &G2Affine, final_key: &G1Affine, opening: &G1Affine| {
+        let mut lhs_g1 = final_key.into_projective();
+        lhs_g1.sub_assign(&g_w_z);
+        let mut rhs_g2 = h_secret.into_projective();
+        rhs_g2.sub_assign(&h_z);
+
+        multi_pairing(&[lhs_g1.into_affine()], &[srs.h])
+            == multi_pairing(&[*opening], &[rhs_g2.into_affine()])
+    };
+
+    check(
+        &srs.h_alpha,
+        &proof.gipa.final_wkey.0,
+        &proof.wkey_opening.0,
+    ) && check(&srs.h_beta, &proof.gipa.final_wkey.1, &proof.wkey_opening.1)
+}
+
+/// Checks `prod e(A_i, B_i)^{r^i} == e(alpha, beta)^{sum r^i} * e(sum S_i * r^i, gamma) *
+/// e(sum C_i * r^i, delta)`, where `S_i` is the accumulated public input of the `i`-th proof.
+fn verify_groth16_combination(
+    vk: &groth16::VerifyingKey<Bls12>,
+    public_inputs: &[Vec<Fr>],
+    proof: &AggregateProof,
+    r: &Fr,
+) -> bool {
+    let r_vec = structured_scalar_power(public_inputs.len(), r);
+    let r_sum = r_vec.iter().fold(Fr::zero(), |mut acc, r| {
+        acc.add_assign(r);
+        acc
+    });
+
+    // The scalar of the j-th input element is the sum of the j-th public inputs, weighted by r^i.
+    let mut scalars = vec![r_sum];
+    scalars.extend(
+        (0..vk.ic.len() - 1)
+            .into_par_iter()
+            .map(|j| {
+                public_inputs
+                    .iter()
+                    .zip(r_vec.iter())
+                    .fold(Fr::zero(), |mut acc, (inputs, r)| {
+                        let mut term = inputs[j];
+                        term.mul_assign(r);
+                        acc.add_assign(&term);
+                        acc
+                    })
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    let acc_inputs = multiexp(&vk.ic, &scalars).into_affine();
+    let alpha_r_sum = vk.alpha_g1.mul(r_sum).into_affine();
+
+    let rhs = multi_pairing(
+        &[alpha_r_sum, acc_inputs, proof.agg_c],
+        &[vk.beta_g2, vk.gamma_g2, vk.delta_g2],
+    );
+
+    proof.ip_ab == rhs
+}
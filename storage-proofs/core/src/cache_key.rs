@@ -7,6 +7,8 @@ pub enum CacheKey {
     CommDTree,
     CommCTree,
     CommRLastTree,
+    LabelsCheckpoint,
+    SectorMetadata,
 }
 
 impl fmt::Display for CacheKey {
@@ -17,6 +19,8 @@ impl fmt::Display for CacheKey {
             CacheKey::CommDTree => write!(f, "tree-d"),
             CacheKey::CommCTree => write!(f, "tree-c"),
             CacheKey::CommRLastTree => write!(f, "tree-r-last"),
+            CacheKey::LabelsCheckpoint => write!(f, "labels-checkpoint"),
+            CacheKey::SectorMetadata => write!(f, "sector-metadata"),
         }
     }
 }
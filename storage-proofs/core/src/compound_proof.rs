@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use anyhow::{ensure, Context};
 use bellperson::{groth16, Circuit};
 use log::info;
@@ -5,11 +7,14 @@ use paired::bls12_381::{Bls12, Fr};
 use rand::{rngs::OsRng, RngCore};
 use rayon::prelude::*;
 
+use crate::aggregate::{self, AggregateProof, GenericSRS, VerifierSRS};
 use crate::error::Result;
 use crate::multi_proof::MultiProof;
 use crate::parameter_cache::{CacheableParameters, ParameterSetMetadata};
 use crate::partitions;
+use crate::progress::{check_cancelled, report, Phase, Progress};
 use crate::proof::ProofScheme;
+use crate::settings;
 
 #[derive(Clone)]
 pub struct SetupParams<'a, S: ProofScheme<'a>> {
@@ -69,6 +74,18 @@ where
         pub_in: &S::PublicInputs,
         priv_in: &S::PrivateInputs,
         groth_params: &'b groth16::MappedParameters<Bls12>,
+    ) -> Result<MultiProof<'b>> {
+        Self::prove_with_progress(pub_params, pub_in, priv_in, groth_params, None)
+    }
+
+    /// prove_with_progress is equivalent to prove, but reports the circuit proof progress
+    /// to `progress` and returns `Error::Cancelled` once it has been cancelled.
+    fn prove_with_progress<'b>(
+        pub_params: &PublicParams<'a, S>,
+        pub_in: &S::PublicInputs,
+        priv_in: &S::PrivateInputs,
+        groth_params: &'b groth16::MappedParameters<Bls12>,
+        progress: Option<&dyn Progress>,
     ) -> Result<MultiProof<'b>> {
         let partition_count = Self::partition_count(pub_params);
 
@@ -84,18 +101,20 @@ where
         )?;
 
         info!("vanilla_proof:finish");
+        check_cancelled(progress)?;
 
         let sanity_check =
             S::verify_all_partitions(&pub_params.vanilla_params, &pub_in, &vanilla_proofs)?;
         ensure!(sanity_check, "sanity check failed");
 
         info!("snark_proof:start");
-        let groth_proofs = Self::circuit_proofs(
+        let groth_proofs = Self::circuit_proofs_with_progress(
             pub_in,
             vanilla_proofs,
             &pub_params.vanilla_params,
             groth_params,
             pub_params.priority,
+            progress,
         )?;
         info!("snark_proof:finish");
 
@@ -196,6 +215,94 @@ where
         Ok(res)
     }
 
+    /// Aggregates the circuit proofs of all `multi_proofs` into a single proof. The number of
+    /// circuit proofs is padded to a power of two, so `srs` must support at least the next power
+    /// of two of proofs.
+    fn aggregate_proofs<'b>(
+        public_params: &PublicParams<'a, S>,
+        public_inputs: &[S::PublicInputs],
+        multi_proofs: &[MultiProof<'b>],
+        srs: &GenericSRS,
+    ) -> Result<AggregateProof> {
+        ensure!(
+            public_inputs.len() == multi_proofs.len(),
+            "Inconsistent inputs"
+        );
+        for proof in multi_proofs {
+            ensure!(
+                proof.circuit_proofs.len() == Self::partition_count(public_params),
+                "Inconsistent inputs"
+            );
+        }
+        ensure!(!public_inputs.is_empty(), "Cannot aggregate empty proofs");
+        let verifying_key = multi_proofs[0].verifying_key;
+        ensure!(
+            multi_proofs
+                .iter()
+                .all(|proof| proof.verifying_key == verifying_key),
+            "Cannot aggregate proofs for different verifying keys"
+        );
+
+        let mut inputs = Self::aggregate_public_inputs(public_params, public_inputs)?;
+        let mut circuit_proofs: Vec<_> = multi_proofs
+            .iter()
+            .flat_map(|m| m.circuit_proofs.iter().cloned())
+            .collect();
+        aggregate::pad_to_power_of_two(&mut inputs);
+        aggregate::pad_to_power_of_two(&mut circuit_proofs);
+
+        let (prover_srs, _) = srs.specialize(circuit_proofs.len())?;
+        aggregate::aggregate_proofs(&prover_srs, verifying_key, &inputs, &circuit_proofs)
+    }
+
+    /// Verifies a proof created by `aggregate_proofs` for the given `public_inputs`.
+    fn verify_aggregate_proofs(
+        public_params: &PublicParams<'a, S>,
+        public_inputs: &[S::PublicInputs],
+        verifying_key: &groth16::VerifyingKey<Bls12>,
+        srs: &VerifierSRS,
+        proof: &AggregateProof,
+        requirements: &S::Requirements,
+    ) -> Result<bool> {
+        ensure!(!public_inputs.is_empty(), "Cannot verify empty proofs");
+
+        if !<S as ProofScheme>::satisfies_requirements(
+            &public_params.vanilla_params,
+            requirements,
+            Self::partition_count(public_params),
+        ) {
+            return Ok(false);
+        }
+
+        let mut inputs = Self::aggregate_public_inputs(public_params, public_inputs)?;
+        aggregate::pad_to_power_of_two(&mut inputs);
+
+        if inputs.len() != proof.gipa.nproofs as usize {
+            return Ok(false);
+        }
+
+        aggregate::verify_aggregate_proof(srs, verifying_key, &inputs, proof)
+    }
+
+    /// Generates the circuit public inputs of every partition of every proof, in the order the
+    /// circuit proofs are aggregated in.
+    fn aggregate_public_inputs(
+        public_params: &PublicParams<'a, S>,
+        public_inputs: &[S::PublicInputs],
+    ) -> Result<Vec<Vec<Fr>>> {
+        let partition_count = Self::partition_count(public_params);
+        let vanilla_public_params = &public_params.vanilla_params;
+
+        public_inputs
+            .par_iter()
+            .flat_map(|pub_inputs| {
+                (0..partition_count).into_par_iter().map(move |k| {
+                    Self::generate_public_inputs(pub_inputs, vanilla_public_params, Some(k))
+                })
+            })
+            .collect()
+    }
+
     /// circuit_proof creates and synthesizes a circuit from concrete params/inputs, then generates a
     /// groth proof from it. It returns a groth proof.
     /// circuit_proof is used internally and should neither be called nor implemented outside of
@@ -206,6 +313,26 @@ where
         pub_params: &S::PublicParams,
         groth_params: &groth16::MappedParameters<Bls12>,
         priority: bool,
+    ) -> Result<Vec<groth16::Proof<Bls12>>> {
+        Self::circuit_proofs_with_progress(
+            pub_in,
+            vanilla_proofs,
+            pub_params,
+            groth_params,
+            priority,
+            None,
+        )
+    }
+
+    /// circuit_proofs_with_progress is equivalent to circuit_proofs, but reports the synthesis
+    /// progress to `progress` and returns `Error::Cancelled` once it has been cancelled.
+    fn circuit_proofs_with_progress(
+        pub_in: &S::PublicInputs,
+        vanilla_proofs: Vec<S::Proof>,
+        pub_params: &S::PublicParams,
+        groth_params: &groth16::MappedParameters<Bls12>,
+        priority: bool,
+        progress: Option<&dyn Progress>,
     ) -> Result<Vec<groth16::Proof<Bls12>>> {
         let mut rng = OsRng;
         ensure!(
@@ -213,17 +340,32 @@ where
             "cannot create a circuit proof over missing vanilla proofs"
         );
 
+        // Progress is counted in steps: one per synthesized circuit, plus one for the
+        // batched groth proof generation.
+        let total = vanilla_proofs.len() + 1;
+        let done = AtomicUsize::new(0);
+        report(progress, Phase::CircuitProofs, 0, 0, total)?;
+
+        // The circuits are built on rayon workers, which need the settings of the caller.
+        let settings = settings::current();
         let circuits = vanilla_proofs
             .into_par_iter()
             .enumerate()
             .map(|(k, vanilla_proof)| {
-                Self::circuit(
-                    &pub_in,
-                    C::ComponentPrivateInputs::default(),
-                    &vanilla_proof,
-                    &pub_params,
-                    Some(k),
-                )
+                check_cancelled(progress)?;
+                let circuit = settings::with_settings(settings.clone(), || {
+                    Self::circuit(
+                        &pub_in,
+                        C::ComponentPrivateInputs::default(),
+                        &vanilla_proof,
+                        &pub_params,
+                        Some(k),
+                    )
+                })?;
+                let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                report(progress, Phase::CircuitProofs, 0, done, total)?;
+
+                Ok(circuit)
             })
             .collect::<Result<Vec<_>>>()?;
 
@@ -232,6 +374,7 @@ where
         } else {
             groth16::create_random_proof_batch(circuits, groth_params, &mut rng)?
         };
+        report(progress, Phase::CircuitProofs, 0, total, total)?;
 
         groth_proofs
             .into_iter()
@@ -1,4 +1,5 @@
 use std::any::Any;
+use std::path::PathBuf;
 
 use bellperson::SynthesisError;
 
@@ -37,6 +38,28 @@ pub enum Error {
     Unclassified(String),
     #[error("Missing Private Input {0} for sector {1}")]
     MissingPrivateInput(&'static str, u64),
+    #[error("operation was cancelled")]
+    Cancelled,
+    #[error("invalid parent cache {path:?}: {fault}")]
+    InvalidParentCache {
+        path: PathBuf,
+        fault: ParentCacheFault,
+    },
+}
+
+/// The reason a parent cache failed verification.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParentCacheFault {
+    #[error("expected {expected} bytes, found {actual}")]
+    Size { expected: u64, actual: u64 },
+    #[error("missing or malformed header")]
+    Header,
+    #[error("header does not match the graph")]
+    GraphMismatch,
+    #[error("digest does not match the contents")]
+    Digest,
+    #[error("wrong parents for node {0}")]
+    Parents(u32),
 }
 
 impl From<Box<dyn Any + Send>> for Error {
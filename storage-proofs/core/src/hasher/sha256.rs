@@ -9,7 +9,7 @@ use merkletree::merkle::Element;
 use paired::bls12_381::{Bls12, Fr, FrRepr};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use sha2raw::{Digest, Sha256Stream};
 
 use super::{Domain, HashFunction, Hasher};
 use crate::crypto::sloth;
@@ -42,7 +42,7 @@ impl Hasher for Sha256Hasher {
 }
 
 #[derive(Default, Clone, Debug)]
-pub struct Sha256Function(Sha256);
+pub struct Sha256Function(Sha256Stream);
 
 impl StdHasher for Sha256Function {
     #[inline]
@@ -168,7 +168,7 @@ impl Element for Sha256Domain {
 
 impl HashFunction<Sha256Domain> for Sha256Function {
     fn hash(data: &[u8]) -> Sha256Domain {
-        let hashed = Sha256::digest(data);
+        let hashed = Sha256Stream::digest(data);
         let mut res = Sha256Domain::default();
         res.0.copy_from_slice(&hashed[..]);
         res.trim_to_fr32();
@@ -176,7 +176,7 @@ impl HashFunction<Sha256Domain> for Sha256Function {
     }
 
     fn hash2(a: &Sha256Domain, b: &Sha256Domain) -> Sha256Domain {
-        let hashed = Sha256::new()
+        let hashed = Sha256Stream::new()
             .chain(AsRef::<[u8]>::as_ref(a))
             .chain(AsRef::<[u8]>::as_ref(b))
             .finalize();
@@ -359,7 +359,7 @@ mod tests {
     use ff::Field;
     use merkletree::hash::Algorithm;
     use paired::bls12_381::{Bls12, Fr};
-    use rand::SeedableRng;
+    use rand::{Rng, SeedableRng};
     use rand_xorshift::XorShiftRng;
 
     #[test]
@@ -404,4 +404,48 @@ mod tests {
             "circuit and non circuit do not match"
         );
     }
+
+    #[test]
+    fn test_matches_sha2() {
+        use sha2::Sha256;
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let trimmed = |hash: &[u8]| {
+            let mut domain = Sha256Domain::default();
+            domain.0.copy_from_slice(hash);
+            domain.trim_to_fr32();
+            domain
+        };
+
+        for len in 0..200 {
+            let data = (0..len).map(|_| rng.gen()).collect::<Vec<u8>>();
+            assert_eq!(
+                <Sha256Function as HashFunction<Sha256Domain>>::hash(&data),
+                trimmed(&Sha256::digest(&data))
+            );
+        }
+
+        let parts = (0..8)
+            .map(|_| Sha256Domain::random(rng))
+            .collect::<Vec<_>>();
+        let concat = parts
+            .iter()
+            .flat_map(|part| part.0.to_vec())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            Sha256Function::hash2(&parts[0], &parts[1]),
+            trimmed(&Sha256::digest(&concat[..64]))
+        );
+        let mut function = Sha256Function::default();
+        assert_eq!(
+            function.node(parts[0], parts[1], 0),
+            trimmed(&Sha256::digest(&concat[..64]))
+        );
+        function.reset();
+        assert_eq!(
+            function.multi_node(&parts, 0),
+            trimmed(&Sha256::digest(&concat))
+        );
+    }
 }
@@ -6,6 +6,7 @@
 #[macro_use]
 pub mod test_helper;
 
+pub mod aggregate;
 pub mod cache_key;
 pub mod compound_proof;
 pub mod crypto;
@@ -22,8 +23,10 @@ pub mod parameter_cache;
 pub mod partitions;
 pub mod pieces;
 pub mod por;
+pub mod progress;
 pub mod proof;
 pub mod sector;
+pub mod sector_metadata;
 pub mod settings;
 pub mod util;
 
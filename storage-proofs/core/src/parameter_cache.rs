@@ -1,5 +1,5 @@
 use crate::error::*;
-use anyhow::bail;
+use anyhow::{bail, ensure, Context};
 use bellperson::groth16::Parameters;
 use bellperson::{groth16, Circuit};
 use fs2::FileExt;
@@ -11,9 +11,10 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use std::fs::{self, create_dir_all, File};
-use std::io::{self, SeekFrom};
+use std::io::{self, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
+use super::aggregate::GenericSRS;
 use super::settings;
 
 /// Bump this when circuits change to invalidate the cache.
@@ -22,6 +23,10 @@ pub const VERSION: usize = 27;
 pub const GROTH_PARAMETER_EXT: &str = "params";
 pub const PARAMETER_METADATA_EXT: &str = "meta";
 pub const VERIFYING_KEY_EXT: &str = "vk";
+pub const SRS_KEY_EXT: &str = "srs";
+
+/// Identifier of the structured reference string used to aggregate proofs.
+pub const SRS_IDENTIFIER: &str = "fil-inner-product-v1";
 
 #[derive(Debug)]
 pub struct LockedFile(File);
@@ -92,7 +97,7 @@ impl Drop for LockedFile {
 }
 
 pub fn parameter_cache_dir_name() -> String {
-    settings::SETTINGS.lock().unwrap().parameter_cache.clone()
+    settings::current().parameter_cache.clone()
 }
 
 pub fn parameter_cache_dir() -> PathBuf {
@@ -123,6 +128,11 @@ pub fn parameter_cache_verifying_key_path(parameter_set_identifier: &str) -> Pat
     ))
 }
 
+pub fn parameter_cache_srs_key_path(srs_identifier: &str) -> PathBuf {
+    let dir = Path::new(&parameter_cache_dir_name()).to_path_buf();
+    dir.join(format!("v{}-{}.{}", VERSION, srs_identifier, SRS_KEY_EXT))
+}
+
 fn ensure_ancestor_dirs_exist(cache_entry_path: PathBuf) -> Result<PathBuf> {
     info!(
         "ensuring that all ancestor directories for: {:?} exist",
@@ -256,6 +266,70 @@ where
     }
 }
 
+/// Returns the structured reference string used to aggregate proofs, which must support at
+/// least `max_proofs` proofs.
+///
+/// The SRS comes from a trusted setup and is published with the other parameters, so it is
+/// never generated here: an error will result if it is not present in the cache.
+pub fn get_srs_key(max_proofs: usize) -> Result<GenericSRS> {
+    let cache_path = parameter_cache_srs_key_path(SRS_IDENTIFIER);
+    let srs = read_cached_srs_key(&cache_path)
+        .with_context(|| format!("No cached srs found for {}", SRS_IDENTIFIER))?;
+    ensure_srs_supports(&srs, max_proofs)?;
+
+    Ok(srs)
+}
+
+/// Same as `get_srs_key`, but generates the SRS from `rng` and caches it if it is not present.
+///
+/// Whoever knows the secrets of a generated SRS can forge aggregate proofs, so this is only
+/// available for testing, and refuses to run unless the settings in effect point to a
+/// parameter cache other than the configured or the default one.
+#[cfg(any(test, feature = "fake-srs"))]
+pub fn get_fake_srs_key<R: RngCore>(rng: &mut R, max_proofs: usize) -> Result<GenericSRS> {
+    use super::aggregate::setup_fake_srs;
+
+    let cache_dir = parameter_cache_dir();
+    let configured = settings::SETTINGS.lock().unwrap().parameter_cache.clone();
+    let default = settings::Settings::default().parameter_cache;
+    ensure!(
+        !same_dir(&cache_dir, Path::new(&configured)) && !same_dir(&cache_dir, Path::new(&default)),
+        "refusing to put a fake srs into the parameter cache {:?}",
+        cache_dir
+    );
+
+    let cache_path = ensure_ancestor_dirs_exist(parameter_cache_srs_key_path(SRS_IDENTIFIER))?;
+    let srs = read_cached_srs_key(&cache_path).or_else(|_| {
+        info!(
+            "Actually generating fake srs. (id: {}, max_proofs: {})",
+            SRS_IDENTIFIER, max_proofs
+        );
+        write_cached_srs_key(&cache_path, setup_fake_srs(rng, max_proofs))
+    })?;
+    ensure_srs_supports(&srs, max_proofs)?;
+
+    Ok(srs)
+}
+
+#[cfg(any(test, feature = "fake-srs"))]
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.components().eq(b.components()),
+    }
+}
+
+fn ensure_srs_supports(srs: &GenericSRS, max_proofs: usize) -> Result<()> {
+    ensure!(
+        srs.max_proofs() >= max_proofs,
+        "the cached srs supports the aggregation of at most {} proofs, not {}",
+        srs.max_proofs(),
+        max_proofs
+    );
+
+    Ok(())
+}
+
 fn ensure_parent(path: &PathBuf) -> Result<()> {
     match path.parent() {
         Some(dir) => {
@@ -291,6 +365,16 @@ fn read_cached_verifying_key(cache_entry_path: &PathBuf) -> Result<groth16::Veri
     })
 }
 
+fn read_cached_srs_key(cache_entry_path: &PathBuf) -> Result<GenericSRS> {
+    info!("checking cache_path: {:?} for srs", cache_entry_path);
+    with_exclusive_read_lock(cache_entry_path, |file| {
+        let srs = GenericSRS::read(io::BufReader::new(file))?;
+        info!("read srs from cache {:?} ", cache_entry_path);
+
+        Ok(srs)
+    })
+}
+
 fn read_cached_metadata(cache_entry_path: &PathBuf) -> Result<CacheEntryMetadata> {
     info!("checking cache_path: {:?} for metadata", cache_entry_path);
     with_exclusive_read_lock(cache_entry_path, |file| {
@@ -325,6 +409,17 @@ fn write_cached_verifying_key(
     })
 }
 
+fn write_cached_srs_key(cache_entry_path: &PathBuf, value: GenericSRS) -> Result<GenericSRS> {
+    with_exclusive_lock(cache_entry_path, |file| {
+        let mut writer = io::BufWriter::new(file);
+        value.write(&mut writer)?;
+        writer.flush()?;
+        info!("wrote srs to cache {:?} ", cache_entry_path);
+
+        Ok(value)
+    })
+}
+
 fn write_cached_params(
     cache_entry_path: &PathBuf,
     value: groth16::Parameters<Bls12>,
@@ -359,3 +454,32 @@ pub fn with_open_file<'a, T>(
     ensure_parent(&file_path)?;
     f(&mut open_file(&file_path)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::TEST_SEED;
+
+    #[test]
+    fn test_fake_srs_key_needs_other_cache() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+        // The srs production code reads is never replaced by a fake one.
+        assert!(get_fake_srs_key(rng, 2).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = settings::Settings::default();
+        config.parameter_cache = dir.path().to_string_lossy().to_string();
+        settings::with_settings(Arc::new(config), || {
+            let srs = get_fake_srs_key(rng, 2).unwrap();
+            assert!(parameter_cache_srs_key_path(SRS_IDENTIFIER).exists());
+            assert_eq!(get_srs_key(2).unwrap(), srs);
+        });
+    }
+}
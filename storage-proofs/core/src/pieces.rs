@@ -1,14 +1,19 @@
 use std::io::Read;
 
 use anyhow::{ensure, Context};
+use merkletree::hash::Algorithm;
 use merkletree::merkle::next_pow2;
+use serde::{Deserialize, Serialize};
 
 use crate::error::*;
 use crate::fr32::Fr32Ary;
 use crate::hasher::{Domain, Hasher};
-use crate::merkle::BinaryMerkleTree;
+use crate::merkle::{BinaryMerkleTree, MerkleProofTrait, MerkleTreeTrait};
 use crate::util::NODE_SIZE;
 
+/// Number of leaves read at once by `PieceInclusionProof::generate_from_source`.
+const SOURCE_CHUNK_LEAVES: usize = 1 << 15;
+
 /// `position`, `length` are in H::Domain units
 #[derive(Clone, Debug)]
 pub struct PieceSpec {
@@ -43,6 +48,158 @@ impl PieceSpec {
     }
 }
 
+/// Proof that the subtree of a piece, whose root is `comm_p`, is part of a binary merkle tree.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct PieceInclusionProof<H: Hasher> {
+    /// Index of the piece subtree among all subtrees of the same height.
+    position: usize,
+    /// Siblings of the piece subtree, from the height of the piece up to the root.
+    proof_elements: Vec<H::Domain>,
+}
+
+impl<H: Hasher> PieceInclusionProof<H> {
+    /// Generates the proof for the piece described by `piece_spec` from the full `tree`.
+    pub fn generate(piece_spec: &PieceSpec, tree: &BinaryMerkleTree<H>) -> Result<Self> {
+        let tree_len = tree.leafs();
+        let (_, proof_length) = piece_spec.compute_packing(tree_len)?;
+        let height = piece_spec.height();
+
+        // The path from the first leaf of the piece contains the path of the piece subtree,
+        // above the height of the piece.
+        let leaf_proof = tree.gen_proof(piece_spec.position)?;
+        let path = leaf_proof.path();
+        ensure!(
+            path.len() == height + proof_length,
+            Error::MalformedMerkleTree
+        );
+
+        let proof = PieceInclusionProof {
+            position: piece_spec.position >> height,
+            proof_elements: path[height..].iter().map(|(hashes, _)| hashes[0]).collect(),
+        };
+
+        ensure!(
+            proof.verify(piece_spec, &tree.root(), tree_len)?,
+            Error::BadPieceCommitment
+        );
+
+        Ok(proof)
+    }
+
+    /// Generates the proof for the piece described by `piece_spec` from the `tree_len` leaves
+    /// of the tree with `root`, read in order from `source`.
+    ///
+    /// Only one pending node per level of the tree is kept in memory, so this does not need
+    /// the tree to have been built or persisted.
+    pub fn generate_from_source(
+        piece_spec: &PieceSpec,
+        source: &mut dyn Read,
+        tree_len: usize,
+        root: &H::Domain,
+    ) -> Result<Self> {
+        ensure!(
+            tree_len.is_power_of_two(),
+            "tree length must be a power of two"
+        );
+        let (_, proof_length) = piece_spec.compute_packing(tree_len)?;
+        let height = piece_spec.height();
+        let position = piece_spec.position >> height;
+
+        let mut proof_elements: Vec<Option<H::Domain>> = vec![None; proof_length];
+        let mut pending: Vec<Option<H::Domain>> = vec![None; height_for_length(tree_len) + 1];
+
+        let mut a = H::Function::default();
+        let mut buf = vec![0; SOURCE_CHUNK_LEAVES.min(tree_len) * NODE_SIZE];
+        let mut leaf_index = 0;
+        while leaf_index < tree_len {
+            let chunk_leaves = (tree_len - leaf_index).min(SOURCE_CHUNK_LEAVES);
+            let chunk = &mut buf[..chunk_leaves * NODE_SIZE];
+            source
+                .read_exact(chunk)
+                .context("failed to read tree leaves")?;
+
+            for leaf in chunk.chunks(NODE_SIZE) {
+                let mut node = H::Domain::try_from_bytes(leaf).context("invalid Fr element")?;
+                let mut index = leaf_index;
+                let mut level = 0;
+                loop {
+                    if level >= height && index == (position >> (level - height)) ^ 1 {
+                        proof_elements[level - height] = Some(node);
+                    }
+                    if index & 1 == 0 {
+                        pending[level] = Some(node);
+                        break;
+                    }
+                    let left = pending[level].take().ok_or(Error::MalformedMerkleTree)?;
+                    a.reset();
+                    node = a.node(left, node, level);
+                    index >>= 1;
+                    level += 1;
+                }
+                leaf_index += 1;
+            }
+        }
+
+        ensure!(
+            pending.last().and_then(|node| node.as_ref()) == Some(root),
+            "source does not match the root of the tree"
+        );
+
+        let proof = PieceInclusionProof {
+            position,
+            proof_elements: proof_elements
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::MalformedMerkleTree)?,
+        };
+
+        ensure!(
+            proof.verify(piece_spec, root, tree_len)?,
+            Error::BadPieceCommitment
+        );
+
+        Ok(proof)
+    }
+
+    /// Verifies that the piece described by `piece_spec` is part of the tree with `root`,
+    /// which has `tree_len` leaves.
+    pub fn verify(
+        &self,
+        piece_spec: &PieceSpec,
+        root: &H::Domain,
+        tree_len: usize,
+    ) -> Result<bool> {
+        let (_, proof_length) = piece_spec.compute_packing(tree_len)?;
+        let height = piece_spec.height();
+
+        if self.position != piece_spec.position >> height
+            || self.proof_elements.len() != proof_length
+        {
+            return Ok(false);
+        }
+
+        let comm_p = H::Domain::try_from_bytes(&piece_spec.comm_p)?;
+
+        let mut a = H::Function::default();
+        let (calculated_root, _) = self.proof_elements.iter().enumerate().fold(
+            (comm_p, self.position),
+            |(h, index), (i, sibling)| {
+                a.reset();
+                let node = if index & 1 == 0 {
+                    a.node(h, *sibling, height + i)
+                } else {
+                    a.node(*sibling, h, height + i)
+                };
+
+                (node, index >> 1)
+            },
+        );
+
+        Ok(&calculated_root == root)
+    }
+}
+
 /// Generate `comm_p` from a source and return it as bytes.
 pub fn generate_piece_commitment_bytes_from_source<H: Hasher>(
     source: &mut dyn Read,
@@ -103,7 +260,12 @@ fn subtree_capacity(pos: usize, total: usize) -> Result<usize> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::hasher::PedersenHasher;
+    use crate::hasher::{PedersenHasher, Sha256Hasher};
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    use crate::TEST_SEED;
 
     #[test]
     fn test_subtree_capacity() {
@@ -145,4 +307,89 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_piece_inclusion_proof() -> Result<()> {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let tree_len = 16;
+
+        let data: Vec<u8> = (0..tree_len)
+            .flat_map(|_| {
+                let node: <Sha256Hasher as Hasher>::Domain = Domain::random(rng);
+                node.into_bytes()
+            })
+            .collect();
+        let tree = BinaryMerkleTree::<Sha256Hasher>::try_from_iter(
+            data.chunks(NODE_SIZE)
+                .map(<Sha256Hasher as Hasher>::Domain::try_from_bytes),
+        )?;
+
+        for &(position, number_of_leaves) in &[(0, 16), (4, 4), (6, 2), (13, 1)] {
+            let mut piece_bytes =
+                &data[position * NODE_SIZE..(position + number_of_leaves) * NODE_SIZE];
+            let comm_p = if number_of_leaves == 1 {
+                let mut comm_p = [0; NODE_SIZE];
+                comm_p.copy_from_slice(piece_bytes);
+                comm_p
+            } else {
+                generate_piece_commitment_bytes_from_source::<Sha256Hasher>(
+                    &mut piece_bytes,
+                    number_of_leaves * NODE_SIZE,
+                )?
+            };
+
+            let piece_spec = PieceSpec {
+                comm_p,
+                position,
+                number_of_leaves,
+            };
+            let proof = PieceInclusionProof::<Sha256Hasher>::generate(&piece_spec, &tree)?;
+            assert!(proof.verify(&piece_spec, &tree.root(), tree_len)?);
+
+            let streamed = PieceInclusionProof::<Sha256Hasher>::generate_from_source(
+                &piece_spec,
+                &mut &data[..],
+                tree_len,
+                &tree.root(),
+            )?;
+            assert_eq!(streamed, proof);
+
+            let mut bad_comm_p = piece_spec.clone();
+            bad_comm_p.comm_p[0] ^= 1;
+            assert!(!proof.verify(&bad_comm_p, &tree.root(), tree_len)?);
+            assert!(PieceInclusionProof::<Sha256Hasher>::generate(&bad_comm_p, &tree).is_err());
+
+            let moved = PieceSpec {
+                position: (position + number_of_leaves) % tree_len,
+                ..piece_spec.clone()
+            };
+            assert!(!proof.verify(&moved, &tree.root(), tree_len)?);
+        }
+
+        let unaligned = PieceSpec {
+            comm_p: [0; NODE_SIZE],
+            position: 2,
+            number_of_leaves: 4,
+        };
+        assert!(PieceInclusionProof::<Sha256Hasher>::generate(&unaligned, &tree).is_err());
+
+        let mut a = <Sha256Hasher as Hasher>::Function::default();
+        let other_root = a.node(tree.root(), tree.root(), 0);
+        let mut comm_p = [0; NODE_SIZE];
+        comm_p.copy_from_slice(&data[..NODE_SIZE]);
+        let piece_spec = PieceSpec {
+            comm_p,
+            position: 0,
+            number_of_leaves: 1,
+        };
+        assert!(PieceInclusionProof::<Sha256Hasher>::generate_from_source(
+            &piece_spec,
+            &mut &data[..],
+            tree_len,
+            &other_root,
+        )
+        .is_err());
+
+        Ok(())
+    }
 }
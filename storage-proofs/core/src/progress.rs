@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::{Error, Result};
+
+/// The stage of a long running operation, as reported to a `Progress` handle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Phase {
+    /// Generating the labels of the stacked layers.
+    Labels,
+    /// Building the column commitment tree (tree-c).
+    TreeC,
+    /// Encoding the replica and building tree-r-last.
+    TreeRLast,
+    /// Generating the vanilla proofs of the partitions.
+    VanillaProofs,
+    /// Synthesizing circuits and generating groth proofs.
+    CircuitProofs,
+}
+
+/// Receives progress updates from long running operations and allows the
+/// caller to cooperatively cancel them.
+///
+/// Both methods are called from the worker threads, so implementations should
+/// be cheap. Once `is_cancelled` returns `true`, the operation returns
+/// `Error::Cancelled` at its next checkpoint.
+pub trait Progress: Send + Sync {
+    /// Called with the current `phase`, the 1-based `layer` (0 where layers do not
+    /// apply) and the number of nodes (or partitions) `done` out of `total`.
+    fn report(&self, _phase: Phase, _layer: usize, _done: usize, _total: usize) {}
+
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// A `Progress` handle that only supports cancellation.
+#[derive(Debug, Default)]
+pub struct CancellationToken(AtomicBool);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Progress for CancellationToken {
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Returns `Error::Cancelled` if the handle was cancelled.
+pub fn check_cancelled(progress: Option<&dyn Progress>) -> Result<()> {
+    match progress {
+        Some(progress) if progress.is_cancelled() => Err(Error::Cancelled.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Checks for cancellation and forwards the update to the handle, if any.
+pub fn report(
+    progress: Option<&dyn Progress>,
+    phase: Phase,
+    layer: usize,
+    done: usize,
+    total: usize,
+) -> Result<()> {
+    check_cancelled(progress)?;
+    if let Some(progress) = progress {
+        progress.report(phase, layer, done, total);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(Phase, usize, usize, usize)>>);
+
+    impl Progress for Recorder {
+        fn report(&self, phase: Phase, layer: usize, done: usize, total: usize) {
+            self.0.lock().unwrap().push((phase, layer, done, total));
+        }
+    }
+
+    #[test]
+    fn test_report_forwards_updates() {
+        let recorder = Recorder::default();
+
+        report(Some(&recorder), Phase::Labels, 2, 10, 20).unwrap();
+        report(None, Phase::Labels, 2, 20, 20).unwrap();
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![(Phase::Labels, 2, 10, 20)]
+        );
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        assert!(report(Some(&token), Phase::TreeC, 0, 1, 2).is_ok());
+
+        token.cancel();
+        let err = report(Some(&token), Phase::TreeC, 0, 2, 2).unwrap_err();
+        match err.downcast_ref::<Error>() {
+            Some(Error::Cancelled) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
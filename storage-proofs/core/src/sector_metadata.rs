@@ -0,0 +1,164 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use generic_array::typenum::Unsigned;
+use serde::{Deserialize, Serialize};
+
+use crate::cache_key::CacheKey;
+use crate::error::Result;
+use crate::merkle::MerkleTreeTrait;
+
+/// Version of the cache directory layout described by `SectorMetadata`. Bump this whenever the
+/// set or format of the files written into a sector's cache directory changes.
+pub const SECTOR_LAYOUT_VERSION: u32 = 1;
+
+/// Arities of the trees built over the replica (tree-c and tree-r-last).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeShape {
+    pub base_arity: usize,
+    pub sub_tree_arity: usize,
+    pub top_tree_arity: usize,
+}
+
+impl TreeShape {
+    pub fn of<Tree: MerkleTreeTrait>() -> Self {
+        TreeShape {
+            base_arity: Tree::Arity::to_usize(),
+            sub_tree_arity: Tree::SubTreeArity::to_usize(),
+            top_tree_arity: Tree::TopTreeArity::to_usize(),
+        }
+    }
+}
+
+/// Small metadata file written into each sector's cache directory at sealing time.
+///
+/// It records the parameters the cached trees were built with, so that a sector can still be
+/// read back and proven after the global configuration (e.g. `rows_to_discard`) was changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorMetadata {
+    pub layout_version: u32,
+    pub sector_size: u64,
+    pub porep_id: [u8; 32],
+    pub tree_shape: TreeShape,
+    /// Number of rows discarded from the on disk tree-d.
+    pub tree_d_rows_to_discard: usize,
+    /// Number of rows discarded from the on disk tree-r-last.
+    pub tree_r_last_rows_to_discard: usize,
+}
+
+impl SectorMetadata {
+    pub fn new<Tree: MerkleTreeTrait>(
+        sector_size: u64,
+        porep_id: [u8; 32],
+        tree_d_rows_to_discard: usize,
+        tree_r_last_rows_to_discard: usize,
+    ) -> Self {
+        SectorMetadata {
+            layout_version: SECTOR_LAYOUT_VERSION,
+            sector_size,
+            porep_id,
+            tree_shape: TreeShape::of::<Tree>(),
+            tree_d_rows_to_discard,
+            tree_r_last_rows_to_discard,
+        }
+    }
+
+    pub fn path<P: AsRef<Path>>(cache_dir: P) -> PathBuf {
+        cache_dir
+            .as_ref()
+            .join(CacheKey::SectorMetadata.to_string())
+    }
+
+    /// Reads the metadata from `cache_dir`, returning `None` for sectors sealed before it
+    /// was recorded.
+    pub fn read<P: AsRef<Path>>(cache_dir: P) -> Result<Option<Self>> {
+        let path = Self::path(cache_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = fs::read(&path)
+            .with_context(|| format!("could not read sector metadata={:?}", path))?;
+        let metadata: Self = serde_json::from_slice(&bytes)
+            .with_context(|| format!("could not deserialize sector metadata={:?}", path))?;
+        ensure!(
+            metadata.layout_version <= SECTOR_LAYOUT_VERSION,
+            "unsupported sector layout version {} in {:?} (latest known is {})",
+            metadata.layout_version,
+            path,
+            SECTOR_LAYOUT_VERSION
+        );
+
+        Ok(Some(metadata))
+    }
+
+    /// Writes the metadata to `cache_dir`, through a temporary file that is renamed into place.
+    pub fn write<P: AsRef<Path>>(&self, cache_dir: P) -> Result<()> {
+        let path = Self::path(&cache_dir);
+        let tmp_path = path.with_extension("tmp");
+
+        let bytes = serde_json::to_vec_pretty(self)?;
+        let mut file = File::create(&tmp_path)
+            .with_context(|| format!("could not create sector metadata={:?}", tmp_path))?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("could not rename {:?} to {:?}", tmp_path, path))?;
+
+        Ok(())
+    }
+
+    /// Checks that the sector was sealed with the tree shape of `Tree`.
+    pub fn ensure_shape<Tree: MerkleTreeTrait>(&self) -> Result<()> {
+        let expected = TreeShape::of::<Tree>();
+        ensure!(
+            self.tree_shape == expected,
+            "sector tree shape mismatch: {:?} != {:?}",
+            self.tree_shape,
+            expected
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hasher::PoseidonHasher;
+    use crate::merkle::{OctMerkleTree, OctSubMerkleTree};
+
+    #[test]
+    fn test_sector_metadata_roundtrip() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        assert!(SectorMetadata::read(cache_dir.path()).unwrap().is_none());
+
+        let metadata = SectorMetadata::new::<OctMerkleTree<PoseidonHasher>>(1 << 11, [3; 32], 1, 2);
+        metadata.write(cache_dir.path()).unwrap();
+
+        let read = SectorMetadata::read(cache_dir.path()).unwrap().unwrap();
+        assert_eq!(read, metadata);
+        read.ensure_shape::<OctMerkleTree<PoseidonHasher>>()
+            .unwrap();
+        assert!(read
+            .ensure_shape::<OctSubMerkleTree<PoseidonHasher>>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_sector_metadata_newer_layout() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let mut metadata =
+            SectorMetadata::new::<OctMerkleTree<PoseidonHasher>>(1 << 11, [3; 32], 1, 2);
+        metadata.layout_version = SECTOR_LAYOUT_VERSION + 1;
+        metadata.write(cache_dir.path()).unwrap();
+
+        assert!(SectorMetadata::read(cache_dir.path()).is_err());
+    }
+}
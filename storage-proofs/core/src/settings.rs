@@ -1,19 +1,25 @@
+use std::cell::RefCell;
 use std::env;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use config::{Config, ConfigError, Environment, File};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
 lazy_static! {
-    pub static ref SETTINGS: Mutex<Settings> =
-        Mutex::new(Settings::new().expect("invalid configuration"));
+    /// The process wide settings, shared by `current` without cloning them.
+    pub static ref SETTINGS: Mutex<Arc<Settings>> =
+        Mutex::new(Arc::new(Settings::new().expect("invalid configuration")));
+}
+
+thread_local! {
+    static CURRENT_SETTINGS: RefCell<Option<Arc<Settings>>> = RefCell::new(None);
 }
 
 const SETTINGS_PATH: &str = "./rust-fil-proofs.config.toml";
 const PREFIX: &str = "FIL_PROOFS";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Settings {
     pub maximize_caching: bool,
@@ -25,8 +31,19 @@ pub struct Settings {
     pub max_gpu_tree_batch_size: u32,
     pub rows_to_discard: u32,
     pub sdr_parents_cache_size: u32,
+    pub verify_parent_cache: bool,
+    pub verify_parent_cache_fully: bool,
+    pub parent_cache_sample_pages: u32,
+    pub use_multicore_sdr: bool,
+    pub multicore_sdr_producers: u32,
+    pub multicore_sdr_producer_stride: u64,
+    pub multicore_sdr_lookahead: u32,
+    pub multicore_sdr_pin_cores: bool,
     pub window_post_synthesis_num_cpus: u32,
     pub parameter_cache: String,
+    pub trusted_params_manifest: String,
+    pub trusted_params_keys: String,
+    pub require_trusted_params: bool,
     pub parent_cache: String,
 }
 
@@ -42,11 +59,22 @@ impl Default for Settings {
             max_gpu_tree_batch_size: 700_000,
             rows_to_discard: 2,
             sdr_parents_cache_size: 2_048,
+            verify_parent_cache: true,
+            verify_parent_cache_fully: false,
+            parent_cache_sample_pages: 32,
+            use_multicore_sdr: false,
+            multicore_sdr_producers: 3,
+            multicore_sdr_producer_stride: 128,
+            multicore_sdr_lookahead: 800,
+            multicore_sdr_pin_cores: false,
             window_post_synthesis_num_cpus: num_cpus::get() as u32,
             // `parameter_cache` does not use the cache() mechanism because it is now used
             // for durable, canonical Groth parameters and verifying keys.
             // The name is retained for backwards compatibility.
             parameter_cache: "/var/tmp/filecoin-proof-parameters/".to_string(),
+            trusted_params_manifest: String::new(),
+            trusted_params_keys: String::new(),
+            require_trusted_params: false,
             parent_cache: cache("filecoin-parents"),
         }
     }
@@ -63,7 +91,9 @@ fn cache(s: &str) -> String {
 }
 
 impl Settings {
-    fn new() -> Result<Settings, ConfigError> {
+    /// Loads the settings from `./rust-fil-proofs.config.toml` and the environment, on top of
+    /// the defaults.
+    pub fn new() -> Result<Settings, ConfigError> {
         let mut s = Config::new();
 
         s.merge(File::with_name(SETTINGS_PATH).required(false))?;
@@ -72,3 +102,73 @@ impl Settings {
         s.try_into()
     }
 }
+
+/// Returns the settings in effect on the calling thread, which are the ones installed with
+/// `with_settings`, or the global `SETTINGS` otherwise.
+pub fn current() -> Arc<Settings> {
+    CURRENT_SETTINGS
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(|| SETTINGS.lock().unwrap().clone())
+}
+
+/// Runs `f` with `settings` in effect on the calling thread, instead of the global `SETTINGS`.
+///
+/// Only the calling thread is affected, so work handed to other threads (e.g. rayon or
+/// crossbeam workers) has to install the settings there again, or be given the values it
+/// needs. Calls can be nested, the previous settings are restored once `f`
+/// returns or panics. `pedersen_hash_exp_window_size` is always taken from the global
+/// `SETTINGS`, as it is only read once per process.
+pub fn with_settings<T>(settings: Arc<Settings>, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Arc<Settings>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_SETTINGS.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let previous = CURRENT_SETTINGS.with(|current| current.replace(Some(settings)));
+    let _restore = Restore(previous);
+
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_settings() {
+        let global_rows_to_discard = current().rows_to_discard;
+
+        let mut outer = Settings::default();
+        outer.rows_to_discard = 7;
+        let mut inner = Settings::default();
+        inner.rows_to_discard = 9;
+
+        with_settings(Arc::new(outer), || {
+            assert_eq!(current().rows_to_discard, 7);
+
+            with_settings(Arc::new(inner), || {
+                assert_eq!(current().rows_to_discard, 9);
+            });
+            assert_eq!(current().rows_to_discard, 7);
+
+            // Other threads are not affected.
+            let other = std::thread::spawn(|| current().rows_to_discard)
+                .join()
+                .unwrap();
+            assert_eq!(other, global_rows_to_discard);
+
+            // The previous settings are restored on panic as well.
+            let res = std::panic::catch_unwind(|| {
+                with_settings(Arc::new(Settings::default()), || panic!("boom"))
+            });
+            assert!(res.is_err());
+            assert_eq!(current().rows_to_discard, 7);
+        });
+
+        assert_eq!(current().rows_to_discard, global_rows_to_discard);
+    }
+}
@@ -1,5 +1,6 @@
 pub mod drg;
 pub mod stacked;
+pub mod update;
 
 mod encode;
 
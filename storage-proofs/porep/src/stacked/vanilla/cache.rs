@@ -1,15 +1,21 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::fs::{self, Metadata, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
 
 use anyhow::{bail, ensure, Context};
 use byteorder::{ByteOrder, LittleEndian};
-use log::info;
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use rand::Rng;
 use rayon::prelude::*;
 use sha2::{Digest, Sha256};
 
 use storage_proofs_core::{
     drgraph::Graph,
     drgraph::BASE_DEGREE,
-    error::Result,
+    error::{Error, ParentCacheFault, Result},
     hasher::Hasher,
     parameter_cache::{with_exclusive_lock, LockedFile, ParameterSetMetadata, VERSION},
     settings,
@@ -20,6 +26,172 @@ use super::graph::{StackedGraph, DEGREE};
 /// u32 = 4 bytes
 const NODE_BYTES: usize = 4;
 
+/// Size of a single cache entry, the parents of one node.
+const ENTRY_BYTES: usize = DEGREE * NODE_BYTES;
+
+/// Space reserved for the header in front of the entries, a whole page so the entries stay
+/// page aligned.
+const HEADER_SIZE: usize = 4096;
+
+/// Granularity of the sampled verification.
+const PAGE_SIZE: usize = 4096;
+
+const MAGIC: &[u8; 8] = b"FILPCACH";
+const FORMAT_VERSION: u32 = 1;
+
+/// Length of the serialized header fields covered by the digest.
+const HEADER_FIELDS_LEN: usize = 8 + 4 + 4 + 4 + 32 + 32;
+
+/// Caches verified by this process, so they are only verified on the first open.
+static VERIFIED: Lazy<Mutex<HashSet<VerifiedCache>>> = Lazy::new(Default::default);
+
+/// A verified cache file. Replacing or modifying the file changes its inode, size or
+/// modification time, and so it is verified again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct VerifiedCache {
+    path: PathBuf,
+    inode: u64,
+    len: u64,
+    modified: SystemTime,
+    graph_key: [u8; 32],
+    /// Whether all entries were checked against the digest, not only sampled pages.
+    fully: bool,
+}
+
+impl VerifiedCache {
+    fn new<H, G>(
+        path: &Path,
+        metadata: &Metadata,
+        cache_entries: u32,
+        graph: &StackedGraph<H, G>,
+        fully: bool,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
+        Ok(VerifiedCache {
+            path: path.to_path_buf(),
+            inode: inode(metadata),
+            len: metadata.len(),
+            modified: metadata.modified()?,
+            graph_key: graph_key(cache_entries, graph),
+            fully,
+        })
+    }
+
+    /// Whether this file was verified before, at least as thoroughly as asked for by `fully`.
+    fn is_verified(&self) -> bool {
+        let verified = VERIFIED.lock().expect("VERIFIED poisoned");
+        let fully = VerifiedCache {
+            fully: true,
+            ..self.clone()
+        };
+        verified.contains(&fully) || verified.contains(self)
+    }
+
+    fn mark_verified(self) {
+        VERIFIED.lock().expect("VERIFIED poisoned").insert(self);
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// Describes the graph a cache file was generated for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Header {
+    degree: u32,
+    num_cache_entries: u32,
+    porep_id: [u8; 32],
+    /// Digest of the graph parameters, see `graph_key`.
+    graph_key: [u8; 32],
+    /// Sha256 of the serialized fields above followed by all entries.
+    digest: [u8; 32],
+}
+
+impl Header {
+    fn new<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> Self
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
+        Header {
+            degree: DEGREE as u32,
+            num_cache_entries: cache_entries,
+            porep_id: graph.porep_id,
+            graph_key: graph_key(cache_entries, graph),
+            digest: [0; 32],
+        }
+    }
+
+    fn fields(&self) -> [u8; HEADER_FIELDS_LEN] {
+        let mut bytes = [0u8; HEADER_FIELDS_LEN];
+        bytes[..8].copy_from_slice(MAGIC);
+        LittleEndian::write_u32(&mut bytes[8..12], FORMAT_VERSION);
+        LittleEndian::write_u32(&mut bytes[12..16], self.degree);
+        LittleEndian::write_u32(&mut bytes[16..20], self.num_cache_entries);
+        bytes[20..52].copy_from_slice(&self.porep_id);
+        bytes[52..84].copy_from_slice(&self.graph_key);
+        bytes
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[..HEADER_FIELDS_LEN].copy_from_slice(&self.fields());
+        bytes[HEADER_FIELDS_LEN..HEADER_FIELDS_LEN + 32].copy_from_slice(&self.digest);
+    }
+
+    /// Returns `None` if `bytes` do not hold a header of this format version.
+    fn read(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_FIELDS_LEN + 32
+            || &bytes[..8] != MAGIC
+            || LittleEndian::read_u32(&bytes[8..12]) != FORMAT_VERSION
+        {
+            return None;
+        }
+
+        let mut header = Header {
+            degree: LittleEndian::read_u32(&bytes[12..16]),
+            num_cache_entries: LittleEndian::read_u32(&bytes[16..20]),
+            porep_id: [0; 32],
+            graph_key: [0; 32],
+            digest: [0; 32],
+        };
+        header.porep_id.copy_from_slice(&bytes[20..52]);
+        header.graph_key.copy_from_slice(&bytes[52..84]);
+        header
+            .digest
+            .copy_from_slice(&bytes[HEADER_FIELDS_LEN..HEADER_FIELDS_LEN + 32]);
+
+        Some(header)
+    }
+
+    fn digest_of(&self, entries: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::default();
+        hasher.update(&self.fields()[..]);
+        hasher.update(entries);
+
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&hasher.finalize());
+        digest
+    }
+
+    fn matches(&self, other: &Header) -> bool {
+        self.degree == other.degree
+            && self.num_cache_entries == other.num_cache_entries
+            && self.porep_id == other.porep_id
+            && self.graph_key == other.graph_key
+    }
+}
+
 // StackedGraph will hold two different (but related) `ParentCache`,
 #[derive(Debug)]
 pub struct ParentCache {
@@ -52,8 +224,8 @@ impl CacheData {
             return Ok(());
         }
 
-        let offset = new_offset as usize * DEGREE * NODE_BYTES;
-        let len = self.len as usize * DEGREE * NODE_BYTES;
+        let offset = HEADER_SIZE + new_offset as usize * ENTRY_BYTES;
+        let len = self.len as usize * ENTRY_BYTES;
 
         self.data = unsafe {
             memmap::MmapOptions::new()
@@ -77,8 +249,8 @@ impl CacheData {
     /// Panics if the `node` is not in the cache.
     fn read(&self, node: u32) -> [u32; DEGREE] {
         assert!(node >= self.offset, "node not in cache");
-        let start = (node - self.offset) as usize * DEGREE * NODE_BYTES;
-        let end = start + DEGREE * NODE_BYTES;
+        let start = (node - self.offset) as usize * ENTRY_BYTES;
+        let end = start + ENTRY_BYTES;
 
         let mut res = [0u32; DEGREE];
         LittleEndian::read_u32_into(&self.data[start..end], &mut res);
@@ -94,7 +266,7 @@ impl CacheData {
     }
 
     fn open(offset: u32, len: u32, path: &PathBuf) -> Result<Self> {
-        let min_cache_size = (offset + len) as usize * DEGREE * NODE_BYTES;
+        let min_cache_size = HEADER_SIZE + (offset + len) as usize * ENTRY_BYTES;
 
         let file = LockedFile::open_shared_read(path)
             .with_context(|| format!("could not open path={}", path.display()))?;
@@ -111,8 +283,8 @@ impl CacheData {
 
         let data = unsafe {
             memmap::MmapOptions::new()
-                .offset((offset as usize * DEGREE * NODE_BYTES) as u64)
-                .len(len as usize * DEGREE * NODE_BYTES)
+                .offset((HEADER_SIZE + offset as usize * ENTRY_BYTES) as u64)
+                .len(len as usize * ENTRY_BYTES)
                 .map(file.as_ref())
                 .with_context(|| format!("could not mmap path={}", path.display()))?
         };
@@ -127,6 +299,8 @@ impl CacheData {
 }
 
 impl ParentCache {
+    /// Opens the cache of `graph`, generating it if it does not exist yet or fails
+    /// verification.
     pub fn new<H, G>(len: u32, cache_entries: u32, graph: &StackedGraph<H, G>) -> Result<Self>
     where
         H: Hasher,
@@ -134,16 +308,38 @@ impl ParentCache {
     {
         let path = cache_path(cache_entries, graph);
         if path.exists() {
-            Self::open(len, cache_entries, path)
-        } else {
-            Self::generate(len, cache_entries, graph, path)
+            match Self::open(len, cache_entries, graph, path.clone()) {
+                Ok(cache) => return Ok(cache),
+                Err(err) => match err.downcast_ref::<Error>() {
+                    Some(Error::InvalidParentCache { .. }) => {
+                        warn!("parent cache: {}, regenerating", err);
+                    }
+                    _ => return Err(err),
+                },
+            }
         }
+
+        Self::generate(len, cache_entries, graph, path)
     }
 
-    /// Opens an existing cache from disk.
-    pub fn open(len: u32, cache_entries: u32, path: PathBuf) -> Result<Self> {
+    /// Opens an existing cache from disk, after verifying it according to the
+    /// `verify_parent_cache` settings.
+    ///
+    /// Fails with `Error::InvalidParentCache` if the cache does not belong to `graph` or is
+    /// corrupted.
+    pub fn open<H, G>(
+        len: u32,
+        cache_entries: u32,
+        graph: &StackedGraph<H, G>,
+        path: PathBuf,
+    ) -> Result<Self>
+    where
+        H: Hasher,
+        G: Graph<H> + ParameterSetMetadata + Send + Sync,
+    {
         info!("parent cache: opening {}", path.display());
 
+        verify(&path, cache_entries, graph)?;
         let cache = CacheData::open(0, len, &path)?;
         info!("parent cache: opened");
 
@@ -155,6 +351,10 @@ impl ParentCache {
     }
 
     /// Generates a new cache and stores it on disk.
+    ///
+    /// The cache is written to a temporary file and moved into place once complete, while
+    /// holding a lock next to it, so concurrent openers either see the old file or the new
+    /// one.
     pub fn generate<H, G>(
         len: u32,
         cache_entries: u32,
@@ -167,36 +367,61 @@ impl ParentCache {
     {
         info!("parent cache: generating {}", path.display());
 
-        with_exclusive_lock(&path.clone(), |file| {
-            let cache_size = cache_entries as usize * NODE_BYTES * DEGREE;
-            file.as_ref()
-                .set_len(cache_size as u64)
+        with_exclusive_lock(&path.with_extension("lock"), |_| {
+            // Another process may have generated the cache while we waited for the lock.
+            if path.exists() && verify(&path, cache_entries, graph).is_ok() {
+                info!("parent cache: generated by another process");
+                return Ok(());
+            }
+
+            let tmp_path = path.with_extension("tmp");
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&tmp_path)
+                .with_context(|| format!("could not create {}", tmp_path.display()))?;
+
+            let cache_size = HEADER_SIZE + cache_entries as usize * ENTRY_BYTES;
+            file.set_len(cache_size as u64)
                 .with_context(|| format!("failed to set length: {}", cache_size))?;
 
             let mut data = unsafe {
                 memmap::MmapOptions::new()
-                    .map_mut(file.as_ref())
-                    .with_context(|| format!("could not mmap path={}", path.display()))?
+                    .map_mut(&file)
+                    .with_context(|| format!("could not mmap path={}", tmp_path.display()))?
             };
 
-            data.par_chunks_mut(DEGREE * NODE_BYTES)
+            data[HEADER_SIZE..]
+                .par_chunks_mut(ENTRY_BYTES)
                 .enumerate()
                 .try_for_each(|(node, entry)| -> Result<()> {
-                    let mut parents = [0u32; DEGREE];
-                    graph
-                        .base_graph()
-                        .parents(node, &mut parents[..BASE_DEGREE])?;
-                    graph.generate_expanded_parents(node, &mut parents[BASE_DEGREE..]);
-
-                    LittleEndian::write_u32_into(&parents, entry);
+                    LittleEndian::write_u32_into(&compute_parents(graph, node)?, entry);
                     Ok(())
                 })?;
-
             info!("parent cache: generated");
+
+            let mut header = Header::new(cache_entries, graph);
+            header.digest = header.digest_of(&data[HEADER_SIZE..]);
+            header.write(&mut data[..HEADER_SIZE]);
+
             data.flush().context("failed to flush parent cache")?;
             drop(data);
+            file.sync_all().context("failed to sync parent cache")?;
+            fs::rename(&tmp_path, &path).with_context(|| {
+                format!(
+                    "could not move {} to {}",
+                    tmp_path.display(),
+                    path.display()
+                )
+            })?;
 
             info!("parent cache: written to disk");
+
+            // The entries were just computed, there is nothing to verify on the next open.
+            let metadata = fs::metadata(&path)?;
+            VerifiedCache::new(&path, &metadata, cache_entries, graph, true)?.mark_verified();
             Ok(())
         })?;
 
@@ -222,9 +447,9 @@ impl ParentCache {
             self.cache.len,
         );
 
-        // Shift cache by its current size.
+        // Shift cache to the window containing the node, reads may skip over nodes.
         let new_offset =
-            (self.num_cache_entries - self.cache.len).min(self.cache.offset + self.cache.len);
+            (self.num_cache_entries - self.cache.len).min(node / self.cache.len * self.cache.len);
         self.cache.shift(new_offset)?;
 
         Ok(self.cache.read(node))
@@ -237,10 +462,113 @@ impl ParentCache {
 }
 
 fn parent_cache_dir_name() -> String {
-    settings::SETTINGS.lock().unwrap().parent_cache.clone()
+    settings::current().parent_cache.clone()
 }
 
-fn cache_path<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> PathBuf
+fn compute_parents<H, G>(graph: &StackedGraph<H, G>, node: usize) -> Result<[u32; DEGREE]>
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Send + Sync,
+{
+    let mut parents = [0u32; DEGREE];
+    graph
+        .base_graph()
+        .parents(node, &mut parents[..BASE_DEGREE])?;
+    graph.generate_expanded_parents(node, &mut parents[BASE_DEGREE..]);
+
+    Ok(parents)
+}
+
+/// Checks the size and header of the cache at `path`, then either its digest or the entries
+/// of randomly sampled pages, depending on the settings. Once a file passed, it is not checked
+/// again by this process unless it changed.
+fn verify<H, G>(path: &Path, cache_entries: u32, graph: &StackedGraph<H, G>) -> Result<()>
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Send + Sync,
+{
+    let invalid = |fault| Error::InvalidParentCache {
+        path: path.to_path_buf(),
+        fault,
+    };
+
+    let settings = settings::current();
+    let file = LockedFile::open_shared_read(path)
+        .with_context(|| format!("could not open path={}", path.display()))?;
+
+    let metadata = file.as_ref().metadata()?;
+    let expected_len = (HEADER_SIZE + cache_entries as usize * ENTRY_BYTES) as u64;
+    let actual_len = metadata.len();
+    if actual_len != expected_len {
+        return Err(invalid(ParentCacheFault::Size {
+            expected: expected_len,
+            actual: actual_len,
+        })
+        .into());
+    }
+    if !settings.verify_parent_cache {
+        return Ok(());
+    }
+
+    let verified = VerifiedCache::new(
+        path,
+        &metadata,
+        cache_entries,
+        graph,
+        settings.verify_parent_cache_fully,
+    )?;
+    if verified.is_verified() {
+        debug!("parent cache: {} verified before", path.display());
+        return Ok(());
+    }
+
+    let data = unsafe {
+        memmap::MmapOptions::new()
+            .map(file.as_ref())
+            .with_context(|| format!("could not mmap path={}", path.display()))?
+    };
+    let header =
+        Header::read(&data[..HEADER_SIZE]).ok_or_else(|| invalid(ParentCacheFault::Header))?;
+    if !header.matches(&Header::new(cache_entries, graph)) {
+        return Err(invalid(ParentCacheFault::GraphMismatch).into());
+    }
+
+    let entries = &data[HEADER_SIZE..];
+    if settings.verify_parent_cache_fully {
+        if header.digest_of(entries) != header.digest {
+            return Err(invalid(ParentCacheFault::Digest).into());
+        }
+        verified.mark_verified();
+        return Ok(());
+    }
+
+    let pages = (entries.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    let mut rng = rand::thread_rng();
+    for _ in 0..settings.parent_cache_sample_pages {
+        let page = rng.gen_range(0, pages);
+        // All entries overlapping the page.
+        let first = page * PAGE_SIZE / ENTRY_BYTES;
+        let last =
+            (((page + 1) * PAGE_SIZE + ENTRY_BYTES - 1) / ENTRY_BYTES).min(cache_entries as usize);
+
+        for node in first..last {
+            let mut parents = [0u32; DEGREE];
+            LittleEndian::read_u32_into(
+                &entries[node * ENTRY_BYTES..(node + 1) * ENTRY_BYTES],
+                &mut parents,
+            );
+            if parents != compute_parents(graph, node)? {
+                return Err(invalid(ParentCacheFault::Parents(node as u32)).into());
+            }
+        }
+    }
+    verified.mark_verified();
+
+    Ok(())
+}
+
+/// Digest of everything that determines the parents, cache files are named after it.
+fn graph_key<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> [u8; 32]
 where
     H: Hasher,
     G: Graph<H> + ParameterSetMetadata + Send + Sync,
@@ -253,11 +581,21 @@ where
         hasher.update(key.to_le_bytes());
     }
     hasher.update(cache_entries.to_le_bytes());
-    let h = hasher.finalize();
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&hasher.finalize());
+    key
+}
+
+fn cache_path<H, G>(cache_entries: u32, graph: &StackedGraph<H, G>) -> PathBuf
+where
+    H: Hasher,
+    G: Graph<H> + ParameterSetMetadata + Send + Sync,
+{
     PathBuf::from(parent_cache_dir_name()).join(format!(
         "v{}-sdr-parent-{}.cache",
         VERSION,
-        hex::encode(h),
+        hex::encode(graph_key(cache_entries, graph)),
     ))
 }
 
@@ -265,8 +603,10 @@ where
 mod tests {
     use super::*;
 
+    use std::sync::Arc;
+
     use crate::stacked::vanilla::graph::{StackedBucketGraph, EXP_DEGREE};
-    use storage_proofs_core::hasher::PoseidonHasher;
+    use storage_proofs_core::{hasher::PoseidonHasher, settings::Settings};
 
     #[test]
     fn test_read_full_range() {
@@ -339,4 +679,176 @@ mod tests {
             assert_eq!(expected_parents, parents);
         }
     }
+
+    #[test]
+    fn test_read_skipping_range() {
+        let nodes = 48u32;
+        let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+            nodes as usize,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [0u8; 32],
+        )
+        .unwrap();
+
+        let mut cache = ParentCache::new(nodes / 8, nodes, &graph).unwrap();
+
+        // Reads may skip over whole windows, as long as they are ascending.
+        for node in (0..nodes).step_by(13) {
+            let mut expected_parents = [0; DEGREE];
+            graph.parents(node as usize, &mut expected_parents).unwrap();
+
+            let parents = cache.read(node).unwrap();
+            assert_eq!(expected_parents, parents);
+        }
+
+        assert!(cache.read(0).is_err());
+    }
+
+    /// Replaces the file at `path`, as a regenerated cache would be. Rewriting it in place may
+    /// keep its modification time, which only has the granularity of a timer tick.
+    fn replace(path: &Path, data: &[u8]) {
+        let tmp_path = path.with_extension("replace");
+        fs::write(&tmp_path, data).unwrap();
+        fs::rename(&tmp_path, path).unwrap();
+    }
+
+    fn assert_fault(err: anyhow::Error, expected: ParentCacheFault) {
+        match err.downcast_ref::<Error>() {
+            Some(Error::InvalidParentCache { fault, .. }) => assert_eq!(*fault, expected),
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_invalid_cache_is_regenerated() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings::default();
+        settings.parent_cache = cache_dir.path().to_string_lossy().into_owned();
+        settings.verify_parent_cache_fully = true;
+
+        settings::with_settings(Arc::new(settings), || {
+            let nodes = 128u32;
+            let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+                nodes as usize,
+                BASE_DEGREE,
+                EXP_DEGREE,
+                [3u8; 32],
+            )
+            .unwrap();
+            let path = cache_path(nodes, &graph);
+            let read_all = |cache: &mut ParentCache| {
+                (0..nodes)
+                    .map(|node| cache.read(node).unwrap())
+                    .collect::<Vec<_>>()
+            };
+
+            let expected = read_all(&mut ParentCache::new(nodes, nodes, &graph).unwrap());
+            ParentCache::open(nodes, nodes, &graph, path.clone()).unwrap();
+
+            // A flipped bit in the entries.
+            let mut data = fs::read(&path).unwrap();
+            data[HEADER_SIZE + 100 * ENTRY_BYTES] ^= 1;
+            replace(&path, &data);
+            let err = ParentCache::open(nodes, nodes, &graph, path.clone()).unwrap_err();
+            assert_fault(err, ParentCacheFault::Digest);
+            assert_eq!(
+                read_all(&mut ParentCache::new(nodes, nodes, &graph).unwrap()),
+                expected
+            );
+
+            // A cache generated for another graph.
+            let other = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+                nodes as usize,
+                BASE_DEGREE,
+                EXP_DEGREE,
+                [4u8; 32],
+            )
+            .unwrap();
+            let err = ParentCache::open(nodes, nodes, &other, path.clone()).unwrap_err();
+            assert_fault(err, ParentCacheFault::GraphMismatch);
+
+            // A truncated cache.
+            replace(&path, &data[..data.len() - 1]);
+            let err = ParentCache::open(nodes, nodes, &graph, path.clone()).unwrap_err();
+            assert_fault(
+                err,
+                ParentCacheFault::Size {
+                    expected: data.len() as u64,
+                    actual: data.len() as u64 - 1,
+                },
+            );
+            assert_eq!(
+                read_all(&mut ParentCache::new(nodes, nodes, &graph).unwrap()),
+                expected
+            );
+        });
+    }
+
+    #[test]
+    fn test_verified_once() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings::default();
+        settings.parent_cache = cache_dir.path().to_string_lossy().into_owned();
+        settings.verify_parent_cache_fully = true;
+
+        settings::with_settings(Arc::new(settings), || {
+            let nodes = 64u32;
+            let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+                nodes as usize,
+                BASE_DEGREE,
+                EXP_DEGREE,
+                [6u8; 32],
+            )
+            .unwrap();
+            let path = cache_path(nodes, &graph);
+            ParentCache::new(nodes, nodes, &graph).unwrap();
+
+            let verified = |fully| {
+                let metadata = fs::metadata(&path).unwrap();
+                VerifiedCache::new(&path, &metadata, nodes, &graph, fully)
+                    .unwrap()
+                    .is_verified()
+            };
+            // A full verification also covers a sampled one.
+            assert!(verified(true));
+            assert!(verified(false));
+
+            // A replaced file is verified again.
+            let data = fs::read(&path).unwrap();
+            replace(&path, &data);
+            assert!(!verified(true));
+            ParentCache::open(nodes, nodes, &graph, path.clone()).unwrap();
+            assert!(verified(true));
+        });
+    }
+
+    #[test]
+    fn test_sampled_verification() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings::default();
+        settings.parent_cache = cache_dir.path().to_string_lossy().into_owned();
+        // A single page of entries, so every sample covers the corrupted one.
+        let nodes = (PAGE_SIZE / ENTRY_BYTES) as u32;
+        settings.parent_cache_sample_pages = 1;
+
+        settings::with_settings(Arc::new(settings), || {
+            let graph = StackedBucketGraph::<PoseidonHasher>::new_stacked(
+                nodes as usize,
+                BASE_DEGREE,
+                EXP_DEGREE,
+                [5u8; 32],
+            )
+            .unwrap();
+            let path = cache_path(nodes, &graph);
+            ParentCache::new(nodes, nodes, &graph).unwrap();
+
+            let mut data = fs::read(&path).unwrap();
+            data[HEADER_SIZE + 7 * ENTRY_BYTES] ^= 1;
+            replace(&path, &data);
+
+            let err = ParentCache::open(nodes, nodes, &graph, path.clone()).unwrap_err();
+            assert_fault(err, ParentCacheFault::Parents(7));
+        });
+    }
 }
@@ -0,0 +1,132 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use merkletree::store::StoreConfig;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use storage_proofs_core::{cache_key::CacheKey, error::Result, hasher::Domain};
+
+/// Digest of the full contents of a layer, recorded in the checkpoint.
+pub type LayerDigest = [u8; 32];
+
+/// Computes the digest of the labels of a whole layer.
+pub fn layer_digest(labels: &[u8]) -> LayerDigest {
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&Sha256::digest(labels));
+    digest
+}
+
+/// Flushes the data of the layer store written with `config` to disk. This must happen
+/// before the checkpoint claims the layer is complete, otherwise a crash can leave the
+/// checkpoint pointing at a torn layer.
+pub fn sync_layer_store(config: &StoreConfig) -> Result<()> {
+    let path = StoreConfig::data_path(&config.path, &config.id);
+    File::open(&path)
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("could not sync layer store={:?}", path))?;
+
+    Ok(())
+}
+
+/// Manifest persisted next to the layer stores while labels are generated.
+/// It records how many layers have been fully written to disk, so that an
+/// interrupted phase1 can continue after the last completed layer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelsCheckpoint<D> {
+    pub replica_id: D,
+    pub nodes: usize,
+    pub layers: usize,
+    pub completed_layers: usize,
+    /// Digest of every completed layer, in layer order.
+    pub layer_digests: Vec<LayerDigest>,
+}
+
+impl<D: Domain> LabelsCheckpoint<D> {
+    pub fn path<P: AsRef<Path>>(cache_dir: P) -> PathBuf {
+        cache_dir
+            .as_ref()
+            .join(CacheKey::LabelsCheckpoint.to_string())
+    }
+
+    /// Reads the checkpoint from `cache_dir`, returning `None` if there is none.
+    pub fn read<P: AsRef<Path>>(cache_dir: P) -> Result<Option<Self>> {
+        let path = Self::path(cache_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let bytes =
+            fs::read(&path).with_context(|| format!("could not read checkpoint={:?}", path))?;
+        let checkpoint = bincode::deserialize(&bytes)
+            .with_context(|| format!("could not deserialize checkpoint={:?}", path))?;
+
+        Ok(Some(checkpoint))
+    }
+
+    /// Writes the checkpoint to `cache_dir`. The manifest is written to a
+    /// temporary file first and renamed into place, so a crash never leaves
+    /// a truncated checkpoint behind.
+    pub fn write<P: AsRef<Path>>(&self, cache_dir: P) -> Result<()> {
+        let path = Self::path(&cache_dir);
+        let tmp_path = path.with_extension("tmp");
+
+        let bytes = bincode::serialize(self)?;
+        let mut file = File::create(&tmp_path)
+            .with_context(|| format!("could not create checkpoint={:?}", tmp_path))?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("could not rename {:?} to {:?}", tmp_path, path))?;
+
+        Ok(())
+    }
+
+    /// Removes the checkpoint from `cache_dir`, if present.
+    pub fn remove<P: AsRef<Path>>(cache_dir: P) -> Result<()> {
+        let path = Self::path(cache_dir);
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("could not remove checkpoint={:?}", path))?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that this checkpoint was written for the same sector and graph.
+    pub fn ensure_matches(&self, replica_id: &D, nodes: usize, layers: usize) -> Result<()> {
+        ensure!(
+            &self.replica_id == replica_id,
+            "checkpoint was written for a different replica_id"
+        );
+        ensure!(
+            self.nodes == nodes,
+            "checkpoint node count mismatch: {} != {}",
+            self.nodes,
+            nodes
+        );
+        ensure!(
+            self.layers == layers,
+            "checkpoint layer count mismatch: {} != {}",
+            self.layers,
+            layers
+        );
+        ensure!(
+            self.completed_layers <= layers,
+            "checkpoint claims {} completed layers, but only {} exist",
+            self.completed_layers,
+            layers
+        );
+        ensure!(
+            self.layer_digests.len() == self.completed_layers,
+            "checkpoint has {} layer digests for {} completed layers",
+            self.layer_digests.len(),
+            self.completed_layers
+        );
+
+        Ok(())
+    }
+}
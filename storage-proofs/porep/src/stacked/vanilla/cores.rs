@@ -0,0 +1,178 @@
+//! Reservation of cores for the multi-core labeling.
+//!
+//! Every labeling run pins its consumer and producers to cores it reserved here, so labeling
+//! runs of concurrently sealed sectors do not end up on the same cores. The cores of a run are
+//! taken from a single group of cores sharing their last level cache where possible, as the
+//! ring buffer between the producers and the consumer lives there.
+
+use std::fs;
+use std::sync::Mutex;
+
+use core_affinity::CoreId;
+use once_cell::sync::Lazy;
+
+/// The cores of the machine, grouped by the last level cache they share.
+static CACHE_GROUPS: Lazy<Vec<Vec<CoreId>>> = Lazy::new(|| {
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    group_by_cache(core_ids, |core| shared_cache_id(core.id))
+});
+
+/// The ids of the cores reserved by running labeling runs.
+static RESERVED: Lazy<Mutex<Vec<usize>>> = Lazy::new(Default::default);
+
+/// Cores reserved for a labeling run, released again when dropped.
+#[derive(Debug)]
+pub(crate) struct CoreGroup {
+    cores: Vec<CoreId>,
+}
+
+impl CoreGroup {
+    pub(crate) fn cores(&self) -> &[CoreId] {
+        &self.cores
+    }
+}
+
+impl Drop for CoreGroup {
+    fn drop(&mut self) {
+        let mut reserved = RESERVED.lock().expect("reserved cores poisoned");
+        reserved.retain(|id| !self.cores.iter().any(|core| core.id == *id));
+    }
+}
+
+/// Reserves `count` cores no other labeling run has reserved. Returns `None` if there are not
+/// that many free cores.
+pub(crate) fn checkout_core_group(count: usize) -> Option<CoreGroup> {
+    let mut reserved = RESERVED.lock().expect("reserved cores poisoned");
+    let cores = select_cores(&CACHE_GROUPS, &reserved, count)?;
+    reserved.extend(cores.iter().map(|core| core.id));
+
+    Some(CoreGroup { cores })
+}
+
+/// Picks `count` cores which are not `reserved`, all from the same group if any group has
+/// enough of them.
+fn select_cores(groups: &[Vec<CoreId>], reserved: &[usize], count: usize) -> Option<Vec<CoreId>> {
+    let free: Vec<Vec<CoreId>> = groups
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .filter(|core| !reserved.contains(&core.id))
+                .copied()
+                .collect()
+        })
+        .collect();
+
+    if let Some(group) = free.iter().find(|group| group.len() >= count) {
+        return Some(group[..count].to_vec());
+    }
+
+    let free: Vec<CoreId> = free.into_iter().flatten().collect();
+    if free.len() >= count {
+        Some(free[..count].to_vec())
+    } else {
+        None
+    }
+}
+
+/// Groups `cores` by `cache_id`, keeping the order in which the cores and groups appear. Cores
+/// without a cache id are grouped together.
+fn group_by_cache<F>(cores: Vec<CoreId>, cache_id: F) -> Vec<Vec<CoreId>>
+where
+    F: Fn(&CoreId) -> Option<String>,
+{
+    let mut groups: Vec<(Option<String>, Vec<CoreId>)> = Vec::new();
+    for core in cores {
+        let id = cache_id(&core);
+        match groups.iter_mut().find(|(group_id, _)| *group_id == id) {
+            Some((_, group)) => group.push(core),
+            None => groups.push((id, vec![core])),
+        }
+    }
+
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// Identifies the last level cache of `core` by the list of cores sharing it, as reported by
+/// Linux. Returns `None` elsewhere.
+fn shared_cache_id(core: usize) -> Option<String> {
+    let dir = format!("/sys/devices/system/cpu/cpu{}/cache", core);
+    let mut last_level = None;
+    for entry in fs::read_dir(dir).ok()? {
+        let path = entry.ok()?.path();
+        let level = match fs::read_to_string(path.join("level")) {
+            Ok(level) => level.trim().parse::<u32>().ok()?,
+            // Not a cache index.
+            Err(_) => continue,
+        };
+        let shared = fs::read_to_string(path.join("shared_cpu_list")).ok()?;
+        if last_level
+            .as_ref()
+            .map(|(last, _)| level > *last)
+            .unwrap_or(true)
+        {
+            last_level = Some((level, shared.trim().to_string()));
+        }
+    }
+
+    last_level.map(|(_, shared)| shared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cores(ids: &[usize]) -> Vec<CoreId> {
+        ids.iter().map(|&id| CoreId { id }).collect()
+    }
+
+    fn ids(cores: &[CoreId]) -> Vec<usize> {
+        cores.iter().map(|core| core.id).collect()
+    }
+
+    #[test]
+    fn test_group_by_cache() {
+        let groups = group_by_cache(cores(&[0, 1, 2, 3, 4]), |core| {
+            Some(format!("{}", core.id % 2))
+        });
+        let groups: Vec<_> = groups.iter().map(|group| ids(group)).collect();
+        assert_eq!(groups, vec![vec![0, 2, 4], vec![1, 3]]);
+
+        let groups = group_by_cache(cores(&[0, 1, 2]), |_| None);
+        assert_eq!(groups.len(), 1);
+    }
+
+    #[test]
+    fn test_select_cores() {
+        let groups = vec![cores(&[0, 1, 2, 3]), cores(&[4, 5, 6, 7])];
+
+        // Concurrent runs get different cores, each within a single group.
+        let first = select_cores(&groups, &[], 3).unwrap();
+        assert_eq!(ids(&first), vec![0, 1, 2]);
+        let second = select_cores(&groups, &ids(&first), 3).unwrap();
+        assert_eq!(ids(&second), vec![4, 5, 6]);
+
+        // Without a group with enough free cores, free cores of several groups are used.
+        let reserved = [ids(&first), ids(&second)].concat();
+        assert_eq!(
+            ids(&select_cores(&groups, &reserved, 2).unwrap()),
+            vec![3, 7]
+        );
+        assert!(select_cores(&groups, &reserved, 3).is_none());
+    }
+
+    #[test]
+    fn test_checkout_core_group() {
+        let count = CACHE_GROUPS.iter().map(Vec::len).sum::<usize>();
+        if count == 0 {
+            return;
+        }
+
+        let group = checkout_core_group(count).unwrap();
+        assert_eq!(group.cores().len(), count);
+        assert!(checkout_core_group(1).is_none());
+
+        drop(group);
+        assert!(checkout_core_group(1).is_some());
+    }
+}
@@ -1,11 +1,15 @@
-use sha2raw::Sha256;
+use anyhow::ensure;
+use sha2raw::{Sha256, Sha256x8, LANES};
 use storage_proofs_core::{
     error::Result,
     hasher::Hasher,
     util::{data_at_node_offset, NODE_SIZE},
 };
 
-use super::{cache::ParentCache, graph::StackedBucketGraph};
+use super::{
+    cache::ParentCache,
+    graph::{lane_blocks, StackedBucketGraph},
+};
 
 pub fn create_label<H: Hasher>(
     graph: &StackedBucketGraph<H>,
@@ -15,12 +19,7 @@ pub fn create_label<H: Hasher>(
     layer_index: usize,
     node: usize,
 ) -> Result<()> {
-    let mut hasher = Sha256::new();
-    let mut buffer = [0u8; 32];
-
-    buffer[..4].copy_from_slice(&(layer_index as u32).to_be_bytes());
-    buffer[4..12].copy_from_slice(&(node as u64).to_be_bytes());
-    hasher.input(&[AsRef::<[u8]>::as_ref(replica_id), &buffer[..]][..]);
+    let hasher = label_hasher::<H>(replica_id, layer_index, node);
 
     // hash parents for all non 0 nodes
     let hash = if node > 0 {
@@ -34,12 +33,7 @@ pub fn create_label<H: Hasher>(
     };
 
     // store the newly generated key
-    let start = data_at_node_offset(node);
-    let end = start + NODE_SIZE;
-    layer_labels[start..end].copy_from_slice(&hash[..]);
-
-    // strip last two bits, to ensure result is in Fr.
-    layer_labels[end - 1] &= 0b0011_1111;
+    store_label(layer_labels, node, &hash);
 
     Ok(())
 }
@@ -53,12 +47,7 @@ pub fn create_label_exp<H: Hasher>(
     layer_index: usize,
     node: usize,
 ) -> Result<()> {
-    let mut hasher = Sha256::new();
-    let mut buffer = [0u8; 32];
-
-    buffer[0..4].copy_from_slice(&(layer_index as u32).to_be_bytes());
-    buffer[4..12].copy_from_slice(&(node as u64).to_be_bytes());
-    hasher.input(&[AsRef::<[u8]>::as_ref(replica_id), &buffer[..]][..]);
+    let hasher = label_hasher::<H>(replica_id, layer_index, node);
 
     // hash parents for all non 0 nodes
     let hash = if node > 0 {
@@ -72,12 +61,247 @@ pub fn create_label_exp<H: Hasher>(
     };
 
     // store the newly generated key
+    store_label(layer_labels, node, &hash);
+
+    Ok(())
+}
+
+/// Same as `create_label`, for several replicas labeled over the same graph in lockstep.
+/// The parents of `node` are looked up once and hashed against the labels of each replica,
+/// `layer_labels[i]` holding the labels of `replica_ids[i]`. Replicas are hashed `LANES` at a
+/// time, the ones left over one by one.
+pub fn create_labels_batch<H: Hasher>(
+    graph: &StackedBucketGraph<H>,
+    cache: Option<&mut ParentCache>,
+    replica_ids: &[H::Domain],
+    layer_labels: &mut [Vec<u8>],
+    layer_index: usize,
+    node: usize,
+) -> Result<()> {
+    ensure!(
+        replica_ids.len() == layer_labels.len(),
+        "need labels for each of the {} replicas",
+        replica_ids.len()
+    );
+    let parents = if node > 0 {
+        Some(graph.parents_of(node as u32, cache)?)
+    } else {
+        None
+    };
+
+    let lanes = replica_ids.len() / LANES * LANES;
+    for (replica_ids, layer_labels) in replica_ids[..lanes]
+        .chunks(LANES)
+        .zip(layer_labels[..lanes].chunks_mut(LANES))
+    {
+        let hasher = label_hasher_x8::<H>(replica_ids, layer_index, node);
+        let hashes = match parents {
+            Some(ref parents) => {
+                graph.copy_parents_data_inner_x8(parents, lanes_of(layer_labels), hasher)
+            }
+            None => hasher.finish(),
+        };
+        store_labels(layer_labels, node, &hashes);
+    }
+
+    for (replica_id, layer_labels) in replica_ids[lanes..]
+        .iter()
+        .zip(layer_labels[lanes..].iter_mut())
+    {
+        let hasher = label_hasher::<H>(replica_id, layer_index, node);
+        let hash = match parents {
+            Some(ref parents) => graph.copy_parents_data_inner(parents, layer_labels, hasher),
+            None => hasher.finish(),
+        };
+        store_label(layer_labels, node, &hash);
+    }
+
+    Ok(())
+}
+
+/// Same as `create_label_exp`, for several replicas labeled over the same graph in lockstep.
+/// `exp_parents_data[i]` and `layer_labels[i]` hold the labels of `replica_ids[i]`. Replicas are
+/// hashed `LANES` at a time, the ones left over one by one.
+pub fn create_labels_exp_batch<H: Hasher>(
+    graph: &StackedBucketGraph<H>,
+    cache: Option<&mut ParentCache>,
+    replica_ids: &[H::Domain],
+    exp_parents_data: &[Vec<u8>],
+    layer_labels: &mut [Vec<u8>],
+    layer_index: usize,
+    node: usize,
+) -> Result<()> {
+    ensure!(
+        replica_ids.len() == layer_labels.len() && replica_ids.len() == exp_parents_data.len(),
+        "need labels for each of the {} replicas",
+        replica_ids.len()
+    );
+    let parents = if node > 0 {
+        Some(graph.parents_of(node as u32, cache)?)
+    } else {
+        None
+    };
+
+    let lanes = replica_ids.len() / LANES * LANES;
+    for ((replica_ids, exp_parents_data), layer_labels) in replica_ids[..lanes]
+        .chunks(LANES)
+        .zip(exp_parents_data[..lanes].chunks(LANES))
+        .zip(layer_labels[..lanes].chunks_mut(LANES))
+    {
+        let hasher = label_hasher_x8::<H>(replica_ids, layer_index, node);
+        let hashes = match parents {
+            Some(ref parents) => graph.copy_parents_data_inner_exp_x8(
+                parents,
+                lanes_of(layer_labels),
+                lanes_of(exp_parents_data),
+                hasher,
+            ),
+            None => hasher.finish(),
+        };
+        store_labels(layer_labels, node, &hashes);
+    }
+
+    for ((replica_id, exp_parents_data), layer_labels) in replica_ids[lanes..]
+        .iter()
+        .zip(exp_parents_data[lanes..].iter())
+        .zip(layer_labels[lanes..].iter_mut())
+    {
+        let hasher = label_hasher::<H>(replica_id, layer_index, node);
+        let hash = match parents {
+            Some(ref parents) => {
+                graph.copy_parents_data_inner_exp(parents, layer_labels, exp_parents_data, hasher)
+            }
+            None => hasher.finish(),
+        };
+        store_label(layer_labels, node, &hash);
+    }
+
+    Ok(())
+}
+
+/// Returns a hasher primed with the replica id, layer and node, the prefix of every label.
+pub(crate) fn label_hasher<H: Hasher>(
+    replica_id: &H::Domain,
+    layer_index: usize,
+    node: usize,
+) -> Sha256 {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 32];
+
+    buffer[0..4].copy_from_slice(&(layer_index as u32).to_be_bytes());
+    buffer[4..12].copy_from_slice(&(node as u64).to_be_bytes());
+    hasher.input(&[AsRef::<[u8]>::as_ref(replica_id), &buffer[..]][..]);
+
+    hasher
+}
+
+/// Same as `label_hasher`, for `LANES` replicas at once.
+fn label_hasher_x8<H: Hasher>(
+    replica_ids: &[H::Domain],
+    layer_index: usize,
+    node: usize,
+) -> Sha256x8 {
+    debug_assert_eq!(replica_ids.len(), LANES);
+    let mut hasher = Sha256x8::new();
+    let mut buffer = [0u8; 32];
+
+    buffer[0..4].copy_from_slice(&(layer_index as u32).to_be_bytes());
+    buffer[4..12].copy_from_slice(&(node as u64).to_be_bytes());
+    let mut blocks = [[&buffer[..]; 2]; LANES];
+    for (blocks, replica_id) in blocks.iter_mut().zip(replica_ids.iter()) {
+        blocks[0] = AsRef::<[u8]>::as_ref(replica_id);
+    }
+    hasher.input(lane_blocks(&blocks, 2));
+
+    hasher
+}
+
+fn lanes_of(data: &[Vec<u8>]) -> [&[u8]; LANES] {
+    debug_assert_eq!(data.len(), LANES);
+    let mut lanes: [&[u8]; LANES] = [&[]; LANES];
+    for (lane, data) in lanes.iter_mut().zip(data.iter()) {
+        *lane = &data[..];
+    }
+    lanes
+}
+
+fn store_labels(layer_labels: &mut [Vec<u8>], node: usize, hashes: &[[u8; 32]; LANES]) {
+    for (layer_labels, hash) in layer_labels.iter_mut().zip(hashes.iter()) {
+        store_label(layer_labels, node, hash);
+    }
+}
+
+pub(crate) fn store_label(layer_labels: &mut [u8], node: usize, hash: &[u8; 32]) {
     let start = data_at_node_offset(node);
     let end = start + NODE_SIZE;
     layer_labels[start..end].copy_from_slice(&hash[..]);
 
     // strip last two bits, to ensure result is in Fr.
     layer_labels[end - 1] &= 0b0011_1111;
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        drgraph::BASE_DEGREE,
+        hasher::{Domain, Sha256Hasher},
+    };
+
+    use crate::stacked::vanilla::graph::EXP_DEGREE;
+
+    #[test]
+    fn test_create_labels_batch_matches_single() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let nodes = 1 << 8;
+        let graph = StackedBucketGraph::<Sha256Hasher>::new_stacked(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [7u8; 32],
+        )
+        .unwrap();
+        let size = nodes * NODE_SIZE;
+
+        // One full set of lanes and some left over.
+        let replica_ids: Vec<_> = (0..LANES + 3)
+            .map(|_| <Sha256Hasher as Hasher>::Domain::random(rng))
+            .collect();
+
+        let mut expected_1 = vec![vec![0u8; size]; replica_ids.len()];
+        let mut expected_2 = vec![vec![0u8; size]; replica_ids.len()];
+        for (i, replica_id) in replica_ids.iter().enumerate() {
+            for node in 0..nodes {
+                create_label(&graph, None, replica_id, &mut expected_1[i], 1, node).unwrap();
+            }
+            for node in 0..nodes {
+                create_label_exp(
+                    &graph,
+                    None,
+                    replica_id,
+                    &expected_1[i],
+                    &mut expected_2[i],
+                    2,
+                    node,
+                )
+                .unwrap();
+            }
+        }
+
+        let mut layer_1 = vec![vec![0u8; size]; replica_ids.len()];
+        let mut layer_2 = vec![vec![0u8; size]; replica_ids.len()];
+        for node in 0..nodes {
+            create_labels_batch(&graph, None, &replica_ids, &mut layer_1, 1, node).unwrap();
+        }
+        for node in 0..nodes {
+            create_labels_exp_batch(&graph, None, &replica_ids, &layer_1, &mut layer_2, 2, node)
+                .unwrap();
+        }
+
+        assert_eq!(layer_1, expected_1);
+        assert_eq!(layer_2, expected_2);
+    }
 }
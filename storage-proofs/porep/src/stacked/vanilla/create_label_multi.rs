@@ -0,0 +1,501 @@
+//! Labeling of a layer on several cores.
+//!
+//! Every label depends on the label of the previous node, so hashing has to happen in order on
+//! a single thread. Looking up the parents of a node and gathering their data does not, so
+//! producer threads do that for upcoming nodes and hand the data to the hashing thread through
+//! a ring buffer. Base parents which were not labeled yet when a producer filled a slot are
+//! copied by the consumer itself.
+
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::sync::atomic::{spin_loop_hint, AtomicBool, AtomicU64, Ordering};
+
+use anyhow::{anyhow, ensure};
+use log::debug;
+use sha2raw::Sha256;
+use storage_proofs_core::{
+    drgraph::{Graph, BASE_DEGREE},
+    error::Result,
+    hasher::Hasher,
+    progress::{report, Phase, Progress},
+    settings,
+    util::NODE_SIZE,
+};
+
+use super::{
+    cache::ParentCache,
+    cores::{checkout_core_group, CoreGroup},
+    create_label::{label_hasher, store_label},
+    graph::{StackedBucketGraph, DEGREE},
+    proof::PROGRESS_INTERVAL,
+};
+
+/// The parents of a node and their data, in the order they are hashed.
+struct Slot {
+    parents: [u32; DEGREE],
+    /// Bit `i` is set if base parent `i` was not labeled yet when the slot was filled.
+    missing: u8,
+    data: [u8; DEGREE * NODE_SIZE],
+}
+
+struct RingBuf {
+    slots: Box<[UnsafeCell<Slot>]>,
+    /// One more than the node held by each slot, zero while the slot was never filled.
+    filled: Box<[AtomicU64]>,
+}
+
+// A slot is only accessed by the producer of its node until `filled` is set, and by the
+// consumer afterwards. Producers do not reuse a slot before the consumer moved past it.
+unsafe impl Sync for RingBuf {}
+
+impl RingBuf {
+    fn new(len: usize) -> Self {
+        RingBuf {
+            slots: (0..len)
+                .map(|_| {
+                    UnsafeCell::new(Slot {
+                        parents: [0; DEGREE],
+                        missing: 0,
+                        data: [0; DEGREE * NODE_SIZE],
+                    })
+                })
+                .collect(),
+            filled: (0..len).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn index(&self, node: u64) -> usize {
+        (node % self.slots.len() as u64) as usize
+    }
+
+    /// The caller must own the slot of `node`, see the `Sync` implementation.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn slot_mut(&self, node: u64) -> &mut Slot {
+        &mut *self.slots[self.index(node)].get()
+    }
+}
+
+/// The labels of the layer, written by the consumer and read by the producers. Producers only
+/// read labels of nodes below `Shared::consumer`, which are not written anymore.
+#[derive(Clone, Copy)]
+struct UnsafeSlice<'a> {
+    ptr: *mut u8,
+    len: usize,
+    _lifetime: PhantomData<&'a mut [u8]>,
+}
+
+unsafe impl<'a> Send for UnsafeSlice<'a> {}
+unsafe impl<'a> Sync for UnsafeSlice<'a> {}
+
+impl<'a> UnsafeSlice<'a> {
+    fn new(slice: &'a mut [u8]) -> Self {
+        UnsafeSlice {
+            ptr: slice.as_mut_ptr(),
+            len: slice.len(),
+            _lifetime: PhantomData,
+        }
+    }
+
+    unsafe fn node(&self, node: usize) -> &[u8] {
+        assert!((node + 1) * NODE_SIZE <= self.len, "node out of range");
+        std::slice::from_raw_parts(self.ptr.add(node * NODE_SIZE), NODE_SIZE)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn node_mut(&self, node: usize) -> &mut [u8] {
+        assert!((node + 1) * NODE_SIZE <= self.len, "node out of range");
+        std::slice::from_raw_parts_mut(self.ptr.add(node * NODE_SIZE), NODE_SIZE)
+    }
+}
+
+struct Shared {
+    /// The number of nodes labeled so far.
+    consumer: AtomicU64,
+    /// The next node to be claimed by a producer.
+    producer: AtomicU64,
+    /// Set once any thread fails, so the others stop waiting.
+    abort: AtomicBool,
+}
+
+/// Sets the abort flag if the owning thread panics.
+struct AbortOnPanic<'a>(&'a AtomicBool);
+
+impl<'a> Drop for AbortOnPanic<'a> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Labels all nodes of the layer `layer_index` into `layer_labels`, using the settings
+/// `multicore_sdr_*` for the number of producers and how far ahead of the consumer they run.
+///
+/// `exp_labels` are the labels of the previous layer, `None` for the first layer. The labels
+/// are the same as the ones of `create_label` and `create_label_exp`.
+pub(crate) fn create_layer_labels<H: Hasher>(
+    graph: &StackedBucketGraph<H>,
+    replica_id: &H::Domain,
+    exp_labels: Option<&[u8]>,
+    layer_labels: &mut [u8],
+    layer_index: usize,
+    progress: Option<&dyn Progress>,
+) -> Result<()> {
+    let nodes = graph.size();
+    ensure!(
+        layer_labels.len() == nodes * NODE_SIZE,
+        "layer labels must hold {} nodes",
+        nodes
+    );
+    if let Some(exp_labels) = exp_labels {
+        ensure!(
+            exp_labels.len() == nodes * NODE_SIZE,
+            "expander labels must hold {} nodes",
+            nodes
+        );
+    }
+
+    let settings = settings::current();
+    let num_producers = settings.multicore_sdr_producers.max(1) as usize;
+    let stride = settings.multicore_sdr_producer_stride.max(1);
+    let lookahead = settings.multicore_sdr_lookahead.max(1) as usize;
+
+    // Producers read the parents cache in ascending order each, so every one needs its own.
+    // They are opened here, as the location of the cache depends on the current settings.
+    let caches = (0..num_producers)
+        .map(|_| {
+            if settings.maximize_caching {
+                graph.parent_cache().map(Some)
+            } else {
+                Ok(None)
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // The cores stay reserved until the layer is labeled, so concurrent labeling runs are
+    // pinned to different ones. Without enough free cores the threads are not pinned.
+    let core_group = if settings.multicore_sdr_pin_cores {
+        checkout_core_group(num_producers + 1)
+    } else {
+        None
+    };
+    let core_ids = core_group.as_ref().map(CoreGroup::cores).unwrap_or(&[]);
+    debug!(
+        "labeling layer {} with {} producers, stride {}, lookahead {}, {} cores to pin",
+        layer_index,
+        num_producers,
+        stride,
+        lookahead,
+        core_ids.len()
+    );
+
+    let ring = RingBuf::new(lookahead);
+    let shared = Shared {
+        consumer: AtomicU64::new(0),
+        // Node 0 has no parents.
+        producer: AtomicU64::new(1),
+        abort: AtomicBool::new(false),
+    };
+    let labels = UnsafeSlice::new(layer_labels);
+
+    crossbeam::thread::scope(|s| {
+        let ring = &ring;
+        let shared = &shared;
+
+        let producers: Vec<_> = caches
+            .into_iter()
+            .enumerate()
+            .map(|(i, cache)| {
+                let core_id = core_ids.get(i + 1).copied();
+                s.spawn(move |_| {
+                    let _abort = AbortOnPanic(&shared.abort);
+                    if let Some(core_id) = core_id {
+                        core_affinity::set_for_current(core_id);
+                    }
+
+                    let res = produce(
+                        graph,
+                        cache,
+                        exp_labels,
+                        labels,
+                        ring,
+                        shared,
+                        stride,
+                        lookahead as u64,
+                    );
+                    if res.is_err() {
+                        shared.abort.store(true, Ordering::SeqCst);
+                    }
+                    res
+                })
+            })
+            .collect();
+
+        let core_id = core_ids.first().copied();
+        let consumer = s.spawn(move |_| {
+            let _abort = AbortOnPanic(&shared.abort);
+            if let Some(core_id) = core_id {
+                core_affinity::set_for_current(core_id);
+            }
+
+            let res = consume::<H>(
+                replica_id,
+                exp_labels.is_some(),
+                labels,
+                nodes,
+                layer_index,
+                ring,
+                shared,
+                progress,
+            );
+            if res.is_err() {
+                shared.abort.store(true, Ordering::SeqCst);
+            }
+            res
+        });
+
+        // Report the first error, a consumer stopped by a failed producer returns `Ok`.
+        let mut result = consumer
+            .join()
+            .unwrap_or_else(|_| Err(anyhow!("labeling consumer panicked")));
+        for producer in producers {
+            let res = producer
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("labeling producer panicked")));
+            if result.is_ok() {
+                result = res;
+            }
+        }
+
+        result
+    })
+    .map_err(|_| anyhow!("labeling thread panicked"))?
+}
+
+/// Claims `stride` nodes at a time and fills their slots, until all nodes are claimed.
+#[allow(clippy::too_many_arguments)]
+fn produce<H: Hasher>(
+    graph: &StackedBucketGraph<H>,
+    mut cache: Option<ParentCache>,
+    exp_labels: Option<&[u8]>,
+    labels: UnsafeSlice<'_>,
+    ring: &RingBuf,
+    shared: &Shared,
+    stride: u64,
+    lookahead: u64,
+) -> Result<()> {
+    let nodes = graph.size() as u64;
+
+    loop {
+        let start = shared.producer.fetch_add(stride, Ordering::SeqCst);
+        if start >= nodes {
+            return Ok(());
+        }
+
+        for node in start..(start + stride).min(nodes) {
+            // Wait for the consumer to move past the previous node of this slot.
+            while node >= shared.consumer.load(Ordering::Acquire) + lookahead {
+                if shared.abort.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                spin_loop_hint();
+            }
+
+            let parents = graph.parents_of(node as u32, cache.as_mut())?;
+            let labeled = shared.consumer.load(Ordering::Acquire);
+
+            let slot = unsafe { ring.slot_mut(node) };
+            slot.parents = parents;
+            slot.missing = 0;
+            for (i, parent) in parents[..BASE_DEGREE].iter().enumerate() {
+                if u64::from(*parent) < labeled {
+                    let label = unsafe { labels.node(*parent as usize) };
+                    slot.data[i * NODE_SIZE..(i + 1) * NODE_SIZE].copy_from_slice(label);
+                } else {
+                    slot.missing |= 1 << i;
+                }
+            }
+            if let Some(exp_labels) = exp_labels {
+                for (i, parent) in parents.iter().enumerate().skip(BASE_DEGREE) {
+                    let start = *parent as usize * NODE_SIZE;
+                    slot.data[i * NODE_SIZE..(i + 1) * NODE_SIZE]
+                        .copy_from_slice(&exp_labels[start..start + NODE_SIZE]);
+                }
+            }
+
+            ring.filled[ring.index(node)].store(node + 1, Ordering::Release);
+        }
+    }
+}
+
+/// Hashes the nodes in order, as soon as their slots are filled.
+#[allow(clippy::too_many_arguments)]
+fn consume<H: Hasher>(
+    replica_id: &H::Domain,
+    has_exp_parents: bool,
+    labels: UnsafeSlice<'_>,
+    nodes: usize,
+    layer_index: usize,
+    ring: &RingBuf,
+    shared: &Shared,
+    progress: Option<&dyn Progress>,
+) -> Result<()> {
+    let hash = label_hasher::<H>(replica_id, layer_index, 0).finish();
+    store_label(unsafe { labels.node_mut(0) }, 0, &hash);
+    shared.consumer.store(1, Ordering::Release);
+
+    for node in 1..nodes as u64 {
+        if node as usize % PROGRESS_INTERVAL == 0 {
+            report(progress, Phase::Labels, layer_index, node as usize, nodes)?;
+        }
+
+        let filled = &ring.filled[ring.index(node)];
+        while filled.load(Ordering::Acquire) != node + 1 {
+            if shared.abort.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            spin_loop_hint();
+        }
+
+        let slot = unsafe { ring.slot_mut(node) };
+        for i in 0..BASE_DEGREE {
+            if slot.missing & (1 << i) != 0 {
+                let label = unsafe { labels.node(slot.parents[i] as usize) };
+                slot.data[i * NODE_SIZE..(i + 1) * NODE_SIZE].copy_from_slice(label);
+            }
+        }
+
+        let hasher = label_hasher::<H>(replica_id, layer_index, node as usize);
+        let hash = if has_exp_parents {
+            hash_exp_parents(hasher, &slot.data)
+        } else {
+            hash_base_parents(hasher, &slot.data)
+        };
+        store_label(unsafe { labels.node_mut(node as usize) }, 0, &hash);
+
+        shared.consumer.store(node + 1, Ordering::Release);
+    }
+
+    Ok(())
+}
+
+/// Same rounds as `StackedGraph::copy_parents_data_inner`.
+fn hash_base_parents(mut hasher: Sha256, data: &[u8]) -> [u8; 32] {
+    let p = |i: usize| &data[i * NODE_SIZE..(i + 1) * NODE_SIZE];
+    let parents = [p(0), p(1), p(2), p(3), p(4), p(5)];
+
+    for _ in 0..6 {
+        hasher.input(&parents);
+    }
+    hasher.finish_with(parents[0])
+}
+
+/// Same rounds as `StackedGraph::copy_parents_data_inner_exp`.
+fn hash_exp_parents(mut hasher: Sha256, data: &[u8]) -> [u8; 32] {
+    let p = |i: usize| &data[i * NODE_SIZE..(i + 1) * NODE_SIZE];
+    let parents = [
+        p(0),
+        p(1),
+        p(2),
+        p(3),
+        p(4),
+        p(5),
+        p(6),
+        p(7),
+        p(8),
+        p(9),
+        p(10),
+        p(11),
+        p(12),
+        p(13),
+    ];
+
+    hasher.input(&parents);
+    hasher.input(&parents);
+    hasher.input(&parents[..8]);
+    hasher.finish_with(parents[8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        hasher::{Domain, Sha256Hasher},
+        settings::Settings,
+    };
+
+    use crate::stacked::vanilla::{
+        create_label::{create_label, create_label_exp},
+        graph::EXP_DEGREE,
+    };
+
+    fn single_core_labels(
+        graph: &StackedBucketGraph<Sha256Hasher>,
+        replica_id: &<Sha256Hasher as Hasher>::Domain,
+    ) -> Vec<Vec<u8>> {
+        let size = graph.size() * NODE_SIZE;
+        let mut layer_1 = vec![0u8; size];
+        let mut layer_2 = vec![0u8; size];
+
+        for node in 0..graph.size() {
+            create_label(graph, None, replica_id, &mut layer_1, 1, node).unwrap();
+        }
+        for node in 0..graph.size() {
+            create_label_exp(graph, None, replica_id, &layer_1, &mut layer_2, 2, node).unwrap();
+        }
+
+        vec![layer_1, layer_2]
+    }
+
+    #[test]
+    fn test_create_layer_labels_matches_single_core() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let nodes = 1 << 11;
+        let graph = StackedBucketGraph::<Sha256Hasher>::new_stacked(
+            nodes,
+            BASE_DEGREE,
+            EXP_DEGREE,
+            [7u8; 32],
+        )
+        .unwrap();
+        let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+
+        let expected = single_core_labels(&graph, &replica_id);
+
+        // Small lookaheads and strides exercise the waiting on both sides, a small parents
+        // cache makes the producers skip over cache windows.
+        for &(producers, stride, lookahead, maximize_caching) in &[
+            (1, 1, 1, false),
+            (3, 128, 800, true),
+            (4, 7, 16, true),
+            (2, 300, 50, false),
+        ] {
+            let mut settings = Settings::default();
+            settings.maximize_caching = maximize_caching;
+            settings.sdr_parents_cache_size = 256;
+            settings.multicore_sdr_producers = producers;
+            settings.multicore_sdr_producer_stride = stride;
+            settings.multicore_sdr_lookahead = lookahead;
+
+            let labels = settings::with_settings(Arc::new(settings), || {
+                let mut layer_1 = vec![0u8; nodes * NODE_SIZE];
+                let mut layer_2 = vec![0u8; nodes * NODE_SIZE];
+                create_layer_labels(&graph, &replica_id, None, &mut layer_1, 1, None)?;
+                create_layer_labels(&graph, &replica_id, Some(&layer_1), &mut layer_2, 2, None)?;
+
+                Ok::<_, anyhow::Error>(vec![layer_1, layer_2])
+            })
+            .unwrap();
+
+            assert_eq!(
+                labels, expected,
+                "labels differ with {} producers, stride {}, lookahead {}",
+                producers, stride, lookahead
+            );
+        }
+    }
+}
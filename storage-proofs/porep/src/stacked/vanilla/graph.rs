@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 
 use anyhow::ensure;
 use log::info;
-use sha2raw::Sha256;
+use sha2raw::{Sha256, Sha256x8, LANES};
 use storage_proofs_core::{
     crypto::{
         derive_porep_domain_seed,
@@ -35,6 +35,7 @@ where
     expansion_degree: usize,
     base_graph: G,
     pub(crate) feistel_keys: [feistel::Index; 4],
+    pub(crate) porep_id: [u8; 32],
     feistel_precomputed: FeistelPrecomputed,
     id: String,
     _h: PhantomData<H>,
@@ -67,6 +68,27 @@ fn prefetch(parents: &[u32], data: &[u8]) {
     }
 }
 
+/// Returns the first `end` blocks of every lane.
+pub(crate) fn lane_blocks<'a, 'b, B: AsRef<[&'b [u8]]>>(
+    lanes: &'a [B; LANES],
+    end: usize,
+) -> [&'a [&'b [u8]]; LANES] {
+    let mut blocks: [&[&[u8]]; LANES] = [&[]; LANES];
+    for (blocks, lane) in blocks.iter_mut().zip(lanes.iter()) {
+        *blocks = &lane.as_ref()[..end];
+    }
+    blocks
+}
+
+/// Returns the `i`th block of every lane.
+fn lane_block<'a>(lanes: &[[&'a [u8]; DEGREE]; LANES], i: usize) -> [&'a [u8]; LANES] {
+    let mut block: [&[u8]; LANES] = [&[]; LANES];
+    for (block, lane) in block.iter_mut().zip(lanes.iter()) {
+        *block = lane[i];
+    }
+    block
+}
+
 #[inline]
 fn read_node<'a>(i: usize, parents: &[u32], data: &'a [u8]) -> &'a [u8] {
     let start = parents[i] as usize * NODE_SIZE;
@@ -116,6 +138,7 @@ where
             ),
             expansion_degree,
             feistel_keys,
+            porep_id,
             feistel_precomputed: feistel::precompute((expansion_degree * nodes) as feistel::Index),
             _h: PhantomData,
         };
@@ -126,7 +149,7 @@ where
     /// Returns a reference to the parent cache.
     pub fn parent_cache(&self) -> Result<ParentCache> {
         // Number of nodes to be cached in memory
-        let default_cache_size = settings::SETTINGS.lock().unwrap().sdr_parents_cache_size;
+        let default_cache_size = settings::current().sdr_parents_cache_size;
         let cache_entries = self.size() as u32;
         let cache_size = cache_entries.min(default_cache_size);
 
@@ -141,17 +164,10 @@ where
         base_data: &[u8],
         exp_data: &[u8],
         hasher: Sha256,
-        mut cache: Option<&mut ParentCache>,
+        cache: Option<&mut ParentCache>,
     ) -> Result<[u8; 32]> {
-        if let Some(ref mut cache) = cache {
-            let cache_parents = cache.read(node as u32)?;
-            Ok(self.copy_parents_data_inner_exp(&cache_parents, base_data, exp_data, hasher))
-        } else {
-            let mut cache_parents = [0u32; DEGREE];
-
-            self.parents(node as usize, &mut cache_parents[..]).unwrap();
-            Ok(self.copy_parents_data_inner_exp(&cache_parents, base_data, exp_data, hasher))
-        }
+        let cache_parents = self.parents_of(node, cache)?;
+        Ok(self.copy_parents_data_inner_exp(&cache_parents, base_data, exp_data, hasher))
     }
 
     pub fn copy_parents_data(
@@ -159,20 +175,25 @@ where
         node: u32,
         base_data: &[u8],
         hasher: Sha256,
-        mut cache: Option<&mut ParentCache>,
+        cache: Option<&mut ParentCache>,
     ) -> Result<[u8; 32]> {
-        if let Some(ref mut cache) = cache {
-            let cache_parents = cache.read(node as u32)?;
-            Ok(self.copy_parents_data_inner(&cache_parents, base_data, hasher))
-        } else {
-            let mut cache_parents = [0u32; DEGREE];
-
-            self.parents(node as usize, &mut cache_parents[..]).unwrap();
-            Ok(self.copy_parents_data_inner(&cache_parents, base_data, hasher))
+        let cache_parents = self.parents_of(node, cache)?;
+        Ok(self.copy_parents_data_inner(&cache_parents, base_data, hasher))
+    }
+
+    /// Returns the parents of `node`, read from `cache` if there is one.
+    pub fn parents_of(&self, node: u32, cache: Option<&mut ParentCache>) -> Result<[u32; DEGREE]> {
+        match cache {
+            Some(cache) => cache.read(node),
+            None => {
+                let mut cache_parents = [0u32; DEGREE];
+                self.parents(node as usize, &mut cache_parents[..])?;
+                Ok(cache_parents)
+            }
         }
     }
 
-    fn copy_parents_data_inner_exp(
+    pub(crate) fn copy_parents_data_inner_exp(
         &self,
         cache_parents: &[u32],
         base_data: &[u8],
@@ -211,7 +232,7 @@ where
         hasher.finish_with(&parents[8])
     }
 
-    fn copy_parents_data_inner(
+    pub(crate) fn copy_parents_data_inner(
         &self,
         cache_parents: &[u32],
         base_data: &[u8],
@@ -250,6 +271,65 @@ where
         // round 7 (37)
         hasher.finish_with(parents[0])
     }
+
+    /// Same as `copy_parents_data_inner_exp`, for the labels of `LANES` replicas at once.
+    pub(crate) fn copy_parents_data_inner_exp_x8(
+        &self,
+        cache_parents: &[u32],
+        base_data: [&[u8]; LANES],
+        exp_data: [&[u8]; LANES],
+        mut hasher: Sha256x8,
+    ) -> [[u8; 32]; LANES] {
+        let mut parents = [[&[][..]; DEGREE]; LANES];
+        for ((parents, base_data), exp_data) in parents
+            .iter_mut()
+            .zip(base_data.iter())
+            .zip(exp_data.iter())
+        {
+            prefetch(&cache_parents[..BASE_DEGREE], base_data);
+            prefetch(&cache_parents[BASE_DEGREE..], exp_data);
+
+            for (i, parent) in parents.iter_mut().enumerate() {
+                let data = if i < BASE_DEGREE { base_data } else { exp_data };
+                *parent = read_node(i, cache_parents, data);
+            }
+        }
+
+        // round 1 (14)
+        hasher.input(lane_blocks(&parents, DEGREE));
+
+        // round 2 (14)
+        hasher.input(lane_blocks(&parents, DEGREE));
+
+        // round 3 (9)
+        hasher.input(lane_blocks(&parents, 8));
+        hasher.finish_with(lane_block(&parents, 8))
+    }
+
+    /// Same as `copy_parents_data_inner`, for the labels of `LANES` replicas at once.
+    pub(crate) fn copy_parents_data_inner_x8(
+        &self,
+        cache_parents: &[u32],
+        base_data: [&[u8]; LANES],
+        mut hasher: Sha256x8,
+    ) -> [[u8; 32]; LANES] {
+        let mut parents = [[&[][..]; DEGREE]; LANES];
+        for (parents, base_data) in parents.iter_mut().zip(base_data.iter()) {
+            prefetch(&cache_parents[..BASE_DEGREE], base_data);
+
+            for (i, parent) in parents[..BASE_DEGREE].iter_mut().enumerate() {
+                *parent = read_node(i, cache_parents, base_data);
+            }
+        }
+
+        // rounds 1 - 6 (0..36)
+        for _ in 0..6 {
+            hasher.input(lane_blocks(&parents, BASE_DEGREE));
+        }
+
+        // round 7 (37)
+        hasher.finish_with(lane_block(&parents, 0))
+    }
 }
 
 impl<H, G> ParameterSetMetadata for StackedGraph<H, G>
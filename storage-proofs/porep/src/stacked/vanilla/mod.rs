@@ -3,9 +3,12 @@ mod macros;
 
 mod cache;
 mod challenges;
+mod checkpoint;
 mod column;
 mod column_proof;
+mod cores;
 mod create_label;
+mod create_label_multi;
 mod encoding_proof;
 mod graph;
 pub(crate) mod hash;
@@ -16,6 +19,7 @@ mod proof;
 mod proof_scheme;
 
 pub use self::challenges::{ChallengeRequirements, LayerChallenges};
+pub use self::checkpoint::{LabelsCheckpoint, LayerDigest};
 pub use self::column::Column;
 pub use self::column_proof::ColumnProof;
 pub use self::create_label::*;
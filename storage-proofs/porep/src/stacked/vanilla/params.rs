@@ -15,12 +15,13 @@ use storage_proofs_core::{
     hasher::{Domain, Hasher},
     merkle::*,
     parameter_cache::ParameterSetMetadata,
+    sector_metadata::SectorMetadata,
     util::data_at_node,
 };
 
 use super::{
-    column::Column, column_proof::ColumnProof, graph::StackedBucketGraph, EncodingProof,
-    LabelingProof, LayerChallenges,
+    checkpoint::LabelsCheckpoint, column::Column, column_proof::ColumnProof,
+    graph::StackedBucketGraph, EncodingProof, LabelingProof, LayerChallenges,
 };
 
 pub const BINARY_ARITY: usize = 2;
@@ -461,6 +462,9 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAux<Tree, G> {
                 trace!("layer {} deleted", i);
             }
         }
+        if let Some(config) = t_aux.labels.labels.first() {
+            LabelsCheckpoint::<<Tree::Hasher as Hasher>::Domain>::remove(&config.path)?;
+        }
 
         Ok(())
     }
@@ -517,9 +521,17 @@ impl<Tree: MerkleTreeTrait, G: Hasher> TemporaryAuxCache<Tree, G> {
 
         // tree_r_last_size stored in the config is the base tree size
         let tree_r_last_size = t_aux.tree_r_last_config.size.unwrap();
-        let tree_r_last_config_rows_to_discard = t_aux.tree_r_last_config.rows_to_discard;
+
+        // The sector metadata describes how tree-r-last was laid out on disk, so it takes
+        // precedence over the config; sectors sealed before it existed fall back to the latter.
+        let mut tree_r_last_config = t_aux.tree_r_last_config.clone();
+        if let Some(metadata) = SectorMetadata::read(&tree_r_last_config.path)? {
+            metadata.ensure_shape::<Tree>()?;
+            tree_r_last_config.rows_to_discard = metadata.tree_r_last_rows_to_discard;
+        }
+        let tree_r_last_config_rows_to_discard = tree_r_last_config.rows_to_discard;
         let (configs, replica_config) = split_config_and_replica(
-            t_aux.tree_r_last_config.clone(),
+            tree_r_last_config,
             replica_path.clone(),
             get_merkle_tree_leafs(tree_r_last_size, Tree::Arity::to_usize())?,
             tree_count,
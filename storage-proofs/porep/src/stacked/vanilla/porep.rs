@@ -1,10 +1,12 @@
 use std::path::PathBuf;
 
+use anyhow::ensure;
 use merkletree::store::StoreConfig;
 use storage_proofs_core::{
     error::Result,
     hasher::Hasher,
     merkle::{BinaryMerkleTree, MerkleTreeTrait},
+    util::NODE_SIZE,
     Data,
 };
 
@@ -64,12 +66,22 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> PoRep<'a, Tre
     }
 
     fn extract(
-        _pp: &PublicParams<Tree>,
-        _replica_id: &<Tree::Hasher as Hasher>::Domain,
-        _data: &[u8],
-        _node: usize,
-        _config: Option<StoreConfig>,
+        pp: &PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        data: &[u8],
+        node: usize,
+        config: Option<StoreConfig>,
     ) -> Result<Vec<u8>> {
-        unimplemented!();
+        let start = node * NODE_SIZE;
+        let end = start + NODE_SIZE;
+        ensure!(end <= data.len(), "node {} is out of bounds", node);
+
+        Self::extract_range(
+            pp,
+            replica_id,
+            &data[start..end],
+            node,
+            config.expect("Missing store config"),
+        )
     }
 }
@@ -4,14 +4,15 @@ use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc, RwLock};
 
+use anyhow::{ensure, Context};
 use bincode::deserialize;
 use generic_array::typenum::{self, Unsigned};
-use log::{info, trace};
+use log::{info, trace, warn};
 use merkletree::merkle::{
     get_merkle_tree_cache_size, get_merkle_tree_leafs, get_merkle_tree_len,
     is_merkle_tree_size_valid,
 };
-use merkletree::store::{DiskStore, StoreConfig};
+use merkletree::store::{DiskStore, Store, StoreConfig};
 use paired::bls12_381::Fr;
 use rayon::prelude::*;
 use storage_proofs_core::{
@@ -25,20 +26,25 @@ use storage_proofs_core::{
         Operation::{CommD, EncodeWindowTimeAll, GenerateTreeC, GenerateTreeRLast},
     },
     merkle::*,
-    settings,
+    progress::{check_cancelled, report, Phase, Progress},
+    settings::{self, Settings},
     util::{default_rows_to_discard, NODE_SIZE},
 };
 use typenum::{U11, U2, U8};
 
 use super::{
     challenges::LayerChallenges,
+    checkpoint::{layer_digest, sync_layer_store, LabelsCheckpoint, LayerDigest},
     column::Column,
     create_label, create_label_exp,
+    create_label_multi::create_layer_labels,
+    create_labels_batch, create_labels_exp_batch,
     graph::StackedBucketGraph,
     hash::hash_single_column,
     params::{
-        get_node, Labels, LabelsCache, PersistentAux, Proof, PublicInputs, PublicParams,
-        ReplicaColumnProof, Tau, TemporaryAux, TemporaryAuxCache, TransformedLayers, BINARY_ARITY,
+        get_node, Labels, LabelsCache, PersistentAux, PrivateInputs, Proof, PublicInputs,
+        PublicParams, ReplicaColumnProof, Tau, TemporaryAux, TemporaryAuxCache, TransformedLayers,
+        BINARY_ARITY,
     },
     EncodingProof, LabelingProof,
 };
@@ -55,6 +61,9 @@ use crate::PoRep;
 
 pub const TOTAL_PARENTS: usize = 37;
 
+/// Number of labels generated between progress reports and cancellation checks.
+pub(crate) const PROGRESS_INTERVAL: usize = 1 << 16;
+
 #[derive(Debug)]
 pub struct StackedDrg<'a, Tree: 'a + MerkleTreeTrait, G: 'a + Hasher> {
     _a: PhantomData<&'a Tree>,
@@ -72,6 +81,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         layers: usize,
         _total_layers: usize,
         partition_count: usize,
+        progress: Option<&dyn Progress>,
     ) -> Result<Vec<Vec<Proof<Tree, G>>>> {
         assert!(layers > 0);
         assert_eq!(t_aux.labels.len(), layers);
@@ -106,9 +116,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             parents.iter().map(|parent| t_aux.column(*parent)).collect()
         };
 
-        (0..partition_count)
-            .map(|k| {
+        let proofs = (0..partition_count)
+            .map(|k| -> Result<Vec<Proof<Tree, G>>> {
                 trace!("proving partition {}/{}", k + 1, partition_count);
+                report(progress, Phase::VanillaProofs, 0, k, partition_count)?;
 
                 // Derive the set of challenges we are proving over.
                 let challenges = pub_inputs.challenges(layer_challenges, graph_size, Some(k));
@@ -246,7 +257,40 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     })
                     .collect()
             })
-            .collect()
+            .collect::<Result<Vec<_>>>()?;
+        report(
+            progress,
+            Phase::VanillaProofs,
+            0,
+            partition_count,
+            partition_count,
+        )?;
+
+        Ok(proofs)
+    }
+
+    /// Same as `ProofScheme::prove_all_partitions`, but reports the proven partitions to
+    /// `progress` and returns `Error::Cancelled` once it has been cancelled.
+    pub fn prove_all_partitions_with_progress(
+        pub_params: &PublicParams<Tree>,
+        pub_inputs: &PublicInputs<<Tree::Hasher as Hasher>::Domain, <G as Hasher>::Domain>,
+        priv_inputs: &PrivateInputs<Tree, G>,
+        partition_count: usize,
+        progress: Option<&dyn Progress>,
+    ) -> Result<Vec<Vec<Proof<Tree, G>>>> {
+        ensure!(partition_count > 0, "partitions must not be 0");
+
+        Self::prove_layers(
+            &pub_params.graph,
+            pub_inputs,
+            &priv_inputs.p_aux,
+            &priv_inputs.t_aux,
+            &pub_params.layer_challenges,
+            pub_params.layer_challenges.layers(),
+            pub_params.layer_challenges.layers(),
+            partition_count,
+            progress,
+        )
     }
 
     pub(crate) fn extract_and_invert_transform_layers(
@@ -262,7 +306,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         assert!(layers > 0);
 
         // generate labels
-        let (labels, _) = Self::generate_labels(graph, layer_challenges, replica_id, config)?;
+        let (labels, _) =
+            Self::generate_labels(graph, layer_challenges, replica_id, config, false, None)?;
 
         let last_layer_labels = labels.labels_for_last_layer()?;
         let size = merkletree::store::Store::len(last_layer_labels);
@@ -283,12 +328,108 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(())
     }
 
+    /// Decodes `data`, the encoded replica nodes starting at `first_node`, using
+    /// only the matching range of the last layer labels, which are read from the
+    /// cache dir of `config`.
+    ///
+    /// If the last layer labels are no longer present, all layers are regenerated
+    /// first, into a temporary dir that is removed again. That costs as much as
+    /// labeling the sector again, no matter how small the range is.
+    pub fn extract_range(
+        pp: &PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        data: &[u8],
+        first_node: usize,
+        config: StoreConfig,
+    ) -> Result<Vec<u8>> {
+        trace!("extract_range");
+        let graph = &pp.graph;
+        let layer_challenges = &pp.layer_challenges;
+
+        ensure!(
+            data.len() % NODE_SIZE == 0,
+            "data length {} is not a multiple of the node size",
+            data.len()
+        );
+        let num_nodes = data.len() / NODE_SIZE;
+        ensure!(
+            first_node + num_nodes <= graph.size(),
+            "node range {}..{} is out of bounds",
+            first_node,
+            first_node + num_nodes
+        );
+
+        let layers = layer_challenges.layers();
+        ensure!(layers > 0, "need at least one layer to extract from");
+
+        let last_layer_config =
+            StoreConfig::from_config(&config, CacheKey::label_layer(layers), Some(graph.size()));
+        let last_layer_path =
+            StoreConfig::data_path(&last_layer_config.path, &last_layer_config.id);
+        let range = first_node..first_node + num_nodes;
+
+        let keys = if Path::new(&last_layer_path).exists() {
+            let last_layer_labels: DiskStore<<Tree::Hasher as Hasher>::Domain> =
+                DiskStore::new_from_disk(
+                    graph.size(),
+                    Tree::Arity::to_usize(),
+                    &last_layer_config,
+                )?;
+            last_layer_labels.read_range(range)?
+        } else {
+            warn!("last layer labels not found, regenerating all layers to decode the range");
+            // The layers are only needed for this range, keep them out of the sector's cache.
+            let labels_dir = tempfile::Builder::new()
+                .prefix("extract-labels")
+                .tempdir_in(&config.path)
+                .context("could not create a temporary labels dir")?;
+            let labels_config =
+                StoreConfig::new(labels_dir.path(), config.id.clone(), config.rows_to_discard);
+            let (mut labels, _) = Self::generate_labels(
+                graph,
+                layer_challenges,
+                replica_id,
+                labels_config,
+                false,
+                None,
+            )?;
+            let keys = labels
+                .labels
+                .pop()
+                .context("missing last layer labels")?
+                .read_range(range)?;
+            drop(labels);
+            labels_dir
+                .close()
+                .context("could not remove the temporary labels dir")?;
+
+            keys
+        };
+
+        let mut decoded = vec![0u8; data.len()];
+        for ((key, encoded_node_bytes), data_node_bytes) in keys
+            .into_iter()
+            .zip(data.chunks(NODE_SIZE))
+            .zip(decoded.chunks_mut(NODE_SIZE))
+        {
+            let encoded_node =
+                <Tree::Hasher as Hasher>::Domain::try_from_bytes(encoded_node_bytes)?;
+            let data_node = decode::<<Tree::Hasher as Hasher>::Domain>(key, encoded_node);
+
+            data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&data_node));
+        }
+
+        Ok(decoded)
+    }
+
     #[allow(clippy::type_complexity)]
     fn generate_labels(
         graph: &StackedBucketGraph<Tree::Hasher>,
         layer_challenges: &LayerChallenges,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
+        resume: bool,
+        progress: Option<&dyn Progress>,
     ) -> Result<(LabelsCache<Tree>, Labels<Tree>)> {
         info!("generate labels");
 
@@ -303,21 +444,65 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let mut layer_labels = vec![0u8; layer_size]; // Buffer for labels of the current layer
         let mut exp_labels = vec![0u8; layer_size]; // Buffer for labels of the previous layer, needed for expander parents
 
-        let use_cache = settings::SETTINGS.lock().unwrap().maximize_caching;
-        let mut cache = if use_cache {
+        let mut layer_digests = if resume {
+            Self::resume_labels(
+                graph,
+                replica_id,
+                layers,
+                &config,
+                &mut layer_labels,
+                &mut exp_labels,
+            )?
+        } else {
+            Vec::new()
+        };
+        let completed_layers = layer_digests.len();
+
+        for layer in 1..=completed_layers {
+            let layer_config =
+                StoreConfig::from_config(&config, CacheKey::label_layer(layer), Some(graph.size()));
+            let layer_store: DiskStore<<Tree::Hasher as Hasher>::Domain> =
+                DiskStore::new_from_disk(graph.size(), Tree::Arity::to_usize(), &layer_config)
+                    .with_context(|| format!("could not open layer {} store", layer))?;
+
+            labels.push(layer_store);
+            label_configs.push(layer_config);
+        }
+
+        let use_cache = settings::current().maximize_caching;
+        // The multi-core labeling opens parents caches for each of its producers.
+        let use_multicore_sdr = settings::current().use_multicore_sdr;
+        let mut cache = if use_cache && !use_multicore_sdr && completed_layers < layers {
             Some(graph.parent_cache()?)
         } else {
             None
         };
 
-        for layer in 1..=layers {
+        for layer in (completed_layers + 1)..=layers {
             info!("generating layer: {}", layer);
             if let Some(ref mut cache) = cache {
                 cache.reset()?;
             }
 
-            if layer == 1 {
+            if use_multicore_sdr {
+                let exp_labels = if layer == 1 {
+                    None
+                } else {
+                    Some(&exp_labels[..])
+                };
+                create_layer_labels(
+                    graph,
+                    replica_id,
+                    exp_labels,
+                    &mut layer_labels,
+                    layer,
+                    progress,
+                )?;
+            } else if layer == 1 {
                 for node in 0..graph.size() {
+                    if node % PROGRESS_INTERVAL == 0 {
+                        report(progress, Phase::Labels, layer, node, graph.size())?;
+                    }
                     create_label(
                         graph,
                         cache.as_mut(),
@@ -329,6 +514,9 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 }
             } else {
                 for node in 0..graph.size() {
+                    if node % PROGRESS_INTERVAL == 0 {
+                        report(progress, Phase::Labels, layer, node, graph.size())?;
+                    }
                     create_label_exp(
                         graph,
                         cache.as_mut(),
@@ -358,6 +546,19 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 "  generated layer {} store with id {}",
                 layer, layer_config.id
             );
+            report(progress, Phase::Labels, layer, graph.size(), graph.size())?;
+
+            // Record the completed layer, so an interrupted run can pick up from here.
+            sync_layer_store(&layer_config)?;
+            layer_digests.push(layer_digest(&layer_labels));
+            LabelsCheckpoint {
+                replica_id: *replica_id,
+                nodes: graph.size(),
+                layers,
+                completed_layers: layer,
+                layer_digests: layer_digests.clone(),
+            }
+            .write(&config.path)?;
 
             info!("  setting exp parents");
             std::mem::swap(&mut layer_labels, &mut exp_labels);
@@ -382,6 +583,164 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         ))
     }
 
+    /// Labels all `replica_ids` in lockstep, writing the layers of each replica to the
+    /// cache dir of its config. A checkpoint is written for every replica after each
+    /// layer, so an interrupted batch can be resumed one replica at a time.
+    fn generate_labels_batch(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        layer_challenges: &LayerChallenges,
+        replica_ids: &[<Tree::Hasher as Hasher>::Domain],
+        configs: &[StoreConfig],
+        progress: Option<&dyn Progress>,
+    ) -> Result<Vec<Labels<Tree>>> {
+        info!("generate labels for {} replicas", replica_ids.len());
+        ensure!(!replica_ids.is_empty(), "no replicas to label");
+        ensure!(
+            replica_ids.len() == configs.len(),
+            "got {} replica ids but {} configs",
+            replica_ids.len(),
+            configs.len()
+        );
+
+        let layers = layer_challenges.layers();
+        let mut label_configs: Vec<Vec<StoreConfig>> =
+            vec![Vec::with_capacity(layers); replica_ids.len()];
+        let mut layer_digests = vec![Vec::with_capacity(layers); replica_ids.len()];
+
+        let layer_size = graph.size() * NODE_SIZE;
+        // NOTE: this keeps 2x sector size around per replica.
+        let mut layer_labels = vec![vec![0u8; layer_size]; replica_ids.len()];
+        let mut exp_labels = vec![vec![0u8; layer_size]; replica_ids.len()];
+
+        let use_cache = settings::current().maximize_caching;
+        let mut cache = if use_cache {
+            Some(graph.parent_cache()?)
+        } else {
+            None
+        };
+
+        for layer in 1..=layers {
+            info!("generating layer: {}", layer);
+            if let Some(ref mut cache) = cache {
+                cache.reset()?;
+            }
+
+            for node in 0..graph.size() {
+                if node % PROGRESS_INTERVAL == 0 {
+                    report(progress, Phase::Labels, layer, node, graph.size())?;
+                }
+                if layer == 1 {
+                    create_labels_batch(
+                        graph,
+                        cache.as_mut(),
+                        replica_ids,
+                        &mut layer_labels,
+                        layer,
+                        node,
+                    )?;
+                } else {
+                    create_labels_exp_batch(
+                        graph,
+                        cache.as_mut(),
+                        replica_ids,
+                        &exp_labels,
+                        &mut layer_labels,
+                        layer,
+                        node,
+                    )?;
+                }
+            }
+
+            info!("  storing labels on disk");
+            for (i, config) in configs.iter().enumerate() {
+                let layer_config = StoreConfig::from_config(
+                    config,
+                    CacheKey::label_layer(layer),
+                    Some(graph.size()),
+                );
+                DiskStore::<<Tree::Hasher as Hasher>::Domain>::new_from_slice_with_config(
+                    graph.size(),
+                    Tree::Arity::to_usize(),
+                    &layer_labels[i],
+                    layer_config.clone(),
+                )?;
+
+                sync_layer_store(&layer_config)?;
+                layer_digests[i].push(layer_digest(&layer_labels[i]));
+                LabelsCheckpoint {
+                    replica_id: replica_ids[i],
+                    nodes: graph.size(),
+                    layers,
+                    completed_layers: layer,
+                    layer_digests: layer_digests[i].clone(),
+                }
+                .write(&config.path)?;
+
+                label_configs[i].push(layer_config);
+            }
+            report(progress, Phase::Labels, layer, graph.size(), graph.size())?;
+
+            info!("  setting exp parents");
+            std::mem::swap(&mut layer_labels, &mut exp_labels);
+        }
+
+        Ok(label_configs.into_iter().map(Labels::new).collect())
+    }
+
+    /// Loads the checkpoint found in the cache dir of `config` and verifies every
+    /// completed layer against the digest recorded for it. On success the labels of
+    /// the last completed layer are left in `exp_labels`, ready to serve as expander
+    /// parents for the next layer, and the digests of the completed layers are
+    /// returned.
+    fn resume_labels(
+        graph: &StackedBucketGraph<Tree::Hasher>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        layers: usize,
+        config: &StoreConfig,
+        layer_labels: &mut [u8],
+        exp_labels: &mut [u8],
+    ) -> Result<Vec<LayerDigest>> {
+        let checkpoint = match LabelsCheckpoint::read(&config.path)? {
+            Some(checkpoint) => checkpoint,
+            None => {
+                info!("no labels checkpoint found, starting from layer 1");
+                return Ok(Vec::new());
+            }
+        };
+        checkpoint.ensure_matches(replica_id, graph.size(), layers)?;
+
+        let completed = checkpoint.completed_layers;
+        if completed == 0 {
+            return Ok(Vec::new());
+        }
+        info!("resuming labels after layer {}", completed);
+
+        for (i, expected) in checkpoint.layer_digests.iter().enumerate() {
+            let layer = i + 1;
+            // The last completed layer is kept, the others only pass through.
+            let buf = if layer == completed {
+                &mut *exp_labels
+            } else {
+                &mut *layer_labels
+            };
+
+            let layer_config =
+                StoreConfig::from_config(config, CacheKey::label_layer(layer), Some(graph.size()));
+            let store: DiskStore<<Tree::Hasher as Hasher>::Domain> =
+                DiskStore::new_from_disk(graph.size(), Tree::Arity::to_usize(), &layer_config)
+                    .with_context(|| format!("could not open layer {} store", layer))?;
+            store.read_range_into(0, graph.size(), buf)?;
+
+            ensure!(
+                &layer_digest(buf) == expected,
+                "layer {} does not match its checkpointed digest, refusing to resume",
+                layer
+            );
+        }
+
+        Ok(checkpoint.layer_digests)
+    }
+
     fn build_binary_tree<K: Hasher>(
         tree_data: &[u8],
         config: StoreConfig,
@@ -401,24 +760,145 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         Ok(tree)
     }
 
+    /// Builds tree-c with the column arity matching `layers` and returns its root.
+    fn generate_tree_c_root(
+        layers: usize,
+        nodes_count: usize,
+        tree_count: usize,
+        configs: Vec<StoreConfig>,
+        labels: &LabelsCache<Tree>,
+        progress: Option<&dyn Progress>,
+    ) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        let tree_c_root = match layers {
+            2 => {
+                let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    labels,
+                    progress,
+                )?;
+                tree_c.root()
+            }
+            8 => {
+                let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    labels,
+                    progress,
+                )?;
+                tree_c.root()
+            }
+            11 => {
+                let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
+                    layers,
+                    nodes_count,
+                    tree_count,
+                    configs,
+                    labels,
+                    progress,
+                )?;
+                tree_c.root()
+            }
+            _ => panic!("Unsupported column arity"),
+        };
+
+        Ok(tree_c_root)
+    }
+
+    /// Rebuilds tree-c from the labels of all layers into the stores of `tree_c_config`,
+    /// returning its root. The labels must still be present in the cache directory.
+    pub fn regenerate_tree_c(
+        tree_c_config: StoreConfig,
+        label_configs: &Labels<Tree>,
+    ) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        info!("regenerate_tree_c");
+        let tree_count = get_base_tree_count::<Tree>();
+        // The size stored in the config is the base tree size.
+        let tree_c_size = tree_c_config.size.context("tree_c_config has no size")?;
+        let nodes_count = get_merkle_tree_leafs(tree_c_size, Tree::Arity::to_usize())?;
+
+        let labels = LabelsCache::<Tree>::new(label_configs).context("labels_cache")?;
+        let configs = split_config(tree_c_config, tree_count)?;
+
+        measure_op(GenerateTreeC, || {
+            Self::generate_tree_c_root(
+                label_configs.len(),
+                nodes_count,
+                tree_count,
+                configs,
+                &labels,
+                None,
+            )
+        })
+    }
+
+    /// Rebuilds tree-r-last from the encoded nodes of an existing replica into the stores of
+    /// `tree_r_last_config`, returning its root. Unlike `generate_tree_r_last`, this needs
+    /// neither the original data nor the labels.
+    pub fn regenerate_tree_r_last(
+        tree_r_last_config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<<Tree::Hasher as Hasher>::Domain> {
+        info!("regenerate_tree_r_last");
+        let tree_count = get_base_tree_count::<Tree>();
+        // The size stored in the config is the base tree size.
+        let tree_r_last_size = tree_r_last_config
+            .size
+            .context("tree_r_last_config has no size")?;
+        let nodes_count = get_merkle_tree_leafs(tree_r_last_size, Tree::Arity::to_usize())?;
+
+        let mut data = Data::from_path(replica_path.clone());
+        data.ensure_data()?;
+        ensure!(
+            data.len() == tree_count * nodes_count * NODE_SIZE,
+            "replica is {} bytes, expected {}",
+            data.len(),
+            tree_count * nodes_count * NODE_SIZE
+        );
+
+        let tree_r_last = measure_op(GenerateTreeRLast, || {
+            Self::generate_tree_r_last::<Tree::Arity>(
+                &mut data,
+                nodes_count,
+                tree_count,
+                tree_r_last_config,
+                replica_path,
+                None,
+                None,
+            )
+        })?;
+        data.drop_data();
+
+        Ok(tree_r_last.root())
+    }
+
     fn generate_tree_c<ColumnArity, TreeArity>(
         layers: usize,
         nodes_count: usize,
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        progress: Option<&dyn Progress>,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: 'static + PoseidonArity,
         TreeArity: PoseidonArity,
     {
-        if settings::SETTINGS.lock().unwrap().use_gpu_column_builder {
+        // The builders hand work to other threads, so the settings are read once, here.
+        let settings = settings::current();
+        if settings.use_gpu_column_builder {
             Self::generate_tree_c_gpu::<ColumnArity, TreeArity>(
                 layers,
                 nodes_count,
                 tree_count,
                 configs,
                 labels,
+                &settings,
+                progress,
             )
         } else {
             Self::generate_tree_c_cpu::<ColumnArity, TreeArity>(
@@ -427,6 +907,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_count,
                 configs,
                 labels,
+                progress,
             )
         }
     }
@@ -438,6 +919,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        settings: &Settings,
+        progress: Option<&dyn Progress>,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: 'static + PoseidonArity,
@@ -457,23 +940,28 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             // Override these values with care using environment variables:
             // FIL_PROOFS_MAX_GPU_COLUMN_BATCH_SIZE, FIL_PROOFS_MAX_GPU_TREE_BATCH_SIZE, and
             // FIL_PROOFS_COLUMN_WRITE_BATCH_SIZE respectively.
-            let max_gpu_column_batch_size =
-                settings::SETTINGS.lock().unwrap().max_gpu_column_batch_size as usize;
-            let max_gpu_tree_batch_size =
-                settings::SETTINGS.lock().unwrap().max_gpu_tree_batch_size as usize;
-            let column_write_batch_size =
-                settings::SETTINGS.lock().unwrap().column_write_batch_size as usize;
+            let max_gpu_column_batch_size = settings.max_gpu_column_batch_size as usize;
+            let max_gpu_tree_batch_size = settings.max_gpu_tree_batch_size as usize;
+            let column_write_batch_size = settings.column_write_batch_size as usize;
 
             // This channel will receive batches of columns and add them to the ColumnTreeBuilder.
             let (builder_tx, builder_rx) = mpsc::sync_channel(0);
 
             let config_count = configs.len(); // Don't move config into closure below.
+            let mut trees_built = 0;
             rayon::scope(|s| {
                 s.spawn(move |_| {
                     for i in 0..config_count {
                         let mut node_index = 0;
                         let builder_tx = builder_tx.clone();
                         while node_index != nodes_count {
+                            let done = i * nodes_count + node_index;
+                            if report(progress, Phase::TreeC, 0, done, tree_count * nodes_count)
+                                .is_err()
+                            {
+                                // Dropping the sender stops the tree builder below.
+                                return;
+                            }
                             let chunked_nodes_count =
                                 std::cmp::min(nodes_count - node_index, max_gpu_column_batch_size);
                             trace!(
@@ -536,6 +1024,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     }
                 });
                 let configs = &configs;
+                let trees_built = &mut trees_built;
                 s.spawn(move |_| {
                     let mut column_tree_builder = ColumnTreeBuilder::<
                             ColumnArity,
@@ -552,7 +1041,11 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
                     // Loop until all trees for all configs have been built.
                     while i < configs.len() {
-                        let (columns, is_final): (Vec<GenericArray<Fr, ColumnArity>>, bool) = builder_rx.recv().expect("failed to recv columns");
+                        let (columns, is_final): (Vec<GenericArray<Fr, ColumnArity>>, bool) = match builder_rx.recv() {
+                            Ok(batch) => batch,
+                            // The sender went away early, checked once the scope is done.
+                            Err(_) => break,
+                        };
 
                         // Just add non-final column batches.
                         if !is_final {
@@ -626,6 +1119,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
                         // Move on to the next config.
                         i += 1;
+                        *trees_built = i;
                         if i == configs.len() {
                             break;
                         }
@@ -633,6 +1127,22 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     }
                 });
             });
+            // The builder only stops early when the columns stop coming, which is
+            // expected if we were cancelled and an error otherwise.
+            check_cancelled(progress)?;
+            ensure!(
+                trees_built == config_count,
+                "tree_c builder stopped after {} of {} trees",
+                trees_built,
+                config_count
+            );
+            report(
+                progress,
+                Phase::TreeC,
+                0,
+                tree_count * nodes_count,
+                tree_count * nodes_count,
+            )?;
 
             create_disk_tree::<
                 DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
@@ -646,6 +1156,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         tree_count: usize,
         configs: Vec<StoreConfig>,
         labels: &LabelsCache<Tree>,
+        progress: Option<&dyn Progress>,
     ) -> Result<DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         ColumnArity: PoseidonArity,
@@ -657,6 +1168,13 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
             let mut trees = Vec::with_capacity(tree_count);
             for (i, config) in configs.iter().enumerate() {
+                report(
+                    progress,
+                    Phase::TreeC,
+                    0,
+                    i * nodes_count,
+                    tree_count * nodes_count,
+                )?;
                 let mut hashes: Vec<<Tree::Hasher as Hasher>::Domain> =
                     vec![<Tree::Hasher as Hasher>::Domain::default(); nodes_count];
 
@@ -703,19 +1221,30 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             }
 
             assert_eq!(tree_count, trees.len());
+            report(
+                progress,
+                Phase::TreeC,
+                0,
+                tree_count * nodes_count,
+                tree_count * nodes_count,
+            )?;
+
             create_disk_tree::<
                 DiskTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
             >(configs[0].size.unwrap(), &configs)
         })
     }
 
+    /// Encodes `data` with the labels of the last layer and builds tree-r-last over it. Without
+    /// `labels`, `data` is taken to be the replica already and is only read.
     fn generate_tree_r_last<TreeArity>(
         data: &mut Data,
         nodes_count: usize,
         tree_count: usize,
         tree_r_last_config: StoreConfig,
         replica_path: PathBuf,
-        labels: &LabelsCache<Tree>,
+        labels: Option<&LabelsCache<Tree>>,
+        progress: Option<&dyn Progress>,
     ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>
     where
         TreeArity: PoseidonArity,
@@ -728,22 +1257,41 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         )?;
 
         data.ensure_data()?;
-        let last_layer_labels = labels.labels_for_last_layer()?;
+        let last_layer_labels = match labels {
+            Some(labels) => Some(labels.labels_for_last_layer()?),
+            None => None,
+        };
 
-        if settings::SETTINGS.lock().unwrap().use_gpu_tree_builder {
+        let settings = settings::current();
+        if settings.use_gpu_tree_builder {
             info!("generating tree r last using the GPU");
-            let max_gpu_tree_batch_size =
-                settings::SETTINGS.lock().unwrap().max_gpu_tree_batch_size as usize;
+            let max_gpu_tree_batch_size = settings.max_gpu_tree_batch_size as usize;
 
             // This channel will receive batches of leaf nodes and add them to the TreeBuilder.
             let (builder_tx, builder_rx) = mpsc::sync_channel::<(Vec<Fr>, bool)>(0);
             let config_count = configs.len(); // Don't move config into closure below.
             let configs = &configs;
+            let mut trees_built = 0;
+            let mut leaf_error = None;
             rayon::scope(|s| {
+                let leaf_error = &mut leaf_error;
                 s.spawn(move |_| {
                     for i in 0..config_count {
                         let mut node_index = 0;
                         while node_index != nodes_count {
+                            let done = i * nodes_count + node_index;
+                            if report(
+                                progress,
+                                Phase::TreeRLast,
+                                0,
+                                done,
+                                tree_count * nodes_count,
+                            )
+                            .is_err()
+                            {
+                                // Dropping the sender stops the tree builder below.
+                                return;
+                            }
                             let chunked_nodes_count =
                                 std::cmp::min(nodes_count - node_index, max_gpu_tree_batch_size);
                             let start = (i * nodes_count) + node_index;
@@ -759,27 +1307,50 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                                 end,
                             );
 
-                            let encoded_data = last_layer_labels
-                                .read_range(start..end)
-                                .expect("failed to read layer range")
-                                .into_par_iter()
-                                .zip(
-                                    data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)]
-                                        .par_chunks_mut(NODE_SIZE),
-                                )
-                                .map(|(key, data_node_bytes)| {
-                                    let data_node =
-                                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(
-                                            data_node_bytes,
+                            let encoded: Vec<Fr> = match last_layer_labels {
+                                Some(last_layer_labels) => {
+                                    last_layer_labels
+                                        .read_range(start..end)
+                                        .expect("failed to read layer range")
+                                        .into_par_iter()
+                                        .zip(
+                                            data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)]
+                                                .par_chunks_mut(NODE_SIZE),
                                         )
-                                        .expect("try_from_bytes failed");
-                                    let encoded_node =
-                                        encode::<<Tree::Hasher as Hasher>::Domain>(key, data_node);
-                                    data_node_bytes
-                                        .copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
-
-                                    encoded_node
-                                });
+                                        .map(|(key, data_node_bytes)| {
+                                            let data_node =
+                                                <Tree::Hasher as Hasher>::Domain::try_from_bytes(
+                                                    data_node_bytes,
+                                                )
+                                                .expect("try_from_bytes failed");
+                                            let encoded_node =
+                                                encode::<<Tree::Hasher as Hasher>::Domain>(
+                                                    key, data_node,
+                                                );
+                                            data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(
+                                                &encoded_node,
+                                            ));
+
+                                            encoded_node.into()
+                                        })
+                                        .collect()
+                                }
+                                None => match data.as_ref()[(start * NODE_SIZE)..(end * NODE_SIZE)]
+                                    .par_chunks(NODE_SIZE)
+                                    .map(|node| {
+                                        <Tree::Hasher as Hasher>::Domain::try_from_bytes(node)
+                                            .map(Into::into)
+                                    })
+                                    .collect::<Result<_>>()
+                                {
+                                    Ok(encoded) => encoded,
+                                    Err(err) => {
+                                        // Dropping the sender stops the tree builder below.
+                                        *leaf_error = Some(err);
+                                        return;
+                                    }
+                                },
+                            };
 
                             node_index += chunked_nodes_count;
                             trace!(
@@ -789,9 +1360,6 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                                 nodes_count,
                             );
 
-                            let encoded: Vec<_> =
-                                encoded_data.into_par_iter().map(|x| x.into()).collect();
-
                             let is_final = node_index == nodes_count;
                             builder_tx
                                 .send((encoded, is_final))
@@ -802,6 +1370,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
                 {
                     let tree_r_last_config = &tree_r_last_config;
+                    let trees_built = &mut trees_built;
                     s.spawn(move |_| {
                         let mut tree_builder = TreeBuilder::<Tree::Arity>::new(
                             Some(BatcherType::GPU),
@@ -816,8 +1385,11 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
                         // Loop until all trees for all configs have been built.
                         while i < configs.len() {
-                            let (encoded, is_final) =
-                                builder_rx.recv().expect("failed to recv encoded data");
+                            let (encoded, is_final) = match builder_rx.recv() {
+                                Ok(batch) => batch,
+                                // The sender went away early, checked once the scope is done.
+                                Err(_) => break,
+                            };
 
                             // Just add non-final leaf batches.
                             if !is_final {
@@ -872,6 +1444,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
 
                             // Move on to the next config.
                             i += 1;
+                            *trees_built = i;
                             if i == configs.len() {
                                 break;
                             }
@@ -880,43 +1453,77 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                     });
                 }
             });
+            // The builder only stops early when the leaves stop coming, which is
+            // expected if we were cancelled and an error otherwise.
+            check_cancelled(progress)?;
+            if let Some(err) = leaf_error {
+                return Err(err.context("invalid replica node"));
+            }
+            ensure!(
+                trees_built == config_count,
+                "tree_r_last builder stopped after {} of {} trees",
+                trees_built,
+                config_count
+            );
         } else {
             info!("generating tree r last using the CPU");
-            let size = Store::len(last_layer_labels);
-
-            let mut start = 0;
-            let mut end = size / tree_count;
-
             for (i, config) in configs.iter().enumerate() {
-                let encoded_data = last_layer_labels
-                    .read_range(start..end)?
-                    .into_par_iter()
-                    .zip(
-                        data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)]
-                            .par_chunks_mut(NODE_SIZE),
-                    )
-                    .map(|(key, data_node_bytes)| {
-                        let data_node =
-                            <Tree::Hasher as Hasher>::Domain::try_from_bytes(data_node_bytes)
-                                .expect("try from bytes failed");
-                        let encoded_node =
-                            encode::<<Tree::Hasher as Hasher>::Domain>(key, data_node);
-                        data_node_bytes.copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
-
-                        encoded_node
-                    });
+                report(
+                    progress,
+                    Phase::TreeRLast,
+                    0,
+                    i * nodes_count,
+                    tree_count * nodes_count,
+                )?;
+                let start = i * nodes_count;
+                let end = start + nodes_count;
 
                 info!(
                     "building base tree_r_last with CPU {}/{}",
                     i + 1,
                     tree_count
                 );
-                LCTree::<Tree::Hasher, Tree::Arity, typenum::U0, typenum::U0>::from_par_iter_with_config(encoded_data, config.clone())?;
+                match last_layer_labels {
+                    Some(last_layer_labels) => {
+                        let encoded_data = last_layer_labels
+                            .read_range(start..end)?
+                            .into_par_iter()
+                            .zip(
+                                data.as_mut()[(start * NODE_SIZE)..(end * NODE_SIZE)]
+                                    .par_chunks_mut(NODE_SIZE),
+                            )
+                            .map(|(key, data_node_bytes)| {
+                                let data_node = <Tree::Hasher as Hasher>::Domain::try_from_bytes(
+                                    data_node_bytes,
+                                )
+                                .expect("try from bytes failed");
+                                let encoded_node =
+                                    encode::<<Tree::Hasher as Hasher>::Domain>(key, data_node);
+                                data_node_bytes
+                                    .copy_from_slice(AsRef::<[u8]>::as_ref(&encoded_node));
 
-                start = end;
-                end += size / tree_count;
+                                encoded_node
+                            });
+                        LCTree::<Tree::Hasher, Tree::Arity, typenum::U0, typenum::U0>::from_par_iter_with_config(encoded_data, config.clone())?;
+                    }
+                    None => {
+                        let nodes = data.as_ref()[(start * NODE_SIZE)..(end * NODE_SIZE)]
+                            .par_chunks(NODE_SIZE)
+                            .map(<Tree::Hasher as Hasher>::Domain::try_from_bytes)
+                            .collect::<Result<Vec<_>>>()
+                            .context("invalid replica node")?;
+                        LCTree::<Tree::Hasher, Tree::Arity, typenum::U0, typenum::U0>::from_par_iter_with_config(nodes, config.clone())?;
+                    }
+                }
             }
         };
+        report(
+            progress,
+            Phase::TreeRLast,
+            0,
+            tree_count * nodes_count,
+            tree_count * nodes_count,
+        )?;
 
         create_lc_tree::<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>(
             tree_r_last_config.size.unwrap(),
@@ -936,7 +1543,14 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
     ) -> Result<TransformedLayers<Tree, G>> {
         // Generate key layers.
         let (_, labels) = measure_op(EncodeWindowTimeAll, || {
-            Self::generate_labels(graph, layer_challenges, replica_id, config.clone())
+            Self::generate_labels(
+                graph,
+                layer_challenges,
+                replica_id,
+                config.clone(),
+                false,
+                None,
+            )
         })?;
 
         Self::transform_and_replicate_layers_inner(
@@ -947,6 +1561,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             config,
             replica_path,
             labels,
+            None,
         )
     }
 
@@ -958,6 +1573,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         config: StoreConfig,
         replica_path: PathBuf,
         label_configs: Labels<Tree>,
+        progress: Option<&dyn Progress>,
     ) -> Result<TransformedLayers<Tree, G>> {
         trace!("transform_and_replicate_layers");
         let nodes_count = graph.size();
@@ -1026,39 +1642,14 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         let labels = LabelsCache::<Tree>::new(&label_configs)?;
         let configs = split_config(tree_c_config.clone(), tree_count)?;
 
-        let tree_c_root = match layers {
-            2 => {
-                let tree_c = Self::generate_tree_c::<U2, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            8 => {
-                let tree_c = Self::generate_tree_c::<U8, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            11 => {
-                let tree_c = Self::generate_tree_c::<U11, Tree::Arity>(
-                    layers,
-                    nodes_count,
-                    tree_count,
-                    configs,
-                    &labels,
-                )?;
-                tree_c.root()
-            }
-            _ => panic!("Unsupported column arity"),
-        };
+        let tree_c_root = Self::generate_tree_c_root(
+            layers,
+            nodes_count,
+            tree_count,
+            configs,
+            &labels,
+            progress,
+        )?;
         info!("tree_c done");
 
         // Build the MerkleTree over the original data (if needed).
@@ -1091,7 +1682,8 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
                 tree_count,
                 tree_r_last_config.clone(),
                 replica_path.clone(),
-                &labels,
+                Some(&labels),
+                progress,
             )
         })?;
         info!("tree_r_last done");
@@ -1124,23 +1716,83 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         ))
     }
 
-    /// Phase1 of replication.
+    /// Phase1 of replication. Labeling progress is reported to `progress`, which
+    /// can also be used to cancel it.
     pub fn replicate_phase1(
         pp: &'a PublicParams<Tree>,
         replica_id: &<Tree::Hasher as Hasher>::Domain,
         config: StoreConfig,
+        progress: Option<&dyn Progress>,
     ) -> Result<Labels<Tree>> {
         info!("replicate_phase1");
 
         let (_, labels) = measure_op(EncodeWindowTimeAll, || {
-            Self::generate_labels(&pp.graph, &pp.layer_challenges, replica_id, config)
+            Self::generate_labels(
+                &pp.graph,
+                &pp.layer_challenges,
+                replica_id,
+                config,
+                false,
+                progress,
+            )
         })?;
 
         Ok(labels)
     }
 
+    /// Phase1 of replication, continuing from the labels checkpoint in the cache dir
+    /// of `config`, if one exists. The last completed layer is verified before
+    /// labeling continues with the next one.
+    pub fn replicate_phase1_resume(
+        pp: &'a PublicParams<Tree>,
+        replica_id: &<Tree::Hasher as Hasher>::Domain,
+        config: StoreConfig,
+        progress: Option<&dyn Progress>,
+    ) -> Result<Labels<Tree>> {
+        info!("replicate_phase1_resume");
+
+        let (_, labels) = measure_op(EncodeWindowTimeAll, || {
+            Self::generate_labels(
+                &pp.graph,
+                &pp.layer_challenges,
+                replica_id,
+                config,
+                true,
+                progress,
+            )
+        })?;
+
+        Ok(labels)
+    }
+
+    /// Phase1 of replication for several replicas sharing the public params, e.g.
+    /// sectors of the same size and porep id. The replicas are labeled in lockstep,
+    /// so the parents of each node are read only once. `configs[i]` is the config
+    /// of `replica_ids[i]`, the labels of each replica are identical to those of
+    /// `replicate_phase1`.
+    pub fn replicate_phase1_batch(
+        pp: &'a PublicParams<Tree>,
+        replica_ids: &[<Tree::Hasher as Hasher>::Domain],
+        configs: &[StoreConfig],
+        progress: Option<&dyn Progress>,
+    ) -> Result<Vec<Labels<Tree>>> {
+        info!("replicate_phase1_batch");
+
+        measure_op(EncodeWindowTimeAll, || {
+            Self::generate_labels_batch(
+                &pp.graph,
+                &pp.layer_challenges,
+                replica_ids,
+                configs,
+                progress,
+            )
+        })
+    }
+
     #[allow(clippy::type_complexity)]
-    /// Phase2 of replication.
+    /// Phase2 of replication. Progress of building tree-c and tree-r-last is
+    /// reported to `progress`, which can also be used to cancel it. Note that
+    /// cancelling while tree-r-last is built leaves the replica partially encoded.
     #[allow(clippy::type_complexity)]
     pub fn replicate_phase2(
         pp: &'a PublicParams<Tree>,
@@ -1149,6 +1801,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
         data_tree: BinaryMerkleTree<G>,
         config: StoreConfig,
         replica_path: PathBuf,
+        progress: Option<&dyn Progress>,
     ) -> Result<(
         <Self as PoRep<'a, Tree::Hasher, G>>::Tau,
         <Self as PoRep<'a, Tree::Hasher, G>>::ProverAux,
@@ -1163,6 +1816,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             config,
             replica_path,
             labels,
+            progress,
         )?;
 
         Ok((tau, (paux, taux)))
@@ -1187,10 +1841,10 @@ impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> StackedDrg<'a, Tr
             tree_count,
         )?;
 
-        if settings::SETTINGS.lock().unwrap().use_gpu_tree_builder {
+        let settings = settings::current();
+        if settings.use_gpu_tree_builder {
             info!("generating tree r last using the GPU");
-            let max_gpu_tree_batch_size =
-                settings::SETTINGS.lock().unwrap().max_gpu_tree_batch_size as usize;
+            let max_gpu_tree_batch_size = settings.max_gpu_tree_batch_size as usize;
 
             let mut tree_builder = TreeBuilder::<Tree::Arity>::new(
                 Some(BatcherType::GPU),
@@ -1368,9 +2022,11 @@ mod tests {
     use rand_xorshift::XorShiftRng;
     use storage_proofs_core::{
         drgraph::BASE_DEGREE,
+        error::Error,
         fr32::fr_into_bytes,
         hasher::{Blake2sHasher, PedersenHasher, PoseidonHasher, Sha256Hasher},
         merkle::MerkleTreeTrait,
+        progress::CancellationToken,
         proof::ProofScheme,
         table_tests,
         test_helper::setup_replica,
@@ -1520,6 +2176,355 @@ mod tests {
         cache_dir.close().expect("Failed to remove cache dir");
     }
 
+    #[test]
+    fn resume_labels_from_checkpoint() {
+        type Tree = DiskTree<Sha256Hasher, typenum::U8, typenum::U0, typenum::U0>;
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+        let nodes = 64;
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+
+        let sp = SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [32; 32],
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        };
+        let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+        let labels = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(
+            &pp,
+            &replica_id,
+            config.clone(),
+            None,
+        )
+        .expect("replicate_phase1 failed");
+        let read_labels = |labels: &Labels<Tree>| -> Vec<Vec<_>> {
+            (1..=DEFAULT_STACKED_LAYERS)
+                .map(|layer| {
+                    labels
+                        .labels_for_layer(layer)
+                        .and_then(|store| store.read_range(0..nodes))
+                        .expect("failed to read layer")
+                })
+                .collect()
+        };
+        let expected = read_labels(&labels);
+
+        let checkpoint = LabelsCheckpoint::read(cache_dir.path())
+            .expect("failed to read checkpoint")
+            .expect("missing checkpoint");
+        assert_eq!(checkpoint.completed_layers, DEFAULT_STACKED_LAYERS);
+
+        // Pretend the process died after layer 3.
+        let completed = 3;
+        for layer in (completed + 1)..=DEFAULT_STACKED_LAYERS {
+            std::fs::remove_file(StoreConfig::data_path(
+                &config.path,
+                &CacheKey::label_layer(layer),
+            ))
+            .expect("failed to remove layer");
+        }
+        LabelsCheckpoint {
+            completed_layers: completed,
+            layer_digests: checkpoint.layer_digests[..completed].to_vec(),
+            ..checkpoint.clone()
+        }
+        .write(cache_dir.path())
+        .expect("failed to write checkpoint");
+
+        let resumed = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_resume(
+            &pp,
+            &replica_id,
+            config.clone(),
+            None,
+        )
+        .expect("replicate_phase1_resume failed");
+        assert_eq!(expected, read_labels(&resumed));
+
+        // A corrupted layer must not be resumed from.
+        LabelsCheckpoint {
+            completed_layers: completed,
+            layer_digests: checkpoint.layer_digests[..completed].to_vec(),
+            ..checkpoint.clone()
+        }
+        .write(cache_dir.path())
+        .expect("failed to write checkpoint");
+        let layer_path = StoreConfig::data_path(&config.path, &CacheKey::label_layer(completed));
+        let mut layer_data = std::fs::read(&layer_path).expect("failed to read layer");
+        layer_data[0] ^= 1;
+        std::fs::write(&layer_path, &layer_data).expect("failed to write layer");
+
+        assert!(StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_resume(
+            &pp,
+            &replica_id,
+            config.clone(),
+            None,
+        )
+        .is_err());
+        layer_data[0] ^= 1;
+        std::fs::write(&layer_path, &layer_data).expect("failed to write layer");
+
+        // Neither must one with a change anywhere in an earlier layer.
+        let layer_path = StoreConfig::data_path(&config.path, &CacheKey::label_layer(1));
+        let mut layer_data = std::fs::read(&layer_path).expect("failed to read layer");
+        let last = layer_data.len() - 1;
+        layer_data[last] ^= 1;
+        std::fs::write(&layer_path, &layer_data).expect("failed to write layer");
+
+        assert!(StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_resume(
+            &pp,
+            &replica_id,
+            config,
+            None,
+        )
+        .is_err());
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn replicate_phase1_batch_matches_single() {
+        type Tree = DiskTree<Sha256Hasher, typenum::U8, typenum::U0, typenum::U0>;
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let nodes = 64;
+        let count = 3;
+
+        let sp = SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [32; 32],
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        };
+        let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+        let read_labels = |labels: &Labels<Tree>| -> Vec<Vec<_>> {
+            (1..=DEFAULT_STACKED_LAYERS)
+                .map(|layer| {
+                    labels
+                        .labels_for_layer(layer)
+                        .and_then(|store| store.read_range(0..nodes))
+                        .expect("failed to read layer")
+                })
+                .collect()
+        };
+        let new_config = |dir: &tempfile::TempDir| {
+            StoreConfig::new(
+                dir.path(),
+                CacheKey::CommDTree.to_string(),
+                default_rows_to_discard(nodes, BINARY_ARITY),
+            )
+        };
+
+        let replica_ids: Vec<_> = (0..count)
+            .map(|_| <Sha256Hasher as Hasher>::Domain::random(rng))
+            .collect();
+        let single_dirs: Vec<_> = (0..count).map(|_| tempfile::tempdir().unwrap()).collect();
+        let batch_dirs: Vec<_> = (0..count).map(|_| tempfile::tempdir().unwrap()).collect();
+
+        let batch_configs: Vec<_> = batch_dirs.iter().map(new_config).collect();
+        let batch = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_batch(
+            &pp,
+            &replica_ids,
+            &batch_configs,
+            None,
+        )
+        .expect("replicate_phase1_batch failed");
+        assert_eq!(batch.len(), count);
+
+        for i in 0..count {
+            let single = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(
+                &pp,
+                &replica_ids[i],
+                new_config(&single_dirs[i]),
+                None,
+            )
+            .expect("replicate_phase1 failed");
+            assert_eq!(read_labels(&single), read_labels(&batch[i]));
+
+            let checkpoint = LabelsCheckpoint::read(batch_dirs[i].path())
+                .expect("failed to read checkpoint")
+                .expect("missing checkpoint");
+            assert_eq!(checkpoint.replica_id, replica_ids[i]);
+            assert_eq!(checkpoint.completed_layers, DEFAULT_STACKED_LAYERS);
+        }
+
+        assert!(StackedDrg::<Tree, Blake2sHasher>::replicate_phase1_batch(
+            &pp,
+            &replica_ids,
+            &batch_configs[1..],
+            None,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn replicate_phase1_progress_and_cancel() {
+        type Tree = DiskTree<Sha256Hasher, typenum::U8, typenum::U0, typenum::U0>;
+
+        #[derive(Default)]
+        struct Recorder(std::sync::Mutex<Vec<(Phase, usize, usize, usize)>>);
+
+        impl Progress for Recorder {
+            fn report(&self, phase: Phase, layer: usize, done: usize, total: usize) {
+                self.0.lock().unwrap().push((phase, layer, done, total));
+            }
+        }
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let replica_id = <Sha256Hasher as Hasher>::Domain::random(rng);
+        let nodes = 64;
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+
+        let sp = SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [32; 32],
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        };
+        let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+        let recorder = Recorder::default();
+        StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(
+            &pp,
+            &replica_id,
+            config.clone(),
+            Some(&recorder),
+        )
+        .expect("replicate_phase1 failed");
+
+        let reports = recorder.0.lock().unwrap();
+        for layer in 1..=DEFAULT_STACKED_LAYERS {
+            assert!(reports.contains(&(Phase::Labels, layer, nodes, nodes)));
+        }
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = StackedDrg::<Tree, Blake2sHasher>::replicate_phase1(
+            &pp,
+            &replica_id,
+            config,
+            Some(&token),
+        )
+        .expect_err("cancelled replicate_phase1 must fail");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Cancelled)
+        ));
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn extract_range_matches_data() {
+        type Tree = DiskTree<PoseidonHasher, typenum::U8, typenum::U0, typenum::U0>;
+
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let replica_id = <PoseidonHasher as Hasher>::Domain::random(rng);
+        let nodes = 64;
+
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| <PoseidonHasher as Hasher>::Domain::random(rng).into_bytes())
+            .collect();
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config = StoreConfig::new(
+            cache_dir.path(),
+            CacheKey::CommDTree.to_string(),
+            default_rows_to_discard(nodes, BINARY_ARITY),
+        );
+        let replica_path = cache_dir.path().join("replica-path");
+        let mut mmapped_data = setup_replica(&data, &replica_path);
+
+        let sp = SetupParams {
+            nodes,
+            degree: BASE_DEGREE,
+            expansion_degree: EXP_DEGREE,
+            porep_id: [32; 32],
+            layer_challenges: LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5),
+        };
+        let pp = StackedDrg::<Tree, Blake2sHasher>::setup(&sp).expect("setup failed");
+
+        StackedDrg::<Tree, Blake2sHasher>::replicate(
+            &pp,
+            &replica_id,
+            (mmapped_data.as_mut()).into(),
+            None,
+            config.clone(),
+            replica_path,
+        )
+        .expect("replication failed");
+
+        let (first_node, num_nodes) = (12, 20);
+        let range = first_node * NODE_SIZE..(first_node + num_nodes) * NODE_SIZE;
+
+        let decoded = StackedDrg::<Tree, Blake2sHasher>::extract_range(
+            &pp,
+            &replica_id,
+            &mmapped_data[range.clone()],
+            first_node,
+            config.clone(),
+        )
+        .expect("failed to extract range");
+        assert_eq!(&data[range.clone()], &decoded[..]);
+
+        let node = StackedDrg::<Tree, Blake2sHasher>::extract(
+            &pp,
+            &replica_id,
+            &mmapped_data,
+            first_node,
+            Some(config.clone()),
+        )
+        .expect("failed to extract node");
+        assert_eq!(&data[range.start..range.start + NODE_SIZE], &node[..]);
+
+        // Without cached labels, the range is still decoded correctly, and the regenerated
+        // labels are not left behind in the cache dir.
+        std::fs::remove_file(StoreConfig::data_path(
+            &config.path,
+            &CacheKey::label_layer(DEFAULT_STACKED_LAYERS),
+        ))
+        .expect("failed to remove last layer");
+        let cache_entries = || {
+            let mut entries: Vec<_> = std::fs::read_dir(cache_dir.path())
+                .expect("failed to read cache dir")
+                .map(|entry| entry.expect("failed to read cache dir entry").file_name())
+                .collect();
+            entries.sort();
+            entries
+        };
+        let entries = cache_entries();
+        let decoded = StackedDrg::<Tree, Blake2sHasher>::extract_range(
+            &pp,
+            &replica_id,
+            &mmapped_data[range.clone()],
+            first_node,
+            config,
+        )
+        .expect("failed to extract range");
+        assert_eq!(&data[range], &decoded[..]);
+        assert_eq!(cache_entries(), entries);
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
     fn prove_verify_fixed(n: usize) {
         let challenges = LayerChallenges::new(DEFAULT_STACKED_LAYERS, 5);
 
@@ -1717,6 +2722,21 @@ mod tests {
         )
         .expect("failed to verify partition proofs");
 
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = StackedDrg::<Tree, Blake2sHasher>::prove_all_partitions_with_progress(
+            &pp,
+            &pub_inputs,
+            &priv_inputs,
+            partitions,
+            Some(&token),
+        )
+        .expect_err("cancelled proving must fail");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Cancelled)
+        ));
+
         // Discard cached MTs that are no longer needed.
         TemporaryAux::<Tree, Blake2sHasher>::clear_temp(t_aux_orig).expect("t_aux delete failed");
 
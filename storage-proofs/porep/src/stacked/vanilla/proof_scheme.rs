@@ -1,4 +1,3 @@
-use anyhow::ensure;
 use log::trace;
 use rayon::prelude::*;
 use storage_proofs_core::{
@@ -65,17 +64,13 @@ impl<'a, 'c, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'
         partition_count: usize,
     ) -> Result<Vec<Self::Proof>> {
         trace!("prove_all_partitions");
-        ensure!(partition_count > 0, "partitions must not be 0");
 
-        Self::prove_layers(
-            &pub_params.graph,
+        Self::prove_all_partitions_with_progress(
+            pub_params,
             pub_inputs,
-            &priv_inputs.p_aux,
-            &priv_inputs.t_aux,
-            &pub_params.layer_challenges,
-            pub_params.layer_challenges.layers(),
-            pub_params.layer_challenges.layers(),
+            priv_inputs,
             partition_count,
+            None,
         )
     }
 
@@ -0,0 +1,307 @@
+use std::marker::PhantomData;
+
+use bellperson::gadgets::num;
+use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+use generic_array::typenum::{U0, U2};
+use paired::bls12_381::{Bls12, Fr};
+use storage_proofs_core::{
+    compound_proof::CircuitComponent,
+    gadgets::constraint,
+    gadgets::por::{AuthPath, PoRCircuit},
+    gadgets::variables::Root,
+    hasher::{HashFunction, Hasher, PoseidonArity},
+    merkle::{DiskStore, MerkleProofTrait, MerkleTreeTrait, MerkleTreeWrapper},
+};
+
+use super::vanilla::{ChallengeProof as VanillaChallengeProof, PublicParams};
+
+type TreeAuthPath<T> = AuthPath<
+    <T as MerkleTreeTrait>::Hasher,
+    <T as MerkleTreeTrait>::Arity,
+    <T as MerkleTreeTrait>::SubTreeArity,
+    <T as MerkleTreeTrait>::TopTreeArity,
+>;
+
+/// Proof for a single challenge.
+#[derive(Debug)]
+pub struct ChallengeProof<Tree: MerkleTreeTrait, G: Hasher> {
+    /// Inclusion path for the challenged data node in tree D.
+    pub comm_d_path: AuthPath<G, U2, U0, U0>,
+    /// The value of the challenged data node.
+    pub data_leaf: Option<Fr>,
+    /// Inclusion path of the challenged node in tree R of the sector key.
+    pub comm_r_last_old_path: TreeAuthPath<Tree>,
+    /// The value of the challenged node of the sector key.
+    pub old_leaf: Option<Fr>,
+    /// Inclusion path of the challenged node in tree R of the updated replica.
+    pub comm_r_last_new_path: TreeAuthPath<Tree>,
+    /// The value of the challenged node of the updated replica.
+    pub new_leaf: Option<Fr>,
+    _t: PhantomData<Tree>,
+}
+
+impl<Tree: MerkleTreeTrait, G: 'static + Hasher> ChallengeProof<Tree, G> {
+    /// Create an empty proof, used in `blank_circuit`s.
+    pub fn empty(params: &PublicParams) -> Self {
+        ChallengeProof {
+            comm_d_path: AuthPath::blank(params.nodes),
+            data_leaf: None,
+            comm_r_last_old_path: AuthPath::blank(params.nodes),
+            old_leaf: None,
+            comm_r_last_new_path: AuthPath::blank(params.nodes),
+            new_leaf: None,
+            _t: PhantomData,
+        }
+    }
+
+    /// Circuit synthesis.
+    pub fn synthesize<CS: ConstraintSystem<Bls12>>(
+        self,
+        mut cs: CS,
+        rho: &num::AllocatedNum<Bls12>,
+        comm_d_new: &num::AllocatedNum<Bls12>,
+        comm_r_last_old: &num::AllocatedNum<Bls12>,
+        comm_r_last_new: &num::AllocatedNum<Bls12>,
+    ) -> Result<(), SynthesisError> {
+        let ChallengeProof {
+            comm_d_path,
+            data_leaf,
+            comm_r_last_old_path,
+            old_leaf,
+            comm_r_last_new_path,
+            new_leaf,
+            ..
+        } = self;
+
+        // PrivateInput: data_leaf
+        let data_leaf_num = num::AllocatedNum::alloc(cs.namespace(|| "data_leaf"), || {
+            data_leaf.ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        // enforce inclusion of the data leaf in the tree D
+        enforce_inclusion(
+            cs.namespace(|| "comm_d_inclusion"),
+            comm_d_path,
+            comm_d_new,
+            &data_leaf_num,
+        )?;
+
+        // PrivateInput: old_leaf
+        let old_leaf_num = num::AllocatedNum::alloc(cs.namespace(|| "old_leaf"), || {
+            old_leaf.ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        // enforce inclusion of the sector key node in the old tree R
+        enforce_inclusion(
+            cs.namespace(|| "comm_r_last_old_inclusion"),
+            comm_r_last_old_path,
+            comm_r_last_old,
+            &old_leaf_num,
+        )?;
+
+        // PrivateInput: new_leaf
+        let new_leaf_num = num::AllocatedNum::alloc(cs.namespace(|| "new_leaf"), || {
+            new_leaf.ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        // enforce inclusion of the updated replica node in the new tree R
+        enforce_inclusion(
+            cs.namespace(|| "comm_r_last_new_inclusion"),
+            comm_r_last_new_path,
+            comm_r_last_new,
+            &new_leaf_num,
+        )?;
+
+        // enforce new_leaf = old_leaf + rho * data_leaf
+        cs.enforce(
+            || "encoding",
+            |lc| lc + rho.get_variable(),
+            |lc| lc + data_leaf_num.get_variable(),
+            |lc| lc + new_leaf_num.get_variable() - old_leaf_num.get_variable(),
+        );
+
+        Ok(())
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> From<VanillaChallengeProof<Tree, G>>
+    for ChallengeProof<Tree, G>
+where
+    Tree::Hasher: 'static,
+{
+    fn from(vanilla_proof: VanillaChallengeProof<Tree, G>) -> Self {
+        let VanillaChallengeProof {
+            comm_d_proof,
+            comm_r_last_old_proof,
+            comm_r_last_new_proof,
+        } = vanilla_proof;
+
+        ChallengeProof {
+            comm_d_path: comm_d_proof.as_options().into(),
+            data_leaf: Some(comm_d_proof.leaf().into()),
+            comm_r_last_old_path: comm_r_last_old_proof.as_options().into(),
+            old_leaf: Some(comm_r_last_old_proof.leaf().into()),
+            comm_r_last_new_path: comm_r_last_new_proof.as_options().into(),
+            new_leaf: Some(comm_r_last_new_proof.leaf().into()),
+            _t: PhantomData,
+        }
+    }
+}
+
+/// Replica update of a sealed sector.
+pub struct ReplicaUpdateCircuit<Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> {
+    pub comm_r_old: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub comm_d_new: Option<G::Domain>,
+    pub comm_r_new: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub comm_c: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub comm_r_last_old: Option<<Tree::Hasher as Hasher>::Domain>,
+    pub comm_r_last_new: Option<<Tree::Hasher as Hasher>::Domain>,
+
+    // one proof per challenge
+    pub proofs: Vec<ChallengeProof<Tree, G>>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> CircuitComponent for ReplicaUpdateCircuit<Tree, G> {
+    type ComponentPrivateInputs = ();
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Circuit<Bls12> for ReplicaUpdateCircuit<Tree, G> {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let ReplicaUpdateCircuit {
+            comm_r_old,
+            comm_d_new,
+            comm_r_new,
+            comm_c,
+            comm_r_last_old,
+            comm_r_last_new,
+            proofs,
+        } = self;
+
+        // Allocate comm_r_old as Fr
+        let comm_r_old_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r_old"), || {
+            comm_r_old
+                .map(Into::into)
+                .ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        // make comm_r_old a public input
+        comm_r_old_num.inputize(cs.namespace(|| "comm_r_old_input"))?;
+
+        // Allocate comm_d_new as Fr
+        let comm_d_new_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_d_new"), || {
+            comm_d_new
+                .map(Into::into)
+                .ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        // make comm_d_new a public input
+        comm_d_new_num.inputize(cs.namespace(|| "comm_d_new_input"))?;
+
+        // Allocate comm_r_new as Fr
+        let comm_r_new_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_r_new"), || {
+            comm_r_new
+                .map(Into::into)
+                .ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        // make comm_r_new a public input
+        comm_r_new_num.inputize(cs.namespace(|| "comm_r_new_input"))?;
+
+        // Allocate comm_c as Fr
+        let comm_c_num = num::AllocatedNum::alloc(cs.namespace(|| "comm_c"), || {
+            comm_c
+                .map(Into::into)
+                .ok_or_else(|| SynthesisError::AssignmentMissing)
+        })?;
+
+        // Allocate comm_r_last_old as Fr
+        let comm_r_last_old_num =
+            num::AllocatedNum::alloc(cs.namespace(|| "comm_r_last_old"), || {
+                comm_r_last_old
+                    .map(Into::into)
+                    .ok_or_else(|| SynthesisError::AssignmentMissing)
+            })?;
+
+        // Allocate comm_r_last_new as Fr
+        let comm_r_last_new_num =
+            num::AllocatedNum::alloc(cs.namespace(|| "comm_r_last_new"), || {
+                comm_r_last_new
+                    .map(Into::into)
+                    .ok_or_else(|| SynthesisError::AssignmentMissing)
+            })?;
+
+        // Verify comm_r_old = H(comm_c || comm_r_last_old)
+        {
+            let hash_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+                cs.namespace(|| "H_comm_c_comm_r_last_old"),
+                &comm_c_num,
+                &comm_r_last_old_num,
+            )?;
+
+            constraint::equal(
+                cs,
+                || "enforce comm_r_old = H(comm_c || comm_r_last_old)",
+                &comm_r_old_num,
+                &hash_num,
+            );
+        }
+
+        // Verify comm_r_new = H(comm_c || comm_r_last_new)
+        {
+            let hash_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+                cs.namespace(|| "H_comm_c_comm_r_last_new"),
+                &comm_c_num,
+                &comm_r_last_new_num,
+            )?;
+
+            constraint::equal(
+                cs,
+                || "enforce comm_r_new = H(comm_c || comm_r_last_new)",
+                &comm_r_new_num,
+                &hash_num,
+            );
+        }
+
+        // rho = H(comm_r_old || comm_d_new)
+        let rho_num = <Tree::Hasher as Hasher>::Function::hash2_circuit(
+            cs.namespace(|| "rho"),
+            &comm_r_old_num,
+            &comm_d_new_num,
+        )?;
+
+        for (i, proof) in proofs.into_iter().enumerate() {
+            proof.synthesize(
+                &mut cs.namespace(|| format!("challenge_{}", i)),
+                &rho_num,
+                &comm_d_new_num,
+                &comm_r_last_old_num,
+                &comm_r_last_new_num,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Enforce the inclusion of the given path, to the given leaf and the root.
+fn enforce_inclusion<H, U, V, W, CS: ConstraintSystem<Bls12>>(
+    cs: CS,
+    path: AuthPath<H, U, V, W>,
+    root: &num::AllocatedNum<Bls12>,
+    leaf: &num::AllocatedNum<Bls12>,
+) -> Result<(), SynthesisError>
+where
+    H: 'static + Hasher,
+    U: 'static + PoseidonArity,
+    V: 'static + PoseidonArity,
+    W: 'static + PoseidonArity,
+{
+    let root = Root::from_allocated::<CS>(root.clone());
+    let leaf = Root::from_allocated::<CS>(leaf.clone());
+
+    PoRCircuit::<MerkleTreeWrapper<H, DiskStore<H::Domain>, U, V, W>>::synthesize(
+        cs, leaf, path, root, true,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use std::marker::PhantomData;
+
+use anyhow::ensure;
+use bellperson::Circuit;
+use paired::bls12_381::{Bls12, Fr};
+use storage_proofs_core::{
+    compound_proof::{CircuitComponent, CompoundProof},
+    error::Result,
+    gadgets::por::PoRCompound,
+    hasher::Hasher,
+    merkle::{BinaryMerkleTree, MerkleTreeTrait},
+    parameter_cache::{CacheableParameters, ParameterSetMetadata},
+    por,
+    proof::ProofScheme,
+};
+
+use super::circuit::{ChallengeProof, ReplicaUpdateCircuit};
+use super::vanilla::ReplicaUpdate;
+
+pub struct ReplicaUpdateCompound<Tree: MerkleTreeTrait, G: Hasher> {
+    _t: PhantomData<Tree>,
+    _g: PhantomData<G>,
+}
+
+impl<C: Circuit<Bls12>, P: ParameterSetMetadata, Tree: MerkleTreeTrait, G: Hasher>
+    CacheableParameters<C, P> for ReplicaUpdateCompound<Tree, G>
+{
+    fn cache_prefix() -> String {
+        format!("empty-sector-update-{}-{}", Tree::display(), G::name())
+    }
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher>
+    CompoundProof<'a, ReplicaUpdate<'a, Tree, G>, ReplicaUpdateCircuit<Tree, G>>
+    for ReplicaUpdateCompound<Tree, G>
+{
+    fn generate_public_inputs(
+        pub_in: &<ReplicaUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicInputs,
+        pub_params: &<ReplicaUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicParams,
+        k: Option<usize>,
+    ) -> Result<Vec<Fr>> {
+        let mut inputs = Vec::new();
+
+        inputs.push(pub_in.comm_r_old.into());
+        inputs.push(pub_in.comm_d_new.into());
+        inputs.push(pub_in.comm_r_new.into());
+
+        let por_setup_params = por::SetupParams {
+            leaves: pub_params.nodes,
+            private: true,
+        };
+
+        let por_params = por::PoR::<Tree>::setup(&por_setup_params)?;
+        let por_params_d = por::PoR::<BinaryMerkleTree<G>>::setup(&por_setup_params)?;
+
+        for challenge in pub_in.challenges(pub_params.challenge_count, pub_params.nodes, k) {
+            // Inclusion Proof: data node in comm_d_new
+            inputs.extend(generate_inclusion_inputs::<BinaryMerkleTree<G>>(
+                &por_params_d,
+                challenge,
+                k,
+            )?);
+
+            // Inclusion Proof: sector key node in comm_r_last_old
+            inputs.extend(generate_inclusion_inputs::<Tree>(
+                &por_params,
+                challenge,
+                k,
+            )?);
+
+            // Inclusion Proof: updated replica node in comm_r_last_new
+            inputs.extend(generate_inclusion_inputs::<Tree>(
+                &por_params,
+                challenge,
+                k,
+            )?);
+        }
+
+        Ok(inputs)
+    }
+
+    fn circuit(
+        public_inputs: &<ReplicaUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicInputs,
+        _component_private_inputs: <ReplicaUpdateCircuit<Tree, G> as CircuitComponent>::ComponentPrivateInputs,
+        vanilla_proof: &<ReplicaUpdate<'a, Tree, G> as ProofScheme<'a>>::Proof,
+        public_params: &<ReplicaUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicParams,
+        _partition_k: Option<usize>,
+    ) -> Result<ReplicaUpdateCircuit<Tree, G>> {
+        ensure!(
+            vanilla_proof.challenge_proofs.len() == public_params.challenge_count,
+            "invalid number of challenge proofs"
+        );
+
+        Ok(ReplicaUpdateCircuit {
+            comm_r_old: Some(public_inputs.comm_r_old),
+            comm_d_new: Some(public_inputs.comm_d_new),
+            comm_r_new: Some(public_inputs.comm_r_new),
+            comm_c: Some(vanilla_proof.comm_c),
+            comm_r_last_old: Some(vanilla_proof.comm_r_last_old),
+            comm_r_last_new: Some(vanilla_proof.comm_r_last_new),
+            proofs: vanilla_proof
+                .challenge_proofs
+                .iter()
+                .cloned()
+                .map(Into::into)
+                .collect(),
+        })
+    }
+
+    fn blank_circuit(
+        public_params: &<ReplicaUpdate<'a, Tree, G> as ProofScheme<'a>>::PublicParams,
+    ) -> ReplicaUpdateCircuit<Tree, G> {
+        ReplicaUpdateCircuit {
+            comm_r_old: None,
+            comm_d_new: None,
+            comm_r_new: None,
+            comm_c: None,
+            comm_r_last_old: None,
+            comm_r_last_new: None,
+            proofs: (0..public_params.challenge_count)
+                .map(|_challenge_index| ChallengeProof::empty(public_params))
+                .collect(),
+        }
+    }
+}
+
+/// Helper to generate public inputs for inclusion proofs.
+fn generate_inclusion_inputs<Tree: 'static + MerkleTreeTrait>(
+    por_params: &por::PublicParams,
+    challenge: usize,
+    k: Option<usize>,
+) -> Result<Vec<Fr>> {
+    let pub_inputs = por::PublicInputs::<<Tree::Hasher as Hasher>::Domain> {
+        challenge,
+        commitment: None,
+    };
+
+    PoRCompound::<Tree>::generate_public_inputs(&pub_inputs, por_params, k)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellperson::util_cs::{metric_cs::MetricCS, test_cs::TestConstraintSystem};
+    use bellperson::ConstraintSystem;
+    use ff::Field;
+    use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
+    use merkletree::merkle::get_merkle_tree_len;
+    use merkletree::store::StoreConfig;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        cache_key::CacheKey,
+        fr32::fr_into_bytes,
+        hasher::{Domain, HashFunction, PoseidonHasher, Sha256Hasher},
+        merkle::{get_base_tree_count, DiskTree},
+        util::{default_rows_to_discard, NODE_SIZE},
+    };
+
+    use crate::update::{PrivateInputs, PublicInputs, SetupParams};
+
+    fn update_input_circuit<Tree: 'static + MerkleTreeTrait>() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+        let nodes = 8 * get_base_tree_count::<Tree>();
+        let nodes_count = nodes / get_base_tree_count::<Tree>();
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let tree_r_last = |name: &str, replica: &[u8]| {
+            let dir = cache_dir.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            let replica_path = dir.join("replica");
+            std::fs::write(&replica_path, replica).unwrap();
+
+            let config = StoreConfig::new(
+                &dir,
+                CacheKey::CommRLastTree.to_string(),
+                default_rows_to_discard(nodes_count, Tree::Arity::to_usize()),
+            );
+            let config = StoreConfig::from_config(
+                &config,
+                CacheKey::CommRLastTree.to_string(),
+                Some(get_merkle_tree_len(nodes_count, Tree::Arity::to_usize()).unwrap()),
+            );
+            let rows_to_discard = config.rows_to_discard;
+            let tree = ReplicaUpdate::<Tree, Sha256Hasher>::generate_tree_r_last(
+                replica,
+                config,
+                replica_path,
+            )
+            .unwrap();
+
+            (tree, rows_to_discard)
+        };
+
+        let sector_key: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+        let data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes(&Fr::random(rng)))
+            .collect();
+
+        let (tree_r_last_old, tree_r_last_old_rows_to_discard) = tree_r_last("old", &sector_key);
+        let comm_c = <Tree::Hasher as Hasher>::Domain::random(rng);
+        let comm_r_old =
+            <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_old.root());
+
+        let tree_d = BinaryMerkleTree::<Sha256Hasher>::try_from_iter(
+            data.chunks(NODE_SIZE)
+                .map(<Sha256Hasher as Hasher>::Domain::try_from_bytes),
+        )
+        .unwrap();
+        let comm_d_new = tree_d.root();
+
+        let rho = ReplicaUpdate::<Tree, Sha256Hasher>::rho(&comm_r_old, &comm_d_new);
+        let mut replica = data.clone();
+        ReplicaUpdate::<Tree, Sha256Hasher>::encode(&sector_key, &mut replica, &rho).unwrap();
+        let (tree_r_last_new, tree_r_last_new_rows_to_discard) = tree_r_last("new", &replica);
+        let comm_r_new =
+            <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_new.root());
+
+        let pub_params = ReplicaUpdate::<Tree, Sha256Hasher>::setup(&SetupParams {
+            nodes,
+            challenge_count: 2,
+        })
+        .unwrap();
+        let pub_inputs = PublicInputs {
+            comm_r_old,
+            comm_d_new,
+            comm_r_new,
+            k: None,
+        };
+        let priv_inputs = PrivateInputs::<Tree, Sha256Hasher> {
+            comm_c,
+            tree_d: &tree_d,
+            tree_r_last_old: &tree_r_last_old,
+            tree_r_last_old_rows_to_discard,
+            tree_r_last_new: &tree_r_last_new,
+            tree_r_last_new_rows_to_discard,
+        };
+
+        let proof =
+            ReplicaUpdate::<Tree, Sha256Hasher>::prove(&pub_params, &pub_inputs, &priv_inputs)
+                .unwrap();
+        assert!(
+            ReplicaUpdate::<Tree, Sha256Hasher>::verify(&pub_params, &pub_inputs, &proof).unwrap()
+        );
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        ReplicaUpdateCompound::<Tree, Sha256Hasher>::circuit(
+            &pub_inputs,
+            (),
+            &proof,
+            &pub_params,
+            None,
+        )
+        .expect("circuit failed")
+        .synthesize(&mut cs.namespace(|| "replica update"))
+        .expect("failed to synthesize circuit");
+
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert_eq!(cs.get_input(0, "ONE"), Fr::one());
+
+        let generated_inputs = <ReplicaUpdateCompound<Tree, Sha256Hasher> as CompoundProof<
+            ReplicaUpdate<Tree, Sha256Hasher>,
+            _,
+        >>::generate_public_inputs(&pub_inputs, &pub_params, None)
+        .expect("failed to generate public inputs");
+        let expected_inputs = cs.get_inputs();
+
+        for ((input, label), generated_input) in
+            expected_inputs.iter().skip(1).zip(generated_inputs.iter())
+        {
+            assert_eq!(input, generated_input, "{}", label);
+        }
+
+        assert_eq!(
+            generated_inputs.len(),
+            expected_inputs.len() - 1,
+            "inputs are not the same length"
+        );
+
+        // The blank circuit must have the same shape.
+        let mut blank_cs = MetricCS::<Bls12>::new();
+        <ReplicaUpdateCompound<Tree, Sha256Hasher> as CompoundProof<
+            ReplicaUpdate<Tree, Sha256Hasher>,
+            _,
+        >>::blank_circuit(&pub_params)
+        .synthesize(&mut blank_cs.namespace(|| "replica update"))
+        .expect("failed to synthesize blank circuit");
+        assert_eq!(blank_cs.num_inputs(), cs.num_inputs());
+        assert_eq!(blank_cs.num_constraints(), cs.num_constraints());
+
+        cache_dir.close().expect("Failed to remove cache dir");
+    }
+
+    #[test]
+    fn update_input_circuit_poseidon_base_8() {
+        update_input_circuit::<DiskTree<PoseidonHasher, U8, U0, U0>>();
+    }
+
+    #[test]
+    fn update_input_circuit_poseidon_sub_8_2() {
+        update_input_circuit::<DiskTree<PoseidonHasher, U8, U2, U0>>();
+    }
+
+    #[test]
+    fn update_input_circuit_poseidon_top_8_4_2() {
+        update_input_circuit::<DiskTree<PoseidonHasher, U8, U4, U2>>();
+    }
+}
@@ -0,0 +1,578 @@
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context};
+use ff::Field;
+use generic_array::typenum;
+use log::{info, trace};
+use merkletree::store::StoreConfig;
+use num_bigint::BigUint;
+use num_traits::cast::ToPrimitive;
+use paired::bls12_381::Fr;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use storage_proofs_core::{
+    error::Result,
+    fr32::{bytes_into_fr, fr_into_bytes},
+    hasher::{Domain, HashFunction, Hasher},
+    merkle::*,
+    parameter_cache::ParameterSetMetadata,
+    proof::{NoRequirements, ProofScheme},
+    util::NODE_SIZE,
+};
+
+#[derive(Debug, Clone)]
+pub struct SetupParams {
+    /// Number of nodes in the sector.
+    pub nodes: usize,
+    /// Number of challenges per partition.
+    pub challenge_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicParams {
+    pub nodes: usize,
+    pub challenge_count: usize,
+}
+
+impl ParameterSetMetadata for PublicParams {
+    fn identifier(&self) -> String {
+        format!(
+            "replica_update::PublicParams{{ nodes: {}, challenge_count: {} }}",
+            self.nodes, self.challenge_count
+        )
+    }
+
+    fn sector_size(&self) -> u64 {
+        (self.nodes * NODE_SIZE) as u64
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PublicInputs<T: Domain, S: Domain> {
+    /// The replica commitment of the sector before the update.
+    pub comm_r_old: T,
+    /// The data commitment of the new data.
+    pub comm_d_new: S,
+    /// The replica commitment of the sector after the update.
+    pub comm_r_new: T,
+    /// Partition index
+    pub k: Option<usize>,
+}
+
+impl<T: Domain, S: Domain> PublicInputs<T, S> {
+    /// Derive the challenged nodes of the given partition.
+    pub fn challenges(
+        &self,
+        challenge_count: usize,
+        leaves: usize,
+        partition_k: Option<usize>,
+    ) -> Vec<usize> {
+        let k = partition_k.unwrap_or(0);
+
+        (0..challenge_count)
+            .map(|i| {
+                let j: u32 = ((challenge_count * k) + i) as u32;
+
+                let hash = Sha256::new()
+                    .chain(self.comm_r_old.into_bytes())
+                    .chain(self.comm_d_new.into_bytes())
+                    .chain(self.comm_r_new.into_bytes())
+                    .chain(&j.to_le_bytes())
+                    .finalize();
+
+                let big_challenge = BigUint::from_bytes_le(hash.as_ref());
+                (big_challenge % leaves)
+                    .to_usize()
+                    .expect("`big_mod_challenge` exceeds size of `usize`")
+            })
+            .collect()
+    }
+}
+
+pub struct PrivateInputs<'a, Tree: MerkleTreeTrait, G: Hasher> {
+    /// The column commitment of the sector, which is not changed by an update.
+    pub comm_c: <Tree::Hasher as Hasher>::Domain,
+    /// The data tree of the new data.
+    pub tree_d: &'a BinaryMerkleTree<G>,
+    /// The tree over the sector key, i.e. the replica before the update.
+    pub tree_r_last_old:
+        &'a LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    pub tree_r_last_old_rows_to_discard: usize,
+    /// The tree over the updated replica.
+    pub tree_r_last_new:
+        &'a LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    pub tree_r_last_new_rows_to_discard: usize,
+}
+
+/// Openings of a single challenged node.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChallengeProof<Tree: MerkleTreeTrait, G: Hasher> {
+    #[serde(bound(
+        serialize = "MerkleProof<G, typenum::U2>: Serialize",
+        deserialize = "MerkleProof<G, typenum::U2>: Deserialize<'de>"
+    ))]
+    pub comm_d_proof: MerkleProof<G, typenum::U2>,
+    #[serde(bound(
+        serialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Deserialize<'de>"
+    ))]
+    pub comm_r_last_old_proof:
+        MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+    #[serde(bound(
+        serialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Serialize",
+        deserialize = "MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>: Deserialize<'de>"
+    ))]
+    pub comm_r_last_new_proof:
+        MerkleProof<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for ChallengeProof<Tree, G> {
+    fn clone(&self) -> Self {
+        Self {
+            comm_d_proof: self.comm_d_proof.clone(),
+            comm_r_last_old_proof: self.comm_r_last_old_proof.clone(),
+            comm_r_last_new_proof: self.comm_r_last_new_proof.clone(),
+        }
+    }
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> ChallengeProof<Tree, G> {
+    /// Verify the openings of `challenge` against the given roots, and that the new replica
+    /// node encodes the data node on top of the old one.
+    pub fn verify(
+        &self,
+        challenge: usize,
+        rho: &Fr,
+        comm_d_new: &G::Domain,
+        comm_r_last_old: &<Tree::Hasher as Hasher>::Domain,
+        comm_r_last_new: &<Tree::Hasher as Hasher>::Domain,
+    ) -> bool {
+        if !self.comm_d_proof.validate(challenge)
+            || !self.comm_r_last_old_proof.validate(challenge)
+            || !self.comm_r_last_new_proof.validate(challenge)
+        {
+            trace!("invalid inclusion proof for challenge {}", challenge);
+            return false;
+        }
+
+        if self.comm_d_proof.root() != *comm_d_new
+            || self.comm_r_last_old_proof.root() != *comm_r_last_old
+            || self.comm_r_last_new_proof.root() != *comm_r_last_new
+        {
+            trace!(
+                "inclusion proof roots do not match for challenge {}",
+                challenge
+            );
+            return false;
+        }
+
+        let mut expected: Fr = self.comm_d_proof.leaf().into();
+        expected.mul_assign(rho);
+        expected.add_assign(&self.comm_r_last_old_proof.leaf().into());
+
+        let new_leaf: Fr = self.comm_r_last_new_proof.leaf().into();
+        expected == new_leaf
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Proof<Tree: MerkleTreeTrait, G: Hasher> {
+    #[serde(bound(
+        serialize = "<Tree::Hasher as Hasher>::Domain: Serialize",
+        deserialize = "<Tree::Hasher as Hasher>::Domain: Deserialize<'de>"
+    ))]
+    pub comm_c: <Tree::Hasher as Hasher>::Domain,
+    #[serde(bound(
+        serialize = "<Tree::Hasher as Hasher>::Domain: Serialize",
+        deserialize = "<Tree::Hasher as Hasher>::Domain: Deserialize<'de>"
+    ))]
+    pub comm_r_last_old: <Tree::Hasher as Hasher>::Domain,
+    #[serde(bound(
+        serialize = "<Tree::Hasher as Hasher>::Domain: Serialize",
+        deserialize = "<Tree::Hasher as Hasher>::Domain: Deserialize<'de>"
+    ))]
+    pub comm_r_last_new: <Tree::Hasher as Hasher>::Domain,
+    #[serde(bound(
+        serialize = "ChallengeProof<Tree, G>: Serialize",
+        deserialize = "ChallengeProof<Tree, G>: Deserialize<'de>"
+    ))]
+    pub challenge_proofs: Vec<ChallengeProof<Tree, G>>,
+}
+
+impl<Tree: MerkleTreeTrait, G: Hasher> Clone for Proof<Tree, G> {
+    fn clone(&self) -> Self {
+        Self {
+            comm_c: self.comm_c,
+            comm_r_last_old: self.comm_r_last_old,
+            comm_r_last_new: self.comm_r_last_new,
+            challenge_proofs: self.challenge_proofs.clone(),
+        }
+    }
+}
+
+/// Replica update of an already sealed sector.
+///
+/// The new data is encoded on top of the existing replica (the sector key), node by node:
+/// `new_replica = sector_key + rho * data`, where `rho = H(comm_r_old || comm_d_new)`. The
+/// column commitment of the sector is kept, only tree r last is rebuilt, so
+/// `comm_r_new = H(comm_c || comm_r_last_new)`.
+#[derive(Debug)]
+pub struct ReplicaUpdate<'a, Tree: 'a + MerkleTreeTrait, G: 'a + Hasher> {
+    _a: PhantomData<&'a Tree>,
+    _b: PhantomData<&'a G>,
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ReplicaUpdate<'a, Tree, G> {
+    /// The factor the data is multiplied with before it is added to the sector key.
+    pub fn rho(comm_r_old: &<Tree::Hasher as Hasher>::Domain, comm_d_new: &G::Domain) -> Fr {
+        let comm_d_new: <Tree::Hasher as Hasher>::Domain = Into::<Fr>::into(*comm_d_new).into();
+
+        <Tree::Hasher as Hasher>::Function::hash2(comm_r_old, &comm_d_new).into()
+    }
+
+    /// Encodes `data` in place on top of `sector_key`, turning it into the updated replica.
+    pub fn encode(sector_key: &[u8], data: &mut [u8], rho: &Fr) -> Result<()> {
+        ensure!(
+            sector_key.len() == data.len(),
+            "sector key and data must have the same length"
+        );
+        ensure!(data.len() % NODE_SIZE == 0, "invalid data length");
+
+        data.par_chunks_mut(NODE_SIZE)
+            .zip(sector_key.par_chunks(NODE_SIZE))
+            .try_for_each(|(node, key_node)| {
+                let mut value = bytes_into_fr(node)?;
+                value.mul_assign(rho);
+                value.add_assign(&bytes_into_fr(key_node)?);
+                node.copy_from_slice(&fr_into_bytes(&value));
+
+                Ok(())
+            })
+    }
+
+    /// Decodes `replica` in place, given the `sector_key` it was encoded on, recovering the
+    /// data.
+    pub fn decode(sector_key: &[u8], replica: &mut [u8], rho: &Fr) -> Result<()> {
+        ensure!(
+            sector_key.len() == replica.len(),
+            "sector key and replica must have the same length"
+        );
+        ensure!(replica.len() % NODE_SIZE == 0, "invalid replica length");
+
+        let rho_inv = rho.inverse().context("rho is not invertible")?;
+
+        replica
+            .par_chunks_mut(NODE_SIZE)
+            .zip(sector_key.par_chunks(NODE_SIZE))
+            .try_for_each(|(node, key_node)| {
+                let mut value = bytes_into_fr(node)?;
+                value.sub_assign(&bytes_into_fr(key_node)?);
+                value.mul_assign(&rho_inv);
+                node.copy_from_slice(&fr_into_bytes(&value));
+
+                Ok(())
+            })
+    }
+
+    /// Builds tree r last over the (updated) `replica`, which is stored at `replica_path`.
+    pub fn generate_tree_r_last(
+        replica: &[u8],
+        tree_r_last_config: StoreConfig,
+        replica_path: PathBuf,
+    ) -> Result<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>> {
+        ensure!(replica.len() % NODE_SIZE == 0, "invalid replica length");
+
+        let tree_count = get_base_tree_count::<Tree>();
+        let nodes_count = replica.len() / NODE_SIZE / tree_count;
+        let (configs, replica_config) = split_config_and_replica(
+            tree_r_last_config.clone(),
+            replica_path,
+            nodes_count,
+            tree_count,
+        )?;
+
+        for (i, config) in configs.iter().enumerate() {
+            info!("building base tree_r_last {}/{}", i + 1, tree_count);
+
+            let start = i * nodes_count * NODE_SIZE;
+            let end = start + nodes_count * NODE_SIZE;
+            let leaves = replica[start..end]
+                .par_chunks(NODE_SIZE)
+                .map(<Tree::Hasher as Hasher>::Domain::try_from_bytes)
+                .collect::<Result<Vec<_>>>()?;
+
+            LCTree::<Tree::Hasher, Tree::Arity, typenum::U0, typenum::U0>::from_par_iter_with_config(leaves, config.clone())?;
+        }
+
+        create_lc_tree::<LCTree<Tree::Hasher, Tree::Arity, Tree::SubTreeArity, Tree::TopTreeArity>>(
+            tree_r_last_config
+                .size
+                .context("tree_r_last config is missing the size")?,
+            &configs,
+            &replica_config,
+        )
+    }
+}
+
+impl<'a, Tree: 'static + MerkleTreeTrait, G: 'static + Hasher> ProofScheme<'a>
+    for ReplicaUpdate<'a, Tree, G>
+{
+    type PublicParams = PublicParams;
+    type SetupParams = SetupParams;
+    type PublicInputs = PublicInputs<<Tree::Hasher as Hasher>::Domain, G::Domain>;
+    type PrivateInputs = PrivateInputs<'a, Tree, G>;
+    type Proof = Proof<Tree, G>;
+    type Requirements = NoRequirements;
+
+    fn setup(sp: &Self::SetupParams) -> Result<Self::PublicParams> {
+        ensure!(sp.challenge_count > 0, "challenge_count must not be 0");
+        ensure!(
+            sp.nodes % get_base_tree_count::<Tree>() == 0,
+            "nodes must be divisible by the number of base trees"
+        );
+
+        Ok(PublicParams {
+            nodes: sp.nodes,
+            challenge_count: sp.challenge_count,
+        })
+    }
+
+    fn prove<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        let challenges =
+            pub_inputs.challenges(pub_params.challenge_count, pub_params.nodes, pub_inputs.k);
+
+        let challenge_proofs = challenges
+            .into_par_iter()
+            .map(|challenge| {
+                trace!("proving challenge {}", challenge);
+
+                let comm_d_proof = priv_inputs.tree_d.gen_proof(challenge)?;
+                let comm_r_last_old_proof = priv_inputs.tree_r_last_old.gen_cached_proof(
+                    challenge,
+                    Some(priv_inputs.tree_r_last_old_rows_to_discard),
+                )?;
+                let comm_r_last_new_proof = priv_inputs.tree_r_last_new.gen_cached_proof(
+                    challenge,
+                    Some(priv_inputs.tree_r_last_new_rows_to_discard),
+                )?;
+
+                Ok(ChallengeProof {
+                    comm_d_proof,
+                    comm_r_last_old_proof,
+                    comm_r_last_new_proof,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Proof {
+            comm_c: priv_inputs.comm_c,
+            comm_r_last_old: priv_inputs.tree_r_last_old.root(),
+            comm_r_last_new: priv_inputs.tree_r_last_new.root(),
+            challenge_proofs,
+        })
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        // comm_r = H(comm_c || comm_r_last), before and after the update
+        let comm_r_old =
+            <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &proof.comm_r_last_old);
+        if comm_r_old != pub_inputs.comm_r_old {
+            trace!("invalid comm_r_old");
+            return Ok(false);
+        }
+        let comm_r_new =
+            <Tree::Hasher as Hasher>::Function::hash2(&proof.comm_c, &proof.comm_r_last_new);
+        if comm_r_new != pub_inputs.comm_r_new {
+            trace!("invalid comm_r_new");
+            return Ok(false);
+        }
+
+        let challenges =
+            pub_inputs.challenges(pub_params.challenge_count, pub_params.nodes, pub_inputs.k);
+        if challenges.len() != proof.challenge_proofs.len() {
+            return Ok(false);
+        }
+
+        let rho = Self::rho(&pub_inputs.comm_r_old, &pub_inputs.comm_d_new);
+
+        let valid = challenges
+            .into_par_iter()
+            .zip(proof.challenge_proofs.par_iter())
+            .all(|(challenge, challenge_proof)| {
+                challenge_proof.verify(
+                    challenge,
+                    &rho,
+                    &pub_inputs.comm_d_new,
+                    &proof.comm_r_last_old,
+                    &proof.comm_r_last_new,
+                )
+            });
+
+        Ok(valid)
+    }
+
+    fn with_partition(mut pub_in: Self::PublicInputs, k: Option<usize>) -> Self::PublicInputs {
+        pub_in.k = k;
+        pub_in
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use generic_array::typenum::{Unsigned, U0, U2, U4, U8};
+    use merkletree::merkle::get_merkle_tree_len;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use storage_proofs_core::{
+        cache_key::CacheKey,
+        hasher::{PoseidonHasher, Sha256Hasher},
+        util::default_rows_to_discard,
+    };
+
+    use crate::TEST_SEED;
+
+    fn random_nodes<R: Rng, H: Hasher>(rng: &mut R, nodes: usize) -> Vec<u8> {
+        (0..nodes)
+            .flat_map(|_| H::Domain::random(rng).into_bytes())
+            .collect()
+    }
+
+    fn test_replica_update<Tree: 'static + MerkleTreeTrait>() {
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let nodes = 64 * get_base_tree_count::<Tree>();
+        let nodes_count = nodes / get_base_tree_count::<Tree>();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let old_dir = cache_dir.path().join("old");
+        let new_dir = cache_dir.path().join("new");
+        std::fs::create_dir_all(&old_dir).unwrap();
+        std::fs::create_dir_all(&new_dir).unwrap();
+
+        let tree_r_last_config = |dir: &std::path::Path| {
+            let config = StoreConfig::new(
+                dir,
+                CacheKey::CommRLastTree.to_string(),
+                default_rows_to_discard(nodes_count, Tree::Arity::to_usize()),
+            );
+            StoreConfig::from_config(
+                &config,
+                CacheKey::CommRLastTree.to_string(),
+                Some(get_merkle_tree_len(nodes_count, Tree::Arity::to_usize()).unwrap()),
+            )
+        };
+
+        // The sector key, as left behind by sealing a committed capacity sector.
+        let sector_key = random_nodes::<_, Tree::Hasher>(rng, nodes);
+        let sector_key_path = old_dir.join("sector-key");
+        std::fs::write(&sector_key_path, &sector_key).unwrap();
+        let old_config = tree_r_last_config(&old_dir);
+        let tree_r_last_old = ReplicaUpdate::<Tree, Sha256Hasher>::generate_tree_r_last(
+            &sector_key,
+            old_config.clone(),
+            sector_key_path,
+        )
+        .unwrap();
+        let comm_c = <Tree::Hasher as Hasher>::Domain::random(rng);
+        let comm_r_old =
+            <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_old.root());
+
+        let data = random_nodes::<_, Sha256Hasher>(rng, nodes);
+        let tree_d = BinaryMerkleTree::<Sha256Hasher>::try_from_iter(
+            data.chunks(NODE_SIZE)
+                .map(<Sha256Hasher as Hasher>::Domain::try_from_bytes),
+        )
+        .unwrap();
+        let comm_d_new = tree_d.root();
+
+        let rho = ReplicaUpdate::<Tree, Sha256Hasher>::rho(&comm_r_old, &comm_d_new);
+        let mut replica = data.clone();
+        ReplicaUpdate::<Tree, Sha256Hasher>::encode(&sector_key, &mut replica, &rho).unwrap();
+        let replica_path = new_dir.join("replica");
+        std::fs::write(&replica_path, &replica).unwrap();
+        let new_config = tree_r_last_config(&new_dir);
+        let tree_r_last_new = ReplicaUpdate::<Tree, Sha256Hasher>::generate_tree_r_last(
+            &replica,
+            new_config.clone(),
+            replica_path,
+        )
+        .unwrap();
+        let comm_r_new =
+            <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &tree_r_last_new.root());
+
+        let mut decoded = replica.clone();
+        ReplicaUpdate::<Tree, Sha256Hasher>::decode(&sector_key, &mut decoded, &rho).unwrap();
+        assert_eq!(decoded, data);
+
+        let pub_params = ReplicaUpdate::<Tree, Sha256Hasher>::setup(&SetupParams {
+            nodes,
+            challenge_count: 5,
+        })
+        .unwrap();
+        let pub_inputs = PublicInputs {
+            comm_r_old,
+            comm_d_new,
+            comm_r_new,
+            k: None,
+        };
+        let priv_inputs = PrivateInputs::<Tree, Sha256Hasher> {
+            comm_c,
+            tree_d: &tree_d,
+            tree_r_last_old: &tree_r_last_old,
+            tree_r_last_old_rows_to_discard: old_config.rows_to_discard,
+            tree_r_last_new: &tree_r_last_new,
+            tree_r_last_new_rows_to_discard: new_config.rows_to_discard,
+        };
+
+        let proofs = ReplicaUpdate::<Tree, Sha256Hasher>::prove_all_partitions(
+            &pub_params,
+            &pub_inputs,
+            &priv_inputs,
+            2,
+        )
+        .unwrap();
+        assert!(ReplicaUpdate::<Tree, Sha256Hasher>::verify_all_partitions(
+            &pub_params,
+            &pub_inputs,
+            &proofs
+        )
+        .unwrap());
+
+        // A proof must not verify against a different comm_r_new.
+        let wrong_pub_inputs = PublicInputs {
+            comm_r_new: comm_r_old,
+            ..pub_inputs
+        };
+        assert!(!ReplicaUpdate::<Tree, Sha256Hasher>::verify_all_partitions(
+            &pub_params,
+            &wrong_pub_inputs,
+            &proofs
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_replica_update_poseidon_base_8() {
+        test_replica_update::<DiskTree<PoseidonHasher, U8, U0, U0>>();
+    }
+
+    #[test]
+    fn test_replica_update_poseidon_sub_8_2() {
+        test_replica_update::<DiskTree<PoseidonHasher, U8, U2, U0>>();
+    }
+
+    #[test]
+    fn test_replica_update_poseidon_top_8_4_2() {
+        test_replica_update::<DiskTree<PoseidonHasher, U8, U4, U2>>();
+    }
+}
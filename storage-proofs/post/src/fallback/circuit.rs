@@ -12,7 +12,7 @@ use storage_proofs_core::{
     gadgets::variables::Root,
     hasher::{HashFunction, Hasher},
     merkle::MerkleTreeTrait,
-    por, settings,
+    por,
     util::NODE_SIZE,
 };
 
@@ -22,6 +22,9 @@ use super::vanilla::{PublicParams, PublicSector, SectorProof};
 pub struct FallbackPoStCircuit<Tree: MerkleTreeTrait> {
     pub prover_id: Option<Fr>,
     pub sectors: Vec<Sector<Tree>>,
+    /// Number of chunks the sectors are synthesized in, in parallel. Synthesis runs on worker
+    /// threads, so this is taken from the settings when the circuit is built.
+    pub synthesis_num_cpus: usize,
 }
 
 #[derive(Clone)]
@@ -184,12 +187,13 @@ impl<Tree: 'static + MerkleTreeTrait> FallbackPoStCircuit<Tree> {
         self,
         cs: &mut CS,
     ) -> Result<(), SynthesisError> {
-        let FallbackPoStCircuit { sectors, .. } = self;
+        let FallbackPoStCircuit {
+            sectors,
+            synthesis_num_cpus,
+            ..
+        } = self;
 
-        let num_chunks = settings::SETTINGS
-            .lock()
-            .unwrap()
-            .window_post_synthesis_num_cpus as usize;
+        let num_chunks = synthesis_num_cpus.max(1);
 
         let chunk_size = (sectors.len() / num_chunks).max(1);
         let css = sectors
@@ -336,6 +340,7 @@ mod tests {
                 tree,
                 comm_c,
                 comm_r_last,
+                rows_to_discard: None,
             });
 
             let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);
@@ -398,6 +403,7 @@ mod tests {
             let instance = FallbackPoStCircuit::<Tree> {
                 sectors: circuit_sectors,
                 prover_id: Some(prover_id.into()),
+                synthesis_num_cpus: 1,
             };
 
             instance
@@ -12,6 +12,7 @@ use storage_proofs_core::{
     parameter_cache::{CacheableParameters, ParameterSetMetadata},
     por,
     proof::ProofScheme,
+    settings,
     util::NODE_SIZE,
 };
 
@@ -140,6 +141,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait>
         Ok(FallbackPoStCircuit {
             prover_id: Some(pub_in.prover_id.into()),
             sectors: res_sectors,
+            synthesis_num_cpus: settings::current().window_post_synthesis_num_cpus as usize,
         })
     }
 
@@ -153,6 +155,7 @@ impl<'a, Tree: 'static + MerkleTreeTrait>
         FallbackPoStCircuit {
             prover_id: None,
             sectors,
+            synthesis_num_cpus: settings::current().window_post_synthesis_num_cpus as usize,
         }
     }
 }
@@ -264,6 +267,7 @@ mod tests {
                 tree,
                 comm_c,
                 comm_r_last,
+                rows_to_discard: None,
             });
 
             let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);
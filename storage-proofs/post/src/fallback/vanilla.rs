@@ -86,6 +86,8 @@ pub struct PrivateSector<'a, Tree: MerkleTreeTrait> {
     >,
     pub comm_c: <Tree::Hasher as Hasher>::Domain,
     pub comm_r_last: <Tree::Hasher as Hasher>::Domain,
+    /// Rows discarded from the cached tree, `None` to use the configured default.
+    pub rows_to_discard: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -230,6 +232,74 @@ pub fn generate_leaf_challenge<T: Domain>(
     Ok(challenged_range_index)
 }
 
+/// Generates the leaf challenges for the sector at `sector_index` among all challenged
+/// sectors, matching the challenges used by `prove_all_partitions`.
+pub fn generate_sector_leaf_challenges<T: Domain>(
+    pub_params: &PublicParams,
+    randomness: T,
+    sector_id: SectorId,
+    sector_index: usize,
+) -> Result<Vec<u64>> {
+    (0..pub_params.challenge_count)
+        .map(|n| {
+            let challenge_index = (sector_index * pub_params.challenge_count + n) as u64;
+            generate_leaf_challenge(pub_params, randomness, sector_id.into(), challenge_index)
+        })
+        .collect()
+}
+
+/// Generates the vanilla proof for a single sector, by opening its tree at each of the
+/// given leaf `challenges`.
+pub fn prove_sector<Tree: MerkleTreeTrait>(
+    priv_sector: &PrivateSector<'_, Tree>,
+    challenges: &[u64],
+) -> Result<SectorProof<Tree::Proof>> {
+    let tree = priv_sector.tree;
+    let tree_leafs = tree.leafs();
+    let rows_to_discard = priv_sector
+        .rows_to_discard
+        .unwrap_or_else(|| default_rows_to_discard(tree_leafs, Tree::Arity::to_usize()));
+
+    trace!(
+        "Generating proof for tree leafs {} and arity {}",
+        tree_leafs,
+        Tree::Arity::to_usize(),
+    );
+
+    let inclusion_proofs = challenges
+        .par_iter()
+        .map(|challenge| tree.gen_cached_proof(*challenge as usize, Some(rows_to_discard)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(SectorProof {
+        inclusion_proofs,
+        comm_c: priv_sector.comm_c,
+        comm_r_last: priv_sector.comm_r_last,
+    })
+}
+
+/// Groups sector proofs, ordered like the public sectors, into partition proofs of
+/// `pub_params.sector_count` sectors each. The last partition is padded by
+/// duplicating its last sector proof, such that it works in the circuit part.
+pub fn partition_sector_proofs<P: MerkleProofTrait>(
+    pub_params: &PublicParams,
+    sector_proofs: Vec<SectorProof<P>>,
+) -> Vec<Proof<P>> {
+    let num_sectors_per_chunk = pub_params.sector_count;
+
+    sector_proofs
+        .chunks(num_sectors_per_chunk)
+        .map(|chunk| {
+            let mut proofs = chunk.to_vec();
+            while proofs.len() < num_sectors_per_chunk {
+                proofs.push(proofs[proofs.len() - 1].clone());
+            }
+
+            Proof { sectors: proofs }
+        })
+        .collect()
+}
+
 impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree> {
     type PublicParams = PublicParams;
     type SetupParams = SetupParams;
@@ -291,7 +361,7 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
             num_sectors_per_chunk,
         );
 
-        let mut partition_proofs = Vec::new();
+        let mut sector_proofs = Vec::with_capacity(num_sectors);
 
         for (j, (pub_sectors_chunk, priv_sectors_chunk)) in pub_inputs
             .sectors
@@ -301,58 +371,23 @@ impl<'a, Tree: 'a + MerkleTreeTrait> ProofScheme<'a> for FallbackPoSt<'a, Tree>
         {
             trace!("proving partition {}", j);
 
-            let mut proofs = Vec::with_capacity(num_sectors_per_chunk);
-
             for (i, (pub_sector, priv_sector)) in pub_sectors_chunk
                 .iter()
                 .zip(priv_sectors_chunk.iter())
                 .enumerate()
             {
-                let tree = priv_sector.tree;
-                let sector_id = pub_sector.id;
-                let tree_leafs = tree.leafs();
-                let rows_to_discard = default_rows_to_discard(tree_leafs, Tree::Arity::to_usize());
-
-                trace!(
-                    "Generating proof for tree leafs {} and arity {}",
-                    tree_leafs,
-                    Tree::Arity::to_usize(),
-                );
-
-                let inclusion_proofs = (0..pub_params.challenge_count)
-                    .into_par_iter()
-                    .map(|n| {
-                        let challenge_index = ((j * num_sectors_per_chunk + i)
-                            * pub_params.challenge_count
-                            + n) as u64;
-                        let challenged_leaf_start = generate_leaf_challenge(
-                            pub_params,
-                            pub_inputs.randomness,
-                            sector_id.into(),
-                            challenge_index,
-                        )?;
-
-                        tree.gen_cached_proof(challenged_leaf_start as usize, Some(rows_to_discard))
-                    })
-                    .collect::<Result<Vec<_>>>()?;
-
-                proofs.push(SectorProof {
-                    inclusion_proofs,
-                    comm_c: priv_sector.comm_c,
-                    comm_r_last: priv_sector.comm_r_last,
-                });
+                let challenges = generate_sector_leaf_challenges(
+                    pub_params,
+                    pub_inputs.randomness,
+                    pub_sector.id,
+                    j * num_sectors_per_chunk + i,
+                )?;
+
+                sector_proofs.push(prove_sector(priv_sector, &challenges)?);
             }
-
-            // If there were less than the required number of sectors provided, we duplicate the last one
-            // to pad the proof out, such that it works in the circuit part.
-            while proofs.len() < num_sectors_per_chunk {
-                proofs.push(proofs[proofs.len() - 1].clone());
-            }
-
-            partition_proofs.push(Proof { sectors: proofs });
         }
 
-        Ok(partition_proofs)
+        Ok(partition_sector_proofs(pub_params, sector_proofs))
     }
 
     fn verify_all_partitions(
@@ -528,6 +563,7 @@ mod tests {
                 tree,
                 comm_c,
                 comm_r_last,
+                rows_to_discard: None,
             });
 
             let comm_r = <Tree::Hasher as Hasher>::Function::hash2(&comm_c, &comm_r_last);